@@ -56,7 +56,9 @@ mod lib_mappings;
 mod library_info;
 mod marker_table;
 mod markers;
+mod meta_info;
 mod native_symbols;
+mod paused_range;
 mod process;
 mod profile;
 mod profile_symbol_info;
@@ -86,7 +88,9 @@ pub use markers::{
     RuntimeSchemaMarkerField, RuntimeSchemaMarkerGraph, RuntimeSchemaMarkerSchema,
     StaticSchemaMarker, StaticSchemaMarkerField, StaticSchemaMarkerGraph,
 };
+pub use meta_info::ProfileConfiguration;
 pub use native_symbols::NativeSymbolHandle;
+pub use paused_range::PausedRangeReason;
 pub use process::ThreadHandle;
 pub use profile::{
     FrameHandle, FrameSymbolInfo, Profile, SamplingInterval, SourceLocation, StackHandle,
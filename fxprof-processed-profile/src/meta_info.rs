@@ -0,0 +1,92 @@
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::Value;
+
+use crate::markers::{MarkerFieldFormat, MarkerFieldFormatKind};
+
+/// The profiler configuration which was used for the recording, displayed in
+/// the profile's metadata. Can be set with [`Profile::set_configuration`](crate::Profile::set_configuration).
+#[derive(Debug, Clone, Default)]
+pub struct ProfileConfiguration {
+    /// The features which were enabled in the profiler, for example the names
+    /// of the recorded events.
+    pub features: Vec<String>,
+    /// The filters for the threads which were captured. Empty if all threads
+    /// were captured.
+    pub threads: Vec<String>,
+    /// The capacity of the profiler's buffer, in bytes.
+    pub capacity: Option<u64>,
+}
+
+impl Serialize for ProfileConfiguration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("features", &self.features)?;
+        map.serialize_entry("threads", &self.threads)?;
+        if let Some(capacity) = self.capacity {
+            map.serialize_entry("capacity", &capacity)?;
+        }
+        map.end()
+    }
+}
+
+/// A labeled section of free-form key / value entries in the profile's metadata.
+#[derive(Debug, Clone)]
+pub(crate) struct ExtraInfoSection {
+    pub label: String,
+    pub entries: Vec<ExtraInfoEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ExtraInfoEntry {
+    pub label: String,
+    /// The number format of the value, or `None` for strings.
+    pub format: Option<MarkerFieldFormat>,
+    pub value: Value,
+}
+
+impl ExtraInfoEntry {
+    pub fn string(label: &str, value: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            format: None,
+            value: Value::from(value),
+        }
+    }
+
+    pub fn number(label: &str, value: f64, format: MarkerFieldFormat) -> Self {
+        assert_eq!(
+            format.kind(),
+            MarkerFieldFormatKind::Number,
+            "Extra info numbers need a number format"
+        );
+        Self {
+            label: label.to_owned(),
+            format: Some(format),
+            value: Value::from(value),
+        }
+    }
+}
+
+impl Serialize for ExtraInfoSection {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("label", &self.label)?;
+        map.serialize_entry("entries", &self.entries)?;
+        map.end()
+    }
+}
+
+impl Serialize for ExtraInfoEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("label", &self.label)?;
+        match &self.format {
+            Some(format) => map.serialize_entry("format", format)?,
+            // "unique-string" values are indexes into the string table, so use
+            // the plain "string" format, which displays the value as it is.
+            None => map.serialize_entry("format", "string")?,
+        }
+        map.serialize_entry("value", &self.value)?;
+        map.end()
+    }
+}
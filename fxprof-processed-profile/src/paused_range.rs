@@ -0,0 +1,36 @@
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::Timestamp;
+
+/// The reason why no samples were collected during a paused range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PausedRangeReason {
+    /// The user paused the profiler.
+    ProfilerPaused,
+    /// The profiler was unable to collect data, for example because its
+    /// collector was suspended, or because events were lost or throttled.
+    /// Gaps which only affect some threads should be added with
+    /// [`Profile::add_thread_paused_range`](crate::Profile::add_thread_paused_range).
+    CollectorPaused,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PausedRange {
+    pub start_time: Timestamp,
+    pub end_time: Option<Timestamp>,
+    pub reason: PausedRangeReason,
+}
+
+impl Serialize for PausedRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let reason = match self.reason {
+            PausedRangeReason::ProfilerPaused => "profiler-paused",
+            PausedRangeReason::CollectorPaused => "collector-paused",
+        };
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("startTime", &self.start_time)?;
+        map.serialize_entry("endTime", &self.end_time)?;
+        map.serialize_entry("reason", reason)?;
+        map.end()
+    }
+}
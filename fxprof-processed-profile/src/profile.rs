@@ -22,10 +22,12 @@ use crate::global_lib_table::{GlobalLibIndex, GlobalLibTable, LibraryHandle};
 use crate::lib_mappings::LibMappings;
 use crate::library_info::{LibraryInfo, SymbolTable};
 use crate::markers::{
-    GraphColor, InternalMarkerSchema, Marker, MarkerHandle, MarkerTiming, MarkerTypeHandle,
-    RuntimeSchemaMarkerSchema, StaticSchemaMarker,
+    GraphColor, InternalMarkerSchema, Marker, MarkerFieldFormat, MarkerHandle, MarkerTiming,
+    MarkerTypeHandle, RuntimeSchemaMarkerSchema, StaticSchemaMarker,
 };
+use crate::meta_info::{ExtraInfoEntry, ExtraInfoSection, ProfileConfiguration};
 use crate::native_symbols::NativeSymbolHandle;
+use crate::paused_range::{PausedRange, PausedRangeReason};
 use crate::process::{Process, ThreadHandle};
 use crate::profile_symbol_info::{LibSymbolInfo, ProfileSymbolInfo};
 use crate::reference_timestamp::ReferenceTimestamp;
@@ -169,6 +171,13 @@ pub enum TimelineUnit {
 pub struct Profile {
     pub(crate) product: String,
     pub(crate) os_name: Option<String>,
+    pub(crate) cpu_name: Option<String>,
+    pub(crate) physical_cpus: Option<u32>,
+    pub(crate) logical_cpus: Option<u32>,
+    pub(crate) main_memory: Option<u64>,
+    pub(crate) arguments: Option<String>,
+    pub(crate) configuration: Option<ProfileConfiguration>,
+    pub(crate) extra_info: Vec<ExtraInfoSection>,
     pub(crate) interval: SamplingInterval,
     pub(crate) timeline_unit: TimelineUnit,
     pub(crate) global_libs: GlobalLibTable,
@@ -185,6 +194,7 @@ pub struct Profile {
     pub(crate) marker_schemas: Vec<InternalMarkerSchema>,
     static_schema_marker_types: FastHashMap<&'static str, MarkerTypeHandle>,
     pub(crate) symbolicated: bool,
+    paused_ranges: Vec<PausedRange>,
     used_pids: FastHashMap<u32, u32>,
     used_tids: FastHashMap<u32, u32>,
 }
@@ -207,6 +217,13 @@ impl Profile {
             interval,
             product: product.to_string(),
             os_name: None,
+            cpu_name: None,
+            physical_cpus: None,
+            logical_cpus: None,
+            main_memory: None,
+            arguments: None,
+            configuration: None,
+            extra_info: Vec::new(),
             timeline_unit: TimelineUnit::Milliseconds,
             threads: Vec::new(),
             initial_visible_threads: Vec::new(),
@@ -221,6 +238,7 @@ impl Profile {
             categories,
             static_schema_marker_types: FastHashMap::default(),
             symbolicated: false,
+            paused_ranges: Vec::new(),
             used_pids: FastHashMap::default(),
             used_tids: FastHashMap::default(),
             counters: Vec::new(),
@@ -255,6 +273,75 @@ impl Profile {
         self.os_name = Some(os_name.to_string());
     }
 
+    /// Set the name of the CPU model, for example "Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz".
+    pub fn set_cpu_name(&mut self, cpu_name: &str) {
+        self.cpu_name = Some(cpu_name.to_string());
+    }
+
+    /// Set the number of physical CPU cores of the machine.
+    pub fn set_physical_cpu_count(&mut self, count: u32) {
+        self.physical_cpus = Some(count);
+    }
+
+    /// Set the number of logical CPUs of the machine, i.e. the number of
+    /// hardware threads.
+    pub fn set_logical_cpu_count(&mut self, count: u32) {
+        self.logical_cpus = Some(count);
+    }
+
+    /// Set the amount of main memory of the machine, in bytes.
+    pub fn set_main_memory(&mut self, bytes: u64) {
+        self.main_memory = Some(bytes);
+    }
+
+    /// Set the command line which was used for the recording.
+    pub fn set_arguments(&mut self, arguments: &str) {
+        self.arguments = Some(arguments.to_string());
+    }
+
+    /// Set the profiler configuration which was used for the recording.
+    pub fn set_configuration(&mut self, configuration: ProfileConfiguration) {
+        self.configuration = Some(configuration);
+    }
+
+    /// Add a string entry to a free-form section of the profile's metadata.
+    ///
+    /// The section is created when it gets its first entry. Sections and entries
+    /// are displayed in the order in which they were added.
+    pub fn add_extra_info_string(&mut self, section: &str, label: &str, value: &str) {
+        self.extra_info_section(section)
+            .push(ExtraInfoEntry::string(label, value));
+    }
+
+    /// Add a number entry to a free-form section of the profile's metadata.
+    ///
+    /// The value is displayed with `format`, which needs to be a format of
+    /// [kind](MarkerFieldFormat::kind) [`MarkerFieldFormatKind::Number`](crate::MarkerFieldFormatKind::Number), and panics otherwise.
+    pub fn add_extra_info_number(
+        &mut self,
+        section: &str,
+        label: &str,
+        value: f64,
+        format: MarkerFieldFormat,
+    ) {
+        self.extra_info_section(section)
+            .push(ExtraInfoEntry::number(label, value, format));
+    }
+
+    fn extra_info_section(&mut self, label: &str) -> &mut Vec<ExtraInfoEntry> {
+        let index = match self.extra_info.iter().position(|s| s.label == label) {
+            Some(index) => index,
+            None => {
+                self.extra_info.push(ExtraInfoSection {
+                    label: label.to_owned(),
+                    entries: Vec::new(),
+                });
+                self.extra_info.len() - 1
+            }
+        };
+        &mut self.extra_info[index].entries
+    }
+
     /// Set the unit that the timeline should display. Default is [`TimelineUnit::Milliseconds`].
     ///
     /// If this is set to [`TimelineUnit::Bytes`], then the sample [`Timestamp`]s are interpreted
@@ -498,6 +585,40 @@ impl Profile {
         self.threads[thread.0].set_name(name);
     }
 
+    /// Add a time range during which no samples were collected for any thread,
+    /// for example because the profiler was paused or because events were lost.
+    ///
+    /// An `end_time` of `None` means that the profile ended while paused.
+    pub fn add_paused_range(
+        &mut self,
+        start_time: Timestamp,
+        end_time: Option<Timestamp>,
+        reason: PausedRangeReason,
+    ) {
+        self.paused_ranges.push(PausedRange {
+            start_time,
+            end_time,
+            reason,
+        });
+    }
+
+    /// Add a time range during which no samples were collected for this thread.
+    ///
+    /// An `end_time` of `None` means that the thread ended while paused.
+    pub fn add_thread_paused_range(
+        &mut self,
+        thread: ThreadHandle,
+        start_time: Timestamp,
+        end_time: Option<Timestamp>,
+        reason: PausedRangeReason,
+    ) {
+        self.threads[thread.0].add_paused_range(PausedRange {
+            start_time,
+            end_time,
+            reason,
+        });
+    }
+
     /// Change the start time of a thread.
     pub fn set_thread_start_time(&mut self, thread: ThreadHandle, start_time: Timestamp) {
         self.threads[thread.0].set_start_time(start_time);
@@ -914,6 +1035,62 @@ impl Profile {
         self.threads[thread.0].add_sample(timestamp, stack_index, cpu_delta, weight);
     }
 
+    /// Add a sample with a fractional weight to the given thread.
+    ///
+    /// This is like [`Profile::add_sample`], but the weight can have a fractional
+    /// part. This is mostly useful for threads whose weight type is
+    /// [`WeightType::TracingMs`], where the weight is a duration in milliseconds.
+    /// Once a thread has a sample with a fractional weight, all of its sample
+    /// weights are stored and serialized as floating-point numbers.
+    pub fn add_sample_with_fractional_weight(
+        &mut self,
+        thread: ThreadHandle,
+        timestamp: Timestamp,
+        stack: Option<StackHandle>,
+        cpu_delta: CpuDelta,
+        weight: f64,
+    ) {
+        let stack_index = match stack {
+            Some(StackHandle(stack_thread_handle, stack_index)) => {
+                assert_eq!(
+                    stack_thread_handle, thread,
+                    "StackHandle from different thread passed to Profile::add_sample_with_fractional_weight"
+                );
+                Some(stack_index)
+            }
+            None => None,
+        };
+        self.threads[thread.0].add_sample_with_fractional_weight(
+            timestamp,
+            stack_index,
+            cpu_delta,
+            weight,
+        );
+    }
+
+    /// Add a sample for a span of time which was spent in `stack`, for example
+    /// a function call which was recorded by a tracing profiler.
+    ///
+    /// The sample is placed at `start`, and its weight is the duration from `start`
+    /// to `end` in (fractional) milliseconds. This also sets the thread's weight type
+    /// to [`WeightType::TracingMs`], so that the call tree and the flame graph
+    /// show the durations rather than sample counts.
+    ///
+    /// The spans of a thread should not overlap. To trace nested calls, add a
+    /// sample for the time spent in each callee's stack, and samples for the
+    /// caller's stack which cover only the time outside of the callees.
+    pub fn add_tracing_sample(
+        &mut self,
+        thread: ThreadHandle,
+        start: Timestamp,
+        end: Timestamp,
+        stack: Option<StackHandle>,
+    ) {
+        self.set_thread_samples_weight_type(thread, WeightType::TracingMs);
+        let duration = end.millis_since(start);
+        self.add_sample_with_fractional_weight(thread, start, stack, CpuDelta::ZERO, duration);
+    }
+
     /// Add a sample with a CPU delta of zero. Internally, multiple consecutive
     /// samples with a delta of zero will be combined into one sample with an accumulated
     /// weight.
@@ -1241,6 +1418,13 @@ impl Profile {
         let Profile {
             product,
             os_name,
+            cpu_name,
+            physical_cpus,
+            logical_cpus,
+            main_memory,
+            arguments,
+            configuration,
+            extra_info,
             interval,
             timeline_unit,
             global_libs,
@@ -1257,6 +1441,7 @@ impl Profile {
             marker_schemas,
             static_schema_marker_types,
             symbolicated,
+            paused_ranges,
             used_pids,
             used_tids,
         } = self;
@@ -1272,6 +1457,13 @@ impl Profile {
         Profile {
             product,
             os_name,
+            cpu_name,
+            physical_cpus,
+            logical_cpus,
+            main_memory,
+            arguments,
+            configuration,
+            extra_info,
             interval,
             timeline_unit,
             global_libs,
@@ -1288,6 +1480,7 @@ impl Profile {
             marker_schemas,
             static_schema_marker_types,
             symbolicated,
+            paused_ranges,
             used_pids,
             used_tids,
         }
@@ -1480,6 +1673,27 @@ impl Serialize for SerializableProfileMeta<'_> {
         if let Some(os_name) = &self.0.os_name {
             map.serialize_entry("oscpu", os_name)?;
         }
+        if let Some(cpu_name) = &self.0.cpu_name {
+            map.serialize_entry("CPUName", cpu_name)?;
+        }
+        if let Some(physical_cpus) = self.0.physical_cpus {
+            map.serialize_entry("physicalCPUs", &physical_cpus)?;
+        }
+        if let Some(logical_cpus) = self.0.logical_cpus {
+            map.serialize_entry("logicalCPUs", &logical_cpus)?;
+        }
+        if let Some(main_memory) = self.0.main_memory {
+            map.serialize_entry("mainMemory", &main_memory)?;
+        }
+        if let Some(arguments) = &self.0.arguments {
+            map.serialize_entry("arguments", arguments)?;
+        }
+        if let Some(configuration) = &self.0.configuration {
+            map.serialize_entry("configuration", configuration)?;
+        }
+        if !self.0.extra_info.is_empty() {
+            map.serialize_entry("extra", &self.0.extra_info)?;
+        }
         let time_unit = match self.0.timeline_unit {
             TimelineUnit::Milliseconds => "ms",
             TimelineUnit::Bytes => "bytes",
@@ -1506,7 +1720,7 @@ impl Serialize for SerializableProfileMeta<'_> {
             None => {}
         }
         map.serialize_entry("symbolicated", &self.0.symbolicated)?;
-        map.serialize_entry("pausedRanges", &[] as &[()])?;
+        map.serialize_entry("pausedRanges", &self.0.paused_ranges)?;
         map.serialize_entry("version", &24)?; // this version is ignored, only "preprocessedProfileVersion" is used
         map.serialize_entry("usesOnlyOneStackType", &(!self.0.contains_js_frame()))?;
         map.serialize_entry("sourceCodeIsNotOnSearchfox", &true)?;
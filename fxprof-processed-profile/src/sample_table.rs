@@ -17,7 +17,7 @@ use crate::timestamp::{
 #[derive(Debug, Clone)]
 pub struct SampleTable {
     sample_weight_type: WeightType,
-    sample_weights: Vec<i32>,
+    sample_weights: SampleWeights,
     sample_timestamps: Vec<Timestamp>,
     /// An index into the thread's stack table for each sample. `None` means the empty stack.
     sample_stack_indexes: Vec<Option<usize>>,
@@ -40,9 +40,9 @@ pub enum WeightType {
     Samples,
     /// The weight is a duration in (fractional) milliseconds.
     ///
-    /// Note that, since [`Profile::add_sample`](crate::Profile::add_sample) currently
-    /// only accepts integer weight values, the usefulness of `TracingMs` is
-    /// currently limited.
+    /// Use [`Profile::add_tracing_sample`](crate::Profile::add_tracing_sample) or
+    /// [`Profile::add_sample_with_fractional_weight`](crate::Profile::add_sample_with_fractional_weight)
+    /// to add samples with sub-millisecond durations.
     TracingMs,
     /// The weight of each sample is a value in bytes.
     ///
@@ -71,11 +71,86 @@ impl Serialize for WeightType {
     }
 }
 
+/// The weight column of the sample table.
+///
+/// The weights are stored as integers until the first fractional weight is
+/// added, so that the weights of profiles which only use integer weights are
+/// serialized as integers.
+#[derive(Debug, Clone)]
+enum SampleWeights {
+    Integer(Vec<i32>),
+    Float(Vec<f64>),
+}
+
+impl SampleWeights {
+    fn push(&mut self, weight: i32) {
+        match self {
+            SampleWeights::Integer(weights) => weights.push(weight),
+            SampleWeights::Float(weights) => weights.push(weight.into()),
+        }
+    }
+
+    fn push_float(&mut self, weight: f64) {
+        self.as_float().push(weight);
+    }
+
+    fn add_to_last(&mut self, weight: i32) {
+        match self {
+            SampleWeights::Integer(weights) => *weights.last_mut().unwrap() += weight,
+            SampleWeights::Float(weights) => *weights.last_mut().unwrap() += f64::from(weight),
+        }
+    }
+
+    /// Converts the column to floats, if it isn't yet.
+    fn as_float(&mut self) -> &mut Vec<f64> {
+        if let SampleWeights::Integer(weights) = self {
+            *self = SampleWeights::Float(weights.iter().map(|&w| w.into()).collect());
+        }
+        match self {
+            SampleWeights::Float(weights) => weights,
+            SampleWeights::Integer(_) => unreachable!(),
+        }
+    }
+
+    fn serialize_with_permutation<S: Serializer>(
+        &self,
+        indexes: &[usize],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match self {
+            SampleWeights::Integer(weights) => {
+                SliceWithPermutation(weights, indexes).serialize(serializer)
+            }
+            SampleWeights::Float(weights) => {
+                SliceWithPermutation(weights, indexes).serialize(serializer)
+            }
+        }
+    }
+}
+
+impl Serialize for SampleWeights {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            SampleWeights::Integer(weights) => weights.serialize(serializer),
+            SampleWeights::Float(weights) => weights.serialize(serializer),
+        }
+    }
+}
+
+/// The weight column in the order of `indexes`.
+struct SampleWeightsWithPermutation<'a>(&'a SampleWeights, &'a [usize]);
+
+impl Serialize for SampleWeightsWithPermutation<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize_with_permutation(self.1, serializer)
+    }
+}
+
 impl SampleTable {
     pub fn new() -> Self {
         Self {
             sample_weight_type: WeightType::Samples,
-            sample_weights: Vec::new(),
+            sample_weights: SampleWeights::Integer(Vec::new()),
             sample_timestamps: Vec::new(),
             sample_stack_indexes: Vec::new(),
             sample_cpu_deltas: Vec::new(),
@@ -92,6 +167,28 @@ impl SampleTable {
         weight: i32,
     ) {
         self.sample_weights.push(weight);
+        self.push_sample(timestamp, stack_index, cpu_delta);
+    }
+
+    /// Adds a sample with a fractional weight. From then on, all weights of
+    /// this table are serialized as floating-point numbers.
+    pub fn add_sample_with_fractional_weight(
+        &mut self,
+        timestamp: Timestamp,
+        stack_index: Option<usize>,
+        cpu_delta: CpuDelta,
+        weight: f64,
+    ) {
+        self.sample_weights.push_float(weight);
+        self.push_sample(timestamp, stack_index, cpu_delta);
+    }
+
+    fn push_sample(
+        &mut self,
+        timestamp: Timestamp,
+        stack_index: Option<usize>,
+        cpu_delta: CpuDelta,
+    ) {
         self.sample_timestamps.push(timestamp);
         self.sample_stack_indexes.push(stack_index);
         self.sample_cpu_deltas.push(cpu_delta);
@@ -106,7 +203,7 @@ impl SampleTable {
     }
 
     pub fn modify_last_sample(&mut self, timestamp: Timestamp, weight: i32) {
-        *self.sample_weights.last_mut().unwrap() += weight;
+        self.sample_weights.add_to_last(weight);
         *self.sample_timestamps.last_mut().unwrap() = timestamp;
     }
 
@@ -151,7 +248,7 @@ impl Serialize for SampleTable {
             )?;
             map.serialize_entry(
                 "weight",
-                &SliceWithPermutation(&self.sample_weights, &indexes),
+                &SampleWeightsWithPermutation(&self.sample_weights, &indexes),
             )?;
             map.serialize_entry(
                 "threadCPUDelta",
@@ -350,4 +447,34 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_serialize_fractional_weights() {
+        let mut samples = SampleTable::new();
+        let ts = Timestamp::from_millis_since_reference;
+        samples.add_sample(ts(2.0), Some(0), CpuDelta::ZERO, 2);
+        let json = serde_json::to_value(&samples).unwrap();
+        assert_json_eq!(json["weight"], json!([2]));
+        assert!(json["weight"][0].is_i64());
+
+        // The first fractional weight converts the existing weights, and the
+        // weights stay in sync with the timestamps when the samples are sorted.
+        samples.set_weight_type(WeightType::TracingMs);
+        samples.add_sample_with_fractional_weight(ts(1.0), Some(1), CpuDelta::ZERO, 0.25);
+        samples.add_sample(ts(3.0), Some(0), CpuDelta::ZERO, 1);
+        samples.modify_last_sample(ts(3.5), 1);
+        let json = serde_json::to_value(&samples).unwrap();
+        assert_json_eq!(
+            json,
+            json!({
+                "stack": [1, 0, 0],
+                "timeDeltas": [1.0, 1.0, 1.5],
+                "weight": [0.25, 2.0, 2.0],
+                "weightType": "tracing-ms",
+                "threadCPUDelta": [0, 0, 0],
+                "length": 3
+            })
+        );
+        assert!(json["weight"][1].is_f64());
+    }
 }
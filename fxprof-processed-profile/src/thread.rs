@@ -11,6 +11,7 @@ use crate::global_lib_table::{GlobalLibIndex, UsedLibraryAddressesCollector};
 use crate::marker_table::MarkerTable;
 use crate::markers::InternalMarkerSchema;
 use crate::native_symbols::{NativeSymbolIndex, NativeSymbols};
+use crate::paused_range::PausedRange;
 use crate::profile_symbol_info::LibSymbolInfo;
 use crate::sample_table::{NativeAllocationsTable, SampleTable, WeightType};
 use crate::stack_table::StackTable;
@@ -39,6 +40,7 @@ pub struct Thread {
     last_sample_stack: Option<usize>,
     last_sample_was_zero_cpu: bool,
     show_markers_in_timeline: bool,
+    paused_ranges: Vec<PausedRange>,
 }
 
 impl Thread {
@@ -59,6 +61,7 @@ impl Thread {
             last_sample_stack: None,
             last_sample_was_zero_cpu: false,
             show_markers_in_timeline: false,
+            paused_ranges: Vec::new(),
         }
     }
 
@@ -74,6 +77,10 @@ impl Thread {
         self.end_time = Some(end_time);
     }
 
+    pub fn add_paused_range(&mut self, paused_range: PausedRange) {
+        self.paused_ranges.push(paused_range);
+    }
+
     pub fn set_tid(&mut self, tid: String) {
         self.tid = tid;
     }
@@ -133,6 +140,19 @@ impl Thread {
         self.last_sample_was_zero_cpu = cpu_delta == CpuDelta::ZERO;
     }
 
+    pub fn add_sample_with_fractional_weight(
+        &mut self,
+        timestamp: Timestamp,
+        stack_index: Option<usize>,
+        cpu_delta: CpuDelta,
+        weight: f64,
+    ) {
+        self.samples
+            .add_sample_with_fractional_weight(timestamp, stack_index, cpu_delta, weight);
+        self.last_sample_stack = stack_index;
+        self.last_sample_was_zero_cpu = cpu_delta == CpuDelta::ZERO;
+    }
+
     pub fn add_allocation_sample(
         &mut self,
         timestamp: Timestamp,
@@ -233,6 +253,7 @@ impl Thread {
             last_sample_stack,
             last_sample_was_zero_cpu,
             show_markers_in_timeline,
+            paused_ranges,
         } = self;
 
         let (frame_interner, native_symbols, stack_table, old_stack_to_new_stack) =
@@ -269,6 +290,7 @@ impl Thread {
             last_sample_stack,
             last_sample_was_zero_cpu,
             show_markers_in_timeline,
+            paused_ranges,
         }
     }
 
@@ -304,7 +326,7 @@ impl Thread {
         map.serialize_entry("name", &thread_name)?;
         map.serialize_entry("isMainThread", &self.is_main)?;
         map.serialize_entry("nativeSymbols", &self.native_symbols)?;
-        map.serialize_entry("pausedRanges", &[] as &[()])?;
+        map.serialize_entry("pausedRanges", &self.paused_ranges)?;
         map.serialize_entry("pid", &pid)?;
         map.serialize_entry("processName", process_name)?;
         map.serialize_entry("processShutdownTime", &process_end_time)?;
@@ -18,6 +18,12 @@ impl Timestamp {
             nanos: (millis * 1_000_000.0) as u64,
         }
     }
+
+    /// The time from `earlier` to `self` in milliseconds, or zero if `earlier`
+    /// is later than `self`.
+    pub fn millis_since(&self, earlier: Timestamp) -> f64 {
+        (self.nanos.saturating_sub(earlier.nanos) as f64) / 1_000_000.0
+    }
 }
 
 impl Serialize for Timestamp {
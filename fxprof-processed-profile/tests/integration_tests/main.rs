@@ -5,9 +5,10 @@ use assert_json_diff::assert_json_eq;
 use debugid::DebugId;
 use fxprof_processed_profile::{
     Category, CategoryColor, CpuDelta, FrameAddress, FrameFlags, GraphColor, LibraryInfo,
-    MarkerFieldFlags, MarkerFieldFormat, MarkerGraphType, MarkerLocations, MarkerTiming, Profile,
-    ReferenceTimestamp, SamplingInterval, StaticSchemaMarker, StaticSchemaMarkerField,
-    StaticSchemaMarkerGraph, StringHandle, Symbol, SymbolTable, Timestamp, WeightType,
+    MarkerFieldFlags, MarkerFieldFormat, MarkerGraphType, MarkerLocations, MarkerTiming,
+    PausedRangeReason, Profile, ProfileConfiguration, ReferenceTimestamp, SamplingInterval,
+    StaticSchemaMarker, StaticSchemaMarkerField, StaticSchemaMarkerGraph, StringHandle, Symbol,
+    SymbolTable, Timestamp, WeightType,
 };
 use serde_json::json;
 
@@ -1792,3 +1793,165 @@ fn test_flow_marker_fields() {
         )
     );
 }
+
+#[test]
+fn test_paused_ranges() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        12345,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+
+    profile.add_paused_range(
+        Timestamp::from_millis_since_reference(5.0),
+        Some(Timestamp::from_millis_since_reference(7.5)),
+        PausedRangeReason::ProfilerPaused,
+    );
+    profile.add_paused_range(
+        Timestamp::from_millis_since_reference(20.0),
+        None,
+        PausedRangeReason::ProfilerPaused,
+    );
+    profile.add_thread_paused_range(
+        thread,
+        Timestamp::from_millis_since_reference(10.0),
+        Some(Timestamp::from_millis_since_reference(12.0)),
+        PausedRangeReason::CollectorPaused,
+    );
+
+    let profile_json = serde_json::to_value(&profile).unwrap();
+    assert_json_eq!(
+        profile_json["meta"]["pausedRanges"],
+        json!([
+            {
+                "startTime": 5.0,
+                "endTime": 7.5,
+                "reason": "profiler-paused"
+            },
+            {
+                "startTime": 20.0,
+                "endTime": null,
+                "reason": "profiler-paused"
+            }
+        ])
+    );
+    assert_json_eq!(
+        profile_json["threads"][0]["pausedRanges"],
+        json!([
+            {
+                "startTime": 10.0,
+                "endTime": 12.0,
+                "reason": "collector-paused"
+            }
+        ])
+    );
+}
+
+#[test]
+fn test_tracing_samples() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        12345,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+
+    let main_string = profile.handle_for_string("main");
+    let work_string = profile.handle_for_string("work");
+    let main_frame = profile.handle_for_frame_with_label(
+        thread,
+        main_string,
+        Category::OTHER,
+        FrameFlags::empty(),
+    );
+    let work_frame = profile.handle_for_frame_with_label(
+        thread,
+        work_string,
+        Category::OTHER,
+        FrameFlags::empty(),
+    );
+    let main_stack = profile.handle_for_stack(thread, main_frame, None);
+    let work_stack = profile.handle_for_stack(thread, work_frame, Some(main_stack));
+
+    let ts = Timestamp::from_millis_since_reference;
+    profile.add_tracing_sample(thread, ts(1.0), ts(1.25), Some(main_stack));
+    profile.add_tracing_sample(thread, ts(1.25), ts(2.75), Some(work_stack));
+    profile.add_tracing_sample(thread, ts(2.75), ts(3.0), Some(main_stack));
+
+    let profile_json = serde_json::to_value(&profile).unwrap();
+    let samples = &profile_json["threads"][0]["samples"];
+    assert_json_eq!(samples["weightType"], json!("tracing-ms"));
+    assert_json_eq!(samples["stack"], json!([0, 1, 0]));
+    assert_json_eq!(samples["timeDeltas"], json!([1.0, 0.25, 1.5]));
+    assert_json_eq!(samples["weight"], json!([0.25, 1.5, 0.25]));
+}
+
+#[test]
+fn test_profile_meta_info() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    profile.set_cpu_name("Example CPU @ 3.00GHz");
+    profile.set_physical_cpu_count(8);
+    profile.set_logical_cpu_count(16);
+    profile.set_main_memory(34359738368);
+    profile.set_arguments("samply record ./app --flag");
+    profile.set_configuration(ProfileConfiguration {
+        features: vec!["cycles".to_string(), "sched:sched_switch".to_string()],
+        threads: vec![],
+        capacity: None,
+    });
+    profile.add_extra_info_string("Host", "Hostname", "runner-1");
+    profile.add_extra_info_number("Recording", "Lost events", 3.0, MarkerFieldFormat::Integer);
+    profile.add_extra_info_string("Host", "Kernel", "6.1.0");
+
+    let profile_json = serde_json::to_value(&profile).unwrap();
+    let meta = &profile_json["meta"];
+    assert_json_eq!(meta["CPUName"], json!("Example CPU @ 3.00GHz"));
+    assert_json_eq!(meta["physicalCPUs"], json!(8));
+    assert_json_eq!(meta["logicalCPUs"], json!(16));
+    assert_json_eq!(meta["mainMemory"], json!(34359738368u64));
+    assert_json_eq!(meta["arguments"], json!("samply record ./app --flag"));
+    assert_json_eq!(
+        meta["configuration"],
+        json!({
+            "features": ["cycles", "sched:sched_switch"],
+            "threads": []
+        })
+    );
+    assert_json_eq!(
+        meta["extra"],
+        json!([
+            {
+                "label": "Host",
+                "entries": [
+                    { "label": "Hostname", "format": "string", "value": "runner-1" },
+                    { "label": "Kernel", "format": "string", "value": "6.1.0" }
+                ]
+            },
+            {
+                "label": "Recording",
+                "entries": [
+                    { "label": "Lost events", "format": "integer", "value": 3.0 }
+                ]
+            }
+        ])
+    );
+}
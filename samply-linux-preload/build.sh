@@ -0,0 +1,7 @@
+# Building for the other architecture needs a cross linker, for example:
+# CARGO_TARGET_AARCH64_UNKNOWN_LINUX_GNU_LINKER=aarch64-linux-gnu-gcc ./build.sh
+set -e
+for arch in x86_64 aarch64; do
+  cargo build --release --target=$arch-unknown-linux-gnu
+  gzip -cvf target/$arch-unknown-linux-gnu/release/libsamply_linux_preload.so > ../samply/resources/libsamply_linux_preload_$arch.so.gz
+done
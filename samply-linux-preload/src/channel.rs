@@ -0,0 +1,127 @@
+//! Sends the sampled allocation events to samply.
+//!
+//! samply passes us one end of a `SOCK_SEQPACKET` socket pair, in the fd given by
+//! the `SAMPLY_ALLOCATIONS_FD` environment variable. Every event is sent as a
+//! single packet: an [`EventHeader`], followed by `stack_len` bytes of the
+//! stack, starting at the stack pointer of the hooked function. samply unwinds
+//! the stack from these bytes, in the same way as for perf samples.
+//!
+//! The layout of the packets needs to match `samply/src/linux/allocations.rs`.
+
+use core::mem;
+use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+use libc::{c_int, c_void, iovec, msghdr};
+
+static FD: AtomicI32 = AtomicI32::new(-1);
+static STACK_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn init(fd: c_int, stack_size: usize) {
+    FD.store(fd, Ordering::Relaxed);
+    STACK_SIZE.store(stack_size & !7, Ordering::Relaxed);
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy)]
+pub enum EventKind {
+    Allocation = 1,
+    Deallocation = 2,
+}
+
+/// The registers in a hooked function, see `current_regs!`.
+#[derive(Clone, Copy)]
+pub struct Regs {
+    #[cfg(target_arch = "x86_64")]
+    regs: [u64; 3],
+    #[cfg(target_arch = "aarch64")]
+    regs: [u64; 4],
+    sp: u64,
+}
+
+impl Regs {
+    #[cfg(target_arch = "x86_64")]
+    pub fn new(regs: [u64; 3], sp: u64) -> Self {
+        Regs { regs, sp }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn new(regs: [u64; 4], sp: u64) -> Self {
+        Regs { regs, sp }
+    }
+}
+
+#[repr(C)]
+struct EventHeader {
+    kind: u32,
+    pid: u32,
+    tid: u32,
+    reg_count: u32,
+    /// CLOCK_MONOTONIC, in nanoseconds, like the timestamps of perf events.
+    timestamp: u64,
+    address: u64,
+    /// The number of bytes that this allocation sample stands for. Zero for
+    /// deallocations.
+    weight: u64,
+    regs: [u64; 4],
+    stack_len: u64,
+}
+
+pub fn send_event(kind: EventKind, address: u64, weight: u64, regs: Regs) {
+    let fd = FD.load(Ordering::Relaxed);
+    let mut header = EventHeader {
+        kind: kind as u32,
+        pid: unsafe { libc::getpid() } as u32,
+        tid: unsafe { libc::syscall(libc::SYS_gettid) } as u32,
+        reg_count: regs.regs.len() as u32,
+        timestamp: clock_monotonic_nanos(),
+        address,
+        weight,
+        regs: [0; 4],
+        stack_len: 0,
+    };
+    header.regs[..regs.regs.len()].copy_from_slice(&regs.regs);
+
+    // We send the stack bytes straight from the stack. We don't know where the
+    // stack ends, so if the kernel can't read all of the requested bytes, we try
+    // again with fewer bytes.
+    let mut stack_len = STACK_SIZE.load(Ordering::Relaxed);
+    loop {
+        header.stack_len = stack_len as u64;
+        let mut iov = [
+            iovec {
+                iov_base: &mut header as *mut EventHeader as *mut c_void,
+                iov_len: mem::size_of::<EventHeader>(),
+            },
+            iovec {
+                iov_base: regs.sp as *mut c_void,
+                iov_len: stack_len,
+            },
+        ];
+        let mut msg: msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = iov.as_mut_ptr();
+        msg.msg_iovlen = iov.len() as _;
+        let result = unsafe { libc::sendmsg(fd, &msg, libc::MSG_NOSIGNAL) };
+        if result >= 0 {
+            return;
+        }
+        match errno() {
+            libc::EINTR => {}
+            libc::EFAULT if stack_len > 0 => stack_len = (stack_len / 2) & !7,
+            _ => {
+                // samply has gone away, or the fd has been closed by the process.
+                crate::disable();
+                return;
+            }
+        }
+    }
+}
+
+fn errno() -> c_int {
+    unsafe { *libc::__errno_location() }
+}
+
+fn clock_monotonic_nanos() -> u64 {
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
@@ -0,0 +1,461 @@
+#![no_std]
+
+use core::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+
+use libc::{c_char, c_int, c_void, off_t, size_t};
+
+/// Gets the current register values, in the order in which perf stores sampled
+/// user registers, for the register mask which samply uses. samply unwinds the
+/// stack from the hooked function, and then leaves out the frame of the hooked
+/// function itself.
+macro_rules! current_regs {
+    () => {{
+        #[cfg(target_arch = "x86_64")]
+        let regs = {
+            let (ip, sp, bp): (u64, u64, u64);
+            unsafe {
+                core::arch::asm!(
+                    "lea {ip}, [rip]",
+                    "mov {sp}, rsp",
+                    "mov {bp}, rbp",
+                    ip = out(reg) ip,
+                    sp = out(reg) sp,
+                    bp = out(reg) bp,
+                    options(nomem, nostack, preserves_flags),
+                )
+            };
+            crate::channel::Regs::new([bp, sp, ip], sp)
+        };
+        #[cfg(target_arch = "aarch64")]
+        let regs = {
+            let (pc, sp, fp, lr): (u64, u64, u64, u64);
+            unsafe {
+                core::arch::asm!(
+                    "adr {pc}, .",
+                    "mov {sp}, sp",
+                    "mov {fp}, x29",
+                    "mov {lr}, x30",
+                    pc = out(reg) pc,
+                    sp = out(reg) sp,
+                    fp = out(reg) fp,
+                    lr = out(reg) lr,
+                    options(nomem, nostack, preserves_flags),
+                )
+            };
+            crate::channel::Regs::new([fp, lr, sp, pc], sp)
+        };
+        regs
+    }};
+}
+
+mod channel;
+mod sampled_addresses;
+
+use channel::{EventKind, Regs};
+
+extern "C" {
+    fn __libc_malloc(size: size_t) -> *mut c_void;
+    fn __libc_calloc(count: size_t, size: size_t) -> *mut c_void;
+    fn __libc_realloc(ptr: *mut c_void, size: size_t) -> *mut c_void;
+    fn __libc_memalign(alignment: size_t, size: size_t) -> *mut c_void;
+    fn __libc_free(ptr: *mut c_void);
+}
+
+/// Set once the connection to samply has been set up. Until then, and after
+/// samply has gone away, all calls are just forwarded.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// One allocation is sampled every SAMPLING_INTERVAL allocated bytes.
+static SAMPLING_INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+/// The number of bytes which can still be allocated until the next allocation
+/// is sampled. This is shared by all threads.
+static BYTES_UNTIL_NEXT_SAMPLE: AtomicI64 = AtomicI64::new(0);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_panic: &core::panic::PanicInfo<'_>) -> ! {
+    unsafe { libc::abort() }
+}
+
+// Run our setup code when the library is loaded, before main().
+#[used]
+#[allow(non_upper_case_globals)]
+#[link_section = ".init_array"]
+static __SETUP_SAMPLY_CONNECTION: unsafe extern "C" fn() = {
+    unsafe extern "C" fn __load_samply_lib() {
+        let _ = set_up_samply_connection();
+    }
+    __load_samply_lib
+};
+
+fn set_up_samply_connection() -> Option<()> {
+    // Safety: These are nul-terminated c strings, and we're running before main(),
+    // so nobody else is calling setenv on other threads.
+    let (fd, interval, stack_size) = unsafe {
+        (
+            getenv_u64(b"SAMPLY_ALLOCATIONS_FD\0")?,
+            getenv_u64(b"SAMPLY_ALLOCATIONS_INTERVAL\0")?,
+            getenv_u64(b"SAMPLY_ALLOCATIONS_STACK_SIZE\0")?,
+        )
+    };
+    enable(fd as c_int, interval, stack_size as usize)
+}
+
+/// Starts sampling one allocation every `interval` bytes, and sending the
+/// events to `fd`.
+fn enable(fd: c_int, interval: u64, stack_size: usize) -> Option<()> {
+    if interval == 0 {
+        return None;
+    }
+    sampled_addresses::init()?;
+    channel::init(fd, stack_size);
+    SAMPLING_INTERVAL.store(interval, Ordering::Relaxed);
+    BYTES_UNTIL_NEXT_SAMPLE.store(interval as i64, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Release);
+    Some(())
+}
+
+unsafe fn getenv_u64(name: &[u8]) -> Option<u64> {
+    let value = libc::getenv(name.as_ptr() as *const c_char);
+    if value.is_null() {
+        return None;
+    }
+    let mut result: u64 = 0;
+    for &digit in core::ffi::CStr::from_ptr(value).to_bytes() {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        result = result
+            .checked_mul(10)?
+            .checked_add(u64::from(digit - b'0'))?;
+    }
+    Some(result)
+}
+
+pub(crate) fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Decides whether an allocation of `size` bytes should be sampled. If so, returns
+/// the number of allocated bytes that this sample stands for, which is a multiple
+/// of the sampling interval.
+fn sample_weight(size: usize) -> Option<u64> {
+    let interval = SAMPLING_INTERVAL.load(Ordering::Relaxed);
+    let size = size.min(i64::MAX as usize / 2) as i64;
+    let mut remaining = BYTES_UNTIL_NEXT_SAMPLE.load(Ordering::Relaxed);
+    loop {
+        let after = remaining.saturating_sub(size);
+        let (new_remaining, weight) = if after > 0 {
+            (after, None)
+        } else {
+            let sample_count = after.unsigned_abs() / interval + 1;
+            let weight = sample_count.saturating_mul(interval);
+            (after.saturating_add(weight as i64), Some(weight))
+        };
+        match BYTES_UNTIL_NEXT_SAMPLE.compare_exchange_weak(
+            remaining,
+            new_remaining,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => return weight,
+            Err(current) => remaining = current,
+        }
+    }
+}
+
+/// Called after a successful allocation.
+#[inline(always)]
+fn on_allocation(address: *mut c_void, size: usize, regs: Regs) {
+    if address.is_null() || !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    let Some(weight) = sample_weight(size) else {
+        return;
+    };
+    // Remember the address so that we can report the deallocation. If the
+    // table is full, we skip this allocation.
+    if sampled_addresses::insert(address as u64) {
+        channel::send_event(EventKind::Allocation, address as u64, weight, regs);
+    }
+}
+
+/// Called before memory is freed, so that the address can't be handed out
+/// again before our deallocation event has been sent.
+#[inline(always)]
+fn on_deallocation(address: *mut c_void, regs: Regs) {
+    if address.is_null() || !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    if sampled_addresses::remove(address as u64) {
+        channel::send_event(EventKind::Deallocation, address as u64, 0, regs);
+    }
+}
+
+#[no_mangle]
+extern "C" fn malloc(size: size_t) -> *mut c_void {
+    let regs = current_regs!();
+    let address = unsafe { __libc_malloc(size) };
+    on_allocation(address, size, regs);
+    address
+}
+
+#[no_mangle]
+extern "C" fn calloc(count: size_t, size: size_t) -> *mut c_void {
+    let regs = current_regs!();
+    let address = unsafe { __libc_calloc(count, size) };
+    on_allocation(address, count.saturating_mul(size), regs);
+    address
+}
+
+/// Returns whether the allocation at `address` was sampled and hasn't been
+/// freed yet.
+#[inline(always)]
+fn is_sampled(address: *mut c_void) -> bool {
+    !address.is_null()
+        && ENABLED.load(Ordering::Acquire)
+        && sampled_addresses::contains(address as u64)
+}
+
+#[no_mangle]
+extern "C" fn realloc(ptr: *mut c_void, size: size_t) -> *mut c_void {
+    let regs = current_regs!();
+    if size != 0 && is_sampled(ptr) {
+        // The old memory is only freed if the reallocation succeeds, but once it
+        // is freed, the address can be handed out again right away. So we move
+        // sampled allocations ourselves, which lets us report the deallocation
+        // between the two.
+        let address = unsafe { __libc_malloc(size) };
+        if address.is_null() {
+            return address;
+        }
+        unsafe {
+            let old_size = libc::malloc_usable_size(ptr);
+            core::ptr::copy_nonoverlapping(
+                ptr as *const u8,
+                address as *mut u8,
+                old_size.min(size),
+            );
+        }
+        on_deallocation(ptr, regs);
+        unsafe { __libc_free(ptr) };
+        on_allocation(address, size, regs);
+        return address;
+    }
+    if size == 0 {
+        // glibc frees the memory and returns null.
+        on_deallocation(ptr, regs);
+    }
+    let address = unsafe { __libc_realloc(ptr, size) };
+    on_allocation(address, size, regs);
+    address
+}
+
+#[no_mangle]
+extern "C" fn memalign(alignment: size_t, size: size_t) -> *mut c_void {
+    let regs = current_regs!();
+    let address = unsafe { __libc_memalign(alignment, size) };
+    on_allocation(address, size, regs);
+    address
+}
+
+#[no_mangle]
+extern "C" fn aligned_alloc(alignment: size_t, size: size_t) -> *mut c_void {
+    let regs = current_regs!();
+    let address = unsafe { __libc_memalign(alignment, size) };
+    on_allocation(address, size, regs);
+    address
+}
+
+#[no_mangle]
+unsafe extern "C" fn posix_memalign(
+    memptr: *mut *mut c_void,
+    alignment: size_t,
+    size: size_t,
+) -> c_int {
+    let regs = current_regs!();
+    if !alignment.is_power_of_two()
+        || !alignment.is_multiple_of(core::mem::size_of::<*mut c_void>())
+    {
+        return libc::EINVAL;
+    }
+    let address = __libc_memalign(alignment, size);
+    if address.is_null() {
+        return libc::ENOMEM;
+    }
+    on_allocation(address, size, regs);
+    *memptr = address;
+    0
+}
+
+#[no_mangle]
+extern "C" fn free(ptr: *mut c_void) {
+    let regs = current_regs!();
+    on_deallocation(ptr, regs);
+    unsafe { __libc_free(ptr) }
+}
+
+// Only anonymous mappings are treated as allocations; file mappings are
+// already visible in the profile as libraries or as mmap markers.
+#[no_mangle]
+extern "C" fn mmap(
+    addr: *mut c_void,
+    len: size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: off_t,
+) -> *mut c_void {
+    let regs = current_regs!();
+    let address =
+        unsafe { libc::syscall(libc::SYS_mmap, addr, len, prot, flags, fd, offset) } as *mut c_void;
+    if address != libc::MAP_FAILED && flags & libc::MAP_ANONYMOUS != 0 {
+        on_allocation(address, len, regs);
+    }
+    address
+}
+
+#[no_mangle]
+extern "C" fn munmap(addr: *mut c_void, len: size_t) -> c_int {
+    let regs = current_regs!();
+    on_deallocation(addr, regs);
+    unsafe { libc::syscall(libc::SYS_munmap, addr, len) as c_int }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("samply-linux-preload only supports x86_64 and aarch64");
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// An event as samply receives it: (kind, address, weight, stack bytes).
+    type ReceivedEvent = (u32, u64, u64, Vec<u8>);
+
+    /// Connects to a socket pair, with every allocated byte sampled, and
+    /// returns our end of it. Other threads of the test process allocate too,
+    /// so the tests only look at the events of their own thread. The guard
+    /// keeps the tests from taking each other's events.
+    fn set_up() -> (c_int, MutexGuard<'static, ()>) {
+        static SOCKET: OnceLock<c_int> = OnceLock::new();
+        static LOCK: Mutex<()> = Mutex::new(());
+        let socket = *SOCKET.get_or_init(|| {
+            let mut fds = [0; 2];
+            let result = unsafe {
+                libc::socketpair(libc::AF_UNIX, libc::SOCK_SEQPACKET, 0, fds.as_mut_ptr())
+            };
+            assert_eq!(result, 0);
+            enable(fds[1], 1, 64).unwrap();
+            disable();
+            fds[0]
+        });
+        (
+            socket,
+            LOCK.lock().unwrap_or_else(|error| error.into_inner()),
+        )
+    }
+
+    /// Receives the waiting events which were sent by the current thread, and
+    /// checks that the packets have the layout which samply expects.
+    fn receive_events(socket: c_int) -> Vec<ReceivedEvent> {
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as u32;
+        let mut events = Vec::new();
+        let mut buffer = [0u8; 256];
+        loop {
+            let len = unsafe {
+                libc::recv(
+                    socket,
+                    buffer.as_mut_ptr() as *mut c_void,
+                    buffer.len(),
+                    libc::MSG_DONTWAIT,
+                )
+            };
+            if len <= 0 {
+                return events;
+            }
+            let packet = &buffer[..len as usize];
+            let u32_at =
+                |offset: usize| u32::from_ne_bytes(packet[offset..][..4].try_into().unwrap());
+            let u64_at =
+                |offset: usize| u64::from_ne_bytes(packet[offset..][..8].try_into().unwrap());
+            if u32_at(8) != tid {
+                continue;
+            }
+            assert_eq!(u32_at(4), unsafe { libc::getpid() } as u32);
+            assert_eq!(
+                u32_at(12) as usize,
+                if cfg!(target_arch = "x86_64") { 3 } else { 4 }
+            );
+            assert_ne!(u64_at(16), 0);
+            let stack = &packet[80..];
+            assert_eq!(u64_at(72), stack.len() as u64);
+            events.push((u32_at(0), u64_at(24), u64_at(32), stack.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_send_event() {
+        let (socket, _guard) = set_up();
+        let stack: [u8; 64] = core::array::from_fn(|i| i as u8);
+        #[cfg(target_arch = "x86_64")]
+        let regs = Regs::new([1, 2, 3], stack.as_ptr() as u64);
+        #[cfg(target_arch = "aarch64")]
+        let regs = Regs::new([1, 2, 3, 4], stack.as_ptr() as u64);
+        channel::send_event(EventKind::Allocation, 0x5000, 4096, regs);
+        channel::send_event(EventKind::Deallocation, 0x5000, 0, regs);
+
+        let events = receive_events(socket);
+        assert_eq!(
+            events,
+            [
+                (1, 0x5000, 4096, stack.to_vec()),
+                (2, 0x5000, 0, stack.to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_realloc_and_free_events() {
+        let (socket, _guard) = set_up();
+        ENABLED.store(true, Ordering::Release);
+        let first = malloc(16);
+        let second = realloc(first, 10000);
+        let third = malloc(16);
+        let freed_by_realloc = realloc(third, 0);
+        free(second);
+        // Memory which wasn't sampled while we were enabled isn't reported.
+        disable();
+        let unsampled = malloc(16);
+        ENABLED.store(true, Ordering::Release);
+        free(unsampled);
+        disable();
+
+        assert!(freed_by_realloc.is_null());
+        let addresses = [first as u64, second as u64, third as u64];
+        let events: Vec<(u32, u64, u64)> = receive_events(socket)
+            .into_iter()
+            .filter(|(_, address, _, _)| addresses.contains(address))
+            .map(|(kind, address, weight, _)| (kind, address, weight))
+            .collect();
+        // Every byte is sampled, and each sample stands for its size. The
+        // realloc of a sampled allocation moves it, with the deallocation of
+        // the old address before the allocation at the new one.
+        assert_eq!(
+            events,
+            [
+                (1, first as u64, 16),
+                (2, first as u64, 0),
+                (1, second as u64, 10000),
+                (1, third as u64, 16),
+                (2, third as u64, 0),
+                (2, second as u64, 0),
+            ]
+        );
+    }
+}
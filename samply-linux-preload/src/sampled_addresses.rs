@@ -0,0 +1,96 @@
+//! A fixed-size, lock-free set of the addresses of the sampled allocations
+//! which haven't been freed yet.
+//!
+//! When memory is freed, we only tell samply about it if its allocation was
+//! sampled, so that samply can subtract the allocation's weight again.
+
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+const CAPACITY: usize = 1 << 20;
+const MAX_PROBES: usize = 64;
+
+const EMPTY: u64 = 0;
+const REMOVED: u64 = 1;
+
+static TABLE: AtomicPtr<AtomicU64> = AtomicPtr::new(ptr::null_mut());
+
+pub fn init() -> Option<()> {
+    // Call mmap via syscall so that we don't end up in our own hook.
+    let table = unsafe {
+        libc::syscall(
+            libc::SYS_mmap,
+            ptr::null_mut::<libc::c_void>(),
+            CAPACITY * 8,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    } as *mut libc::c_void;
+    if table == libc::MAP_FAILED {
+        return None;
+    }
+    TABLE.store(table as *mut AtomicU64, Ordering::Release);
+    Some(())
+}
+
+fn slots() -> &'static [AtomicU64] {
+    let table = TABLE.load(Ordering::Acquire);
+    // Safety: init() succeeded before any allocations were sampled. The mapping
+    // is zero-initialized, which is EMPTY, and is never unmapped.
+    unsafe { core::slice::from_raw_parts(table, CAPACITY) }
+}
+
+fn probe_sequence(address: u64) -> impl Iterator<Item = usize> {
+    let hash = (address >> 4).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let start = (hash >> (64 - CAPACITY.trailing_zeros())) as usize;
+    (0..MAX_PROBES).map(move |i| (start + i) % CAPACITY)
+}
+
+/// Adds the address to the set. Returns false if there was no free slot for it.
+pub fn insert(address: u64) -> bool {
+    let slots = slots();
+    for index in probe_sequence(address) {
+        let slot = &slots[index];
+        let mut current = slot.load(Ordering::Relaxed);
+        while current == EMPTY || current == REMOVED {
+            match slot.compare_exchange(current, address, Ordering::AcqRel, Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+    false
+}
+
+/// Removes the address from the set. Returns whether it was in the set.
+pub fn remove(address: u64) -> bool {
+    let slots = slots();
+    for index in probe_sequence(address) {
+        let slot = &slots[index];
+        match slot.load(Ordering::Acquire) {
+            EMPTY => return false,
+            current if current == address => {
+                return slot
+                    .compare_exchange(address, REMOVED, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok();
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Returns whether the address is in the set.
+pub fn contains(address: u64) -> bool {
+    let slots = slots();
+    for index in probe_sequence(address) {
+        match slots[index].load(Ordering::Acquire) {
+            EMPTY => return false,
+            current if current == address => return true,
+            _ => {}
+        }
+    }
+    false
+}
@@ -231,6 +231,7 @@ mod macho;
 mod mapped_path;
 mod path_mapper;
 mod shared;
+mod stapsdt;
 mod symbol_map;
 mod symbol_map_object;
 mod windows;
@@ -256,6 +257,7 @@ pub use crate::shared::{
     MultiArchDisambiguator, OptionallySendFuture, PeCodeId, SourceFilePath, SymbolInfo,
     SyncAddressInfo,
 };
+pub use crate::stapsdt::{sdt_probes, SdtArgument, SdtArgumentLocation, SdtProbe};
 pub use crate::symbol_map::{SymbolMap, SymbolMapTrait};
 
 pub struct SymbolManager<H: FileAndPathHelper> {
@@ -0,0 +1,399 @@
+//! Parsing of the USDT (SystemTap SDT) probes in the `.note.stapsdt` section of
+//! ELF binaries.
+//!
+//! Each probe is an ELF note of type 3 with the owner name `stapsdt`. Its
+//! descriptor contains three addresses (the probe's pc, the link-time address
+//! of the `.stapsdt.base` section, and the address of the probe's semaphore),
+//! followed by the NUL-terminated provider name, probe name, and argument spec.
+//! The argument spec describes where each argument lives at the probe's pc,
+//! e.g. `-4@%edi 8@-16(%rbp)` on x86_64 or `-4@x0 8@[sp, 16]` on aarch64.
+
+use object::{Endianness, Object, ObjectSection};
+
+const NT_STAPSDT: u32 = 3;
+
+/// A USDT probe in a binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdtProbe {
+    pub provider: String,
+    pub name: String,
+    /// The address of the probe, i.e. of the nop instruction at the probe site.
+    pub address: u64,
+    /// The address of the probe's semaphore, a `u16` counter which the process
+    /// checks before evaluating the probe's arguments. `None` if the probe has
+    /// no semaphore.
+    pub semaphore_address: Option<u64>,
+    pub arguments: Vec<SdtArgument>,
+}
+
+/// An argument of a USDT probe, parsed from an entry in the argument spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdtArgument {
+    /// The size of the argument in bytes.
+    pub size: u8,
+    pub signed: bool,
+    pub location: SdtArgumentLocation,
+}
+
+/// Where the value of a USDT argument can be found when the probe fires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdtArgumentLocation {
+    /// A constant, e.g. `$5` on x86_64 or `#5` on aarch64.
+    Constant(i64),
+    /// A register, e.g. `%edi` on x86_64 or `x0` on aarch64. The name is given
+    /// without the `%` prefix.
+    Register(String),
+    /// The memory at a register plus an offset, e.g. `-20(%rbp)` on x86_64 or
+    /// `[sp, 16]` on aarch64.
+    Memory { base: String, offset: i64 },
+    /// A location which is not supported, e.g. a RIP-relative symbol or an
+    /// indexed address.
+    Unknown(String),
+}
+
+/// Returns the USDT probes in the `.note.stapsdt` section of an ELF file.
+///
+/// The probe and semaphore addresses are link-time addresses, i.e. they are
+/// adjusted for prelinking the same way as `systemtap` and `bpftrace` do.
+pub fn sdt_probes<'data>(object_file: &impl Object<'data>) -> Vec<SdtProbe> {
+    let Some(data) = object_file
+        .section_by_name(".note.stapsdt")
+        .and_then(|section| section.data().ok())
+    else {
+        return Vec::new();
+    };
+    let base_section_address = object_file
+        .section_by_name(".stapsdt.base")
+        .map(|section| section.address());
+    let endian = object_file.endianness();
+    let address_size = if object_file.is_64() { 8 } else { 4 };
+
+    let mut probes = Vec::new();
+    let mut remaining = data;
+    while let Some((note, rest)) = next_note(remaining, endian) {
+        remaining = rest;
+        if note.note_type != NT_STAPSDT || note.name != b"stapsdt" {
+            continue;
+        }
+        if let Some(probe) = parse_probe(note.desc, endian, address_size, base_section_address) {
+            probes.push(probe);
+        }
+    }
+    probes
+}
+
+fn read_u32(data: &[u8], endian: Endianness) -> Option<u32> {
+    let bytes = data.get(..4)?.try_into().ok()?;
+    Some(match endian {
+        Endianness::Little => u32::from_le_bytes(bytes),
+        Endianness::Big => u32::from_be_bytes(bytes),
+    })
+}
+
+fn read_address(data: &[u8], endian: Endianness, address_size: usize) -> Option<u64> {
+    if address_size == 4 {
+        return read_u32(data, endian).map(u64::from);
+    }
+    let bytes = data.get(..8)?.try_into().ok()?;
+    Some(match endian {
+        Endianness::Little => u64::from_le_bytes(bytes),
+        Endianness::Big => u64::from_be_bytes(bytes),
+    })
+}
+
+/// An ELF note, with its name without the NUL terminator.
+struct Note<'data> {
+    note_type: u32,
+    name: &'data [u8],
+    desc: &'data [u8],
+}
+
+/// Splits off the next note, returning it and the remaining data.
+fn next_note(data: &[u8], endian: Endianness) -> Option<(Note<'_>, &[u8])> {
+    let name_size = read_u32(data, endian)? as usize;
+    let desc_size = read_u32(data.get(4..)?, endian)? as usize;
+    let note_type = read_u32(data.get(8..)?, endian)?;
+    let name_start = 12;
+    let desc_start = name_start + ((name_size + 3) & !3);
+    let next_start = desc_start + ((desc_size + 3) & !3);
+    let name = data.get(name_start..name_start + name_size)?;
+    let name = name.strip_suffix(b"\0").unwrap_or(name);
+    let desc = data.get(desc_start..desc_start + desc_size)?;
+    let rest = data.get(next_start..).unwrap_or_default();
+    let note = Note {
+        note_type,
+        name,
+        desc,
+    };
+    Some((note, rest))
+}
+
+fn parse_probe(
+    desc: &[u8],
+    endian: Endianness,
+    address_size: usize,
+    base_section_address: Option<u64>,
+) -> Option<SdtProbe> {
+    let mut address = read_address(desc, endian, address_size)?;
+    let base = read_address(desc.get(address_size..)?, endian, address_size)?;
+    let semaphore = read_address(desc.get(2 * address_size..)?, endian, address_size)?;
+    if let Some(base_section_address) = base_section_address {
+        address = address.wrapping_add(base_section_address.wrapping_sub(base));
+    }
+
+    let mut strings = desc
+        .get(3 * address_size..)?
+        .split(|&b| b == 0)
+        .map(|s| String::from_utf8_lossy(s).into_owned());
+    let provider = strings.next()?;
+    let name = strings.next()?;
+    let arguments = strings
+        .next()
+        .map(|spec| parse_arguments(&spec))
+        .unwrap_or_default();
+    Some(SdtProbe {
+        provider,
+        name,
+        address,
+        semaphore_address: (semaphore != 0).then_some(semaphore),
+        arguments,
+    })
+}
+
+/// Parses an argument spec like `-4@%edi 8@-16(%rbp)`. The arguments are
+/// separated by spaces, but aarch64 memory operands like `[sp, 16]` contain a
+/// space too.
+fn parse_arguments(spec: &str) -> Vec<SdtArgument> {
+    let mut arguments = Vec::new();
+    let mut current = String::new();
+    let mut in_brackets = false;
+    for c in spec.chars() {
+        match c {
+            '[' => in_brackets = true,
+            ']' => in_brackets = false,
+            ' ' if !in_brackets => {
+                if !current.is_empty() {
+                    arguments.push(parse_argument(&current));
+                    current.clear();
+                }
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        arguments.push(parse_argument(&current));
+    }
+    arguments
+}
+
+fn parse_argument(argument: &str) -> SdtArgument {
+    // The size is optional, and defaults to the size of a long.
+    let (size, location) = match argument.split_once('@') {
+        Some((size, location)) => match size.parse::<i8>() {
+            Ok(size) => (size, location),
+            Err(_) => (8, argument),
+        },
+        None => (8, argument),
+    };
+    SdtArgument {
+        size: size.unsigned_abs(),
+        signed: size < 0,
+        location: parse_location(location),
+    }
+}
+
+fn parse_integer(s: &str) -> Option<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()? as i64,
+        None => s.parse::<u64>().ok()? as i64,
+    };
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+fn is_register_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn parse_location(location: &str) -> SdtArgumentLocation {
+    let unknown = || SdtArgumentLocation::Unknown(location.to_owned());
+
+    // x86: $5, %edi, -20(%rbp)
+    if let Some(constant) = location.strip_prefix('$') {
+        return parse_integer(constant).map_or_else(unknown, SdtArgumentLocation::Constant);
+    }
+    if let Some(register) = location.strip_prefix('%') {
+        return if is_register_name(register) {
+            SdtArgumentLocation::Register(register.to_owned())
+        } else {
+            unknown()
+        };
+    }
+    if let Some((offset, rest)) = location.split_once("(%") {
+        let Some(base) = rest.strip_suffix(')') else {
+            return unknown();
+        };
+        // Symbol offsets (e.g. `sym(%rip)`) and indexed operands (e.g.
+        // `(%rax,%rbx,8)`) are not supported.
+        let offset = if offset.is_empty() {
+            Some(0)
+        } else {
+            parse_integer(offset)
+        };
+        return match offset {
+            Some(offset) if is_register_name(base) => SdtArgumentLocation::Memory {
+                base: base.to_owned(),
+                offset,
+            },
+            _ => unknown(),
+        };
+    }
+
+    // aarch64: #5, x0, [sp, 16], [x1]
+    if let Some(constant) = location.strip_prefix('#') {
+        return parse_integer(constant).map_or_else(unknown, SdtArgumentLocation::Constant);
+    }
+    if let Some(memory) = location
+        .strip_prefix('[')
+        .and_then(|memory| memory.strip_suffix(']'))
+    {
+        let (base, offset) = match memory.split_once(',') {
+            Some((base, offset)) => {
+                let offset = offset.trim();
+                (
+                    base.trim(),
+                    parse_integer(offset.strip_prefix('#').unwrap_or(offset)),
+                )
+            }
+            None => (memory.trim(), Some(0)),
+        };
+        return match offset {
+            Some(offset) if is_register_name(base) => SdtArgumentLocation::Memory {
+                base: base.to_owned(),
+                offset,
+            },
+            _ => unknown(),
+        };
+    }
+    if let Some(constant) = parse_integer(location) {
+        return SdtArgumentLocation::Constant(constant);
+    }
+    if is_register_name(location) {
+        return SdtArgumentLocation::Register(location.to_owned());
+    }
+    unknown()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn arg(size: u8, signed: bool, location: SdtArgumentLocation) -> SdtArgument {
+        SdtArgument {
+            size,
+            signed,
+            location,
+        }
+    }
+
+    #[test]
+    fn test_parse_x86_64_arguments() {
+        use SdtArgumentLocation::*;
+        assert_eq!(
+            parse_arguments("-4@%edi 8@$5 -4@-20(%rbp) 8@(%rax) 8@sym(%rip) 8@(%rax,%rbx,8)"),
+            vec![
+                arg(4, true, Register("edi".into())),
+                arg(8, false, Constant(5)),
+                arg(
+                    4,
+                    true,
+                    Memory {
+                        base: "rbp".into(),
+                        offset: -20
+                    }
+                ),
+                arg(
+                    8,
+                    false,
+                    Memory {
+                        base: "rax".into(),
+                        offset: 0
+                    }
+                ),
+                arg(8, false, Unknown("sym(%rip)".into())),
+                arg(8, false, Unknown("(%rax,%rbx,8)".into())),
+            ]
+        );
+        assert_eq!(
+            parse_arguments("%rdi"),
+            vec![arg(8, false, Register("rdi".into()))]
+        );
+        assert_eq!(parse_arguments(""), vec![]);
+    }
+
+    #[test]
+    fn test_parse_aarch64_arguments() {
+        use SdtArgumentLocation::*;
+        assert_eq!(
+            parse_arguments("-4@x0 8@[sp, 16] 2@[x1] -8@#-3 4@7"),
+            vec![
+                arg(4, true, Register("x0".into())),
+                arg(
+                    8,
+                    false,
+                    Memory {
+                        base: "sp".into(),
+                        offset: 16
+                    }
+                ),
+                arg(
+                    2,
+                    false,
+                    Memory {
+                        base: "x1".into(),
+                        offset: 0
+                    }
+                ),
+                arg(8, true, Constant(-3)),
+                arg(4, false, Constant(7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_probe_note() {
+        let mut desc = Vec::new();
+        desc.extend_from_slice(&0x1139u64.to_le_bytes());
+        desc.extend_from_slice(&0x2004u64.to_le_bytes());
+        desc.extend_from_slice(&0x4010u64.to_le_bytes());
+        desc.extend_from_slice(b"myapp\0request\0-4@%edi 8@%rsi\0");
+        let probe = parse_probe(&desc, Endianness::Little, 8, Some(0x3004)).unwrap();
+        assert_eq!(probe.provider, "myapp");
+        assert_eq!(probe.name, "request");
+        assert_eq!(probe.address, 0x2139);
+        assert_eq!(probe.semaphore_address, Some(0x4010));
+        assert_eq!(probe.arguments.len(), 2);
+
+        let mut note = Vec::new();
+        note.extend_from_slice(&8u32.to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&NT_STAPSDT.to_le_bytes());
+        note.extend_from_slice(b"stapsdt\0");
+        note.extend_from_slice(&desc);
+        note.resize((note.len() + 3) & !3, 0);
+        let (parsed, rest) = next_note(&note, Endianness::Little).unwrap();
+        assert_eq!(parsed.note_type, NT_STAPSDT);
+        assert_eq!(parsed.name, b"stapsdt");
+        assert_eq!(parsed.desc, &desc[..]);
+        assert!(rest.is_empty());
+    }
+}
@@ -9,8 +9,8 @@ use super::cli_utils::{parse_time_range, split_at_first_equals};
 use super::server::{PortSelection, ServerProps};
 use super::shared::included_processes::IncludedProcesses;
 use super::shared::prop_types::{
-    CoreClrProfileProps, ImportProps, ProcessLaunchProps, ProfileCreationProps, RecordingMode,
-    RecordingProps, SymbolProps,
+    CallGraphMode, CoreClrProfileProps, FlightRecorderProps, ImportProps, IterationProcesses,
+    ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps, SymbolProps,
 };
 
 #[derive(Debug, Parser)]
@@ -58,6 +58,9 @@ pub enum Action {
     /// Import a perf.data file and display the profile.
     Import(ImportArgs),
 
+    /// List the USDT (SDT) probes in an ELF binary, with their arguments.
+    ListUsdt(ListUsdtArgs),
+
     #[cfg(target_os = "windows")]
     #[clap(hide = true)]
     /// Used in the elevated helper process.
@@ -80,6 +83,12 @@ pub struct LoadArgs {
     pub symbol_args: SymbolArgs,
 }
 
+#[derive(Debug, Args)]
+pub struct ListUsdtArgs {
+    /// Path to the binary or library whose probes should be listed.
+    pub file: PathBuf,
+}
+
 #[derive(Debug, Args)]
 pub struct ImportArgs {
     /// Path to the profile file that should be imported.
@@ -141,6 +150,15 @@ pub struct RecordArgs {
     #[arg(long, default_value = "1")]
     pub iteration_count: u32,
 
+    /// Whether the runs of --iteration-count are kept as separate processes, which
+    /// get the number of the run in their names, or merged into one process per
+    /// name, so that the call tree aggregates them (Linux only). Either way, each
+    /// run gets an "Iteration" marker, and the wall-clock time of each run is
+    /// printed.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_enum, default_value = "separate")]
+    pub iteration_processes: IterationProcessesArgs,
+
     /// Ignore exit code and continue running when iteration_count > 0
     #[arg(short, long)]
     pub ignore_exit_code: bool,
@@ -164,21 +182,38 @@ pub struct RecordArgs {
 
     /// Profile the execution of this command.
     #[arg(
-        required_unless_present_any = ["pid", "all"],
-        conflicts_with_all = ["pid", "all"],
+        required_unless_present_any = ["pid", "all", "process_name", "cgroup"],
+        conflicts_with_all = ["pid", "all", "process_name", "include_new_processes", "cgroup"],
         allow_hyphen_values = true,
         trailing_var_arg = true
     )]
     pub command: Vec<std::ffi::OsString>,
 
-    /// Process ID of existing process to attach to.
+    /// Process ID of existing process to attach to (can be specified multiple times).
+    /// On Linux, the descendants of these processes are recorded as well.
     #[arg(short, long, conflicts_with = "all")]
-    pub pid: Option<u32>,
+    pub pid: Vec<u32>,
 
     /// Profile entire system (all processes). Not supported on macOS.
     #[arg(short, long, conflicts_with = "pid")]
     pub all: bool,
 
+    /// Attach to all running processes whose name contains this substring (can be
+    /// specified multiple times). Not supported on macOS.
+    #[arg(long, value_name = "SUBSTRING", conflicts_with_all = ["pid", "all"])]
+    pub process_name: Vec<String>,
+
+    /// With --process-name, also attach to matching processes which are started
+    /// while recording. On Windows, these processes are always included.
+    #[arg(long, requires = "process_name")]
+    pub include_new_processes: bool,
+
+    /// Profile all processes in this cgroup, e.g. the processes of a container (Linux only).
+    /// Relative paths are resolved against the mount point of the cgroup hierarchy
+    /// with the perf_event controller, e.g. /sys/fs/cgroup.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["pid", "all", "process_name"])]
+    pub cgroup: Option<PathBuf>,
+
     /// VM hack for arm64 Windows VMs to not try to record PROFILE events (Windows only).
     #[cfg(target_os = "windows")]
     #[arg(long)]
@@ -196,6 +231,201 @@ pub struct RecordArgs {
     #[cfg(target_os = "windows")]
     #[arg(long)]
     pub keep_etl: bool,
+
+    /// Also save the raw perf events to a perf.data file at this path (Linux only).
+    /// The file can be converted again with "samply import", or opened with "perf report".
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_name = "PATH")]
+    pub save_perf_data: Option<PathBuf>,
+
+    /// How to collect user stacks (Linux only). "fp" lets the kernel walk frame pointers,
+    /// "dwarf" copies the raw stack into each sample for DWARF unwinding, and "mixed"
+    /// does both and falls back to DWARF unwinding when frame pointers are missing.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_enum, default_value = "dwarf")]
+    pub call_graph: CallGraphArgs,
+
+    /// The number of bytes of user stack to copy per sample, for the "dwarf" and "mixed"
+    /// call graph modes (Linux only). At most 64512.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, default_value = "32000")]
+    pub stack_size: u32,
+
+    /// Start with recording paused (Linux only). While samply is running, send it
+    /// SIGUSR2 to resume recording and SIGUSR1 to pause it again.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub start_paused: bool,
+
+    /// Record native memory allocations of the launched process (Linux only). A helper
+    /// library is injected with LD_PRELOAD, which intercepts malloc, calloc, realloc,
+    /// memalign, aligned_alloc, posix_memalign, free, mmap and munmap and captures the
+    /// stacks of a sample of the allocations.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, conflicts_with_all = ["pid", "all", "process_name", "cgroup"])]
+    pub allocations: bool,
+
+    /// With --allocations, sample one allocation per this many allocated bytes (Linux only).
+    /// Use 1 to capture every allocation.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value = "65536",
+        requires = "allocations"
+    )]
+    pub allocation_sampling_interval: u64,
+
+    /// Record the text which is written to the ftrace trace_marker file, and turn
+    /// atrace-style `B|pid|name`, `E|pid` and `C|pid|name|value` writes into interval
+    /// markers and counters (Linux only). The writes are read from a separate ftrace
+    /// instance, which needs write access to tracefs and a kernel with the
+    /// `copy_trace_marker` option.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub trace_markers: bool,
+
+    /// Count instructions and cache misses in the same perf event group as the
+    /// sampled CPU cycles, and add them as counter tracks, and the instructions per
+    /// cycle as a graph of markers on each thread (Linux only). This needs hardware
+    /// performance counters and Linux 6.12 or newer.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub hw_counters: bool,
+
+    /// Record the memory usage of the recorded processes as a counter track, and
+    /// page faults and mmap/mprotect calls as markers (Linux only). This samples
+    /// the `kmem:rss_stat`, `exceptions:page_fault_user` and mmap/mprotect syscall
+    /// tracepoints, which needs access to tracefs and a perf_event_paranoid
+    /// level of -1. Otherwise, only the memory usage is recorded, by polling
+    /// /proc/<pid>/statm.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub memory: bool,
+
+    /// Record which threads wake up which other threads, as markers which are
+    /// linked to the switch-in of the woken thread (Linux only). This samples the
+    /// `sched:sched_waking` tracepoint, which needs access to tracefs and a
+    /// perf_event_paranoid level of -1. Only wakeups which are performed by the
+    /// recorded processes are seen. When importing, the `sched_waking` or
+    /// `sched_wakeup` tracepoints in the perf.data file are always used.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub wakeups: bool,
+
+    /// Add a "Spawned" marker with the stack of the spawning thread for each
+    /// thread or process which is created, linked to the start of the new thread,
+    /// and an "Exec" marker for each executed program (Linux only). This samples
+    /// the `sched:sched_process_fork` and `sched:sched_process_exec` tracepoints,
+    /// and `syscalls:sys_enter_exit_group`, `signal:signal_generate` and
+    /// `signal:signal_deliver` for the exit codes and signals in the process
+    /// lifetime markers, which needs access to tracefs and a perf_event_paranoid
+    /// level of -1. When importing, these tracepoints in the perf.data file are
+    /// always used.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub spawns: bool,
+
+    /// Record block I/O requests as markers with their device, sector, size and
+    /// latency, and add per-device throughput and queue depth counters (Linux
+    /// only). This samples the `block:block_rq_insert`, `block_rq_issue` and
+    /// `block_rq_complete` tracepoints on all CPUs, which needs access to tracefs
+    /// and a perf_event_paranoid level of -1. Requests which weren't submitted by
+    /// the recorded processes are shown on a thread for their device. When
+    /// importing, these tracepoints in the perf.data file are always used.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub block_io: bool,
+
+    /// Record the frequency of each CPU as a counter track, and the time which
+    /// each CPU spends in idle states (C-states) as markers (Linux only). This
+    /// samples the `power:cpu_frequency` and `power:cpu_idle` tracepoints on all
+    /// CPUs, which needs access to tracefs and a perf_event_paranoid level of -1.
+    /// Otherwise, only the frequencies are recorded, by polling
+    /// /sys/devices/system/cpu/*/cpufreq. When importing, these tracepoints in
+    /// the perf.data file are always used.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub cpu_power: bool,
+
+    /// Sample a tracepoint, e.g. `sched:sched_process_exec`, and add a marker
+    /// with the stack and the tracepoint's fields for each hit (Linux only). The
+    /// fields are decoded with the tracepoint's format in tracefs. Can be given
+    /// several times. This needs access to tracefs and a perf_event_paranoid
+    /// level of -1. When importing, tracepoints in the perf.data file get their
+    /// fields from the file's tracing data.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_name = "SYSTEM:NAME")]
+    pub tracepoint: Vec<String>,
+
+    /// Probe a function with uprobes, and add a marker with the duration and the
+    /// stack for each call, and a counter of the calls (Linux only). The function
+    /// is given as `/path/to/binary:symbol`, which probes its entry and its return.
+    /// With `/path/to/binary:symbol%return`, only the return is probed, and each
+    /// return becomes an instant marker. Can be given several times. This needs
+    /// root or CAP_PERFMON. When importing, probes from `perf probe -x` in the
+    /// perf.data file are always used.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_name = "BINARY:SYMBOL")]
+    pub probe: Vec<String>,
+
+    /// Record the hits of a USDT (SDT) probe as markers with the stack and the
+    /// probe's arguments (Linux only). The probe is given as `provider:name`,
+    /// which is looked up in the launched command's binary, or as
+    /// `/path/to/binary:provider:name`. Probes with a semaphore are enabled in
+    /// all processes which use the binary while recording. Can be given several
+    /// times. Use `samply list-usdt` to see the
+    /// probes in a binary. This needs root or CAP_PERFMON. When importing, the
+    /// `sdt_*` events in the perf.data file are always used, without arguments.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_name = "[BINARY:]PROVIDER:NAME")]
+    pub usdt: Vec<String>,
+
+    /// Keep recording with bounded memory, and only keep the most recent events
+    /// (Linux only). A profile of at least the last SECONDS, and usually less
+    /// than twice that, is written whenever samply receives SIGHUP, or gets a
+    /// command on the --dump-socket, or when a marker file gets a line with the
+    /// --dump-trigger string. A dump can cover more if samply falls behind with
+    /// the events or the recording was paused, and less if it comes less than
+    /// SECONDS after the start of the recording or after the previous dump.
+    /// The dumps are written next to the output file, with `-dump<N>` inserted
+    /// before the first '.' of its name: `-o profile.json.gz` gives
+    /// `profile-dump1.json.gz`, and `-o fr.json` gives `fr-dump1.json`. The
+    /// dumps aren't symbolicated. When recording stops, the most recent events
+    /// are written to the output file as usual.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_name = "SECONDS", conflicts_with = "save_perf_data")]
+    pub flight_recorder: Option<f64>,
+
+    /// Listen on a Unix socket at this path while recording with
+    /// --flight-recorder. Writing `dump` followed by a newline to the socket
+    /// writes a dump, and the path of the dump is written back once it's saved
+    /// (Linux only).
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_name = "PATH", requires = "flight_recorder")]
+    pub dump_socket: Option<PathBuf>,
+
+    /// Write a dump while recording with --flight-recorder when a marker file
+    /// of a recorded process gets a line which contains this string (Linux
+    /// only). Marker files are checked four times per second.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, value_name = "STRING", requires = "flight_recorder")]
+    pub dump_trigger: Option<String>,
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IterationProcessesArgs {
+    Separate,
+    Merged,
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CallGraphArgs {
+    Fp,
+    Dwarf,
+    Mixed,
 }
 
 #[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
@@ -331,6 +561,43 @@ pub struct ProfileCreationArgs {
     #[arg(long)]
     pub presymbolicate: bool,
 
+    /// Turn syscall tracepoints into syscall markers with the arguments, the return
+    /// value and the stack at entry (Linux only). When recording, this samples the
+    /// `raw_syscalls:sys_enter` and `raw_syscalls:sys_exit` tracepoints, which needs
+    /// access to tracefs and a perf_event_paranoid level of -1. When importing, the
+    /// `raw_syscalls` or per-syscall `syscalls:sys_enter_<name>` / `sys_exit_<name>`
+    /// tracepoints in the perf.data file are paired up.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub syscalls: bool,
+
+    /// Add a timeline of the thread states, i.e. running, runnable, sleeping and
+    /// uninterruptible (e.g. waiting for disk I/O), as markers, and label the
+    /// off-CPU samples with the state in which the thread went off-CPU (Linux only).
+    /// When recording, this samples the `sched:sched_switch`, `sched:sched_waking`
+    /// and `sched:sched_migrate_task` tracepoints, which needs access to tracefs and
+    /// a perf_event_paranoid level of -1, and also adds the wakeup markers of
+    /// `--wakeups`. Like with `--wakeups`, only the wakeups which are performed by
+    /// the recorded processes are seen; a thread which is woken by another process
+    /// or by an interrupt goes straight from sleeping to running, without a
+    /// runnable state. When importing, the same tracepoints are used if they're in
+    /// the perf.data file.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub thread_states: bool,
+
+    /// Add a marker for each futex wait and each contended kernel lock, with the
+    /// lock address and the waiting stack, and a "Lock contention" thread whose
+    /// samples are the waiting stacks, weighted by the wait time in milliseconds
+    /// (Linux only). When recording, this samples the `syscalls:sys_enter_futex`
+    /// and `syscalls:sys_exit_futex` tracepoints, and the `lock:contention_begin`
+    /// and `lock:contention_end` tracepoints on Linux 5.19 and newer, which needs
+    /// access to tracefs and a perf_event_paranoid level of -1. When importing,
+    /// the same tracepoints are used if they're in the perf.data file.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub lock_contention: bool,
+
     /// Emit markers for any unknown ETW events that are encountered.
     #[cfg(target_os = "windows")]
     #[arg(long)]
@@ -429,6 +696,28 @@ impl RecordArgs {
             std::process::exit(1);
         }
         let interval = Duration::from_secs_f64(1.0 / self.rate);
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self.allocation_sampling_interval == 0 {
+            eprintln!("Error: the allocation sampling interval must be at least 1 byte");
+            std::process::exit(1);
+        }
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self
+            .flight_recorder
+            .is_some_and(|seconds| !seconds.is_finite() || seconds <= 0.0)
+        {
+            eprintln!("Error: the flight recorder window must be positive");
+            std::process::exit(1);
+        }
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if self.stack_size > 63 * 1024 {
+            eprintln!(
+                "Error: stack size can be at most {}, got {}",
+                63 * 1024,
+                self.stack_size
+            );
+            std::process::exit(1);
+        }
         RecordingProps {
             output_file: self.output.clone(),
             time_limit,
@@ -443,15 +732,104 @@ impl RecordArgs {
             keep_etl: self.keep_etl,
             #[cfg(not(target_os = "windows"))]
             keep_etl: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            perf_data_path: self.save_perf_data.clone(),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            perf_data_path: None,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            call_graph: match self.call_graph {
+                CallGraphArgs::Fp => CallGraphMode::FramePointer,
+                CallGraphArgs::Dwarf => CallGraphMode::Dwarf,
+                CallGraphArgs::Mixed => CallGraphMode::Mixed,
+            },
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            call_graph: CallGraphMode::Dwarf,
+            // The kernel requires the stack size to be a multiple of 8.
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            stack_size: self.stack_size.next_multiple_of(8),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            stack_size: 0,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            start_paused: self.start_paused,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            start_paused: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            allocation_sampling_interval: self
+                .allocations
+                .then_some(self.allocation_sampling_interval),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            allocation_sampling_interval: None,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            trace_markers: self.trace_markers,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            trace_markers: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            hardware_counters: self.hw_counters,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            hardware_counters: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            memory: self.memory,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            memory: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            wakeups: self.wakeups,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            wakeups: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            spawns: self.spawns,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            spawns: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            block_io: self.block_io,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            block_io: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            cpu_power: self.cpu_power,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            cpu_power: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            tracepoints: self.tracepoint.clone(),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            tracepoints: Vec::new(),
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            probes: self.probe.clone(),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            probes: Vec::new(),
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            usdt: self.usdt.clone(),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            usdt: Vec::new(),
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            flight_recorder: self.flight_recorder.map(|seconds| FlightRecorderProps {
+                window: Duration::from_secs_f64(seconds),
+                socket_path: self.dump_socket.clone(),
+                trigger: self.dump_trigger.clone(),
+            }),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            flight_recorder: None,
         }
     }
 
     pub fn recording_mode(&self) -> RecordingMode {
-        let (command, iteration_count) = match (self.all, &self.pid) {
-            (true, _) => return RecordingMode::All,
-            (false, Some(pid)) => return RecordingMode::Pid(*pid),
-            (false, None) => (&self.command, self.iteration_count),
-        };
+        if self.all {
+            return RecordingMode::All;
+        }
+        if !self.pid.is_empty() {
+            return RecordingMode::Pid(self.pid.clone());
+        }
+        if !self.process_name.is_empty() {
+            return RecordingMode::ProcessName {
+                included_processes: IncludedProcesses {
+                    name_substrings: self.process_name.clone(),
+                    pids: Vec::new(),
+                },
+                include_new_processes: self.include_new_processes,
+            };
+        }
+        if let Some(cgroup) = &self.cgroup {
+            return RecordingMode::Cgroup(cgroup.clone());
+        }
+        let command = &self.command;
 
         assert!(
             !command.is_empty(),
@@ -474,8 +852,15 @@ impl RecordArgs {
             env_vars,
             command_name,
             args,
-            iteration_count,
+            iteration_count: self.iteration_count,
             ignore_exit_code: self.ignore_exit_code,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            iteration_processes: match self.iteration_processes {
+                IterationProcessesArgs::Separate => IterationProcesses::Separate,
+                IterationProcessesArgs::Merged => IterationProcesses::Merged,
+            },
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            iteration_processes: IterationProcesses::Separate,
         };
 
         RecordingMode::Launch(launch_props)
@@ -484,7 +869,17 @@ impl RecordArgs {
     pub fn profile_creation_props(&self) -> ProfileCreationProps {
         let fallback_profile_name = match self.recording_mode() {
             RecordingMode::All => "All processes".to_string(),
-            RecordingMode::Pid(pid) => format!("PID {pid}"),
+            RecordingMode::Pid(pids) => match pids.as_slice() {
+                [pid] => format!("PID {pid}"),
+                pids => {
+                    let pids: Vec<String> = pids.iter().map(|pid| pid.to_string()).collect();
+                    format!("PIDs {}", pids.join(", "))
+                }
+            },
+            RecordingMode::ProcessName {
+                included_processes, ..
+            } => included_processes.name_substrings.join(", "),
+            RecordingMode::Cgroup(path) => format!("cgroup {}", path.display()),
             RecordingMode::Launch(launch_props) => {
                 let filename = Path::new(&launch_props.command_name)
                     .file_name()
@@ -537,6 +932,18 @@ impl ProfileCreationArgs {
             unknown_event_markers: self.unknown_event_markers,
             #[cfg(not(target_os = "windows"))]
             unknown_event_markers: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            syscalls: self.syscalls,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            syscalls: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            thread_states: self.thread_states,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            thread_states: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            lock_contention: self.lock_contention,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            lock_contention: false,
         }
     }
 }
@@ -623,5 +1030,415 @@ mod test {
         // Make sure you can't pass both a pid and a command name at the same time.
         let opt_res = Opt::try_parse_from(["samply", "record", "-p", "1234", "rustup"]);
         assert!(opt_res.is_err());
+
+        let opt = Opt::parse_from(["samply", "record", "--process-name", "nginx"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(
+            matches!(record_args.recording_mode(), RecordingMode::ProcessName { included_processes, include_new_processes: false } if included_processes.name_substrings == ["nginx"])
+        );
+        let opt_res =
+            Opt::try_parse_from(["samply", "record", "--include-new-processes", "rustup"]);
+        assert!(opt_res.is_err());
+
+        // Make sure that -p can be passed multiple times.
+        let opt = Opt::parse_from(["samply", "record", "-p", "1234", "-p", "5678"]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.pid == [1234, 5678] && record_args.command.is_empty())
+        );
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_call_graph() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        let props = record_args.recording_props();
+        assert_eq!(props.call_graph, CallGraphMode::Dwarf);
+        assert_eq!(props.stack_size, 32000);
+
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--call-graph",
+            "mixed",
+            "--stack-size",
+            "60001",
+            "rustup",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        let props = record_args.recording_props();
+        assert_eq!(props.call_graph, CallGraphMode::Mixed);
+        assert_eq!(props.stack_size, 60008);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_allocations() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert_eq!(
+            record_args.recording_props().allocation_sampling_interval,
+            None
+        );
+
+        let opt = Opt::parse_from(["samply", "record", "--allocations", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert_eq!(
+            record_args.recording_props().allocation_sampling_interval,
+            Some(65536)
+        );
+
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--allocations",
+            "--allocation-sampling-interval",
+            "1",
+            "rustup",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert_eq!(
+            record_args.recording_props().allocation_sampling_interval,
+            Some(1)
+        );
+
+        let opt_res = Opt::try_parse_from(["samply", "record", "--allocations", "-p", "1234"]);
+        assert!(opt_res.is_err());
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_trace_markers() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(!record_args.recording_props().trace_markers);
+
+        let opt = Opt::parse_from(["samply", "record", "--trace-markers", "-p", "1234"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.recording_props().trace_markers);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_hw_counters() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(!record_args.recording_props().hardware_counters);
+
+        let opt = Opt::parse_from(["samply", "record", "--hw-counters", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.recording_props().hardware_counters);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_memory() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(!record_args.recording_props().memory);
+
+        let opt = Opt::parse_from(["samply", "record", "--memory", "-p", "1234"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.recording_props().memory);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_wakeups() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(!record_args.recording_props().wakeups);
+
+        let opt = Opt::parse_from(["samply", "record", "--wakeups", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.recording_props().wakeups);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_spawns() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(!record_args.recording_props().spawns);
+
+        let opt = Opt::parse_from(["samply", "record", "--spawns", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.recording_props().spawns);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_iteration_processes() {
+        let launch_props = |args: &[&str]| {
+            let opt = Opt::parse_from(args);
+            let Action::Record(record_args) = opt.action else {
+                panic!("expected record action")
+            };
+            let RecordingMode::Launch(launch_props) = record_args.recording_mode() else {
+                panic!("expected launch mode")
+            };
+            launch_props
+        };
+
+        let props = launch_props(&["samply", "record", "--iteration-count", "3", "rustup"]);
+        assert_eq!(props.iteration_count, 3);
+        assert_eq!(props.iteration_processes, IterationProcesses::Separate);
+
+        let props = launch_props(&[
+            "samply",
+            "record",
+            "--iteration-count",
+            "3",
+            "--iteration-processes",
+            "merged",
+            "rustup",
+        ]);
+        assert_eq!(props.iteration_processes, IterationProcesses::Merged);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_flight_recorder() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.recording_props().flight_recorder.is_none());
+
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--flight-recorder",
+            "2.5",
+            "--dump-socket",
+            "/tmp/samply.sock",
+            "--dump-trigger",
+            "SLOW FRAME",
+            "rustup",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        let flight_recorder = record_args.recording_props().flight_recorder.unwrap();
+        assert_eq!(flight_recorder.window, Duration::from_millis(2500));
+        assert_eq!(
+            flight_recorder.socket_path.as_deref(),
+            Some(Path::new("/tmp/samply.sock"))
+        );
+        assert_eq!(flight_recorder.trigger.as_deref(), Some("SLOW FRAME"));
+
+        // The dump options need the flight recorder, and raw events can't be
+        // saved with it.
+        assert!(
+            Opt::try_parse_from(["samply", "record", "--dump-trigger", "x", "rustup"]).is_err()
+        );
+        assert!(Opt::try_parse_from([
+            "samply",
+            "record",
+            "--flight-recorder",
+            "5",
+            "--save-perf-data",
+            "perf.data",
+            "rustup"
+        ])
+        .is_err());
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_block_io() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(!record_args.recording_props().block_io);
+
+        let opt = Opt::parse_from(["samply", "record", "--block-io", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.recording_props().block_io);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_cpu_power() {
+        let opt = Opt::parse_from(["samply", "record", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(!record_args.recording_props().cpu_power);
+
+        let opt = Opt::parse_from(["samply", "record", "--cpu-power", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.recording_props().cpu_power);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_probes() {
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--probe",
+            "/usr/lib/libc.so.6:malloc",
+            "--probe",
+            "/usr/lib/libc.so.6:free%return",
+            "rustup",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert_eq!(
+            record_args.recording_props().probes,
+            [
+                "/usr/lib/libc.so.6:malloc",
+                "/usr/lib/libc.so.6:free%return"
+            ]
+        );
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_tracepoints() {
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--tracepoint",
+            "sched:sched_process_exec",
+            "--tracepoint",
+            "signal:signal_deliver",
+            "rustup",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert_eq!(
+            record_args.recording_props().tracepoints,
+            ["sched:sched_process_exec", "signal:signal_deliver"]
+        );
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record_usdt() {
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--usdt",
+            "postgres:query__start",
+            "--usdt",
+            "/usr/lib/libc.so.6:libc:setjmp",
+            "postgres",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert_eq!(
+            record_args.recording_props().usdt,
+            ["postgres:query__start", "/usr/lib/libc.so.6:libc:setjmp"]
+        );
+    }
+
+    #[test]
+    fn verify_cli_list_usdt() {
+        let opt = Opt::parse_from(["samply", "list-usdt", "/usr/lib/libc.so.6"]);
+        assert!(
+            matches!(opt.action, Action::ListUsdt(args) if args.file == Path::new("/usr/lib/libc.so.6"))
+        );
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_syscalls() {
+        let opt = Opt::parse_from(["samply", "record", "--syscalls", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.profile_creation_props().syscalls);
+
+        let opt = Opt::parse_from(["samply", "import", "--syscalls", "perf.data"]);
+        let Action::Import(import_args) = opt.action else {
+            panic!("expected import action")
+        };
+        assert!(import_args.profile_creation_props().syscalls);
+
+        let opt = Opt::parse_from(["samply", "import", "perf.data"]);
+        let Action::Import(import_args) = opt.action else {
+            panic!("expected import action")
+        };
+        assert!(!import_args.profile_creation_props().syscalls);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_thread_states() {
+        let opt = Opt::parse_from(["samply", "record", "--thread-states", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.profile_creation_props().thread_states);
+
+        let opt = Opt::parse_from(["samply", "import", "perf.data"]);
+        let Action::Import(import_args) = opt.action else {
+            panic!("expected import action")
+        };
+        assert!(!import_args.profile_creation_props().thread_states);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn verify_cli_lock_contention() {
+        let opt = Opt::parse_from(["samply", "record", "--lock-contention", "rustup"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected record action")
+        };
+        assert!(record_args.profile_creation_props().lock_contention);
+
+        let opt = Opt::parse_from(["samply", "import", "--lock-contention", "perf.data"]);
+        let Action::Import(import_args) = opt.action else {
+            panic!("expected import action")
+        };
+        assert!(import_args.profile_creation_props().lock_contention);
+
+        let opt = Opt::parse_from(["samply", "import", "perf.data"]);
+        let Action::Import(import_args) = opt.action else {
+            panic!("expected import action")
+        };
+        assert!(!import_args.profile_creation_props().lock_contention);
     }
 }
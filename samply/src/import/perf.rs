@@ -6,12 +6,14 @@ use std::time::SystemTime;
 
 use framehop::{Module, Unwinder};
 use fxprof_processed_profile::{Profile, ReferenceTimestamp};
-use linux_perf_data::{linux_perf_event_reader, DsoInfo, DsoKey, PerfFileReader, PerfFileRecord};
+use linux_perf_data::{
+    linux_perf_event_reader, DsoInfo, DsoKey, Feature, PerfFile, PerfFileReader, PerfFileRecord,
+};
 use linux_perf_event_reader::EventRecord;
 
 use crate::linux_shared::{
-    ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64, Converter, EventInterpretation, KnownEvent,
-    MmapRangeOrVec,
+    parse_tracing_data, split_off_group_read, ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64,
+    Converter, EventInterpretation, HostInfo, KnownEvent, MmapRangeOrVec,
 };
 use crate::shared::prop_types::ProfileCreationProps;
 
@@ -187,16 +189,28 @@ where
         converter.set_os_name(&format!("Android {android_version}"));
     }
 
+    converter.set_host_info(&host_info_from_perf_file(&perf_file));
+
+    if let Some(tracing_data) = perf_file.feature_section_data(Feature::TRACING_DATA) {
+        converter.set_tracepoint_formats(&parse_tracing_data(tracing_data));
+    }
+
     let mut last_timestamp = 0;
+    let mut group_read_buffer = Vec::new();
 
     while let Ok(Some(record)) = record_iter.next_record(&mut perf_file) {
-        let (record, parsed_record, attr_index) = match record {
-            PerfFileRecord::EventRecord { attr_index, record } => match record.parse() {
-                Ok(r) => (record, r, attr_index),
-                Err(_) => continue,
-            },
+        let (record, attr_index) = match record {
+            PerfFileRecord::EventRecord { attr_index, record } => (record, attr_index),
             PerfFileRecord::UserRecord(_) => continue,
         };
+        let (record, group_read_values) =
+            match split_off_group_read(&record, &mut group_read_buffer) {
+                Some((record, values)) => (record, Some(values)),
+                None => (record, None),
+            };
+        let Ok(parsed_record) = record.parse() else {
+            continue;
+        };
         if let Some(timestamp) = record.timestamp() {
             if timestamp < last_timestamp {
                 eprintln!(
@@ -216,6 +230,73 @@ where
 
                 match interpretation.known_event_indices.get(&attr_index) {
                     Some(KnownEvent::RssStat) => converter.handle_rss_stat_sample::<C>(&e),
+                    Some(KnownEvent::FtracePrint) => {
+                        converter.handle_ftrace_print_sample(&e, attr_index)
+                    }
+                    Some(
+                        KnownEvent::SyscallEnter
+                        | KnownEvent::SyscallExit
+                        | KnownEvent::LockContentionBegin
+                        | KnownEvent::LockContentionEnd,
+                    ) if converter.handles_lock_contention_event(attr_index) => {
+                        converter.handle_lock_contention_sample::<C>(&e, attr_index);
+                        // With `--syscalls`, futex calls also get syscall markers.
+                        if converter.handles_syscall_event(attr_index) {
+                            converter.handle_syscall_sample::<C>(&e, attr_index)
+                        }
+                    }
+                    Some(KnownEvent::SyscallEnter | KnownEvent::SyscallExit)
+                        if converter.handles_syscall_event(attr_index) =>
+                    {
+                        converter.handle_syscall_sample::<C>(&e, attr_index)
+                    }
+                    Some(KnownEvent::SchedWaking | KnownEvent::SchedWakeup)
+                        if converter.handles_wakeup_event(attr_index) =>
+                    {
+                        converter.handle_sched_wakeup_sample::<C>(&e)
+                    }
+                    Some(KnownEvent::SchedMigrateTask) if converter.has_thread_states() => {
+                        converter.handle_sched_migrate_task_sample(&e)
+                    }
+                    Some(KnownEvent::SchedProcessFork) => {
+                        converter.handle_sched_process_fork_sample::<C>(&e, attr_index)
+                    }
+                    Some(KnownEvent::SchedProcessExec) => {
+                        converter.handle_sched_process_exec_sample::<C>(&e, attr_index)
+                    }
+                    // The exit statuses come from these events, but they're also recorded
+                    // with `--tracepoint`, so they get the same markers as other tracepoints.
+                    Some(KnownEvent::SignalGenerate) => {
+                        converter.handle_signal_generate_sample(&e, attr_index);
+                        converter.handle_other_event_sample::<C>(&e, attr_index)
+                    }
+                    Some(KnownEvent::SignalDeliver) => {
+                        converter.handle_signal_deliver_sample(&e, attr_index);
+                        converter.handle_other_event_sample::<C>(&e, attr_index)
+                    }
+                    Some(KnownEvent::ExitGroup) => {
+                        converter.handle_exit_group_sample(&e, attr_index);
+                        // With `--syscalls`, exit_group calls get syscall markers instead.
+                        if converter.handles_syscall_event(attr_index) {
+                            converter.handle_syscall_sample::<C>(&e, attr_index)
+                        } else {
+                            converter.handle_other_event_sample::<C>(&e, attr_index)
+                        }
+                    }
+                    Some(
+                        event @ (KnownEvent::BlockRqInsert
+                        | KnownEvent::BlockRqIssue
+                        | KnownEvent::BlockRqComplete),
+                    ) => converter.handle_block_rq_sample::<C>(&e, event, attr_index),
+                    Some(KnownEvent::ProbeEntry | KnownEvent::ProbeReturn) => {
+                        converter.handle_probe_sample::<C>(&e, attr_index)
+                    }
+                    Some(KnownEvent::UsdtProbe) => {
+                        converter.handle_usdt_sample::<C>(&e, attr_index)
+                    }
+                    Some(event @ (KnownEvent::CpuFrequency | KnownEvent::CpuIdle)) => {
+                        converter.handle_cpu_power_sample(&e, event)
+                    }
                     _ => {
                         // the main event and sched_switch are already covered by regular samples so don't add other event markers
                         if !(attr_index == interpretation.main_event_attr_index
@@ -225,6 +306,10 @@ where
                         }
                     }
                 }
+
+                if let Some(values) = &group_read_values {
+                    converter.handle_group_read_values(&e, values);
+                }
             }
             EventRecord::Fork(e) => {
                 converter.handle_fork(e);
@@ -257,6 +342,21 @@ where
                 };
                 converter.handle_context_switch(e, common);
             }
+            EventRecord::Lost(e) => {
+                if let Ok(common) = record.common_data() {
+                    converter.handle_lost(e, common);
+                }
+            }
+            EventRecord::Throttle(e) => {
+                if let Ok(common) = record.common_data() {
+                    converter.handle_throttle(e, common);
+                }
+            }
+            EventRecord::Unthrottle(e) => {
+                if let Ok(common) = record.common_data() {
+                    converter.handle_unthrottle(e, common);
+                }
+            }
             _ => {
                 // println!("{:?}", record.record_type);
             }
@@ -272,6 +372,43 @@ fn get_simpleperf_timestamp(meta_info: Option<&HashMap<&str, &str>>) -> Option<f
     timestamp_str.parse().ok()
 }
 
+/// The information about the recording machine from the feature headers:
+/// CPUDESC, NRCPUS, TOTAL_MEM and CMDLINE, plus the hostname, the kernel
+/// release and the architecture.
+fn host_info_from_perf_file(perf_file: &PerfFile) -> HostInfo {
+    let mut extra = Vec::new();
+    if let Some(hostname) = perf_file.hostname().ok().flatten() {
+        extra.push(("Hostname", hostname.to_owned()));
+    }
+    if let Some(os_release) = perf_file.os_release().ok().flatten() {
+        extra.push(("Kernel release", os_release.to_owned()));
+    }
+    if let Some(arch) = perf_file.arch().ok().flatten() {
+        extra.push(("Architecture", arch.to_owned()));
+    }
+    if let Some(perf_version) = perf_file.perf_version().ok().flatten() {
+        extra.push(("perf version", perf_version.to_owned()));
+    }
+    HostInfo {
+        cpu_name: perf_file.cpu_desc().ok().flatten().map(str::to_owned),
+        // NRCPUS only has the number of logical CPUs.
+        physical_cpus: None,
+        logical_cpus: perf_file
+            .nr_cpus()
+            .ok()
+            .flatten()
+            .map(|nr_cpus| nr_cpus.nr_cpus_online),
+        // TOTAL_MEM is in kilobytes.
+        main_memory: perf_file.total_mem().ok().flatten().map(|kb| kb * 1024),
+        arguments: perf_file
+            .cmdline()
+            .ok()
+            .flatten()
+            .map(|args| args.join(" ")),
+        extra,
+    }
+}
+
 /// This is a terrible hack to work around ambiguous build IDs in old versions
 /// of perf (tested with perf 5.4.224). Those versions of perf do two things:
 ///
@@ -0,0 +1,412 @@
+//! Native allocation profiling for launched processes.
+//!
+//! We inject `libsamply_linux_preload.so` into the launched process with `LD_PRELOAD`.
+//! This library intercepts the allocation functions, samples the allocations, and
+//! sends the sampled allocations and deallocations to us over a `SOCK_SEQPACKET`
+//! socket, together with the register values and the raw stack bytes of the caller.
+//! We unwind these stacks in the same way as the stacks of perf samples.
+//!
+//! The library is built from the `samply-linux-preload` directory; see its
+//! `channel.rs` for the layout of the packets.
+
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use byteorder::{ByteOrder, NativeEndian};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use flate2::write::GzDecoder;
+use linux_perf_data::linux_perf_event_reader::{RawData, RawDataU64, Regs};
+use tempfile::TempDir;
+
+use super::profiler::ConvertRegsNative;
+use crate::linux_shared::{AllocationSample, ConvertRegs};
+
+#[cfg(target_arch = "x86_64")]
+static PRELOAD_LIB_BYTES_GZ: &[u8] =
+    include_bytes!("../../resources/libsamply_linux_preload_x86_64.so.gz");
+
+#[cfg(target_arch = "aarch64")]
+static PRELOAD_LIB_BYTES_GZ: &[u8] =
+    include_bytes!("../../resources/libsamply_linux_preload_aarch64.so.gz");
+
+/// The size of the header at the start of each packet.
+const HEADER_SIZE: usize = 4 * 4 + 3 * 8 + 4 * 8 + 8;
+
+const EVENT_KIND_ALLOCATION: u32 = 1;
+const EVENT_KIND_DEALLOCATION: u32 = 2;
+
+/// Owns the preload library and our end of the socket.
+///
+/// The launched processes inherit the other end of the socket. Call
+/// [`AllocationRecorder::close_child_socket`] once no more processes will be
+/// launched, so that the event receiver is closed once all launched processes
+/// have quit. The preload library is deleted when this is dropped; the converter
+/// needs it for unwinding until all events have been processed.
+pub struct AllocationRecorder {
+    preload_lib_dir: TempDir,
+    child_socket: Option<OwnedFd>,
+    sampling_interval: u64,
+    stack_size: u32,
+}
+
+impl AllocationRecorder {
+    /// Writes out the preload library and starts a thread which receives the
+    /// events from the launched processes.
+    pub fn new(
+        sampling_interval: u64,
+        stack_size: u32,
+    ) -> std::io::Result<(Self, AllocationEvents)> {
+        // Like on macOS, we write out the preload library to a temporary directory,
+        // so that it can be embedded in the samply binary.
+        let preload_lib_dir = TempDir::new()?;
+        let mut decoder = GzDecoder::new(File::create(
+            preload_lib_dir.path().join("libsamply_linux_preload.so"),
+        )?);
+        decoder.write_all(PRELOAD_LIB_BYTES_GZ)?;
+        decoder.finish()?;
+
+        let mut fds = [0; 2];
+        // Our end is close-on-exec. The end for the launched process is made
+        // inheritable below.
+        let result = unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        };
+        if result == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let (our_socket, child_socket) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        nix::fcntl::fcntl(
+            &child_socket,
+            nix::fcntl::FcntlArg::F_SETFD(nix::fcntl::FdFlag::empty()),
+        )?;
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let packet_size = HEADER_SIZE + stack_size as usize;
+        let socket = Arc::new(Mutex::new(our_socket));
+        let thread_socket = socket.clone();
+        thread::spawn(move || receive_events(&thread_socket, packet_size, &sender));
+        let events = AllocationEvents {
+            receiver,
+            socket,
+            packet_size,
+        };
+
+        let recorder = AllocationRecorder {
+            preload_lib_dir,
+            child_socket: Some(child_socket),
+            sampling_interval,
+            stack_size,
+        };
+        Ok((recorder, events))
+    }
+
+    /// Closes our copy of the socket end which the launched processes inherit.
+    pub fn close_child_socket(&mut self) {
+        self.child_socket = None;
+    }
+
+    /// The environment variables for the launched process. `env_vars` are the
+    /// environment variables which were already requested for it.
+    ///
+    /// Must not be called after [`AllocationRecorder::close_child_socket`].
+    pub fn env_vars(&self, env_vars: &[(OsString, OsString)]) -> Vec<(OsString, OsString)> {
+        let mut ld_preload: OsString = self
+            .preload_lib_dir
+            .path()
+            .join("libsamply_linux_preload.so")
+            .into();
+        let existing_ld_preload = env_vars
+            .iter()
+            .rev()
+            .find(|(name, _)| name == "LD_PRELOAD")
+            .map(|(_, value)| value.clone())
+            .or_else(|| std::env::var_os("LD_PRELOAD"));
+        if let Some(existing_ld_preload) = existing_ld_preload {
+            ld_preload.push(":");
+            ld_preload.push(existing_ld_preload);
+        }
+        vec![
+            ("LD_PRELOAD".into(), ld_preload),
+            (
+                "SAMPLY_ALLOCATIONS_FD".into(),
+                self.child_socket
+                    .as_ref()
+                    .expect("child socket was closed")
+                    .as_raw_fd()
+                    .to_string()
+                    .into(),
+            ),
+            (
+                "SAMPLY_ALLOCATIONS_INTERVAL".into(),
+                self.sampling_interval.to_string().into(),
+            ),
+            (
+                "SAMPLY_ALLOCATIONS_STACK_SIZE".into(),
+                self.stack_size.to_string().into(),
+            ),
+        ]
+    }
+}
+
+/// Receives the packets on the receiving thread until all launched processes
+/// have quit and the child socket has been closed.
+fn receive_events(socket: &Mutex<OwnedFd>, packet_size: usize, sender: &Sender<AllocationEvent>) {
+    let mut buffer = vec![0u8; packet_size];
+    loop {
+        // Wait without holding the lock, so that AllocationEvents::receive_sent
+        // isn't blocked in the meantime.
+        let mut pollfd = libc::pollfd {
+            fd: socket.lock().unwrap().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let result = unsafe { libc::poll(&mut pollfd, 1, -1) };
+        if result < 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+            continue;
+        }
+        if result < 0 {
+            break;
+        }
+        let socket = socket.lock().unwrap();
+        let is_open = receive_waiting_events(&socket, &mut buffer, |event| {
+            let _ = sender.send(event);
+        });
+        if !is_open {
+            break;
+        }
+    }
+}
+
+/// Parses the packets which are waiting in the socket, without blocking.
+/// Returns false once all launched processes have quit and the child socket
+/// has been closed.
+fn receive_waiting_events(
+    socket: &OwnedFd,
+    buffer: &mut [u8],
+    mut on_event: impl FnMut(AllocationEvent),
+) -> bool {
+    loop {
+        let len = unsafe {
+            libc::recv(
+                socket.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                libc::MSG_DONTWAIT,
+            )
+        };
+        if len < 0 {
+            match std::io::Error::last_os_error().raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::EAGAIN) => return true,
+                _ => return false,
+            }
+        }
+        if len == 0 {
+            return false;
+        }
+        if let Some(event) = AllocationEvent::parse(&buffer[..len as usize]) {
+            on_event(event);
+        }
+    }
+}
+
+/// The events from the launched processes.
+///
+/// They're received on a separate thread, so that the processes don't have to
+/// wait for us when the socket is full. But before we handle the exit of a
+/// process, we need all of its events, and some of them may still be waiting in
+/// the socket; see [`AllocationEvents::receive_sent`].
+pub struct AllocationEvents {
+    receiver: Receiver<AllocationEvent>,
+    /// Our end of the socket. The receiving thread holds the lock while it takes
+    /// packets out of the socket and passes them on to `receiver`.
+    socket: Arc<Mutex<OwnedFd>>,
+    packet_size: usize,
+}
+
+impl AllocationEvents {
+    /// The events which the receiving thread has passed on so far.
+    pub fn try_iter(&self) -> impl Iterator<Item = AllocationEvent> + '_ {
+        self.receiver.try_iter()
+    }
+
+    /// Appends all events which were sent before this call to `events`,
+    /// including the ones which the receiving thread hasn't picked up yet.
+    /// Once a process has exited, this includes all of its events.
+    pub fn receive_sent(&self, events: &mut VecDeque<AllocationEvent>) {
+        let socket = self.socket.lock().unwrap();
+        events.extend(self.receiver.try_iter());
+        let mut buffer = vec![0u8; self.packet_size];
+        receive_waiting_events(&socket, &mut buffer, |event| events.push_back(event));
+    }
+
+    /// Waits for the next event, once all launched processes have quit.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<AllocationEvent, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+}
+
+/// An allocation or deallocation which was sampled in a launched process.
+#[derive(Debug, Clone)]
+pub struct AllocationEvent {
+    pub pid: i32,
+    pub timestamp: u64,
+    address: u64,
+    /// None for deallocations.
+    size: Option<u64>,
+    regs: Vec<u8>,
+    user_stack: Vec<u8>,
+}
+
+impl AllocationEvent {
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < HEADER_SIZE {
+            return None;
+        }
+        let (header, user_stack) = packet.split_at(HEADER_SIZE);
+        let kind = NativeEndian::read_u32(&header[0..]);
+        let pid = NativeEndian::read_u32(&header[4..]) as i32;
+        let _tid = NativeEndian::read_u32(&header[8..]);
+        let reg_count = NativeEndian::read_u32(&header[12..]) as usize;
+        let timestamp = NativeEndian::read_u64(&header[16..]);
+        let address = NativeEndian::read_u64(&header[24..]);
+        let weight = NativeEndian::read_u64(&header[32..]);
+        let regs = &header[40..72];
+        let stack_len = NativeEndian::read_u64(&header[72..]) as usize;
+
+        let size = match kind {
+            EVENT_KIND_ALLOCATION => Some(weight),
+            EVENT_KIND_DEALLOCATION => None,
+            _ => return None,
+        };
+        // The preload library sends the registers in the order of our regs mask.
+        if reg_count != ConvertRegsNative::regs_mask().count_ones() as usize
+            || stack_len != user_stack.len()
+        {
+            return None;
+        }
+        Some(AllocationEvent {
+            pid,
+            timestamp,
+            address,
+            size,
+            regs: regs[..reg_count * mem::size_of::<u64>()].to_vec(),
+            user_stack: user_stack.to_vec(),
+        })
+    }
+
+    /// Returns this event in the form that the converter understands, for
+    /// `Converter::handle_allocation_sample::<ConvertRegsNative>`.
+    pub fn as_sample(&self) -> AllocationSample<'_> {
+        let regs = RawDataU64::from_raw_data::<NativeEndian>(RawData::Single(&self.regs));
+        AllocationSample {
+            pid: self.pid,
+            timestamp: self.timestamp,
+            address: self.address,
+            size: self.size,
+            regs: Regs::new(ConvertRegsNative::regs_mask(), regs),
+            user_stack: &self.user_stack,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A packet like the ones from the preload library, with `stack_len`
+    /// bytes of stack.
+    fn packet(kind: u32, timestamp: u64, address: u64, weight: u64, stack_len: usize) -> Vec<u8> {
+        let reg_count = ConvertRegsNative::regs_mask().count_ones();
+        let mut packet = vec![0; HEADER_SIZE + stack_len];
+        NativeEndian::write_u32(&mut packet[0..], kind);
+        NativeEndian::write_u32(&mut packet[4..], 123);
+        NativeEndian::write_u32(&mut packet[8..], 124);
+        NativeEndian::write_u32(&mut packet[12..], reg_count);
+        NativeEndian::write_u64(&mut packet[16..], timestamp);
+        NativeEndian::write_u64(&mut packet[24..], address);
+        NativeEndian::write_u64(&mut packet[32..], weight);
+        for i in 0..reg_count as usize {
+            NativeEndian::write_u64(&mut packet[40 + i * 8..], 0x1000 + i as u64);
+        }
+        NativeEndian::write_u64(&mut packet[72..], stack_len as u64);
+        for (i, byte) in packet[HEADER_SIZE..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        packet
+    }
+
+    #[test]
+    fn test_parse_allocation_event() {
+        let reg_count = ConvertRegsNative::regs_mask().count_ones() as usize;
+
+        let event =
+            AllocationEvent::parse(&packet(EVENT_KIND_ALLOCATION, 1000, 0x5000, 4096, 16)).unwrap();
+        assert_eq!(event.pid, 123);
+        assert_eq!(event.timestamp, 1000);
+        assert_eq!(event.address, 0x5000);
+        assert_eq!(event.size, Some(4096));
+        assert_eq!(event.regs.len(), reg_count * 8);
+        assert_eq!(NativeEndian::read_u64(&event.regs), 0x1000);
+        assert_eq!(event.user_stack, (0..16).collect::<Vec<u8>>());
+
+        let event =
+            AllocationEvent::parse(&packet(EVENT_KIND_DEALLOCATION, 2000, 0x5000, 0, 0)).unwrap();
+        assert_eq!(event.address, 0x5000);
+        assert_eq!(event.size, None);
+        assert!(event.user_stack.is_empty());
+
+        // Unknown kinds, truncated packets and other register layouts are skipped.
+        assert!(AllocationEvent::parse(&packet(3, 1000, 0x5000, 0, 0)).is_none());
+        let full = packet(EVENT_KIND_ALLOCATION, 1000, 0x5000, 4096, 16);
+        assert!(AllocationEvent::parse(&full[..full.len() - 8]).is_none());
+        assert!(AllocationEvent::parse(&full[..HEADER_SIZE - 1]).is_none());
+        let mut other_regs = full.clone();
+        NativeEndian::write_u32(&mut other_regs[12..], reg_count as u32 + 1);
+        assert!(AllocationEvent::parse(&other_regs).is_none());
+    }
+
+    #[test]
+    fn test_receive_sent_events() {
+        let (mut recorder, events) = AllocationRecorder::new(1, 16).unwrap();
+        let child_socket = recorder.child_socket.as_ref().unwrap().as_raw_fd();
+        for timestamp in 0..100 {
+            let packet = packet(EVENT_KIND_ALLOCATION, timestamp, 0x5000 + timestamp, 16, 16);
+            let len = unsafe {
+                libc::send(
+                    child_socket,
+                    packet.as_ptr() as *const libc::c_void,
+                    packet.len(),
+                    0,
+                )
+            };
+            assert_eq!(len, packet.len() as isize);
+        }
+
+        // All sent events are there right away, in order, no matter how many
+        // the receiving thread has picked up.
+        let mut received = VecDeque::new();
+        events.receive_sent(&mut received);
+        let timestamps: Vec<u64> = received.iter().map(|event| event.timestamp).collect();
+        assert_eq!(timestamps, (0..100).collect::<Vec<u64>>());
+
+        recorder.close_child_socket();
+        assert_eq!(
+            events.recv_timeout(Duration::from_secs(5)).unwrap_err(),
+            RecvTimeoutError::Disconnected
+        );
+    }
+}
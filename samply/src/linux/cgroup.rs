@@ -0,0 +1,131 @@
+//! Helpers for finding the cgroup hierarchy which perf events are filtered by.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+/// Resolves the cgroup path given on the command line to a directory in the
+/// hierarchy which perf uses for cgroup filtering. Absolute paths are used as
+/// they are, relative paths are resolved against the mount point of the
+/// hierarchy with the `perf_event` controller.
+///
+/// With cgroup v1, the `perf_event` controller has its own hierarchy, e.g.
+/// /sys/fs/cgroup/perf_event. With cgroup v2 (or a hybrid setup where the
+/// controller isn't bound to a v1 hierarchy), it's the unified hierarchy.
+pub fn resolve_cgroup_path(path: &Path) -> io::Result<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+        let root = perf_event_cgroup_root(&mountinfo).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No cgroup hierarchy with the perf_event controller is mounted",
+            )
+        })?;
+        root.join(path)
+    };
+    if !path.join("cgroup.procs").exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a cgroup directory", path.display()),
+        ));
+    }
+    Ok(path)
+}
+
+/// Finds the mount point of the cgroup hierarchy which has the `perf_event`
+/// controller, from the contents of /proc/self/mountinfo.
+fn perf_event_cgroup_root(mountinfo: &str) -> Option<PathBuf> {
+    let mut unified = None;
+    for line in mountinfo.lines() {
+        // 36 35 0:30 / /sys/fs/cgroup/perf_event rw,nosuid shared:17 - cgroup cgroup rw,perf_event
+        let Some((mount, filesystem)) = line.split_once(" - ") else {
+            continue;
+        };
+        let Some(mount_point) = mount.split(' ').nth(4) else {
+            continue;
+        };
+        let mut filesystem = filesystem.split(' ');
+        let fs_type = filesystem.next();
+        let super_options = filesystem.nth(1).unwrap_or_default();
+        match fs_type {
+            Some("cgroup")
+                if super_options
+                    .split(',')
+                    .any(|option| option == "perf_event") =>
+            {
+                return Some(unescape_mount_point(mount_point));
+            }
+            Some("cgroup2") if unified.is_none() => {
+                unified = Some(unescape_mount_point(mount_point));
+            }
+            _ => {}
+        }
+    }
+    unified
+}
+
+/// Mount points in /proc/self/mountinfo have spaces, tabs, newlines and
+/// backslashes escaped as three-digit octal sequences, e.g. `\040`.
+fn unescape_mount_point(mount_point: &str) -> PathBuf {
+    let bytes = mount_point.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|_| bytes[i] == b'\\');
+        match escape
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(unescaped))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_perf_event_cgroup_root() {
+        let unified = "\
+24 30 0:22 / /sys rw,nosuid,nodev,noexec,relatime shared:7 - sysfs sysfs rw
+29 24 0:26 / /sys/fs/cgroup rw,nosuid,nodev,noexec,relatime shared:9 - cgroup2 cgroup2 rw,nsdelegate
+";
+        assert_eq!(
+            perf_event_cgroup_root(unified),
+            Some(PathBuf::from("/sys/fs/cgroup"))
+        );
+
+        let v1 = "\
+29 24 0:26 / /sys/fs/cgroup ro,nosuid,nodev,noexec shared:9 - tmpfs tmpfs ro,mode=755
+30 29 0:27 / /sys/fs/cgroup/unified rw,nosuid,nodev,noexec,relatime shared:10 - cgroup2 cgroup2 rw
+33 29 0:30 / /sys/fs/cgroup/cpu,cpuacct rw,nosuid,nodev,noexec,relatime shared:14 - cgroup cgroup rw,cpu,cpuacct
+36 29 0:33 / /sys/fs/cgroup/perf_event rw,nosuid,nodev,noexec,relatime shared:17 - cgroup cgroup rw,perf_event
+";
+        assert_eq!(
+            perf_event_cgroup_root(v1),
+            Some(PathBuf::from("/sys/fs/cgroup/perf_event"))
+        );
+
+        let escaped = "36 29 0:33 / /mnt/my\\040cgroups rw - cgroup none rw,perf_event,freezer\n";
+        assert_eq!(
+            perf_event_cgroup_root(escaped),
+            Some(PathBuf::from("/mnt/my cgroups"))
+        );
+
+        let none = "33 29 0:30 / /sys/fs/cgroup/cpu rw shared:14 - cgroup cgroup rw,cpu\n";
+        assert_eq!(perf_event_cgroup_root(none), None);
+    }
+}
@@ -0,0 +1,401 @@
+//! `--flight-recorder`: keep recording with bounded memory, and write a profile
+//! of the most recent events when asked to.
+//!
+//! All events are fed to two generations of converters. Every time the current
+//! generation has covered the window, a new generation is started, seeded with
+//! the processes, threads and mappings from /proc, and the oldest generation is
+//! dropped. The older of the two generations therefore covers between one and
+//! two windows, and is kept fully unwound, so a dump only needs to finish it
+//! and serialize the profile. Generations are only rotated between batches of
+//! events and not while paused, so they can cover a bit more than two windows
+//! when the main loop falls behind. The generations share the stacks of the samples,
+//! so that each sample is only unwound once.
+//!
+//! A dump takes the older generation away, so until the next rotation, the
+//! current generation is all we have. A dump in that time may cover less than
+//! the window: it has the events since the start of the recording, or at least
+//! the ones since the previous dump.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::linux_shared::{Converter, MmapRangeOrVec, SharedSampleStack};
+use crate::shared::prop_types::FlightRecorderProps;
+use crate::shared::save_profile::save_profile_to_file;
+
+pub type LiveConverter =
+    Converter<framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>>;
+
+/// How often the marker files are checked for the trigger string.
+const TRIGGER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+static DUMP_SIGNAL_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(_signal: libc::c_int) {
+    DUMP_SIGNAL_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+/// Installs the handler for SIGHUP, which requests a dump.
+fn install_handler() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_signal as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut());
+    }
+}
+
+pub struct FlightRecorder {
+    window: Duration,
+    output_path: PathBuf,
+    new_converter: Box<dyn FnMut() -> LiveConverter + Send>,
+    /// The stack of the last sample, which all generations share.
+    shared_sample_stack: Arc<Mutex<SharedSampleStack>>,
+    /// The older generation, which covers at least the window once it exists.
+    previous: Option<LiveConverter>,
+    /// When the current generation was started.
+    generation_start: Instant,
+    socket: Option<DumpSocket>,
+    trigger: Option<MarkerFileTrigger>,
+    dump_count: u32,
+    saving_threads: Vec<JoinHandle<()>>,
+}
+
+impl FlightRecorder {
+    /// `new_converter` creates an empty converter for a new generation, with the
+    /// same settings as the first one.
+    pub fn new(
+        props: FlightRecorderProps,
+        output_path: &Path,
+        new_converter: impl FnMut() -> LiveConverter + Send + 'static,
+    ) -> Self {
+        install_handler();
+        let socket = props.socket_path.map(|path| match DumpSocket::bind(&path) {
+            Ok(socket) => socket,
+            Err(error) => {
+                eprintln!("Could not listen on {}: {error}", path.display());
+                std::process::exit(1);
+            }
+        });
+        Self {
+            window: props.window,
+            output_path: output_path.to_owned(),
+            new_converter: Box::new(new_converter),
+            shared_sample_stack: Default::default(),
+            previous: None,
+            generation_start: Instant::now(),
+            socket,
+            trigger: props.trigger.map(MarkerFileTrigger::new),
+            dump_count: 0,
+            saving_threads: Vec::new(),
+        }
+    }
+
+    /// Lets the first converter share the stacks of the samples with the
+    /// generations which follow it.
+    pub fn share_sample_stacks(&self, converter: &mut LiveConverter) {
+        converter.share_sample_stacks(self.shared_sample_stack.clone());
+    }
+
+    pub fn previous_mut(&mut self) -> Option<&mut LiveConverter> {
+        self.previous.as_mut()
+    }
+
+    /// Whether the current generation has covered the window, so that it can
+    /// take the place of the previous one.
+    pub fn should_rotate(&self) -> bool {
+        self.generation_start.elapsed() >= self.window
+    }
+
+    /// Creates the converter for a new generation. The caller seeds it with the
+    /// running processes, and passes the replaced converter to `rotate`.
+    pub fn new_generation(&mut self) -> LiveConverter {
+        let mut converter = (self.new_converter)();
+        self.share_sample_stacks(&mut converter);
+        converter
+    }
+
+    /// Drops the previous generation, and keeps `current`, which has just been
+    /// replaced by a new generation, in its place.
+    pub fn rotate(&mut self, current: LiveConverter) {
+        self.previous = Some(current);
+        self.generation_start = Instant::now();
+    }
+
+    /// Takes the previous generation for a dump.
+    pub fn take_previous(&mut self) -> Option<LiveConverter> {
+        self.previous.take()
+    }
+
+    /// Called when the current generation has been replaced for a dump, because
+    /// there was no previous generation yet.
+    pub fn restart_generation(&mut self) {
+        self.generation_start = Instant::now();
+    }
+
+    /// Checks for SIGHUP, for commands on the socket, and for the trigger string
+    /// in the marker files of `current` and of the previous generation. Returns
+    /// whether a dump should be written.
+    pub fn dump_requested(&mut self, current: &LiveConverter) -> bool {
+        let mut requested = DUMP_SIGNAL_RECEIVED.swap(false, Ordering::SeqCst);
+        if let Some(socket) = &mut self.socket {
+            requested |= socket.poll();
+        }
+        if let Some(trigger) = &mut self.trigger {
+            let previous = self.previous.as_ref();
+            requested |= trigger.poll(|| {
+                let mut paths = current.marker_file_paths();
+                paths.extend(previous.into_iter().flat_map(Converter::marker_file_paths));
+                paths
+            });
+        }
+        requested
+    }
+
+    /// Finishes the profile of `converter` and saves it to the next dump path,
+    /// in the background. Clients of the socket which asked for the dump get its
+    /// path once it's saved.
+    pub fn dump(&mut self, converter: LiveConverter) {
+        self.dump_count += 1;
+        let path = dump_path(&self.output_path, self.dump_count);
+        let clients = match &mut self.socket {
+            Some(socket) => std::mem::take(&mut socket.waiting_clients),
+            None => Vec::new(),
+        };
+        eprintln!("Writing flight recorder dump to {}...", path.display());
+        self.saving_threads.retain(|thread| !thread.is_finished());
+        self.saving_threads.push(std::thread::spawn(move || {
+            let profile = converter.finish();
+            let reply = match save_profile_to_file(&profile, &path) {
+                Ok(()) => {
+                    eprintln!("Saved flight recorder dump to {}", path.display());
+                    format!("{}\n", path.display())
+                }
+                Err(error) => {
+                    eprintln!("Could not write {}: {error}", path.display());
+                    format!("error: {error}\n")
+                }
+            };
+            for mut client in clients {
+                let _ = client.write_all(reply.as_bytes());
+            }
+        }));
+    }
+
+    /// Waits for the dumps which are still being saved, and returns the
+    /// converter with the most recent events. These cover at least the window,
+    /// unless the recording or the time since the last dump was shorter.
+    pub fn finish(self, current: LiveConverter) -> LiveConverter {
+        for thread in self.saving_threads {
+            let _ = thread.join();
+        }
+        self.previous.unwrap_or(current)
+    }
+}
+
+/// The path of the `number`th dump, with `-dump<number>` inserted before the
+/// first '.' of the file name: `profile.json.gz` becomes `profile-dump1.json.gz`.
+fn dump_path(output_path: &Path, number: u32) -> PathBuf {
+    let file_name = output_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "profile.json.gz".to_owned());
+    let (stem, extension) = match file_name.find('.') {
+        Some(pos) if pos > 0 => file_name.split_at(pos),
+        _ => (file_name.as_str(), ""),
+    };
+    output_path.with_file_name(format!("{stem}-dump{number}{extension}"))
+}
+
+/// A Unix socket which accepts `dump` commands. The connections are handled
+/// on a separate thread, so that slow clients don't hold up the recording.
+struct DumpSocket {
+    path: PathBuf,
+    /// The clients which sent a `dump` command, from the listening thread.
+    dump_requests: Receiver<UnixStream>,
+    /// The clients which are waiting for the path of the next dump.
+    waiting_clients: Vec<UnixStream>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl DumpSocket {
+    fn bind(path: &Path) -> std::io::Result<Self> {
+        let listener = UnixListener::bind(path)?;
+        let (sender, dump_requests) = channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        std::thread::Builder::new()
+            .name("dump socket".to_owned())
+            .spawn(move || accept_commands(listener, sender, &thread_stopped))?;
+        Ok(Self {
+            path: path.to_owned(),
+            dump_requests,
+            waiting_clients: Vec::new(),
+            stopped,
+        })
+    }
+
+    /// Returns whether a dump was requested since the last call.
+    fn poll(&mut self) -> bool {
+        let count = self.waiting_clients.len();
+        self.waiting_clients.extend(self.dump_requests.try_iter());
+        self.waiting_clients.len() > count
+    }
+}
+
+impl Drop for DumpSocket {
+    fn drop(&mut self) {
+        // Wake up the listening thread so that it sees that we're done.
+        self.stopped.store(true, Ordering::SeqCst);
+        let _ = UnixStream::connect(&self.path);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reads the command of each connection to the dump socket, and passes on the
+/// clients which asked for a dump.
+fn accept_commands(
+    listener: UnixListener,
+    dump_requests: Sender<UnixStream>,
+    stopped: &AtomicBool,
+) {
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        // Clients send their command right after connecting.
+        if stream
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .is_err()
+        {
+            continue;
+        }
+        let mut command = String::new();
+        let _ = BufReader::new(&stream).read_line(&mut command);
+        match command.trim() {
+            "dump" => {
+                if dump_requests.send(stream).is_err() {
+                    return;
+                }
+            }
+            command => {
+                let _ = writeln!(stream, "error: unknown command {command:?}");
+            }
+        }
+    }
+}
+
+/// Looks for a trigger string in the lines which are appended to marker files.
+struct MarkerFileTrigger {
+    trigger: String,
+    /// How far each marker file has been read.
+    offsets: HashMap<PathBuf, u64>,
+    last_poll: Option<Instant>,
+}
+
+impl MarkerFileTrigger {
+    fn new(trigger: String) -> Self {
+        Self {
+            trigger,
+            offsets: HashMap::new(),
+            last_poll: None,
+        }
+    }
+
+    /// Reads the new complete lines of the marker files, including the ones from
+    /// `marker_file_paths` which haven't been seen before. Returns whether one of
+    /// them contains the trigger string.
+    fn poll(&mut self, marker_file_paths: impl FnOnce() -> Vec<PathBuf>) -> bool {
+        if self
+            .last_poll
+            .is_some_and(|last_poll| last_poll.elapsed() < TRIGGER_POLL_INTERVAL)
+        {
+            return false;
+        }
+        self.last_poll = Some(Instant::now());
+
+        for path in marker_file_paths() {
+            self.offsets.entry(path).or_insert(0);
+        }
+        let mut triggered = false;
+        // Files which can't be read anymore have been deleted, so forget about them.
+        self.offsets.retain(
+            |path, offset| match read_new_lines(path, offset, &self.trigger) {
+                Ok(found) => {
+                    triggered |= found;
+                    true
+                }
+                Err(_) => false,
+            },
+        );
+        triggered
+    }
+}
+
+/// Reads the complete lines after `offset`, advances `offset` past them, and
+/// returns whether one of them contains `trigger`.
+fn read_new_lines(path: &Path, offset: &mut u64, trigger: &str) -> std::io::Result<bool> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(*offset))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    // A line which is still being written is read again next time.
+    let Some(end) = data.iter().rposition(|&b| b == b'\n') else {
+        return Ok(false);
+    };
+    *offset += end as u64 + 1;
+    Ok(data[..end]
+        .split(|&b| b == b'\n')
+        .any(|line| String::from_utf8_lossy(line).contains(trigger)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dump_path() {
+        assert_eq!(
+            dump_path(Path::new("/tmp/profile.json.gz"), 1),
+            Path::new("/tmp/profile-dump1.json.gz")
+        );
+        assert_eq!(
+            dump_path(Path::new("out.json"), 12),
+            Path::new("out-dump12.json")
+        );
+        assert_eq!(dump_path(Path::new("out"), 2), Path::new("out-dump2"));
+    }
+
+    #[test]
+    fn test_read_new_lines() {
+        let path = std::env::temp_dir().join(format!("marker-{}-trigger.txt", std::process::id()));
+        std::fs::write(&path, "1 2 frame\n3 4 SLOW").unwrap();
+        let mut offset = 0;
+        assert!(!read_new_lines(&path, &mut offset, "SLOW").unwrap());
+        assert_eq!(offset, 10);
+
+        // The trigger is only found once its line is complete, and only once.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b" frame\n")
+            .unwrap();
+        assert!(read_new_lines(&path, &mut offset, "SLOW").unwrap());
+        assert!(!read_new_lines(&path, &mut offset, "SLOW").unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_new_lines(&path, &mut offset, "SLOW").is_err());
+    }
+}
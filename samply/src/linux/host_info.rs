@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+use crate::linux_shared::HostInfo;
+
+/// Collects the information about this machine from /proc/cpuinfo,
+/// /proc/meminfo and uname, and samply's own command line.
+pub fn current_host_info() -> HostInfo {
+    let mut info = HostInfo::default();
+    if let Ok(cpuinfo) = std::fs::read_to_string("/proc/cpuinfo") {
+        let cpus = parse_cpuinfo(&cpuinfo);
+        info.cpu_name = cpus.name;
+        info.physical_cpus = cpus.physical;
+        info.logical_cpus = cpus.logical;
+    }
+    if let Ok(meminfo) = std::fs::read_to_string("/proc/meminfo") {
+        info.main_memory = parse_mem_total(&meminfo);
+    }
+    info.arguments = Some(std::env::args().collect::<Vec<_>>().join(" "));
+    if let Ok(uname) = uname::uname() {
+        info.extra = vec![
+            ("Hostname", uname.nodename),
+            ("Kernel release", uname.release),
+            ("Kernel version", uname.version),
+            ("Architecture", uname.machine),
+        ];
+    }
+    info
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CpuInfo {
+    pub name: Option<String>,
+    pub physical: Option<u32>,
+    pub logical: Option<u32>,
+}
+
+/// The CPU model and the CPU counts from the contents of /proc/cpuinfo, which
+/// has one block of `key : value` lines per logical CPU.
+///
+/// The model is the first `model name`, or the `Hardware` line on ARM systems
+/// without one. The physical cores are the distinct pairs of `physical id` and
+/// `core id`, which not all architectures list.
+pub fn parse_cpuinfo(cpuinfo: &str) -> CpuInfo {
+    let mut name = None;
+    let mut hardware = None;
+    let mut logical = 0;
+    let mut cores = HashSet::new();
+    let mut physical_id = None;
+    for line in cpuinfo.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "processor" => logical += 1,
+            "model name" if name.is_none() => name = Some(value.to_owned()),
+            "Hardware" => hardware = Some(value.to_owned()),
+            "physical id" => physical_id = Some(value.to_owned()),
+            "core id" => {
+                cores.insert((physical_id.take(), value.to_owned()));
+            }
+            _ => {}
+        }
+    }
+    CpuInfo {
+        name: name.or(hardware),
+        physical: (!cores.is_empty()).then_some(cores.len() as u32),
+        logical: (logical != 0).then_some(logical),
+    }
+}
+
+/// The total memory in bytes, from the `MemTotal` line of /proc/meminfo.
+pub fn parse_mem_total(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kilobytes = line["MemTotal:".len()..].trim().strip_suffix("kB")?;
+    Some(kilobytes.trim().parse::<u64>().ok()? * 1024)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpuinfo() {
+        let cpuinfo = "\
+processor\t: 0
+vendor_id\t: GenuineIntel
+model name\t: Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz
+physical id\t: 0
+core id\t\t: 0
+
+processor\t: 1
+model name\t: Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz
+physical id\t: 0
+core id\t\t: 1
+
+processor\t: 2
+model name\t: Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz
+physical id\t: 0
+core id\t\t: 0
+";
+        assert_eq!(
+            parse_cpuinfo(cpuinfo),
+            CpuInfo {
+                name: Some("Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz".to_owned()),
+                physical: Some(2),
+                logical: Some(3),
+            }
+        );
+
+        // aarch64 doesn't have a model name or core IDs.
+        let cpuinfo = "processor\t: 0\nBogoMIPS\t: 48.00\n\nprocessor\t: 1\nBogoMIPS\t: 48.00\n";
+        assert_eq!(
+            parse_cpuinfo(cpuinfo),
+            CpuInfo {
+                name: None,
+                physical: None,
+                logical: Some(2),
+            }
+        );
+
+        let cpuinfo = "processor\t: 0\nFeatures\t: half thumb\n\nHardware\t: BCM2835\n";
+        assert_eq!(parse_cpuinfo(cpuinfo).name.as_deref(), Some("BCM2835"));
+    }
+
+    #[test]
+    fn test_parse_mem_total() {
+        let meminfo = "MemTotal:       16310468 kB\nMemFree:         1234567 kB\n";
+        assert_eq!(parse_mem_total(meminfo), Some(16310468 * 1024));
+        assert_eq!(parse_mem_total("MemFree: 1 kB\n"), None);
+    }
+}
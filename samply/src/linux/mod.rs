@@ -1,3 +1,9 @@
+mod allocations;
+mod cgroup;
+mod flight_recorder;
+mod host_info;
+mod pause_signals;
+mod perf_data_writer;
 mod perf_event;
 mod perf_group;
 mod proc_maps;
@@ -5,3 +11,6 @@ mod process;
 pub mod profiler;
 mod sorter;
 mod sys;
+mod trace_markers;
+mod tracefs;
+mod uprobes;
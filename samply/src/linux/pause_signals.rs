@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// A request to pause or resume recording, sent to samply with SIGUSR1 / SIGUSR2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseRequest {
+    Pause,
+    Resume,
+}
+
+const NO_REQUEST: u8 = 0;
+const PAUSE_REQUEST: u8 = 1;
+const RESUME_REQUEST: u8 = 2;
+
+/// Only the most recent signal matters, so a single slot is enough.
+static PENDING_REQUEST: AtomicU8 = AtomicU8::new(NO_REQUEST);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    let request = if signal == libc::SIGUSR1 {
+        PAUSE_REQUEST
+    } else {
+        RESUME_REQUEST
+    };
+    PENDING_REQUEST.store(request, Ordering::SeqCst);
+}
+
+/// Installs the handlers for SIGUSR1 (pause) and SIGUSR2 (resume).
+///
+/// SA_RESTART makes sure that blocking calls on other threads, like waiting
+/// for the launched process, aren't interrupted by these signals.
+pub fn install_handlers() {
+    for signal in [libc::SIGUSR1, libc::SIGUSR2] {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_signal as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Returns the request from the most recent signal, if one arrived since the last call.
+pub fn take_request() -> Option<PauseRequest> {
+    match PENDING_REQUEST.swap(NO_REQUEST, Ordering::SeqCst) {
+        PAUSE_REQUEST => Some(PauseRequest::Pause),
+        RESUME_REQUEST => Some(PauseRequest::Resume),
+        _ => None,
+    }
+}
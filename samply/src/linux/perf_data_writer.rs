@@ -0,0 +1,663 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{NativeEndian, WriteBytesExt};
+use linux_perf_data::linux_perf_event_reader;
+use linux_perf_data::Feature;
+use linux_perf_event_reader::constants::{
+    PERF_RECORD_MISC_BUILD_ID_SIZE, PERF_RECORD_MISC_KERNEL, PERF_RECORD_MISC_USER,
+};
+use linux_perf_event_reader::{
+    CpuMode, EventRecord, Mmap2FileId, Mmap2Record, PerfEventAttr, RawData, RawEventRecord,
+    RecordType, SampleFormat,
+};
+use object::Object;
+
+use super::host_info::{parse_cpuinfo, parse_mem_total};
+use crate::linux_shared::build_id_from_notes_section_data;
+use crate::linux_shared::vdso::VdsoObject;
+
+/// `sizeof(struct perf_file_header)`
+const PERF_FILE_HEADER_SIZE: u64 = 104;
+/// `sizeof(struct perf_file_section)`
+const PERF_FILE_SECTION_SIZE: u64 = 16;
+/// The magic number at the start of the file, "PERFILE2" when written in little endian.
+const PERF_MAGIC2: u64 = 0x32454c4946524550;
+/// Strings in feature sections are padded to a multiple of this size.
+const NAME_ALIGN: usize = 64;
+
+/// An event attribute which will be written into the attr section and the
+/// `HEADER_EVENT_DESC` feature section.
+#[derive(Debug, Clone)]
+pub struct PerfDataAttr {
+    /// The raw bytes of the `perf_event_attr` which was passed to `perf_event_open`.
+    pub attr_bytes: Vec<u8>,
+    /// The event name, e.g. "cycles".
+    pub name: String,
+    /// The event IDs of the perf event fds which were opened with this attr.
+    pub ids: Vec<u64>,
+}
+
+/// Writes the raw records from the perf event ring buffers into a file
+/// in the `perf.data` format, so that the recording can be converted
+/// again later with `samply import`, or inspected with Linux `perf`.
+///
+/// The file layout is:
+///
+///  - The file header, which is rewritten with the final offsets in `finish`.
+///  - The data section with all records, in the order they were passed to us.
+///  - The feature section table, followed by the feature sections.
+///  - The attr section, followed by the event IDs for each attr.
+pub struct PerfDataWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    attrs: Vec<PerfDataAttr>,
+    sample_format: SampleFormat,
+    data_size: u64,
+    sample_time_range: Option<(u64, u64)>,
+    /// The paths of all executable user-space mappings, for the BUILD_ID section.
+    dso_paths: BTreeSet<Vec<u8>>,
+    /// The formats of the recorded tracepoints, for the TRACING_DATA section.
+    tracing_data: Option<Vec<u8>>,
+}
+
+impl PerfDataWriter {
+    /// Creates the file at `path` and prepares it for receiving records.
+    ///
+    /// The first attr determines the layout of the `sample_id` fields in any
+    /// synthesized records.
+    pub fn create(path: &Path, attrs: Vec<PerfDataAttr>) -> io::Result<Self> {
+        let first_attr = attrs
+            .first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "need at least one attr"))?;
+        let (attr, _size) = PerfEventAttr::parse::<_, NativeEndian>(&first_attr.attr_bytes[..])?;
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        // Reserve space for the header. The real header is written in `finish`.
+        writer.write_all(&[0; PERF_FILE_HEADER_SIZE as usize])?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            writer,
+            attrs,
+            sample_format: attr.sample_format,
+            data_size: 0,
+            sample_time_range: None,
+            dso_paths: BTreeSet::new(),
+            tracing_data: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sets the contents of the TRACING_DATA section, see
+    /// [`write_tracing_data`](crate::linux_shared::write_tracing_data).
+    pub fn set_tracing_data(&mut self, tracing_data: Vec<u8>) {
+        self.tracing_data = Some(tracing_data);
+    }
+
+    /// Adds the IDs of newly opened perf event fds to the attr at `attr_index`.
+    /// IDs which are already known are skipped.
+    pub fn add_event_ids(&mut self, attr_index: usize, ids: &[u64]) {
+        let Some(attr) = self.attrs.get_mut(attr_index) else {
+            return;
+        };
+        for &id in ids {
+            if !attr.ids.contains(&id) {
+                attr.ids.push(id);
+            }
+        }
+    }
+
+    /// Appends a record from the ring buffer to the data section, unmodified.
+    pub fn write_record(&mut self, record: &RawEventRecord) -> io::Result<()> {
+        match record.record_type {
+            RecordType::SAMPLE => {
+                if let Some(timestamp) = record.timestamp() {
+                    self.observe_sample_time(timestamp);
+                }
+            }
+            RecordType::MMAP | RecordType::MMAP2 => match record.parse() {
+                Ok(EventRecord::Mmap(e)) if e.is_executable && e.cpu_mode != CpuMode::Kernel => {
+                    self.dso_paths.insert(e.path.as_slice().into_owned());
+                }
+                Ok(EventRecord::Mmap2(e)) => self.observe_mmap2(&e),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        let data_len = record.data.len();
+        self.write_record_header(record.record_type.0, record.misc, data_len)?;
+        match record.data {
+            RawData::Single(data) => self.writer.write_all(data)?,
+            RawData::Split(left, right) => {
+                self.writer.write_all(left)?;
+                self.writer.write_all(right)?;
+            }
+        }
+        self.data_size += (8 + data_len) as u64;
+        Ok(())
+    }
+
+    /// Appends a synthesized COMM record, for a thread which already existed
+    /// when we attached to it.
+    pub fn write_synthesized_comm(
+        &mut self,
+        pid: i32,
+        tid: i32,
+        name: &str,
+        timestamp: u64,
+    ) -> io::Result<()> {
+        let mut data = Vec::new();
+        data.write_i32::<NativeEndian>(pid)?;
+        data.write_i32::<NativeEndian>(tid)?;
+        write_padded_c_string(&mut data, name.as_bytes(), 8);
+        self.write_sample_id(&mut data, pid, tid, timestamp)?;
+        self.write_synthesized_record(RecordType::COMM.0, PERF_RECORD_MISC_USER, &data)
+    }
+
+    /// Appends a synthesized MMAP2 record, for a mapping which already existed
+    /// when we attached to its process.
+    pub fn write_synthesized_mmap2(&mut self, e: &Mmap2Record, timestamp: u64) -> io::Result<()> {
+        self.observe_mmap2(e);
+
+        let mut data = Vec::new();
+        data.write_i32::<NativeEndian>(e.pid)?;
+        data.write_i32::<NativeEndian>(e.tid)?;
+        data.write_u64::<NativeEndian>(e.address)?;
+        data.write_u64::<NativeEndian>(e.length)?;
+        data.write_u64::<NativeEndian>(e.page_offset)?;
+        match &e.file_id {
+            Mmap2FileId::InodeAndVersion(inode) => {
+                data.write_u32::<NativeEndian>(inode.major)?;
+                data.write_u32::<NativeEndian>(inode.minor)?;
+                data.write_u64::<NativeEndian>(inode.inode)?;
+                data.write_u64::<NativeEndian>(inode.inode_generation)?;
+            }
+            Mmap2FileId::BuildId(_) => {
+                // The build ID will be written into the BUILD_ID feature section.
+                data.extend_from_slice(&[0; 24]);
+            }
+        }
+        data.write_u32::<NativeEndian>(e.protection)?;
+        data.write_u32::<NativeEndian>(e.flags)?;
+        write_padded_c_string(&mut data, &e.path.as_slice(), 8);
+        self.write_sample_id(&mut data, e.pid, e.tid, timestamp)?;
+        let misc = match e.cpu_mode {
+            CpuMode::Kernel => PERF_RECORD_MISC_KERNEL,
+            _ => PERF_RECORD_MISC_USER,
+        };
+        self.write_synthesized_record(RecordType::MMAP2.0, misc, &data)
+    }
+
+    /// Writes the feature sections, the attr section and the final file header.
+    pub fn finish(mut self) -> io::Result<()> {
+        let data_offset = PERF_FILE_HEADER_SIZE;
+        let data_size = self.data_size;
+
+        let feature_sections = self.feature_sections()?;
+
+        // The feature section table needs to come directly after the data section.
+        let table_offset = data_offset + data_size;
+        let mut section_offset =
+            table_offset + feature_sections.len() as u64 * PERF_FILE_SECTION_SIZE;
+        for (_feature, section_data) in &feature_sections {
+            let section_size = section_data.len() as u64;
+            self.writer.write_u64::<NativeEndian>(section_offset)?;
+            self.writer.write_u64::<NativeEndian>(section_size)?;
+            section_offset += section_size;
+        }
+        for (_feature, section_data) in &feature_sections {
+            self.writer.write_all(section_data)?;
+        }
+
+        // Write the event IDs, followed by the attr section. Each attr section entry
+        // is a perf_event_attr followed by the file section of its IDs.
+        let mut ids_offset = section_offset;
+        let mut attr_section = Vec::new();
+        let mut attr_entry_size = 0;
+        for attr in &self.attrs {
+            for id in &attr.ids {
+                self.writer.write_u64::<NativeEndian>(*id)?;
+            }
+            let ids_size = attr.ids.len() as u64 * 8;
+            attr_section.extend_from_slice(&attr.attr_bytes);
+            attr_section.write_u64::<NativeEndian>(ids_offset)?;
+            attr_section.write_u64::<NativeEndian>(ids_size)?;
+            attr_entry_size = attr.attr_bytes.len() as u64 + PERF_FILE_SECTION_SIZE;
+            ids_offset += ids_size;
+        }
+        let attr_offset = ids_offset;
+        self.writer.write_all(&attr_section)?;
+
+        let mut feature_bits = [0u64; 4];
+        for (feature, _) in &feature_sections {
+            feature_bits[(feature.0 / 64) as usize] |= 1 << (feature.0 % 64);
+        }
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        let w = &mut self.writer;
+        w.write_u64::<NativeEndian>(PERF_MAGIC2)?;
+        w.write_u64::<NativeEndian>(PERF_FILE_HEADER_SIZE)?;
+        w.write_u64::<NativeEndian>(attr_entry_size)?;
+        // attrs
+        w.write_u64::<NativeEndian>(attr_offset)?;
+        w.write_u64::<NativeEndian>(attr_section.len() as u64)?;
+        // data
+        w.write_u64::<NativeEndian>(data_offset)?;
+        w.write_u64::<NativeEndian>(data_size)?;
+        // event_types, unused
+        w.write_u64::<NativeEndian>(0)?;
+        w.write_u64::<NativeEndian>(0)?;
+        for bits in feature_bits {
+            w.write_u64::<NativeEndian>(bits)?;
+        }
+        w.flush()
+    }
+
+    fn observe_sample_time(&mut self, timestamp: u64) {
+        self.sample_time_range = Some(match self.sample_time_range {
+            Some((first, last)) => (first.min(timestamp), last.max(timestamp)),
+            None => (timestamp, timestamp),
+        });
+    }
+
+    fn observe_mmap2(&mut self, e: &Mmap2Record) {
+        const PROT_EXEC: u32 = 0b100;
+        if e.protection & PROT_EXEC != 0 && e.cpu_mode != CpuMode::Kernel {
+            self.dso_paths.insert(e.path.as_slice().into_owned());
+        }
+    }
+
+    fn write_record_header(
+        &mut self,
+        record_type: u32,
+        misc: u16,
+        data_len: usize,
+    ) -> io::Result<()> {
+        let size = u16::try_from(8 + data_len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "record too large"))?;
+        self.writer.write_u32::<NativeEndian>(record_type)?;
+        self.writer.write_u16::<NativeEndian>(misc)?;
+        self.writer.write_u16::<NativeEndian>(size)
+    }
+
+    fn write_synthesized_record(
+        &mut self,
+        record_type: u32,
+        misc: u16,
+        data: &[u8],
+    ) -> io::Result<()> {
+        self.write_record_header(record_type, misc, data.len())?;
+        self.writer.write_all(data)?;
+        self.data_size += (8 + data.len()) as u64;
+        Ok(())
+    }
+
+    /// Appends the `sample_id` fields which follow non-sample records if
+    /// `sample_id_all` is set. Their layout depends on the attr's sample format.
+    fn write_sample_id(
+        &self,
+        data: &mut Vec<u8>,
+        pid: i32,
+        tid: i32,
+        timestamp: u64,
+    ) -> io::Result<()> {
+        let format = self.sample_format;
+        let id = self.attrs[0].ids.first().copied().unwrap_or(0);
+        if format.contains(SampleFormat::TID) {
+            data.write_i32::<NativeEndian>(pid)?;
+            data.write_i32::<NativeEndian>(tid)?;
+        }
+        if format.contains(SampleFormat::TIME) {
+            data.write_u64::<NativeEndian>(timestamp)?;
+        }
+        if format.contains(SampleFormat::ID) {
+            data.write_u64::<NativeEndian>(id)?;
+        }
+        if format.contains(SampleFormat::STREAM_ID) {
+            data.write_u64::<NativeEndian>(id)?;
+        }
+        if format.contains(SampleFormat::CPU) {
+            data.write_u32::<NativeEndian>(0)?;
+            data.write_u32::<NativeEndian>(0)?;
+        }
+        if format.contains(SampleFormat::IDENTIFIER) {
+            data.write_u64::<NativeEndian>(id)?;
+        }
+        Ok(())
+    }
+
+    /// Collects the contents of all feature sections, ordered by feature bit.
+    fn feature_sections(&self) -> io::Result<Vec<(Feature, Vec<u8>)>> {
+        let mut sections = Vec::new();
+
+        if let Some(tracing_data) = &self.tracing_data {
+            sections.push((Feature::TRACING_DATA, tracing_data.clone()));
+        }
+        sections.push((Feature::BUILD_ID, self.build_id_section()?));
+
+        let uname = uname::uname().ok();
+        if let Some(uname) = &uname {
+            sections.push((Feature::HOSTNAME, header_string(&uname.nodename)));
+            sections.push((Feature::OSRELEASE, header_string(&uname.release)));
+        }
+        let version = format!("samply {}", env!("CARGO_PKG_VERSION"));
+        sections.push((Feature::VERSION, header_string(&version)));
+        if let Some(uname) = &uname {
+            sections.push((Feature::ARCH, header_string(&uname.machine)));
+        }
+
+        let mut nr_cpus = Vec::new();
+        nr_cpus.write_u32::<NativeEndian>(num_cpus::get() as u32)?;
+        nr_cpus.write_u32::<NativeEndian>(num_cpus::get() as u32)?;
+        sections.push((Feature::NRCPUS, nr_cpus));
+
+        let cpu_name = std::fs::read_to_string("/proc/cpuinfo")
+            .ok()
+            .and_then(|cpuinfo| parse_cpuinfo(&cpuinfo).name);
+        if let Some(cpu_name) = cpu_name {
+            sections.push((Feature::CPUDESC, header_string(&cpu_name)));
+        }
+        let total_mem = std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|meminfo| parse_mem_total(&meminfo));
+        if let Some(total_mem) = total_mem {
+            // perf stores the total memory in kB.
+            let total_mem_kb = total_mem / 1024;
+            sections.push((Feature::TOTAL_MEM, total_mem_kb.to_ne_bytes().to_vec()));
+        }
+
+        let args: Vec<String> = std::env::args().collect();
+        let mut cmdline = Vec::new();
+        cmdline.write_u32::<NativeEndian>(args.len() as u32)?;
+        for arg in &args {
+            cmdline.extend_from_slice(&header_string(arg));
+        }
+        sections.push((Feature::CMDLINE, cmdline));
+
+        let mut event_desc = Vec::new();
+        event_desc.write_u32::<NativeEndian>(self.attrs.len() as u32)?;
+        let attr_size = self.attrs[0].attr_bytes.len() as u32;
+        event_desc.write_u32::<NativeEndian>(attr_size)?;
+        for attr in &self.attrs {
+            event_desc.extend_from_slice(&attr.attr_bytes);
+            event_desc.write_u32::<NativeEndian>(attr.ids.len() as u32)?;
+            event_desc.extend_from_slice(&header_string(&attr.name));
+            for id in &attr.ids {
+                event_desc.write_u64::<NativeEndian>(*id)?;
+            }
+        }
+        sections.push((Feature::EVENT_DESC, event_desc));
+
+        if let Some((first, last)) = self.sample_time_range {
+            let mut sample_time = Vec::new();
+            sample_time.write_u64::<NativeEndian>(first)?;
+            sample_time.write_u64::<NativeEndian>(last)?;
+            sections.push((Feature::SAMPLE_TIME, sample_time));
+        }
+
+        Ok(sections)
+    }
+
+    /// Creates the BUILD_ID section, with one `build_id_event` for each
+    /// executable mapping that we've seen, and one for the kernel.
+    fn build_id_section(&self) -> io::Result<Vec<u8>> {
+        let mut section = Vec::new();
+
+        if let Ok(notes) = std::fs::read("/sys/kernel/notes") {
+            if let Some(build_id) = build_id_from_notes_section_data(&notes) {
+                write_build_id_event(
+                    &mut section,
+                    PERF_RECORD_MISC_KERNEL,
+                    build_id,
+                    b"[kernel.kallsyms]",
+                )?;
+            }
+        }
+
+        for path in &self.dso_paths {
+            let build_id = if path == b"[vdso]" {
+                VdsoObject::shared_instance_for_this_process()
+                    .map(|vdso| vdso.build_id().to_owned())
+            } else {
+                read_elf_build_id(path)
+            };
+            if let Some(build_id) = build_id {
+                write_build_id_event(&mut section, PERF_RECORD_MISC_USER, &build_id, path)?;
+            }
+        }
+
+        Ok(section)
+    }
+}
+
+fn read_elf_build_id(path: &[u8]) -> Option<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = Path::new(std::ffi::OsStr::from_bytes(path));
+    let file = File::open(path).ok()?;
+    let mmap = unsafe { memmap2::MmapOptions::new().map(&file) }.ok()?;
+    let object = object::File::parse(&mmap[..]).ok()?;
+    let build_id = object.build_id().ok()??;
+    Some(build_id.to_owned())
+}
+
+/// Writes a `struct build_id_event`, as used in the BUILD_ID feature section.
+fn write_build_id_event(
+    section: &mut Vec<u8>,
+    misc: u16,
+    build_id: &[u8],
+    path: &[u8],
+) -> io::Result<()> {
+    let build_id_len = build_id.len().min(20);
+    let mut build_id_bytes = [0; 24];
+    build_id_bytes[..build_id_len].copy_from_slice(&build_id[..build_id_len]);
+    build_id_bytes[20] = build_id_len as u8;
+
+    let mut path_bytes = Vec::new();
+    write_padded_c_string(&mut path_bytes, path, NAME_ALIGN);
+
+    // header + pid + build ID + filename
+    let size = 8 + 4 + 24 + path_bytes.len();
+    section.write_u32::<NativeEndian>(0)?;
+    section.write_u16::<NativeEndian>(misc | PERF_RECORD_MISC_BUILD_ID_SIZE)?;
+    section.write_u16::<NativeEndian>(size as u16)?;
+    section.write_i32::<NativeEndian>(-1)?;
+    section.extend_from_slice(&build_id_bytes);
+    section.extend_from_slice(&path_bytes);
+    Ok(())
+}
+
+/// Encodes a `struct perf_header_string`: a u32 length followed by the
+/// nul-terminated string, padded to `NAME_ALIGN`.
+fn header_string(s: &str) -> Vec<u8> {
+    let mut padded = Vec::new();
+    write_padded_c_string(&mut padded, s.as_bytes(), NAME_ALIGN);
+    let mut result = Vec::with_capacity(4 + padded.len());
+    result.extend_from_slice(&(padded.len() as u32).to_ne_bytes());
+    result.extend_from_slice(&padded);
+    result
+}
+
+/// Appends `s` plus a nul terminator, padded with zeros to a multiple of `align`.
+fn write_padded_c_string(data: &mut Vec<u8>, s: &[u8], align: usize) {
+    let padded_len = (s.len() + 1).div_ceil(align) * align;
+    data.extend_from_slice(s);
+    data.resize(data.len() + padded_len - s.len(), 0);
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_data::{PerfFileReader, PerfFileRecord};
+    use linux_perf_event_reader::{Endianness, Mmap2InodeAndVersion, RecordParseInfo};
+
+    use super::*;
+    use crate::linux::sys;
+
+    const SAMPLE_TYPE: u64 = sys::PERF_SAMPLE_IDENTIFIER
+        | sys::PERF_SAMPLE_IP
+        | sys::PERF_SAMPLE_TID
+        | sys::PERF_SAMPLE_TIME
+        | sys::PERF_SAMPLE_PERIOD;
+
+    fn attr_bytes(config: u64) -> Vec<u8> {
+        let mut attr: sys::PerfEventAttr = unsafe { std::mem::zeroed() };
+        attr.kind = sys::PERF_TYPE_SOFTWARE;
+        attr.size = std::mem::size_of::<sys::PerfEventAttr>() as u32;
+        attr.config = config;
+        attr.sample_period_or_freq = 1000;
+        attr.sample_type = SAMPLE_TYPE;
+        attr.flags = sys::PERF_ATTR_FLAG_SAMPLE_ID_ALL;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &attr as *const sys::PerfEventAttr as *const u8,
+                std::mem::size_of::<sys::PerfEventAttr>(),
+            )
+        };
+        bytes.to_owned()
+    }
+
+    /// The body of a sample record with the fields of `SAMPLE_TYPE`.
+    fn sample_data(id: u64, ip: u64, pid: i32, tid: i32, timestamp: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.write_u64::<NativeEndian>(id).unwrap();
+        data.write_u64::<NativeEndian>(ip).unwrap();
+        data.write_i32::<NativeEndian>(pid).unwrap();
+        data.write_i32::<NativeEndian>(tid).unwrap();
+        data.write_u64::<NativeEndian>(timestamp).unwrap();
+        data.write_u64::<NativeEndian>(1000).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("perf.data");
+        let cpu_clock = attr_bytes(sys::PERF_COUNT_SW_CPU_CLOCK);
+        let task_clock = attr_bytes(sys::PERF_COUNT_SW_TASK_CLOCK);
+        let attrs = vec![
+            PerfDataAttr {
+                attr_bytes: cpu_clock.clone(),
+                name: "cpu-clock".to_owned(),
+                ids: vec![7],
+            },
+            PerfDataAttr {
+                attr_bytes: task_clock,
+                name: "task-clock".to_owned(),
+                ids: Vec::new(),
+            },
+        ];
+        let mut writer = PerfDataWriter::create(&path, attrs).unwrap();
+        writer.add_event_ids(1, &[8, 9]);
+        writer.add_event_ids(1, &[8]);
+
+        let exe = std::env::current_exe().unwrap();
+        let exe = exe.as_os_str().as_encoded_bytes();
+        writer
+            .write_synthesized_comm(100, 101, "worker", 1000)
+            .unwrap();
+        let mmap = Mmap2Record {
+            pid: 100,
+            tid: 100,
+            address: 0x10000,
+            length: 0x2000,
+            page_offset: 0,
+            file_id: Mmap2FileId::InodeAndVersion(Mmap2InodeAndVersion {
+                major: 0,
+                minor: 0,
+                inode: 0,
+                inode_generation: 0,
+            }),
+            protection: 0b101,
+            flags: 0,
+            cpu_mode: CpuMode::User,
+            path: RawData::Single(exe),
+        };
+        writer.write_synthesized_mmap2(&mmap, 1500).unwrap();
+
+        let (attr, _size) = PerfEventAttr::parse::<_, NativeEndian>(&cpu_clock[..]).unwrap();
+        let parse_info = RecordParseInfo::new(&attr, Endianness::NATIVE);
+        for (id, timestamp) in [(7, 3000), (9, 2000)] {
+            let data = sample_data(id, 0x10100, 100, 101, timestamp);
+            let record = RawEventRecord::new(
+                RecordType::SAMPLE,
+                PERF_RECORD_MISC_USER,
+                RawData::Single(&data),
+                parse_info,
+            );
+            writer.write_record(&record).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(File::open(&path).unwrap()).unwrap();
+
+        let attributes = perf_file.event_attributes();
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(attributes[0].name.as_deref(), Some("cpu-clock"));
+        assert_eq!(attributes[0].event_ids, [7]);
+        assert_eq!(attributes[0].attr.sample_format.bits(), SAMPLE_TYPE);
+        assert_eq!(attributes[1].name.as_deref(), Some("task-clock"));
+        assert_eq!(attributes[1].event_ids, [8, 9]);
+
+        let version = format!("samply {}", env!("CARGO_PKG_VERSION"));
+        assert_eq!(perf_file.perf_version().unwrap(), Some(version.as_str()));
+        let args: Vec<String> = std::env::args().collect();
+        assert_eq!(
+            perf_file.cmdline().unwrap(),
+            Some(args.iter().map(String::as_str).collect())
+        );
+        let time_range = perf_file.sample_time_range().unwrap().unwrap();
+        assert_eq!(time_range.first_sample_time, 2000);
+        assert_eq!(time_range.last_sample_time, 3000);
+        assert!(perf_file.nr_cpus().unwrap().is_some());
+        assert!(perf_file.features().has_feature(Feature::BUILD_ID));
+        // The executable mapping gets a build ID, if the binary has one.
+        let build_ids = perf_file.build_ids().unwrap();
+        let exe_build_id = build_ids
+            .values()
+            .find(|dso| dso.path == exe)
+            .map(|dso| dso.build_id.clone());
+        assert_eq!(exe_build_id, read_elf_build_id(exe));
+
+        // The records come back sorted by time, with the attr of their ID.
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            let PerfFileRecord::EventRecord { attr_index, record } = record else {
+                panic!("unexpected user record");
+            };
+            let timestamp = record.common_data().unwrap().timestamp;
+            let description = match record.parse().unwrap() {
+                EventRecord::Comm(e) => {
+                    let name = String::from_utf8_lossy(&e.name.as_slice()).into_owned();
+                    format!("comm {} {} {name}", e.pid, e.tid)
+                }
+                EventRecord::Mmap2(e) => {
+                    assert_eq!(&*e.path.as_slice(), exe);
+                    format!("mmap2 {} {:#x} {:#x}", e.pid, e.address, e.length)
+                }
+                EventRecord::Sample(e) => {
+                    format!("sample {} {:#x}", e.tid.unwrap(), e.ip.unwrap())
+                }
+                other => panic!("unexpected record {other:?}"),
+            };
+            records.push((attr_index, timestamp, description));
+        }
+        assert_eq!(
+            records,
+            [
+                (0, Some(1000), "comm 100 101 worker".to_owned()),
+                (0, Some(1500), "mmap2 100 0x10000 0x2000".to_owned()),
+                (1, Some(2000), "sample 101 0x10100".to_owned()),
+                (0, Some(3000), "sample 101 0x10100".to_owned()),
+            ]
+        );
+    }
+}
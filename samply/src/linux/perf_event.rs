@@ -1,7 +1,9 @@
 use std::cell::RefCell;
 use std::cmp::max;
 use std::collections::BinaryHeap;
+use std::ffi::CString;
 use std::ops::Range;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::os::unix::io::RawFd;
 use std::rc::Rc;
 use std::sync::atomic::{fence, Ordering};
@@ -86,6 +88,10 @@ pub struct Perf {
     fd: RawFd,
     position: u64,
     parse_info: RecordParseInfo,
+    attr_bytes: Vec<u8>,
+    id: u64,
+    counted_events: Vec<CountedEventFd>,
+    attr_index: usize,
 }
 
 impl Drop for Perf {
@@ -150,19 +156,109 @@ pub enum EventSource {
     SwCpuClock,
 }
 
+impl EventSource {
+    /// The event name as used by Linux perf.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventSource::HwCpuCycles => "cycles",
+            EventSource::SwCpuClock => "cpu-clock",
+        }
+    }
+}
+
+/// A hardware event which is counted, not sampled, in the same perf event group
+/// as the sampling event. Its value is read into every sample.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CountedEvent {
+    Instructions,
+    CacheMisses,
+}
+
+impl CountedEvent {
+    /// The event name as used by Linux perf.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CountedEvent::Instructions => "instructions",
+            CountedEvent::CacheMisses => "cache-misses",
+        }
+    }
+
+    fn config(&self) -> u64 {
+        match self {
+            CountedEvent::Instructions => PERF_COUNT_HW_INSTRUCTIONS,
+            CountedEvent::CacheMisses => PERF_COUNT_HW_CACHE_MISSES,
+        }
+    }
+}
+
+/// A kernel tracepoint whose hits are sampled, together with their raw data.
+#[derive(Clone, Debug)]
+pub struct Tracepoint {
+    /// The name as used by Linux perf, e.g. `kmem:rss_stat`.
+    pub name: String,
+    /// The ID from tracefs, see [`tracepoint_id`](super::tracefs::tracepoint_id).
+    pub id: u64,
+    /// Whether the tracepoint is sampled for all processes, with one event per
+    /// CPU, instead of alongside the sampling event of the profiled processes.
+    /// This is needed for tracepoints which are hit outside of the context of
+    /// the thread which caused them, e.g. in interrupt handlers, and for
+    /// uprobes, whose events can't be inherited by new threads.
+    pub system_wide: bool,
+    /// Whether the samples have the kernel and user stack, from frame pointers.
+    /// This is off for tracepoints whose samples are only turned into counters
+    /// or markers without a stack, e.g. the CPU power tracepoints.
+    pub sample_callchain: bool,
+    /// The uprobe of a `--probe`, which is created through the uprobe PMU
+    /// instead of tracefs. If this is set, `id` is unused.
+    pub uprobe: Option<Uprobe>,
+}
+
+/// A uprobe or uretprobe on an instruction in an ELF file.
+#[derive(Clone, Debug)]
+pub struct Uprobe {
+    /// The `type` of the uprobe PMU, from `/sys/bus/event_source/devices/uprobe/type`.
+    pub pmu_type: u32,
+    /// The `config` of the event, which has the retprobe bit set for uretprobes.
+    pub config: u64,
+    pub path: CString,
+    /// The file offset of the probed instruction.
+    pub offset: u64,
+    /// The user registers which are sampled with each hit, together with
+    /// `argument_stack_size` bytes of the user stack, so that the arguments of
+    /// USDT probes can be read. Zero for function probes.
+    pub argument_regs_mask: u64,
+    pub argument_stack_size: u32,
+}
+
+/// The perf event fd of a [`CountedEvent`], which is a member of the group of a [`Perf`].
+#[derive(Debug)]
+pub struct CountedEventFd {
+    pub id: u64,
+    pub attr_bytes: Vec<u8>,
+    _fd: OwnedFd,
+}
+
 #[derive(Clone, Debug)]
 pub struct PerfBuilder {
     pid: u32,
+    all_processes: bool,
+    cgroup_fd: Option<RawFd>,
     cpu: Option<u32>,
     frequency: u64,
     stack_size: u32,
     reg_mask: u64,
+    callchain: bool,
     event_source: EventSource,
     inherit: bool,
     start_disabled: bool,
     enable_on_exec: bool,
     exclude_kernel: bool,
     gather_context_switches: bool,
+    counted_events: Vec<CountedEvent>,
+    tracepoint_id: Option<u64>,
+    uprobe: Option<Uprobe>,
+    sample_identifier: bool,
+    attr_index: usize,
 }
 
 impl PerfBuilder {
@@ -171,6 +267,20 @@ impl PerfBuilder {
         self
     }
 
+    /// Samples all processes in the cgroup whose directory is opened as `cgroup_fd`,
+    /// instead of a single process. This requires a specific CPU.
+    pub fn cgroup(mut self, cgroup_fd: RawFd) -> Self {
+        self.cgroup_fd = Some(cgroup_fd);
+        self
+    }
+
+    /// Samples all processes, including the kernel's own threads, instead of a
+    /// single process. This requires a specific CPU.
+    pub fn all_processes(mut self) -> Self {
+        self.all_processes = true;
+        self
+    }
+
     pub fn only_cpu(mut self, cpu: u32) -> Self {
         self.cpu = Some(cpu);
         self
@@ -196,6 +306,13 @@ impl PerfBuilder {
         self
     }
 
+    /// Asks the kernel to walk the stack using frame pointers and to put the
+    /// resulting return addresses into each sample.
+    pub fn sample_callchain(mut self, callchain: bool) -> Self {
+        self.callchain = callchain;
+        self
+    }
+
     /// Turns on the kernel measurements. This requires the `/proc/sys/kernel/perf_event_paranoid` to be less than `2`.
     pub fn sample_kernel(mut self) -> Self {
         self.exclude_kernel = false;
@@ -227,12 +344,54 @@ impl PerfBuilder {
         self
     }
 
+    /// Opens counting events for these hardware events in the group of this
+    /// event, and reads their values into every sample.
+    pub fn count_events(mut self, counted_events: &[CountedEvent]) -> Self {
+        self.counted_events = counted_events.to_owned();
+        self
+    }
+
+    /// Samples every hit of the tracepoint with this ID, instead of sampling
+    /// the event source. Tracepoint events don't emit MMAP, COMM, FORK or EXIT
+    /// records; those come from the sampling event.
+    pub fn tracepoint(mut self, tracepoint_id: u64) -> Self {
+        self.tracepoint_id = Some(tracepoint_id);
+        self
+    }
+
+    /// Samples every hit of the tracepoint or of the uprobe, see [`Self::tracepoint`].
+    pub fn trace_event(self, tracepoint: &Tracepoint) -> Self {
+        match &tracepoint.uprobe {
+            Some(uprobe) => Self {
+                uprobe: Some(uprobe.clone()),
+                ..self
+            },
+            None => self.tracepoint(tracepoint.id),
+        }
+    }
+
+    /// Puts the event ID at the start of each sample, so that samples from
+    /// different events can be told apart in perf.data files.
+    pub fn sample_identifier(mut self) -> Self {
+        self.sample_identifier = true;
+        self
+    }
+
+    /// The index of this event among the attrs of the recording, which is
+    /// passed on to every [`EventRef`] from this event.
+    pub fn attr_index(mut self, attr_index: usize) -> Self {
+        self.attr_index = attr_index;
+        self
+    }
+
     pub fn open(self) -> io::Result<Perf> {
         let pid = self.pid;
+        let cgroup_fd = self.cgroup_fd;
         let cpu = self.cpu.map(|cpu| cpu as i32).unwrap_or(-1);
         let frequency = self.frequency;
         let stack_size = self.stack_size;
         let reg_mask = self.reg_mask;
+        let callchain = self.callchain;
         let event_source = self.event_source;
         let inherit = self.inherit;
         let start_disabled = self.start_disabled;
@@ -275,6 +434,13 @@ impl PerfBuilder {
             ));
         }
 
+        if cpu == -1 && cgroup_fd.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cgroup events have to run on a specific cpu",
+            ));
+        }
+
         assert_eq!(mem::size_of::<PerfEventMmapPage>(), 1088);
 
         if cfg!(target_arch = "x86_64") {
@@ -286,12 +452,24 @@ impl PerfBuilder {
         let mut attr: PerfEventAttr = unsafe { mem::zeroed() };
         attr.size = mem::size_of::<PerfEventAttr>() as u32;
 
-        match event_source {
-            EventSource::HwCpuCycles => {
+        let is_trace_event = self.tracepoint_id.is_some() || self.uprobe.is_some();
+        match (&self.uprobe, self.tracepoint_id, event_source) {
+            (Some(uprobe), _, _) => {
+                attr.kind = uprobe.pmu_type;
+                attr.config = uprobe.config;
+                // The kernel copies the path during perf_event_open.
+                attr.bp_addr_or_config = uprobe.path.as_ptr() as u64;
+                attr.bp_len_or_config = uprobe.offset;
+            }
+            (None, Some(tracepoint_id), _) => {
+                attr.kind = PERF_TYPE_TRACEPOINT;
+                attr.config = tracepoint_id;
+            }
+            (None, None, EventSource::HwCpuCycles) => {
                 attr.kind = PERF_TYPE_HARDWARE;
                 attr.config = PERF_COUNT_HW_CPU_CYCLES;
             }
-            EventSource::SwCpuClock => {
+            (None, None, EventSource::SwCpuClock) => {
                 attr.kind = PERF_TYPE_SOFTWARE;
                 attr.config = PERF_COUNT_SW_CPU_CLOCK;
             }
@@ -311,20 +489,42 @@ impl PerfBuilder {
             attr.sample_type |= PERF_SAMPLE_STACK_USER;
         }
 
+        if callchain {
+            attr.sample_type |= PERF_SAMPLE_CALLCHAIN;
+        }
+
+        if is_trace_event {
+            attr.sample_type |= PERF_SAMPLE_RAW;
+        }
+
+        if self.sample_identifier {
+            attr.sample_type |= PERF_SAMPLE_IDENTIFIER;
+        }
+
+        if !self.counted_events.is_empty() {
+            attr.sample_type |= PERF_SAMPLE_READ;
+            attr.read_format = PERF_FORMAT_GROUP | PERF_FORMAT_ID;
+        }
+
         attr.sample_regs_user = reg_mask;
         attr.sample_stack_user = stack_size;
-        attr.sample_period_or_freq = frequency;
         attr.clock_id = libc::CLOCK_MONOTONIC;
 
-        attr.flags = PERF_ATTR_FLAG_DISABLED
-            | PERF_ATTR_FLAG_MMAP
-            | PERF_ATTR_FLAG_MMAP2
-            | PERF_ATTR_FLAG_MMAP_DATA
-            | PERF_ATTR_FLAG_COMM
-            | PERF_ATTR_FLAG_FREQ
-            | PERF_ATTR_FLAG_TASK
-            | PERF_ATTR_FLAG_SAMPLE_ID_ALL
-            | PERF_ATTR_FLAG_USE_CLOCKID;
+        attr.flags =
+            PERF_ATTR_FLAG_DISABLED | PERF_ATTR_FLAG_SAMPLE_ID_ALL | PERF_ATTR_FLAG_USE_CLOCKID;
+
+        if is_trace_event {
+            // Every hit is sampled.
+            attr.sample_period_or_freq = 1;
+        } else {
+            attr.sample_period_or_freq = frequency;
+            attr.flags |= PERF_ATTR_FLAG_MMAP
+                | PERF_ATTR_FLAG_MMAP2
+                | PERF_ATTR_FLAG_MMAP_DATA
+                | PERF_ATTR_FLAG_COMM
+                | PERF_ATTR_FLAG_FREQ
+                | PERF_ATTR_FLAG_TASK;
+        }
 
         if self.enable_on_exec {
             attr.flags |= PERF_ATTR_FLAG_ENABLE_ON_EXEC;
@@ -338,11 +538,19 @@ impl PerfBuilder {
             attr.flags |= PERF_ATTR_FLAG_INHERIT;
         }
 
-        if gather_context_switches {
+        if gather_context_switches && !is_trace_event {
             attr.flags |= PERF_ATTR_FLAG_CONTEX_SWITCH;
         }
 
-        let fd = sys_perf_event_open(&attr, pid as pid_t, cpu as _, -1, PERF_FLAG_FD_CLOEXEC);
+        let (pid, flags) = match cgroup_fd {
+            Some(cgroup_fd) => (
+                cgroup_fd as pid_t,
+                PERF_FLAG_FD_CLOEXEC | PERF_FLAG_PID_CGROUP,
+            ),
+            None if self.all_processes => (-1, PERF_FLAG_FD_CLOEXEC),
+            None => (pid as pid_t, PERF_FLAG_FD_CLOEXEC),
+        };
+        let fd = sys_perf_event_open(&attr, pid, cpu as _, -1, flags);
         if fd == -1 {
             let err = io::Error::last_os_error();
 
@@ -359,6 +567,26 @@ impl PerfBuilder {
             return Err(err);
         }
 
+        let id = match perf_event_id(fd) {
+            Ok(id) => id,
+            Err(err) => {
+                unsafe { libc::close(fd) };
+                return Err(err);
+            }
+        };
+
+        let mut counted_events = Vec::new();
+        for &event in &self.counted_events {
+            match open_counted_event(event, &attr, pid, cpu, fd, flags) {
+                Ok(counted_event) => counted_events.push(counted_event),
+                Err(err) => {
+                    drop(counted_events);
+                    unsafe { libc::close(fd) };
+                    return Err(err);
+                }
+            }
+        }
+
         const STACK_COUNT_PER_BUFFER: u32 = 32;
         let required_space = max(stack_size, 4096) * STACK_COUNT_PER_BUFFER;
         let page_size = 4096;
@@ -385,6 +613,7 @@ impl PerfBuilder {
             );
             if buffer == libc::MAP_FAILED {
                 let err = io::Error::last_os_error();
+                drop(counted_events);
                 libc::close(fd);
                 return Err(io::Error::new(err.kind(), format!("mmap failed: {err}")));
             }
@@ -409,6 +638,10 @@ impl PerfBuilder {
             fd,
             position: 0,
             parse_info,
+            attr_bytes: attr_bytes.to_owned(),
+            id,
+            counted_events,
+            attr_index: self.attr_index,
         };
 
         if !start_disabled {
@@ -419,6 +652,62 @@ impl PerfBuilder {
     }
 }
 
+fn perf_event_id(fd: RawFd) -> io::Result<u64> {
+    let mut id: u64 = 0;
+    let result = unsafe { libc::ioctl(fd, PERF_EVENT_IOC_ID as _, &mut id as *mut u64) };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(id)
+}
+
+/// Opens a counting event as a member of the group of `group_fd`, for the same
+/// target as the group leader, which was opened with `leader_attr`.
+fn open_counted_event(
+    event: CountedEvent,
+    leader_attr: &PerfEventAttr,
+    pid: pid_t,
+    cpu: i32,
+    group_fd: RawFd,
+    flags: libc::c_ulong,
+) -> io::Result<CountedEventFd> {
+    let mut attr: PerfEventAttr = unsafe { mem::zeroed() };
+    attr.size = mem::size_of::<PerfEventAttr>() as u32;
+    attr.kind = PERF_TYPE_HARDWARE;
+    attr.config = event.config();
+    // Group members don't produce samples, but the sample format needs to match
+    // the leader's so that perf.data files with all events can be parsed.
+    attr.sample_type = leader_attr.sample_type;
+    attr.read_format = leader_attr.read_format;
+    attr.sample_regs_user = leader_attr.sample_regs_user;
+    attr.sample_stack_user = leader_attr.sample_stack_user;
+    attr.clock_id = leader_attr.clock_id;
+    attr.flags = leader_attr.flags
+        & (PERF_ATTR_FLAG_INHERIT
+            | PERF_ATTR_FLAG_EXCLUDE_KERNEL
+            | PERF_ATTR_FLAG_SAMPLE_ID_ALL
+            | PERF_ATTR_FLAG_USE_CLOCKID);
+
+    let fd = sys_perf_event_open(&attr, pid, cpu as _, group_fd, flags);
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let id = perf_event_id(fd.as_raw_fd())?;
+
+    let attr_bytes = unsafe {
+        slice::from_raw_parts(
+            &attr as *const PerfEventAttr as *const u8,
+            mem::size_of::<PerfEventAttr>(),
+        )
+    };
+    Ok(CountedEventFd {
+        id,
+        attr_bytes: attr_bytes.to_owned(),
+        _fd: fd,
+    })
+}
+
 impl Perf {
     pub fn max_sample_rate() -> Option<u64> {
         let data = std::fs::read_to_string("/proc/sys/kernel/perf_event_max_sample_rate").ok()?;
@@ -428,16 +717,24 @@ impl Perf {
     pub fn build() -> PerfBuilder {
         PerfBuilder {
             pid: 0,
+            all_processes: false,
+            cgroup_fd: None,
             cpu: None,
             frequency: 0,
             stack_size: 0,
             reg_mask: 0,
+            callchain: false,
             event_source: EventSource::SwCpuClock,
             inherit: false,
             start_disabled: false,
             enable_on_exec: false,
             exclude_kernel: true,
             gather_context_switches: false,
+            counted_events: Vec::new(),
+            tracepoint_id: None,
+            uprobe: None,
+            sample_identifier: false,
+            attr_index: 0,
         }
     }
 
@@ -447,12 +744,38 @@ impl Perf {
         assert!(result != -1);
     }
 
+    pub fn disable(&mut self) {
+        let result = unsafe { libc::ioctl(self.fd, PERF_EVENT_IOC_DISABLE as _) };
+
+        assert!(result != -1);
+    }
+
     #[inline]
     pub fn are_events_pending(&self) -> bool {
         let head = unsafe { read_head(self.buffer) };
         head != self.position
     }
 
+    /// The raw bytes of the `perf_event_attr` this event was opened with.
+    pub fn attr_bytes(&self) -> &[u8] {
+        &self.attr_bytes
+    }
+
+    /// The event ID which the kernel assigned to this event.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The counting events in the group of this event.
+    pub fn counted_events(&self) -> &[CountedEventFd] {
+        &self.counted_events
+    }
+
+    /// The attr index which was given to [`PerfBuilder::attr_index`].
+    pub fn attr_index(&self) -> usize {
+        self.attr_index
+    }
+
     #[inline]
     pub fn fd(&self) -> RawFd {
         self.fd
@@ -526,6 +849,7 @@ pub struct EventRef {
     prev_position: u64,
     position: u64,
     parse_info: RecordParseInfo,
+    attr_index: usize,
 }
 
 impl fmt::Debug for EventRef {
@@ -553,6 +877,11 @@ impl EventRef {
 
         self.event_location.get(buffer, self.parse_info)
     }
+
+    /// The attr index of the event which produced this record.
+    pub fn attr_index(&self) -> usize {
+        self.attr_index
+    }
 }
 
 pub struct EventIter<'a> {
@@ -582,6 +911,7 @@ impl Iterator for EventIter<'_> {
             prev_position,
             position: perf.position,
             parse_info: self.perf.parse_info,
+            attr_index: self.perf.attr_index,
         })
     }
 }
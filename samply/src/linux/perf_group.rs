@@ -1,7 +1,8 @@
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::ops::{Deref, DerefMut};
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
 use std::time::Duration;
 use std::{fs, io};
 
@@ -10,7 +11,7 @@ use linux_perf_data::linux_perf_event_reader::get_record_timestamp;
 use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Token};
 
-use super::perf_event::{EventRef, EventSource, Perf};
+use super::perf_event::{CountedEvent, EventRef, EventSource, Perf, PerfBuilder, Tracepoint};
 use super::sorter::EventSorter;
 
 struct StoppedProcess(u32);
@@ -71,8 +72,15 @@ pub struct PerfGroup {
     frequency: u32,
     stack_size: u32,
     regs_mask: u64,
+    sample_callchain: bool,
     event_source: EventSource,
+    counted_events: Vec<CountedEvent>,
+    tracepoints: Vec<Tracepoint>,
     stopped_processes: Vec<StoppedProcess>,
+    pids: Vec<u32>,
+    event_attr_bytes: Option<Vec<u8>>,
+    counted_event_attr_bytes: Vec<Vec<u8>>,
+    tracepoint_attr_bytes: Vec<Option<Vec<u8>>>,
 }
 
 fn get_threads(pid: u32) -> Result<Vec<u32>, io::Error> {
@@ -91,6 +99,43 @@ fn get_threads(pid: u32) -> Result<Vec<u32>, io::Error> {
     Ok(tids)
 }
 
+/// Returns the CPUs which are online, from /sys/devices/system/cpu/online.
+/// CPU numbers can have gaps, e.g. when CPUs were taken offline.
+pub fn online_cpus() -> Result<Vec<u32>, io::Error> {
+    let online = fs::read_to_string("/sys/devices/system/cpu/online")?;
+    parse_cpu_list(online.trim()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid CPU list {online:?}"),
+        )
+    })
+}
+
+/// Parses a CPU list in the kernel's format, e.g. `0-3,5,7-8`.
+fn parse_cpu_list(list: &str) -> Option<Vec<u32>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => cpus.extend(start.parse::<u32>().ok()?..=end.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Whether the member is an event of a tracepoint which is sampled for all
+/// processes.
+fn is_system_wide_tracepoint(
+    tracepoints: &[Tracepoint],
+    counted_events: &[CountedEvent],
+    member: &Member,
+) -> bool {
+    member
+        .attr_index()
+        .checked_sub(1 + counted_events.len())
+        .is_some_and(|i| tracepoints[i].system_wide)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AttachMode {
     AttachWithEnableOnExec,
@@ -98,7 +143,15 @@ pub enum AttachMode {
 }
 
 impl PerfGroup {
-    pub fn new(frequency: u32, stack_size: u32, regs_mask: u64, event_source: EventSource) -> Self {
+    pub fn new(
+        frequency: u32,
+        stack_size: u32,
+        regs_mask: u64,
+        sample_callchain: bool,
+        event_source: EventSource,
+        counted_events: &[CountedEvent],
+        tracepoints: &[Tracepoint],
+    ) -> Self {
         PerfGroup {
             event_sorter: EventSorter::new(),
             members: Default::default(),
@@ -108,20 +161,40 @@ impl PerfGroup {
             stack_size,
             event_source,
             regs_mask,
+            sample_callchain,
+            counted_events: counted_events.to_owned(),
+            tracepoints: tracepoints.to_owned(),
             stopped_processes: Vec::new(),
+            pids: Vec::new(),
+            event_attr_bytes: None,
+            counted_event_attr_bytes: Vec::new(),
+            tracepoint_attr_bytes: vec![None; tracepoints.len()],
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn open(
         pid: u32,
         frequency: u32,
         stack_size: u32,
         event_source: EventSource,
         regs_mask: u64,
+        sample_callchain: bool,
+        counted_events: &[CountedEvent],
+        tracepoints: &[Tracepoint],
         attach_mode: AttachMode,
     ) -> Result<Self, io::Error> {
-        let mut group = PerfGroup::new(frequency, stack_size, regs_mask, event_source);
+        let mut group = PerfGroup::new(
+            frequency,
+            stack_size,
+            regs_mask,
+            sample_callchain,
+            event_source,
+            counted_events,
+            tracepoints,
+        );
         group.open_process(pid, attach_mode)?;
+        group.open_system_wide_tracepoints()?;
         Ok(group)
     }
 
@@ -140,9 +213,11 @@ impl PerfGroup {
                 .frequency(self.frequency as u64)
                 .sample_user_stack(self.stack_size)
                 .sample_user_regs(self.regs_mask)
+                .sample_callchain(self.sample_callchain)
                 .sample_kernel()
                 .gather_context_switches()
                 .event_source(self.event_source)
+                .count_events(&self.counted_events)
                 .inherit_to_children()
                 .start_disabled();
 
@@ -150,9 +225,9 @@ impl PerfGroup {
                 builder = builder.enable_on_exec();
             }
 
-            let perf = builder.open()?;
-
-            perf_events.push((Some(cpu), perf));
+            for perf in self.open_with_tracepoints(builder)? {
+                perf_events.push((Some(cpu), perf));
+            }
         }
 
         if cpu_count * (threads.len() + 1) >= 1000 {
@@ -163,15 +238,17 @@ impl PerfGroup {
                     .frequency(self.frequency as u64)
                     .sample_user_stack(self.stack_size)
                     .sample_user_regs(self.regs_mask)
+                    .sample_callchain(self.sample_callchain)
                     .sample_kernel()
                     .event_source(self.event_source)
+                    .count_events(&self.counted_events)
                     .start_disabled();
                 if attach_mode == AttachMode::AttachWithEnableOnExec {
                     builder = builder.enable_on_exec();
                 }
-                let perf = builder.open()?;
-
-                perf_events.push((None, perf));
+                for perf in self.open_with_tracepoints(builder)? {
+                    perf_events.push((None, perf));
+                }
             }
         } else {
             for cpu in 0..cpu_count as u32 {
@@ -182,36 +259,221 @@ impl PerfGroup {
                         .frequency(self.frequency as u64)
                         .sample_user_stack(self.stack_size)
                         .sample_user_regs(self.regs_mask)
+                        .sample_callchain(self.sample_callchain)
                         .sample_kernel()
                         .gather_context_switches()
                         .event_source(self.event_source)
+                        .count_events(&self.counted_events)
                         .inherit_to_children()
                         .start_disabled();
                     if attach_mode == AttachMode::AttachWithEnableOnExec {
                         builder = builder.enable_on_exec();
                     }
-                    let perf = builder.open()?;
-
-                    perf_events.push((Some(cpu), perf));
+                    for perf in self.open_with_tracepoints(builder)? {
+                        perf_events.push((Some(cpu), perf));
+                    }
                 }
             }
         }
 
         for (_cpu, perf) in perf_events {
-            let fd = perf.fd();
-            self.members.insert(fd, Member::new(perf));
-            self.poll.registry().register(
-                &mut SourceFd(&fd),
-                Token(fd as usize),
-                Interest::READABLE,
-            )?;
+            self.add_member(perf)?;
+        }
+
+        self.pids.push(pid);
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn open_cgroup(
+        cgroup_path: &Path,
+        frequency: u32,
+        stack_size: u32,
+        event_source: EventSource,
+        regs_mask: u64,
+        sample_callchain: bool,
+        counted_events: &[CountedEvent],
+        tracepoints: &[Tracepoint],
+    ) -> Result<Self, io::Error> {
+        let mut group = PerfGroup::new(
+            frequency,
+            stack_size,
+            regs_mask,
+            sample_callchain,
+            event_source,
+            counted_events,
+            tracepoints,
+        );
+        // The kernel keeps its own reference to the cgroup, so the directory
+        // doesn't need to stay open once the events are created.
+        let cgroup_dir = fs::File::open(cgroup_path)?;
+
+        // Processes in a cgroup can only be sampled with per-CPU events, and
+        // processes which enter the cgroup later are covered automatically.
+        for cpu in online_cpus()? {
+            let builder = Perf::build()
+                .cgroup(cgroup_dir.as_raw_fd())
+                .only_cpu(cpu)
+                .frequency(group.frequency as u64)
+                .sample_user_stack(group.stack_size)
+                .sample_user_regs(group.regs_mask)
+                .sample_callchain(group.sample_callchain)
+                .sample_kernel()
+                .gather_context_switches()
+                .event_source(group.event_source)
+                .count_events(&group.counted_events)
+                .start_disabled();
+            for perf in group.open_with_tracepoints(builder)? {
+                group.add_member(perf)?;
+            }
+        }
+        group.open_system_wide_tracepoints()?;
+
+        Ok(group)
+    }
+
+    /// Opens the sampling event from `builder`, followed by an event for each
+    /// tracepoint with the same target.
+    fn open_with_tracepoints(&self, builder: PerfBuilder) -> Result<Vec<Perf>, io::Error> {
+        if self.tracepoints.is_empty() {
+            return Ok(vec![builder.open()?]);
+        }
+
+        // With several events in the perf.data file, the samples need an ID.
+        let builder = builder.sample_identifier();
+        let first_tracepoint_attr_index = 1 + self.counted_events.len();
+        let mut perf_events = vec![builder.clone().open()?];
+        for (i, tracepoint) in self.tracepoints.iter().enumerate() {
+            if tracepoint.system_wide {
+                continue;
+            }
+            // The stacks of tracepoint hits only come from frame pointers, because
+            // some tracepoints are hit much more often than we sample.
+            let tracepoint_builder = builder
+                .clone()
+                .trace_event(tracepoint)
+                .attr_index(first_tracepoint_attr_index + i)
+                .sample_user_stack(0)
+                .sample_user_regs(0)
+                .sample_callchain(tracepoint.sample_callchain)
+                .count_events(&[]);
+            perf_events.push(tracepoint_builder.open()?);
         }
+        Ok(perf_events)
+    }
 
+    /// Opens an event on each online CPU for the tracepoints which are sampled
+    /// for all processes. They start disabled, like the other events, but
+    /// `enable_on_exec` only applies to the events of the exec'ing process, so
+    /// they're enabled with [`PerfGroup::enable_system_wide_tracepoints`] before
+    /// the launched process runs.
+    fn open_system_wide_tracepoints(&mut self) -> Result<(), io::Error> {
+        let first_tracepoint_attr_index = 1 + self.counted_events.len();
+        let tracepoints = self.tracepoints.clone();
+        if !tracepoints.iter().any(|tracepoint| tracepoint.system_wide) {
+            return Ok(());
+        }
+        let cpus = online_cpus()?;
+        for (i, tracepoint) in tracepoints.iter().enumerate() {
+            if !tracepoint.system_wide {
+                continue;
+            }
+            // USDT probes sample the registers and the top of the stack to read
+            // their arguments.
+            let (stack_size, regs_mask) = match &tracepoint.uprobe {
+                Some(uprobe) => (uprobe.argument_stack_size, uprobe.argument_regs_mask),
+                None => (0, 0),
+            };
+            for &cpu in &cpus {
+                let perf = Perf::build()
+                    .all_processes()
+                    .only_cpu(cpu)
+                    .sample_user_stack(stack_size)
+                    .sample_user_regs(regs_mask)
+                    .sample_callchain(tracepoint.sample_callchain)
+                    .sample_kernel()
+                    .trace_event(tracepoint)
+                    .sample_identifier()
+                    .attr_index(first_tracepoint_attr_index + i)
+                    .start_disabled()
+                    .open()?;
+                self.add_member(perf)?;
+            }
+        }
         Ok(())
     }
 
+    fn add_member(&mut self, perf: Perf) -> Result<(), io::Error> {
+        let tracepoint_index = perf.attr_index().checked_sub(1 + self.counted_events.len());
+        if let Some(tracepoint_index) = tracepoint_index {
+            self.tracepoint_attr_bytes[tracepoint_index]
+                .get_or_insert_with(|| perf.attr_bytes().to_owned());
+        } else if self.event_attr_bytes.is_none() {
+            self.event_attr_bytes = Some(perf.attr_bytes().to_owned());
+            self.counted_event_attr_bytes = perf
+                .counted_events()
+                .iter()
+                .map(|counted_event| counted_event.attr_bytes.clone())
+                .collect();
+        }
+        let fd = perf.fd();
+        self.members.insert(fd, Member::new(perf));
+        self.poll
+            .registry()
+            .register(&mut SourceFd(&fd), Token(fd as usize), Interest::READABLE)
+    }
+
+    pub fn event_source(&self) -> EventSource {
+        self.event_source
+    }
+
+    /// The `perf_event_attr` of the first opened event, as raw bytes. All events
+    /// in this group share the same sample format.
+    pub fn event_attr_bytes(&self) -> Option<&[u8]> {
+        self.event_attr_bytes.as_deref()
+    }
+
+    /// The events which are counted in the group of each sampling event.
+    pub fn counted_events(&self) -> &[CountedEvent] {
+        &self.counted_events
+    }
+
+    /// The `perf_event_attr` of each counted event, as raw bytes.
+    pub fn counted_event_attr_bytes(&self) -> &[Vec<u8>] {
+        &self.counted_event_attr_bytes
+    }
+
+    /// The tracepoints which are sampled alongside the sampling event.
+    pub fn tracepoints(&self) -> &[Tracepoint] {
+        &self.tracepoints
+    }
+
+    /// The `perf_event_attr` of each tracepoint event, as raw bytes.
+    pub fn tracepoint_attr_bytes(&self) -> impl Iterator<Item = &[u8]> {
+        self.tracepoint_attr_bytes
+            .iter()
+            .map(|attr_bytes| attr_bytes.as_deref().unwrap_or_default())
+    }
+
+    /// The IDs of the open perf events, for the sampling event (attr index 0),
+    /// followed by the IDs for each counted event and for each tracepoint.
+    pub fn event_ids_by_attr_index(&self) -> Vec<Vec<u64>> {
+        let mut ids = vec![Vec::new(); 1 + self.counted_events.len() + self.tracepoints.len()];
+        for member in self.members.values() {
+            ids[member.attr_index()].push(member.id());
+            for (i, counted_event) in member.counted_events().iter().enumerate() {
+                ids[1 + i].push(counted_event.id);
+            }
+        }
+        ids
+    }
+
+    /// Whether all events of the profiled processes have been closed. The
+    /// events of system-wide tracepoints stay open after the processes exit.
     pub fn is_empty(&self) -> bool {
-        self.members.is_empty()
+        self.members.values().all(|member| {
+            is_system_wide_tracepoint(&self.tracepoints, &self.counted_events, member)
+        })
     }
 
     pub fn enable(&mut self) {
@@ -222,6 +484,29 @@ impl PerfGroup {
         self.stopped_processes.clear();
     }
 
+    /// Enables the events of the system-wide tracepoints, which don't get
+    /// enabled when the launched process execs.
+    pub fn enable_system_wide_tracepoints(&mut self) {
+        for member in self.members.values_mut() {
+            if is_system_wide_tracepoint(&self.tracepoints, &self.counted_events, member) {
+                member.enable();
+            }
+        }
+    }
+
+    /// Stops sampling. Child events which were inherited by forked processes and
+    /// threads are disabled as well.
+    pub fn disable(&mut self) {
+        for perf in self.members.values_mut() {
+            perf.disable();
+        }
+    }
+
+    /// The pids of the processes which were passed to `open_process`.
+    pub fn pids(&self) -> &[u32] {
+        &self.pids
+    }
+
     pub fn wait(&mut self) {
         for member in self.members.values() {
             if member.are_events_pending() {
@@ -233,7 +518,10 @@ impl PerfGroup {
             .poll
             .poll(&mut self.poll_events, Some(Duration::from_millis(100)));
         if let Err(err) = result {
-            eprintln!("poll failed: {err}");
+            // Signals, e.g. the ones for pausing and resuming, interrupt the poll.
+            if err.kind() != io::ErrorKind::Interrupted {
+                eprintln!("poll failed: {err}");
+            }
             return;
         }
 
@@ -295,3 +583,77 @@ impl PerfGroup {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use linux_perf_data::linux_perf_event_reader::EventRecord;
+
+    use super::*;
+    use crate::linux::uprobes::open_uprobes;
+
+    #[no_mangle]
+    #[inline(never)]
+    extern "C" fn samply_test_probed_function(value: u32) -> u32 {
+        std::hint::black_box(value) + 1
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0"), Some(vec![0]));
+        assert_eq!(parse_cpu_list("0-3,5,7-8"), Some(vec![0, 1, 2, 3, 5, 7, 8]));
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    /// Threads which are created after the events were opened need to hit the
+    /// uprobes, and keep their other inherited events. This needs the uprobe
+    /// PMU and a perf_event_paranoid level of -1.
+    #[test]
+    #[ignore]
+    fn test_uprobes_in_new_threads() {
+        let exe = std::env::current_exe().unwrap();
+        let probe = format!("{}:samply_test_probed_function", exe.display());
+        let tracepoints = open_uprobes(&[probe]).unwrap();
+        let mut group = PerfGroup::open(
+            std::process::id(),
+            1000,
+            0,
+            EventSource::SwCpuClock,
+            0,
+            false,
+            &[],
+            &tracepoints,
+            AttachMode::AttachWithEnableOnExec,
+        )
+        .unwrap();
+        group.enable();
+
+        let tid = std::thread::spawn(|| {
+            for i in 0..10 {
+                samply_test_probed_function(i);
+            }
+            // Keep the thread on the CPU for a while, so that it gets sampled.
+            let start = std::time::Instant::now();
+            while start.elapsed() < Duration::from_millis(50) {
+                std::hint::black_box(start);
+            }
+            unsafe { libc::gettid() }
+        })
+        .join()
+        .unwrap();
+        group.disable();
+
+        let mut counts_by_attr_index = [0; 3];
+        group.consume_events(&mut |event| {
+            if let Ok(EventRecord::Sample(sample)) = event.get().parse() {
+                if sample.tid == Some(tid) {
+                    counts_by_attr_index[event.attr_index()] += 1;
+                }
+            }
+        });
+        let [samples, entries, returns] = counts_by_attr_index;
+        assert!(samples > 0);
+        assert_eq!(entries, 10);
+        assert_eq!(returns, 10);
+    }
+}
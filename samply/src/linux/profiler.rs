@@ -1,30 +1,46 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::Deref;
 use std::os::unix::process::ExitStatusExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use crossbeam_channel::{Receiver, Sender};
 use fxprof_processed_profile::{Profile, ReferenceTimestamp};
 use linux_perf_data::linux_perf_event_reader::{
     CpuMode, Endianness, EventRecord, Mmap2FileId, Mmap2InodeAndVersion, Mmap2Record, RawData,
+    RawEventRecord, RecordType,
 };
 use nix::sys::wait::WaitStatus;
 use tokio::sync::oneshot;
-
-use super::perf_event::EventSource;
+use wholesym::samply_symbols::SdtArgument;
+
+use super::allocations::{AllocationEvent, AllocationEvents, AllocationRecorder};
+use super::cgroup::resolve_cgroup_path;
+use super::flight_recorder::{FlightRecorder, LiveConverter};
+use super::host_info::current_host_info;
+use super::pause_signals::{self, PauseRequest};
+use super::perf_data_writer::{PerfDataAttr, PerfDataWriter};
+use super::perf_event::{CountedEvent, EventSource, Perf, Tracepoint};
 use super::perf_group::{AttachMode, PerfGroup};
 use super::proc_maps;
 use super::process::SuspendedLaunchedProcess;
+use super::sys::clock_monotonic_nanos;
+use super::trace_markers::{TraceMarkerEvent, TraceMarkerRecorder};
+use super::tracefs::{find_tracefs, tracepoint_format, tracepoint_id};
+use super::uprobes::{launched_binary, open_uprobes, open_usdt_probes};
 use crate::linux_shared::vdso::VdsoObject;
 use crate::linux_shared::{
-    ConvertRegs, Converter, EventInterpretation, MmapRangeOrVec, OffCpuIndicator,
+    split_off_group_read, write_tracing_data, ConvertRegs, Converter, EventInterpretation,
+    GroupReadValues, KnownEvent, MmapRangeOrVec, OffCpuIndicator, TracepointFormat,
 };
 use crate::shared::ctrl_c::CtrlC;
+use crate::shared::included_processes::IncludedProcesses;
+use crate::shared::iteration_times::IterationTimes;
 use crate::shared::prop_types::{
-    ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
+    CallGraphMode, FlightRecorderProps, IterationProcesses, ProcessLaunchProps,
+    ProfileCreationProps, RecordingMode, RecordingProps,
 };
 
 #[cfg(target_arch = "x86_64")]
@@ -36,17 +52,76 @@ pub type ConvertRegsNative = crate::linux_shared::ConvertRegsAarch64;
 pub fn run(
     recording_mode: RecordingMode,
     recording_props: RecordingProps,
-    profile_creation_props: ProfileCreationProps,
+    mut profile_creation_props: ProfileCreationProps,
 ) -> Result<(Profile, ExitStatus), ()> {
+    pause_signals::install_handlers();
+    if recording_props.start_paused {
+        eprintln!(
+            "Recording is paused. Send SIGUSR2 to samply (pid {}) to start recording, and SIGUSR1 to pause it again.",
+            std::process::id()
+        );
+    }
+
     let process_launch_props = match recording_mode {
         RecordingMode::All => {
             // TODO: Implement, by sudo launching a helper process which opens cpu-wide perf events
             eprintln!("Error: Profiling all processes is currently not supported on Linux.");
-            eprintln!("You can profile processes which you launch via samply, or attach to a single process.");
+            eprintln!("You can profile processes which you launch via samply, or attach to existing processes.");
             std::process::exit(1)
         }
-        RecordingMode::Pid(pid) => {
-            let profile = start_profiling_pid(pid, recording_props, profile_creation_props);
+        RecordingMode::Pid(pids) => {
+            let profile = start_profiling_existing(
+                PerfTarget::Processes(pids),
+                None,
+                recording_props,
+                profile_creation_props,
+            );
+            return Ok((profile, ExitStatus::from_raw(0)));
+        }
+        RecordingMode::ProcessName {
+            included_processes,
+            include_new_processes,
+        } => {
+            let mut pids = find_matching_processes(&included_processes);
+            if pids.is_empty() {
+                if !include_new_processes {
+                    eprintln!(
+                        "Error: No running process matches {:?}.",
+                        included_processes.name_substrings
+                    );
+                    std::process::exit(1)
+                }
+                eprintln!(
+                    "Waiting for a process matching {:?}...",
+                    included_processes.name_substrings
+                );
+                while pids.is_empty() {
+                    thread::sleep(NEW_PROCESS_POLL_INTERVAL);
+                    pids = find_matching_processes(&included_processes);
+                }
+            }
+            let profile = start_profiling_existing(
+                PerfTarget::Processes(pids),
+                include_new_processes.then_some(included_processes),
+                recording_props,
+                profile_creation_props,
+            );
+            return Ok((profile, ExitStatus::from_raw(0)));
+        }
+        RecordingMode::Cgroup(path) => {
+            let path = match resolve_cgroup_path(&path) {
+                Ok(path) => path,
+                Err(err) => {
+                    eprintln!("Error: Could not find cgroup {}: {err}", path.display());
+                    std::process::exit(1)
+                }
+            };
+            let profile = start_profiling_existing(
+                PerfTarget::Cgroup(path),
+                None,
+                recording_props,
+                profile_creation_props,
+            );
             return Ok((profile, ExitStatus::from_raw(0)));
         }
         RecordingMode::Launch(process_launch_props) => process_launch_props,
@@ -60,8 +135,14 @@ pub fn run(
         args,
         iteration_count,
         ignore_exit_code,
+        iteration_processes,
     } = process_launch_props;
 
+    if iteration_count > 1 && iteration_processes == IterationProcesses::Merged {
+        // The processes of the iterations are merged by recycling them by name.
+        profile_creation_props.reuse_threads = true;
+    }
+
     if profile_creation_props.coreclr.any_enabled() {
         // We need to set DOTNET_PerfMapEnabled=2 in the environment if it's not already set.
         // TODO: implement unlink_aux_files for linux
@@ -70,6 +151,36 @@ pub fn run(
         }
     }
 
+    let tracepoints = match recording_tracepoints(TracepointOptions::new(
+        &recording_props,
+        &profile_creation_props,
+    )) {
+        Ok(tracepoints) => tracepoints,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            std::process::exit(1)
+        }
+    };
+
+    // Inject our preload library if we're recording allocations.
+    let (mut allocation_recorder, allocation_events) =
+        match recording_props.allocation_sampling_interval {
+            Some(sampling_interval) => {
+                match AllocationRecorder::new(sampling_interval, recording_props.stack_size) {
+                    Ok((recorder, events)) => {
+                        let recorder_env_vars = recorder.env_vars(&env_vars);
+                        env_vars.extend(recorder_env_vars);
+                        (Some(recorder), Some(events))
+                    }
+                    Err(error) => {
+                        eprintln!("Error: Could not set up allocation recording: {error}");
+                        std::process::exit(1)
+                    }
+                }
+            }
+            None => (None, None),
+        };
+
     // Ignore Ctrl+C while the subcommand is running. The signal still reaches the process
     // under observation while we continue to record it. (ctrl+c will send the SIGINT signal
     // to all processes in the foreground process group).
@@ -92,6 +203,20 @@ pub fn run(
     // Launch the observer thread. This thread will manage the perf events.
     let interval = recording_props.interval;
     let time_limit = recording_props.time_limit;
+    let perf_data_path = recording_props.perf_data_path;
+    let call_graph = recording_props.call_graph;
+    let stack_size = recording_props.stack_size;
+    let start_paused = recording_props.start_paused;
+    let trace_markers = recording_props.trace_markers;
+    let counted_events = counted_events(recording_props.hardware_counters);
+    let memory = recording_props.memory;
+    let cpu_power = recording_props.cpu_power;
+    let custom_tracepoint_names = recording_props.tracepoints;
+    let probes = recording_props.probes;
+    let usdt = recording_props.usdt;
+    let flight_recorder_props = recording_props.flight_recorder;
+    let output_file = recording_props.output_file;
+    let usdt_binary = launched_binary(&command_name);
     let initial_exec_name = command_name.to_string_lossy().to_string();
     let initial_cmdline: Vec<String> = std::iter::once(initial_exec_name.clone())
         .chain(args.iter().map(|arg| arg.to_string_lossy().to_string()))
@@ -102,7 +227,35 @@ pub fn run(
     };
     let initial_exec_name_and_cmdline = (initial_exec_name, initial_cmdline);
     let observer_thread = thread::spawn(move || {
-        let mut converter = make_converter(interval, profile_creation_props);
+        let mut tracepoints = tracepoints;
+        tracepoints.extend(custom_tracepoints(&custom_tracepoint_names));
+        tracepoints.extend(probe_tracepoints(&probes));
+        let usdt_arguments = add_usdt_tracepoints(
+            &usdt,
+            usdt_binary.as_deref(),
+            &counted_events,
+            &mut tracepoints,
+        );
+        let poll_statm = memory && !has_rss_stat_tracepoint(&tracepoints);
+        let cpufreq_polling = cpufreq_polling(cpu_power, &tracepoints);
+        let interpretation = live_event_interpretation(interval, &counted_events, &tracepoints);
+        let flight_recorder = flight_recorder_props.map(|props| {
+            make_flight_recorder(
+                props,
+                &output_file,
+                &interpretation,
+                &profile_creation_props,
+                &usdt_arguments,
+                &tracepoints,
+            )
+        });
+        let mut converter = make_converter(interpretation.clone(), profile_creation_props);
+        for (attr_index, arguments) in usdt_arguments {
+            converter.set_usdt_arguments(attr_index, arguments);
+        }
+        if iteration_count > 1 {
+            converter.record_iterations(iteration_processes == IterationProcesses::Separate);
+        }
 
         // Wait for the initial pid to profile.
         let SamplerRequest::StartProfilingAnotherProcess(pid, attach_mode) =
@@ -110,9 +263,22 @@ pub fn run(
         else {
             panic!("The first message should be a StartProfilingAnotherProcess")
         };
+        converter.launch_iteration(pid as i32);
+
+        let trace_marker_recorder = start_trace_marker_recorder(trace_markers);
 
         // Create the perf events, setting ENABLE_ON_EXEC.
-        let perf_group = init_profiler(interval, pid, attach_mode, &mut converter);
+        let (perf_group, perf_data_writer) = init_profiler(
+            interval,
+            &PerfTarget::Processes(vec![pid]),
+            attach_mode,
+            call_graph,
+            stack_size,
+            &counted_events,
+            &tracepoints,
+            perf_data_path.as_deref(),
+            &mut converter,
+        );
 
         // Tell the main thread to tell the child process to begin executing.
         profile_another_pid_reply_sender.send(true).unwrap();
@@ -128,11 +294,19 @@ pub fn run(
         run_profiler(
             perf_group,
             converter,
+            perf_data_writer,
+            start_paused,
             time_limit,
             profile_another_pid_request_receiver,
             profile_another_pid_reply_sender,
             stop_receiver,
             Some(initial_exec_name_and_cmdline),
+            allocation_events,
+            trace_marker_recorder,
+            interpretation,
+            poll_statm,
+            cpufreq_polling,
+            flight_recorder,
         )
     });
 
@@ -148,6 +322,8 @@ pub fn run(
     let _ = profile_another_pid_reply_receiver.recv().unwrap();
 
     // Now tell the child process to start executing.
+    let mut iteration_times = IterationTimes::new(iteration_count);
+    let start = Instant::now();
     let process = match process.unsuspend_and_run() {
         Ok(process) => process,
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
@@ -170,6 +346,7 @@ pub fn run(
     // Wait for the child process to quit.
     // This is where the main thread spends all its time during profiling.
     let mut wait_status = process.wait().unwrap();
+    iteration_times.add(start.elapsed());
 
     for i in 2..=iteration_count {
         let previous_run_exited_with_success = match &wait_status {
@@ -201,6 +378,7 @@ pub fn run(
         }
 
         // Now tell the child process to start executing.
+        let start = Instant::now();
         let process = match process.unsuspend_and_run() {
             Ok(process) => process,
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
@@ -215,12 +393,20 @@ pub fn run(
         };
 
         wait_status = process.wait().expect("couldn't wait for child");
+        iteration_times.add(start.elapsed());
     }
+    iteration_times.print_summary();
 
     profile_another_pid_request_sender
         .send(SamplerRequest::StopProfilingOncePerfEventsExhausted)
         .unwrap();
 
+    // No more processes will be launched, so close our copy of their end of the
+    // allocation socket.
+    if let Some(allocation_recorder) = &mut allocation_recorder {
+        allocation_recorder.close_child_socket();
+    }
+
     // The launched subprocess is done. From now on, we want to terminate if the user presses Ctrl+C.
     ctrl_c_receiver.close();
 
@@ -231,6 +417,9 @@ pub fn run(
         .join()
         .expect("couldn't join observer thread");
 
+    // The converter has read the preload library by now, so it can be deleted.
+    drop(allocation_recorder);
+
     let exit_status = match wait_status {
         WaitStatus::Exited(_pid, exit_code) => ExitStatus::from_raw(exit_code),
         _ => ExitStatus::default(),
@@ -239,11 +428,31 @@ pub fn run(
     Ok((profile, exit_status))
 }
 
-fn start_profiling_pid(
-    pid: u32,
+/// How often we look for new processes with --include-new-processes.
+const NEW_PROCESS_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Records processes which are already running, until Ctrl+C is pressed or until
+/// all of them have quit.
+///
+/// If `new_processes` is given, processes which match it and which are started
+/// during recording are attached to as well, and recording only stops on Ctrl+C.
+fn start_profiling_existing(
+    target: PerfTarget,
+    new_processes: Option<IncludedProcesses>,
     recording_props: RecordingProps,
     profile_creation_props: ProfileCreationProps,
 ) -> Profile {
+    let tracepoints = match recording_tracepoints(TracepointOptions::new(
+        &recording_props,
+        &profile_creation_props,
+    )) {
+        Ok(tracepoints) => tracepoints,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            std::process::exit(1)
+        }
+    };
+
     // When the first Ctrl+C is received, stop recording.
     let ctrl_c_receiver = CtrlC::observe_oneshot();
 
@@ -255,16 +464,53 @@ fn start_profiling_pid(
         crossbeam_channel::bounded(2);
 
     let observer_thread = thread::spawn({
+        let target = target.clone();
         move || {
             let interval = recording_props.interval;
             let time_limit = recording_props.time_limit;
-            let mut converter = make_converter(interval, profile_creation_props);
-            let SamplerRequest::StartProfilingAnotherProcess(pid, attach_mode) =
-                profile_another_pid_request_receiver.recv().unwrap()
-            else {
-                panic!("The first message should be a StartProfilingAnotherProcess")
-            };
-            let perf_group = init_profiler(interval, pid, attach_mode, &mut converter);
+            let perf_data_path = recording_props.perf_data_path;
+            let call_graph = recording_props.call_graph;
+            let stack_size = recording_props.stack_size;
+            let start_paused = recording_props.start_paused;
+            let counted_events = counted_events(recording_props.hardware_counters);
+            let mut tracepoints = tracepoints;
+            tracepoints.extend(custom_tracepoints(&recording_props.tracepoints));
+            tracepoints.extend(probe_tracepoints(&recording_props.probes));
+            let usdt_arguments = add_usdt_tracepoints(
+                &recording_props.usdt,
+                None,
+                &counted_events,
+                &mut tracepoints,
+            );
+            let poll_statm = recording_props.memory && !has_rss_stat_tracepoint(&tracepoints);
+            let cpufreq_polling = cpufreq_polling(recording_props.cpu_power, &tracepoints);
+            let interpretation = live_event_interpretation(interval, &counted_events, &tracepoints);
+            let flight_recorder = recording_props.flight_recorder.map(|props| {
+                make_flight_recorder(
+                    props,
+                    &recording_props.output_file,
+                    &interpretation,
+                    &profile_creation_props,
+                    &usdt_arguments,
+                    &tracepoints,
+                )
+            });
+            let mut converter = make_converter(interpretation.clone(), profile_creation_props);
+            for (attr_index, arguments) in usdt_arguments {
+                converter.set_usdt_arguments(attr_index, arguments);
+            }
+            let trace_marker_recorder = start_trace_marker_recorder(recording_props.trace_markers);
+            let (perf_group, perf_data_writer) = init_profiler(
+                interval,
+                &target,
+                AttachMode::StopAttachEnableResume,
+                call_graph,
+                stack_size,
+                &counted_events,
+                &tracepoints,
+                perf_data_path.as_deref(),
+                &mut converter,
+            );
 
             // Tell the main thread that we are now executing.
             profile_another_pid_reply_sender.send(true).unwrap();
@@ -272,32 +518,75 @@ fn start_profiling_pid(
             run_profiler(
                 perf_group,
                 converter,
+                perf_data_writer,
+                start_paused,
                 time_limit,
                 profile_another_pid_request_receiver,
                 profile_another_pid_reply_sender,
                 ctrl_c_receiver,
                 None,
+                None,
+                trace_marker_recorder,
+                interpretation,
+                poll_statm,
+                cpufreq_polling,
+                flight_recorder,
             )
         }
     });
 
     // We're on the main thread here and the observer thread has just been launched.
 
-    // Request profiling of our process and wait for profiler initialization.
-    profile_another_pid_request_sender
-        .send(SamplerRequest::StartProfilingAnotherProcess(
-            pid,
-            AttachMode::StopAttachEnableResume,
-        ))
-        .unwrap();
+    // Wait for profiler initialization.
     let _ = profile_another_pid_reply_receiver.recv().unwrap();
 
     // Now that we know that profiler initialization has succeeded, tell the user about it.
-    eprintln!("Recording process with PID {pid} until Ctrl+C...");
+    match &target {
+        PerfTarget::Processes(pids) => match pids.as_slice() {
+            [pid] => eprintln!("Recording process with PID {pid} until Ctrl+C..."),
+            pids => eprintln!("Recording processes with PIDs {pids:?} until Ctrl+C..."),
+        },
+        PerfTarget::Cgroup(path) => {
+            eprintln!("Recording cgroup {} until Ctrl+C...", path.display())
+        }
+    }
 
-    profile_another_pid_request_sender
-        .send(SamplerRequest::StopProfilingOncePerfEventsExhausted)
-        .unwrap();
+    match (new_processes, &target) {
+        (Some(new_processes), PerfTarget::Processes(pids)) => {
+            // Keep looking for new matching processes until the observer thread quits.
+            let mut covered_pids: HashSet<u32> = get_process_tree(pids).into_iter().collect();
+            while !observer_thread.is_finished() {
+                thread::sleep(NEW_PROCESS_POLL_INTERVAL);
+                let parent_pids = get_parent_pids();
+                for pid in find_matching_processes(&new_processes) {
+                    if covered_pids.contains(&pid) {
+                        continue;
+                    }
+                    // Processes which were forked from a recorded process are already
+                    // covered by the inherited perf events.
+                    let is_descendant =
+                        std::iter::successors(parent_pids.get(&pid), |ppid| parent_pids.get(ppid))
+                            .take_while(|&&ppid| ppid > 1)
+                            .any(|ppid| covered_pids.contains(ppid));
+                    if !is_descendant {
+                        let request = SamplerRequest::AttachToRunningProcess(pid);
+                        if profile_another_pid_request_sender.send(request).is_err()
+                            || profile_another_pid_reply_receiver.recv() != Ok(true)
+                        {
+                            continue;
+                        }
+                        eprintln!("Attached to process with PID {pid}.");
+                    }
+                    covered_pids.extend(get_process_tree(&[pid]));
+                }
+            }
+        }
+        _ => {
+            profile_another_pid_request_sender
+                .send(SamplerRequest::StopProfilingOncePerfEventsExhausted)
+                .unwrap();
+        }
+    }
 
     // Now wait for the observer thread to quit. It will keep running until the
     // CtrlC receiver has been notified, or until all perf events are closed,
@@ -310,22 +599,517 @@ fn start_profiling_pid(
     // dropped its CtrlC receiver by now.
 }
 
+/// The hardware events which are counted alongside the sampling event, if
+/// `--hw-counters` was given.
+fn counted_events(hardware_counters: bool) -> Vec<CountedEvent> {
+    if hardware_counters {
+        vec![CountedEvent::Instructions, CountedEvent::CacheMisses]
+    } else {
+        Vec::new()
+    }
+}
+
+/// The tracepoints which `--memory` samples. The same events are turned into
+/// memory counters and markers when importing perf.data files.
+const MEMORY_TRACEPOINTS: [&str; 5] = [
+    "kmem:rss_stat",
+    "exceptions:page_fault_user",
+    "syscalls:sys_enter_mmap",
+    "syscalls:sys_exit_mmap",
+    "syscalls:sys_enter_mprotect",
+];
+
+/// The tracepoints which `--syscalls` samples. Their samples are paired up
+/// into syscall markers.
+const SYSCALL_TRACEPOINTS: [&str; 2] = ["raw_syscalls:sys_enter", "raw_syscalls:sys_exit"];
+
+/// The tracepoint which `--wakeups` samples. Unlike `sched:sched_wakeup`, it
+/// fires in the context of the waking thread, so it has the waker's stack.
+/// Like the other per-process tracepoints, it only sees the wakeups which are
+/// performed by the recorded processes.
+const SCHED_WAKEUP_TRACEPOINTS: [&str; 1] = ["sched:sched_waking"];
+
+/// The tracepoints which `--thread-states` samples. This includes the wakeup
+/// tracepoint of `--wakeups`.
+const THREAD_STATE_TRACEPOINTS: [&str; 3] = [
+    "sched:sched_switch",
+    "sched:sched_waking",
+    "sched:sched_migrate_task",
+];
+
+/// The tracepoints which `--spawns` samples. `sched_process_fork` fires in the
+/// context of the spawning thread, so it has the spawning stack.
+const SPAWN_TRACEPOINTS: [&str; 2] = ["sched:sched_process_fork", "sched:sched_process_exec"];
+
+/// The tracepoints for the exit statuses with `--spawns`. The syscall
+/// tracepoint only exists if the kernel was built with syscall tracepoints. The
+/// signal tracepoints are only used together with it, because the other threads
+/// of a process which calls `exit_group` are killed with a SIGKILL.
+const EXIT_TRACEPOINTS: [&str; 3] = [
+    "syscalls:sys_enter_exit_group",
+    "signal:signal_generate",
+    "signal:signal_deliver",
+];
+
+/// The tracepoints which `--block-io` samples. Requests are often issued from
+/// kworkers and completed in interrupt handlers, so these are sampled for all
+/// processes.
+const BLOCK_IO_TRACEPOINTS: [&str; 3] = [
+    "block:block_rq_insert",
+    "block:block_rq_issue",
+    "block:block_rq_complete",
+];
+
+/// The tracepoints which `--cpu-power` samples. They're hit in the idle task
+/// and in the cpufreq governor, so these are sampled for all processes.
+const CPU_POWER_TRACEPOINTS: [&str; 2] = ["power:cpu_frequency", "power:cpu_idle"];
+
+/// The tracepoints which `--lock-contention` samples. The `lock` tracepoints
+/// only exist since Linux 5.19, so on older kernels we only see futex waits.
+const LOCK_CONTENTION_TRACEPOINTS: [&str; 4] = [
+    "syscalls:sys_enter_futex",
+    "syscalls:sys_exit_futex",
+    "lock:contention_begin",
+    "lock:contention_end",
+];
+
+/// The options which are recorded with tracepoints: `--memory`, `--syscalls`,
+/// `--wakeups`, `--spawns`, `--thread-states`, `--block-io`, `--cpu-power` and
+/// `--lock-contention`.
+#[derive(Debug, Clone, Copy)]
+struct TracepointOptions {
+    memory: bool,
+    syscalls: bool,
+    wakeups: bool,
+    spawns: bool,
+    thread_states: bool,
+    block_io: bool,
+    cpu_power: bool,
+    lock_contention: bool,
+}
+
+impl TracepointOptions {
+    fn new(
+        recording_props: &RecordingProps,
+        profile_creation_props: &ProfileCreationProps,
+    ) -> Self {
+        Self {
+            memory: recording_props.memory,
+            syscalls: profile_creation_props.syscalls,
+            wakeups: recording_props.wakeups,
+            spawns: recording_props.spawns,
+            thread_states: profile_creation_props.thread_states,
+            block_io: recording_props.block_io,
+            cpu_power: recording_props.cpu_power,
+            lock_contention: profile_creation_props.lock_contention,
+        }
+    }
+}
+
+/// The tracepoints which are opened for one of the [`TracepointOptions`].
+struct TracepointGroup {
+    names: &'static [&'static str],
+    /// What the tracepoints are, for the error message.
+    description: &'static str,
+    system_wide: bool,
+    /// Whether the samples need a stack.
+    sample_callchain: bool,
+    /// What we do instead if the tracepoints can't be opened, or None if the
+    /// option doesn't work without them.
+    fallback: Option<&'static str>,
+}
+
+/// Opens the tracepoints for the given options. Returns an error if the
+/// tracepoints of an option can't be opened and the option has no fallback.
+fn recording_tracepoints(options: TracepointOptions) -> Result<Vec<Tracepoint>, String> {
+    let mut groups = Vec::new();
+    if options.memory {
+        groups.push(TracepointGroup {
+            names: &MEMORY_TRACEPOINTS,
+            description: "the memory tracepoints",
+            system_wide: false,
+            sample_callchain: true,
+            fallback: Some("Polling /proc/<pid>/statm for the memory usage instead."),
+        });
+    }
+    if options.syscalls {
+        groups.push(TracepointGroup {
+            names: &SYSCALL_TRACEPOINTS,
+            description: "the syscall tracepoints",
+            system_wide: false,
+            sample_callchain: true,
+            fallback: None,
+        });
+    }
+    if options.wakeups && !options.thread_states {
+        groups.push(TracepointGroup {
+            names: &SCHED_WAKEUP_TRACEPOINTS,
+            description: "the sched_waking tracepoint",
+            system_wide: false,
+            sample_callchain: true,
+            fallback: None,
+        });
+    }
+    if options.thread_states {
+        groups.push(TracepointGroup {
+            names: &THREAD_STATE_TRACEPOINTS,
+            description: "the scheduler tracepoints",
+            system_wide: false,
+            sample_callchain: true,
+            fallback: None,
+        });
+    }
+    if options.spawns {
+        groups.push(TracepointGroup {
+            names: &SPAWN_TRACEPOINTS,
+            description: "the process tracepoints",
+            system_wide: false,
+            sample_callchain: true,
+            fallback: None,
+        });
+        groups.push(TracepointGroup {
+            names: &EXIT_TRACEPOINTS,
+            description: "the exit tracepoints",
+            system_wide: false,
+            sample_callchain: true,
+            fallback: Some("The process lifetimes won't have exit codes or signals."),
+        });
+    }
+    if options.block_io {
+        groups.push(TracepointGroup {
+            names: &BLOCK_IO_TRACEPOINTS,
+            description: "the block I/O tracepoints",
+            system_wide: true,
+            sample_callchain: true,
+            fallback: None,
+        });
+    }
+    if options.cpu_power {
+        groups.push(TracepointGroup {
+            names: &CPU_POWER_TRACEPOINTS,
+            description: "the power tracepoints",
+            system_wide: true,
+            sample_callchain: false,
+            fallback: Some(
+                "Polling /sys/devices/system/cpu/*/cpufreq for the CPU frequencies instead.",
+            ),
+        });
+    }
+    if options.lock_contention {
+        groups.push(TracepointGroup {
+            names: &LOCK_CONTENTION_TRACEPOINTS,
+            description: "the lock contention tracepoints",
+            system_wide: false,
+            sample_callchain: true,
+            fallback: None,
+        });
+    }
+
+    let mut tracepoints = Vec::new();
+    for group in groups {
+        match open_tracepoints(group.names) {
+            Ok(group_tracepoints) => {
+                tracepoints.extend(group_tracepoints.into_iter().map(|tracepoint| Tracepoint {
+                    system_wide: group.system_wide,
+                    sample_callchain: group.sample_callchain,
+                    ..tracepoint
+                }))
+            }
+            Err(error) => {
+                let message = format!("Could not open {}: {error}", group.description);
+                let Some(fallback) = group.fallback else {
+                    return Err(message);
+                };
+                eprintln!("{message}");
+                eprintln!("{fallback}");
+            }
+        }
+    }
+    Ok(tracepoints)
+}
+
+fn custom_tracepoints(names: &[String]) -> Vec<Tracepoint> {
+    if names.is_empty() {
+        return Vec::new();
+    }
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    let result = find_tracefs().and_then(|tracefs| {
+        for name in &names {
+            tracepoint_id(&tracefs, name).map_err(|error| {
+                std::io::Error::new(error.kind(), format!("{name} not found: {error}"))
+            })?;
+        }
+        open_tracepoints(&names)
+    });
+    match result {
+        Ok(tracepoints) => tracepoints,
+        Err(error) => {
+            eprintln!("Error: Could not open the tracepoints: {error}");
+            std::process::exit(1)
+        }
+    }
+}
+
+/// The uprobes for `--probe`.
+fn probe_tracepoints(probes: &[String]) -> Vec<Tracepoint> {
+    match open_uprobes(probes) {
+        Ok(uprobes) => uprobes,
+        Err(error) => {
+            eprintln!("Error: Could not create the probes: {error}");
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Adds the uprobes for `--usdt`, and returns the attr index and the argument
+/// spec of each of them. The attr indexes follow the counted events and the
+/// tracepoints which were added before, see [`live_event_interpretation`].
+fn add_usdt_tracepoints(
+    usdt: &[String],
+    default_binary: Option<&Path>,
+    counted_events: &[CountedEvent],
+    tracepoints: &mut Vec<Tracepoint>,
+) -> Vec<(usize, Vec<SdtArgument>)> {
+    let probes = match open_usdt_probes(usdt, default_binary) {
+        Ok(probes) => probes,
+        Err(error) => {
+            eprintln!("Error: Could not create the USDT probes: {error}");
+            std::process::exit(1)
+        }
+    };
+    let first_attr_index = 1 + counted_events.len() + tracepoints.len();
+    probes
+        .into_iter()
+        .enumerate()
+        .map(|(i, probe)| {
+            tracepoints.push(probe.tracepoint);
+            (first_attr_index + i, probe.arguments)
+        })
+        .collect()
+}
+
+/// Reads the tracefs `format` files of the tracepoints, skipping uprobes and any
+/// tracepoints whose format can't be read.
+fn read_tracepoint_formats(tracepoints: &[Tracepoint]) -> Vec<(String, String)> {
+    let Ok(tracefs) = find_tracefs() else {
+        return Vec::new();
+    };
+    tracepoints
+        .iter()
+        .filter(|tracepoint| tracepoint.uprobe.is_none())
+        .filter_map(|tracepoint| {
+            let format = tracepoint_format(&tracefs, &tracepoint.name).ok()?;
+            Some((tracepoint.name.clone(), format))
+        })
+        .collect()
+}
+
+/// Parses the formats of the tracepoints, for decoding their samples.
+fn parse_tracepoint_formats(
+    tracepoint_formats: &[(String, String)],
+) -> HashMap<String, TracepointFormat> {
+    tracepoint_formats
+        .iter()
+        .filter_map(|(name, format)| Some((name.clone(), TracepointFormat::parse(format)?)))
+        .collect()
+}
+
+/// Creates the TRACING_DATA section of the perf.data file from the formats of
+/// the tracepoints.
+fn tracing_data(tracepoint_formats: &[(String, String)]) -> Vec<u8> {
+    let tracefs = find_tracefs().ok();
+    let read_header = |name: &str| {
+        tracefs
+            .as_ref()
+            .and_then(|tracefs| std::fs::read(tracefs.join("events").join(name)).ok())
+            .unwrap_or_default()
+    };
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u32;
+    write_tracing_data(
+        page_size,
+        &read_header("header_page"),
+        &read_header("header_event"),
+        tracepoint_formats,
+    )
+}
+
+/// Looks up tracepoints and checks that we're allowed to sample them.
+/// Tracepoints which don't exist on this system, e.g.
+/// `exceptions:page_fault_user` outside of x86, are left out.
+fn open_tracepoints(names: &[&str]) -> std::io::Result<Vec<Tracepoint>> {
+    let tracefs = find_tracefs()?;
+    let tracepoints: Vec<Tracepoint> = names
+        .iter()
+        .filter_map(|name| {
+            let id = tracepoint_id(&tracefs, name).ok()?;
+            Some(Tracepoint {
+                name: name.to_string(),
+                id,
+                system_wide: false,
+                sample_callchain: true,
+                uprobe: None,
+            })
+        })
+        .collect();
+
+    // Sampling the raw data of tracepoints needs more privileges than sampling
+    // CPU cycles, so try it on ourselves first.
+    let Some(tracepoint) = tracepoints.first() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no tracepoints found in tracefs",
+        ));
+    };
+    Perf::build()
+        .pid(std::process::id())
+        .any_cpu()
+        .sample_kernel()
+        .tracepoint(tracepoint.id)
+        .start_disabled()
+        .open()?;
+    Ok(tracepoints)
+}
+
+fn has_rss_stat_tracepoint(tracepoints: &[Tracepoint]) -> bool {
+    tracepoints
+        .iter()
+        .any(|tracepoint| tracepoint.name == "kmem:rss_stat")
+}
+
+/// How often we read `/proc/<pid>/statm` if the `kmem:rss_stat` tracepoint
+/// isn't available.
+const STATM_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// When the live recorder reads the CPU frequencies from cpufreq.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CpufreqPolling {
+    Never,
+    /// The `power:cpu_frequency` tracepoint only tells us about changes, so we
+    /// need the frequencies at the start.
+    AtStart,
+    /// The `power:cpu_frequency` tracepoint isn't available.
+    Periodically,
+}
+
+fn cpufreq_polling(cpu_power: bool, tracepoints: &[Tracepoint]) -> CpufreqPolling {
+    if !cpu_power {
+        CpufreqPolling::Never
+    } else if tracepoints
+        .iter()
+        .any(|tracepoint| tracepoint.name == "power:cpu_frequency")
+    {
+        CpufreqPolling::AtStart
+    } else {
+        CpufreqPolling::Periodically
+    }
+}
+
+/// How often we read the CPU frequencies if the `power:cpu_frequency`
+/// tracepoint isn't available.
+const CPUFREQ_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Reads the current frequency of each CPU from cpufreq, in kHz. CPUs without
+/// a cpufreq driver, e.g. in VMs, are left out.
+fn read_cpu_frequencies() -> Vec<(u32, u32)> {
+    let Ok(entries) = std::fs::read_dir("/sys/devices/system/cpu") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let cpu = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("cpu")?
+                .parse()
+                .ok()?;
+            let khz = read_string_lossy(entry.path().join("cpufreq/scaling_cur_freq")).ok()?;
+            Some((cpu, khz.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Reads the resident anonymous memory of a process, in bytes.
+fn read_anon_rss_size(pid: i32) -> Option<i64> {
+    // The fields are: size resident shared text lib data dt, in pages. The
+    // shared pages are the resident file and shmem pages.
+    let statm = read_string_lossy(format!("/proc/{pid}/statm")).ok()?;
+    let mut fields = statm.split_whitespace().skip(1);
+    let resident: i64 = fields.next()?.parse().ok()?;
+    let shared: i64 = fields.next()?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as i64;
+    Some((resident - shared) * page_size)
+}
+
+/// Starts reading the `trace_marker` writes, if `--trace-markers` was given.
+fn start_trace_marker_recorder(trace_markers: bool) -> Option<TraceMarkerRecorder> {
+    if !trace_markers {
+        return None;
+    }
+    match TraceMarkerRecorder::new() {
+        Ok(recorder) => Some(recorder),
+        Err(error) => {
+            eprintln!("Error: Could not set up trace marker recording: {error}");
+            std::process::exit(1)
+        }
+    }
+}
+
 fn paranoia_level() -> Option<u32> {
     let level = read_string_lossy("/proc/sys/kernel/perf_event_paranoid").ok()?;
     let level = level.trim().parse::<u32>().ok()?;
     Some(level)
 }
 
-fn make_converter(
+/// Describes the events which the live recorder opens.
+fn live_event_interpretation(
     interval: Duration,
-    profile_creation_props: ProfileCreationProps,
-) -> Converter<framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>> {
+    counted_events: &[CountedEvent],
+    tracepoints: &[Tracepoint],
+) -> EventInterpretation {
     let interval_nanos = if interval.as_nanos() > 0 {
         interval.as_nanos() as u64
     } else {
         1_000_000 // 1 million nano seconds = 1 milli second
     };
 
+    // The counted events and the tracepoints come after the sampling event, in
+    // the same order as the attrs in PerfGroup::event_ids_by_attr_index.
+    let event_names: Vec<String> = std::iter::once("cycles")
+        .chain(counted_events.iter().map(CountedEvent::name))
+        .map(ToOwned::to_owned)
+        .chain(tracepoints.iter().map(|tracepoint| tracepoint.name.clone()))
+        .collect();
+    let instructions_attr_index = counted_events
+        .iter()
+        .position(|event| *event == CountedEvent::Instructions)
+        .map(|index| index + 1);
+    let sched_switch_attr_index = event_names
+        .iter()
+        .position(|name| name == "sched:sched_switch");
+    let known_event_indices = event_names
+        .iter()
+        .enumerate()
+        .filter_map(|(index, name)| Some((index, KnownEvent::from_name(name)?)))
+        .collect();
+    EventInterpretation {
+        main_event_attr_index: 0,
+        main_event_name: "cycles".to_string(),
+        sampling_is_time_based: Some(interval_nanos),
+        off_cpu_indicator: Some(OffCpuIndicator::ContextSwitches),
+        sched_switch_attr_index,
+        known_event_indices,
+        event_names,
+        attr_index_by_event_id: HashMap::new(),
+        instructions_attr_index,
+        cycles_attr_index: Some(0),
+    }
+}
+
+fn make_converter(
+    interpretation: EventInterpretation,
+    profile_creation_props: ProfileCreationProps,
+) -> Converter<framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>> {
     let first_sample_time = 0;
 
     let endian = if cfg!(target_endian = "little") {
@@ -334,15 +1118,6 @@ fn make_converter(
         Endianness::BigEndian
     };
     let machine_info = uname::uname().ok();
-    let interpretation = EventInterpretation {
-        main_event_attr_index: 0,
-        main_event_name: "cycles".to_string(),
-        sampling_is_time_based: Some(interval_nanos),
-        off_cpu_indicator: Some(OffCpuIndicator::ContextSwitches),
-        sched_switch_attr_index: None,
-        known_event_indices: HashMap::new(),
-        event_names: vec!["cycles".to_string()],
-    };
 
     let mut converter = Converter::<
         framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
@@ -364,17 +1139,61 @@ fn make_converter(
     if let Ok(os_release) = os_release::OsRelease::new() {
         converter.set_os_name(&os_release.pretty_name);
     }
+    converter.set_host_info(&current_host_info());
     converter
 }
 
+/// Creates the flight recorder, which sets up the converters of its generations
+/// like the first converter.
+fn make_flight_recorder(
+    props: FlightRecorderProps,
+    output_file: &Path,
+    interpretation: &EventInterpretation,
+    profile_creation_props: &ProfileCreationProps,
+    usdt_arguments: &[(usize, Vec<SdtArgument>)],
+    tracepoints: &[Tracepoint],
+) -> FlightRecorder {
+    let interpretation = interpretation.clone();
+    let profile_creation_props = profile_creation_props.clone();
+    let usdt_arguments = usdt_arguments.to_vec();
+    let tracepoint_formats = parse_tracepoint_formats(&read_tracepoint_formats(tracepoints));
+    FlightRecorder::new(props, output_file, move || {
+        let mut converter = make_converter(interpretation.clone(), profile_creation_props.clone());
+        for (attr_index, arguments) in &usdt_arguments {
+            converter.set_usdt_arguments(*attr_index, arguments.clone());
+        }
+        if !tracepoint_formats.is_empty() {
+            converter.set_tracepoint_formats(&tracepoint_formats);
+        }
+        converter
+    })
+}
+
+/// The processes that the perf events are opened for.
+#[derive(Debug, Clone)]
+enum PerfTarget {
+    /// The given processes and all their descendants.
+    Processes(Vec<u32>),
+    /// All processes in the cgroup with this directory.
+    Cgroup(PathBuf),
+}
+
+/// Opens the perf events for the target, and registers the processes which
+/// are already running with the converter.
+#[allow(clippy::too_many_arguments)]
 fn init_profiler(
     interval: Duration,
-    pid: u32,
+    target: &PerfTarget,
     attach_mode: AttachMode,
+    call_graph: CallGraphMode,
+    stack_size: u32,
+    counted_events: &[CountedEvent],
+    tracepoints: &[Tracepoint],
+    perf_data_path: Option<&Path>,
     converter: &mut Converter<
         framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
     >,
-) -> PerfGroup {
+) -> (PerfGroup, Option<PerfDataWriter>) {
     let interval_nanos = if interval.as_nanos() > 0 {
         interval.as_nanos() as u64
     } else {
@@ -382,17 +1201,39 @@ fn init_profiler(
     };
 
     let frequency = (1_000_000_000 / interval_nanos) as u32;
-    let stack_size = 32000;
-    let regs_mask = ConvertRegsNative::regs_mask();
-
-    let perf = PerfGroup::open(
-        pid,
-        frequency,
-        stack_size,
-        EventSource::HwCpuCycles,
-        regs_mask,
-        attach_mode,
-    );
+    // With frame pointer unwinding, the kernel walks the user stack for us and we don't
+    // need a copy of the stack bytes and registers.
+    let (stack_size, regs_mask, sample_callchain) = match call_graph {
+        CallGraphMode::FramePointer => (0, 0, true),
+        CallGraphMode::Dwarf => (stack_size, ConvertRegsNative::regs_mask(), false),
+        CallGraphMode::Mixed => (stack_size, ConvertRegsNative::regs_mask(), true),
+    };
+
+    let open_perf_group = |event_source| match target {
+        PerfTarget::Processes(pids) => PerfGroup::open(
+            pids[0],
+            frequency,
+            stack_size,
+            event_source,
+            regs_mask,
+            sample_callchain,
+            counted_events,
+            tracepoints,
+            attach_mode,
+        ),
+        PerfTarget::Cgroup(path) => PerfGroup::open_cgroup(
+            path,
+            frequency,
+            stack_size,
+            event_source,
+            regs_mask,
+            sample_callchain,
+            counted_events,
+            tracepoints,
+        ),
+    };
+
+    let perf = open_perf_group(EventSource::HwCpuCycles);
 
     if let Err(error) = &perf {
         if error.kind() == std::io::ErrorKind::PermissionDenied {
@@ -415,6 +1256,14 @@ fn init_profiler(
 
     let mut perf = match perf {
         Ok(perf) => perf,
+        Err(error) if !counted_events.is_empty() => {
+            // Without hardware performance counters there's nothing to count.
+            eprintln!("Failed to open hardware performance counters: {error}");
+            if error.raw_os_error() == Some(libc::EINVAL) {
+                eprintln!("Reading counter values into samples of inherited events requires Linux 6.12 or newer.");
+            }
+            std::process::exit(1);
+        }
         Err(_) => {
             // We've already checked for permission denied due to paranoia
             // level, and exited with a warning in that case.
@@ -422,14 +1271,7 @@ fn init_profiler(
             // Another reason for the error could be the type of perf event:
             // The "Hardware CPU cycles" event is not supported in some contexts, for example in VMs.
             // Try a different event type.
-            let perf = PerfGroup::open(
-                pid,
-                frequency,
-                stack_size,
-                EventSource::SwCpuClock,
-                regs_mask,
-                attach_mode,
-            );
+            let perf = open_perf_group(EventSource::SwCpuClock);
             match perf {
                 Ok(perf) => perf, // Success!
                 Err(error) => {
@@ -440,29 +1282,168 @@ fn init_profiler(
         }
     };
 
-    let (exe_name, cmdline) = get_process_cmdline(pid).expect("Couldn't read process cmdline");
-    let comm_data = std::fs::read(format!("/proc/{pid}/comm")).expect("Couldn't read process comm");
-    let length = memchr::memchr(b'\0', &comm_data).unwrap_or(comm_data.len());
-    let comm_name = std::str::from_utf8(&comm_data[..length])
-        .unwrap()
-        .trim_end();
-    converter.register_existing_process(pid as i32, comm_name, &exe_name, cmdline);
-
-    // TODO: Gather threads / processes recursively, here and in PerfGroup setup.
-    for thread_entry in std::fs::read_dir(format!("/proc/{pid}/task"))
-        .unwrap()
-        .flatten()
-    {
-        let tid: u32 = thread_entry.file_name().to_string_lossy().parse().unwrap();
+    let existing_pids = match target {
+        PerfTarget::Processes(pids) => {
+            // Processes which are forked from now on are covered by the inherited events,
+            // but existing descendants need their own events.
+            for pid in get_process_tree(pids) {
+                if pid == pids[0] {
+                    continue;
+                }
+                if let Err(error) = perf.open_process(pid, attach_mode) {
+                    if pids.contains(&pid) {
+                        eprintln!("Failed to start profiling process with PID {pid}: {error}");
+                        std::process::exit(1);
+                    }
+                    // The descendant process may have exited in the meantime.
+                }
+            }
+            perf.pids().to_owned()
+        }
+        PerfTarget::Cgroup(path) => get_cgroup_pids(path),
+    };
+
+    let mut perf_data_writer = perf_data_path.map(|path| {
+        let attr = PerfDataAttr {
+            attr_bytes: perf.event_attr_bytes().unwrap_or_default().to_owned(),
+            name: perf.event_source().name().to_string(),
+            ids: Vec::new(),
+        };
+        let counted_event_attrs = perf
+            .counted_events()
+            .iter()
+            .zip(perf.counted_event_attr_bytes())
+            .map(|(event, attr_bytes)| PerfDataAttr {
+                attr_bytes: attr_bytes.clone(),
+                name: event.name().to_string(),
+                ids: Vec::new(),
+            });
+        let tracepoint_attrs = perf
+            .tracepoints()
+            .iter()
+            .zip(perf.tracepoint_attr_bytes())
+            .map(|(tracepoint, attr_bytes)| PerfDataAttr {
+                attr_bytes: attr_bytes.to_owned(),
+                name: tracepoint.name.clone(),
+                ids: Vec::new(),
+            });
+        let attrs = std::iter::once(attr)
+            .chain(counted_event_attrs)
+            .chain(tracepoint_attrs)
+            .collect();
+        match PerfDataWriter::create(path, attrs) {
+            Ok(writer) => writer,
+            Err(error) => {
+                eprintln!("Could not create {}: {error}", path.display());
+                std::process::exit(1);
+            }
+        }
+    });
+
+    // The formats of the tracepoints let the converter decode the samples of the
+    // tracepoints without custom handling, and make the perf.data file usable
+    // with `perf script`.
+    let tracepoint_formats = read_tracepoint_formats(tracepoints);
+    if !tracepoint_formats.is_empty() {
+        converter.set_tracepoint_formats(&parse_tracepoint_formats(&tracepoint_formats));
+        if let Some(perf_data_writer) = &mut perf_data_writer {
+            perf_data_writer.set_tracing_data(tracing_data(&tracepoint_formats));
+        }
+    }
+
+    register_event_ids(&perf, converter, &mut perf_data_writer);
+
+    for (i, &pid) in existing_pids.iter().enumerate() {
+        let result = register_existing_process_from_proc(pid, 0, converter, &mut perf_data_writer);
+        if i == 0 && matches!(target, PerfTarget::Processes(_)) {
+            result.expect("Couldn't read process information from /proc");
+        }
+    }
+
+    // eprintln!("Enabling perf events...");
+    match attach_mode {
+        AttachMode::StopAttachEnableResume => perf.enable(),
+        AttachMode::AttachWithEnableOnExec => {
+            // The perf event will get enabled automatically once the forked child process execs.
+            // The events for all processes aren't, and they need to be enabled before the
+            // child runs, so that uprobes don't miss its first hits.
+            perf.enable_system_wide_tracepoints();
+        }
+    }
+
+    (perf, perf_data_writer)
+}
+
+/// Tells the converter, and the perf.data writer if present, about the IDs of
+/// the open perf events, so that counter values can be matched to their events.
+fn register_event_ids(
+    perf: &PerfGroup,
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    perf_data_writer: &mut Option<PerfDataWriter>,
+) {
+    for (attr_index, ids) in perf.event_ids_by_attr_index().iter().enumerate() {
+        converter.add_event_ids(attr_index, ids);
+        if let Some(writer) = perf_data_writer {
+            writer.add_event_ids(attr_index, ids);
+        }
+    }
+}
+
+/// Tells the converter, and the perf.data writer if present, about a process
+/// which is already running: its name, its threads, and its current mappings.
+///
+/// Processes and threads which the converter already knows about keep their
+/// names and start times, and mappings which it already knows about are
+/// skipped, so this can also be used to catch up on changes which happened
+/// while no perf events were being recorded.
+fn register_existing_process_from_proc(
+    pid: u32,
+    timestamp: u64,
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    perf_data_writer: &mut Option<PerfDataWriter>,
+) -> std::io::Result<()> {
+    let is_new_process = !converter.has_process(pid as i32);
+    if is_new_process {
+        let (exe_name, cmdline) = get_process_cmdline(pid)?;
+        let comm_data = std::fs::read(format!("/proc/{pid}/comm"))?;
+        let length = memchr::memchr(b'\0', &comm_data).unwrap_or(comm_data.len());
+        let comm_name = String::from_utf8_lossy(&comm_data[..length]);
+        converter.register_existing_process(pid as i32, comm_name.trim_end(), &exe_name, cmdline);
+    }
+
+    for thread_entry in std::fs::read_dir(format!("/proc/{pid}/task"))?.flatten() {
+        let Ok(tid) = thread_entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        let is_new_thread = if tid == pid {
+            is_new_process
+        } else {
+            !converter.has_thread(pid as i32, tid as i32)
+        };
+        if !is_new_thread {
+            continue;
+        }
         let comm_path = format!("/proc/{pid}/task/{tid}/comm");
         if let Ok(buffer) = std::fs::read(comm_path) {
             let length = memchr::memchr(b'\0', &buffer).unwrap_or(buffer.len());
-            let name = std::str::from_utf8(&buffer[..length]).unwrap().trim_end();
+            let name = String::from_utf8_lossy(&buffer[..length]);
+            let name = name.trim_end();
             converter.register_existing_thread(pid as i32, tid as i32, name);
+            if let Some(writer) = perf_data_writer {
+                if let Err(error) =
+                    writer.write_synthesized_comm(pid as i32, tid as i32, name, timestamp)
+                {
+                    eprintln!("Could not write to {}: {error}", writer.path().display());
+                }
+            }
         }
     }
 
-    let maps = read_string_lossy(format!("/proc/{pid}/maps")).expect("couldn't read proc maps");
+    let maps = read_string_lossy(format!("/proc/{pid}/maps"))?;
     let maps = proc_maps::parse(&maps);
 
     let vdso_file_id = VdsoObject::shared_instance_for_this_process()
@@ -497,36 +1478,65 @@ fn init_profiler(
             }),
         };
 
-        converter.handle_mmap2(
-            Mmap2Record {
-                pid: pid as i32,
-                tid: pid as i32,
-                address: region.start,
-                length: region.end - region.start,
-                page_offset: region.file_offset,
-                file_id,
-                protection: protection as _,
-                flags: flags as _,
-                path: RawData::Single(&region.name.into_bytes()),
-                cpu_mode: CpuMode::User,
-            },
-            0,
-        );
+        let path = region.name.into_bytes();
+        let record = Mmap2Record {
+            pid: pid as i32,
+            tid: pid as i32,
+            address: region.start,
+            length: region.end - region.start,
+            page_offset: region.file_offset,
+            file_id,
+            protection: protection as _,
+            flags: flags as _,
+            path: RawData::Single(&path),
+            cpu_mode: CpuMode::User,
+        };
+        // Like the converter, only write the mappings which are new.
+        let is_new = converter.handle_existing_mmap2(record.clone(), timestamp);
+        if let Some(writer) = perf_data_writer.as_mut().filter(|_| is_new) {
+            if let Err(error) = writer.write_synthesized_mmap2(&record, timestamp) {
+                eprintln!("Could not write to {}: {error}", writer.path().display());
+            }
+        }
     }
 
-    // eprintln!("Enabling perf events...");
-    match attach_mode {
-        AttachMode::StopAttachEnableResume => perf.enable(),
-        AttachMode::AttachWithEnableOnExec => {
-            // The perf event will get enabled automatically once the forked child process execs.
+    Ok(())
+}
+
+/// Opens perf events for a process which is already running and for its descendants,
+/// and registers them with the converter. Returns whether the process could be attached to.
+fn attach_to_running_process(
+    pid: u32,
+    perf: &mut PerfGroup,
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    perf_data_writer: &mut Option<PerfDataWriter>,
+) -> bool {
+    let timestamp = clock_monotonic_nanos();
+    for tree_pid in get_process_tree(&[pid]) {
+        if let Err(error) = perf.open_process(tree_pid, AttachMode::StopAttachEnableResume) {
+            if tree_pid == pid {
+                eprintln!("Failed to start profiling process with PID {pid}: {error}");
+                return false;
+            }
+            // The descendant process may have exited in the meantime.
+            continue;
         }
+        let _ =
+            register_existing_process_from_proc(tree_pid, timestamp, converter, perf_data_writer);
     }
-
-    perf
+    register_event_ids(perf, converter, perf_data_writer);
+    // This also resumes the stopped processes. If recording is paused, the events
+    // are disabled again once their first samples arrive.
+    perf.enable();
+    true
 }
 
 enum SamplerRequest {
     StartProfilingAnotherProcess(u32, AttachMode),
+    /// Start profiling a process which is already running, and its descendants.
+    AttachToRunningProcess(u32),
     StopProfilingOncePerfEventsExhausted,
 }
 
@@ -536,62 +1546,123 @@ fn run_profiler(
     mut converter: Converter<
         framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
     >,
+    mut perf_data_writer: Option<PerfDataWriter>,
+    start_paused: bool,
     _time_limit: Option<Duration>,
     more_processes_request_receiver: Receiver<SamplerRequest>,
     more_processes_reply_sender: Sender<bool>,
     mut stop_receiver: oneshot::Receiver<()>,
     mut initial_exec_name_and_cmdline: Option<(String, Vec<String>)>,
+    allocation_events: Option<AllocationEvents>,
+    mut trace_marker_recorder: Option<TraceMarkerRecorder>,
+    interpretation: EventInterpretation,
+    poll_statm: bool,
+    cpufreq_polling: CpufreqPolling,
+    mut flight_recorder: Option<FlightRecorder>,
 ) -> Profile {
     // eprintln!("Running...");
 
+    // Allocation events usually arrive sooner than the perf events, so we hold on
+    // to them and interleave them with the perf events by timestamp. This way, the
+    // MMAP events of the libraries on their stacks have been handled, and the EXIT
+    // event of their process hasn't. Before an EXIT event, we also receive the
+    // events which are still waiting in the socket.
+    let mut pending_allocation_events: VecDeque<AllocationEvent> = VecDeque::new();
+
+    // The same goes for trace marker writes, except that these can also arrive
+    // later than the perf events. We look for new ones before handling an EXIT
+    // event, because the converter ignores the writes of processes it doesn't know.
+    let mut pending_trace_markers: VecDeque<TraceMarkerEvent> = VecDeque::new();
+
+    // Polled CPU frequencies are read at the current time, but the perf events
+    // which we haven't handled yet can be older, so these are interleaved by
+    // timestamp as well. Each entry is (timestamp, cpu, khz).
+    let mut pending_cpu_frequencies: VecDeque<(u64, u32, u32)> = VecDeque::new();
+
+    let mut group_read_buffer = Vec::new();
+    let mut last_statm_poll: Option<Instant> = None;
+    let mut last_cpufreq_poll: Option<Instant> = None;
+
     let mut should_stop_profiling_once_perf_events_exhausted = false;
     let mut pending_lost_events = 0;
     let mut total_lost_events = 0;
     let mut last_timestamp = 0;
+
+    if let Some(flight_recorder) = &flight_recorder {
+        flight_recorder.share_sample_stacks(&mut converter);
+    }
+
+    let mut is_paused = false;
+    if start_paused {
+        perf.disable();
+        converter.handle_pause(clock_monotonic_nanos());
+        is_paused = true;
+    }
+
     loop {
         if stop_receiver.try_recv().is_ok() {
             break;
         }
 
-        match more_processes_request_receiver.try_recv() {
-            Ok(SamplerRequest::StartProfilingAnotherProcess(another_pid, attach_mode)) => {
-                match perf.open_process(another_pid, attach_mode) {
-                    Ok(_) => {
-                        more_processes_reply_sender.send(true).unwrap();
-                    }
-                    Err(error) => {
-                        eprintln!("Failed to start profiling on subsequent process: {error}");
-                        more_processes_reply_sender.send(false).unwrap();
-                    }
+        match pause_signals::take_request() {
+            Some(PauseRequest::Pause) if !is_paused => {
+                perf.disable();
+                let timestamp = clock_monotonic_nanos();
+                for converter in converters(&mut converter, &mut flight_recorder) {
+                    converter.handle_pause(timestamp);
                 }
+                is_paused = true;
+                eprintln!("Recording paused.");
             }
-            Ok(SamplerRequest::StopProfilingOncePerfEventsExhausted) => {
-                should_stop_profiling_once_perf_events_exhausted = true;
-            }
-            Err(_) => {
-                // No requests pending at the moment.
+            Some(PauseRequest::Resume) if is_paused => {
+                let timestamp = clock_monotonic_nanos();
+                perf.enable();
+                // No FORK, COMM or MMAP records were emitted while the events were
+                // disabled, so catch up on what happened from /proc.
+                let pids = get_process_tree(perf.pids());
+                for converter in converters(&mut converter, &mut flight_recorder) {
+                    converter.handle_resume(timestamp);
+                    for &pid in &pids {
+                        let _ = register_existing_process_from_proc(
+                            pid,
+                            timestamp,
+                            converter,
+                            &mut perf_data_writer,
+                        );
+                    }
+                }
+                is_paused = false;
+                eprintln!("Recording resumed.");
             }
+            _ => {}
+        }
+
+        if let Ok(request) = more_processes_request_receiver.try_recv() {
+            handle_sampler_request(
+                request,
+                &mut perf,
+                &mut converter,
+                &mut flight_recorder,
+                &mut perf_data_writer,
+                &more_processes_reply_sender,
+                &mut should_stop_profiling_once_perf_events_exhausted,
+            );
         }
 
         if perf.is_empty() && !should_stop_profiling_once_perf_events_exhausted {
-            match more_processes_request_receiver.recv() {
-                Ok(SamplerRequest::StartProfilingAnotherProcess(another_pid, attach_mode)) => {
-                    match perf.open_process(another_pid, attach_mode) {
-                        Ok(_) => {
-                            more_processes_reply_sender.send(true).unwrap();
-                        }
-                        Err(error) => {
-                            eprintln!("Failed to start profiling on subsequent process: {error}");
-                            more_processes_reply_sender.send(false).unwrap();
-                        }
-                    }
-                }
-                Ok(SamplerRequest::StopProfilingOncePerfEventsExhausted) => {
-                    should_stop_profiling_once_perf_events_exhausted = true;
-                }
-                Err(_) => {
-                    // No requests pending at the moment.
-                }
+            // Don't block indefinitely, so that we still notice Ctrl+C.
+            if let Ok(request) =
+                more_processes_request_receiver.recv_timeout(Duration::from_millis(100))
+            {
+                handle_sampler_request(
+                    request,
+                    &mut perf,
+                    &mut converter,
+                    &mut flight_recorder,
+                    &mut perf_data_writer,
+                    &more_processes_reply_sender,
+                    &mut should_stop_profiling_once_perf_events_exhausted,
+                );
             }
         }
 
@@ -599,8 +1670,119 @@ fn run_profiler(
             break;
         }
 
+        if let Some(allocation_events) = &allocation_events {
+            pending_allocation_events.extend(allocation_events.try_iter());
+        }
+        if let Some(recorder) = &trace_marker_recorder {
+            pending_trace_markers.extend(recorder.events().try_iter());
+        }
+
+        if poll_statm
+            && !is_paused
+            && last_statm_poll.is_none_or(|last_poll| last_poll.elapsed() >= STATM_POLL_INTERVAL)
+        {
+            let timestamp = clock_monotonic_nanos();
+            for converter in converters(&mut converter, &mut flight_recorder) {
+                for pid in converter.process_pids() {
+                    if let Some(size) = read_anon_rss_size(pid) {
+                        converter.handle_anon_rss_size(pid, timestamp, size);
+                    }
+                }
+            }
+            last_statm_poll = Some(Instant::now());
+        }
+
+        let should_poll_cpufreq = match cpufreq_polling {
+            CpufreqPolling::Never => false,
+            CpufreqPolling::AtStart => last_cpufreq_poll.is_none(),
+            CpufreqPolling::Periodically => last_cpufreq_poll
+                .is_none_or(|last_poll| last_poll.elapsed() >= CPUFREQ_POLL_INTERVAL),
+        };
+        if should_poll_cpufreq && !is_paused {
+            let timestamp = clock_monotonic_nanos();
+            for (cpu, khz) in read_cpu_frequencies() {
+                pending_cpu_frequencies.push_back((timestamp, cpu, khz));
+            }
+            last_cpufreq_poll = Some(Instant::now());
+        }
+
+        let mut received_samples_while_paused = false;
         perf.consume_events(&mut |event_ref| {
+            let attr_index = event_ref.attr_index();
             let record = event_ref.get();
+            if let Some(timestamp) = record.timestamp() {
+                if record.record_type == RecordType::EXIT {
+                    // The exiting thread has sent all of its allocation events and
+                    // trace_marker writes by now, but they may still be in the socket
+                    // or the ftrace buffer. If we handled them after the EXIT, the
+                    // converter would lose them.
+                    if let Some(allocation_events) = &allocation_events {
+                        allocation_events.receive_sent(&mut pending_allocation_events);
+                    }
+                    if let Some(recorder) = &trace_marker_recorder {
+                        recorder.receive_written(&mut pending_trace_markers);
+                    }
+                }
+
+                // Handle the allocations which happened before this event.
+                while pending_allocation_events
+                    .front()
+                    .is_some_and(|event| event.timestamp <= timestamp)
+                {
+                    let event = pending_allocation_events.pop_front().unwrap();
+                    if !is_paused {
+                        for converter in converters(&mut converter, &mut flight_recorder) {
+                            converter
+                                .handle_allocation_sample::<ConvertRegsNative>(&event.as_sample());
+                        }
+                    }
+                }
+
+                while pending_cpu_frequencies
+                    .front()
+                    .is_some_and(|&(polled_at, _, _)| polled_at <= timestamp)
+                {
+                    let (polled_at, cpu, khz) = pending_cpu_frequencies.pop_front().unwrap();
+                    if !is_paused {
+                        for converter in converters(&mut converter, &mut flight_recorder) {
+                            converter.handle_cpu_frequency(cpu, polled_at, khz);
+                        }
+                    }
+                }
+                while pending_trace_markers
+                    .front()
+                    .is_some_and(|event| event.timestamp <= timestamp)
+                {
+                    let event = pending_trace_markers.pop_front().unwrap();
+                    if !is_paused {
+                        for converter in converters(&mut converter, &mut flight_recorder) {
+                            converter.handle_trace_marker(
+                                event.pid,
+                                event.tid,
+                                event.timestamp,
+                                &event.text,
+                            );
+                        }
+                    }
+                }
+            }
+            if is_paused && record.record_type == RecordType::SAMPLE {
+                received_samples_while_paused = true;
+                return;
+            }
+            if let Some(writer) = &mut perf_data_writer {
+                if let Err(error) = writer.write_record(&record) {
+                    eprintln!("Could not write to {}: {error}", writer.path().display());
+                    perf_data_writer = None;
+                }
+            }
+            // The perf.data file gets the original record, but we parse the
+            // counter values separately.
+            let (record, group_read_values) =
+                match split_off_group_read(&record, &mut group_read_buffer) {
+                    Some((record, values)) => (record, Some(values)),
+                    None => (record, None),
+                };
             let parsed_record = record.parse().unwrap();
             // debug!("Recording parsed_record: {:#?}", parsed_record);
 
@@ -613,63 +1795,58 @@ fn run_profiler(
                 last_timestamp = timestamp;
             }
 
-            match parsed_record {
-                EventRecord::Sample(e) => {
-                    converter.handle_main_event_sample::<ConvertRegsNative>(&e);
-                    /*
-                    } else if interpretation.sched_switch_attr_index == Some(attr_index) {
-                        converter.handle_sched_switch_sample::<C>(e);
-                    }*/
-                }
-                EventRecord::Fork(e) => {
-                    converter.handle_fork(e);
-                }
-                EventRecord::Comm(e) => {
-                    if e.is_execve {
-                        // Try to get the command line arguments for this process.
-                        let exec_name_and_cmdline =
-                            if let Some(initial) = initial_exec_name_and_cmdline.take() {
-                                // This COMM event is the first exec that we're processing. If we get
-                                // here, it means we're in the "launch process" case and we're seeing
-                                // the exec for that initial launched process.
-                                Some(initial)
-                            } else {
-                                // Attempt to get the process cmdline from /proc/{pid}/cmdline.
-                                // This isn't very reliable because we're processing the perf event records
-                                // in batches, with a delay, so the COMM record may be old enough that the
-                                // pid no longer exists, or the pid may even refer to a different process now.
-                                // Unfortunately there are no perf event records that give us the process
-                                // command line.
-                                get_process_cmdline(e.pid as u32).ok()
-                            };
-                        converter.handle_exec(e, record.timestamp(), exec_name_and_cmdline);
+            // Try to get the command line arguments for a process which executes
+            // a new program.
+            let exec_name_and_cmdline = match &parsed_record {
+                EventRecord::Comm(e) if e.is_execve => {
+                    if let Some(initial) = initial_exec_name_and_cmdline.take() {
+                        // This COMM event is the first exec that we're processing. If we get
+                        // here, it means we're in the "launch process" case and we're seeing
+                        // the exec for that initial launched process.
+                        Some(initial)
                     } else {
-                        converter.handle_thread_rename(e, record.timestamp());
+                        // Attempt to get the process cmdline from /proc/{pid}/cmdline.
+                        // This isn't very reliable because we're processing the perf event records
+                        // in batches, with a delay, so the COMM record may be old enough that the
+                        // pid no longer exists, or the pid may even refer to a different process now.
+                        // Unfortunately there are no perf event records that give us the process
+                        // command line.
+                        get_process_cmdline(e.pid as u32).ok()
                     }
                 }
-                EventRecord::Exit(e) => {
-                    converter.handle_exit(e);
-                }
-                EventRecord::Mmap(e) => {
-                    converter.handle_mmap(e, last_timestamp);
-                }
-                EventRecord::Mmap2(e) => {
-                    converter.handle_mmap2(e, last_timestamp);
-                }
-                EventRecord::ContextSwitch(e) => {
-                    let common = match record.common_data() {
-                        Ok(common) => common,
-                        Err(_) => return,
-                    };
-                    converter.handle_context_switch(e, common);
-                }
-                EventRecord::Lost(event) => {
-                    pending_lost_events += event.count;
-                    total_lost_events += event.count;
-                    return;
-                }
-                _ => {}
+                _ => None,
+            };
+            if let EventRecord::Lost(event) = &parsed_record {
+                pending_lost_events += event.count;
+                total_lost_events += event.count;
+            }
+
+            // The previous generation of the flight recorder gets the same records.
+            if let Some(previous) = flight_recorder
+                .as_mut()
+                .and_then(FlightRecorder::previous_mut)
+            {
+                handle_record(
+                    previous,
+                    &record,
+                    parsed_record.clone(),
+                    attr_index,
+                    group_read_values.as_ref(),
+                    &interpretation,
+                    exec_name_and_cmdline.clone(),
+                    last_timestamp,
+                );
             }
+            handle_record(
+                &mut converter,
+                &record,
+                parsed_record,
+                attr_index,
+                group_read_values.as_ref(),
+                &interpretation,
+                exec_name_and_cmdline,
+                last_timestamp,
+            );
 
             if pending_lost_events > 0 {
                 // eprintln!("Pending lost events: {pending_lost_events}");
@@ -677,14 +1854,306 @@ fn run_profiler(
             }
         });
 
+        if received_samples_while_paused {
+            // Events which were opened with enable_on_exec get enabled when the
+            // process execs, even if we're paused.
+            perf.disable();
+        }
+
+        if let Some(flight_recorder) = &mut flight_recorder {
+            if flight_recorder.dump_requested(&converter) {
+                let dumped = match flight_recorder.take_previous() {
+                    Some(previous) => previous,
+                    None => {
+                        // The current generation is all we have.
+                        let next =
+                            new_flight_recorder_generation(flight_recorder, &perf, &converter);
+                        flight_recorder.restart_generation();
+                        std::mem::replace(&mut converter, next)
+                    }
+                };
+                flight_recorder.dump(dumped);
+            } else if flight_recorder.should_rotate() && !is_paused {
+                let next = new_flight_recorder_generation(flight_recorder, &perf, &converter);
+                flight_recorder.rotate(std::mem::replace(&mut converter, next));
+            }
+        }
+
         perf.wait();
     }
 
+    if !is_paused {
+        for (polled_at, cpu, khz) in pending_cpu_frequencies {
+            for converter in converters(&mut converter, &mut flight_recorder) {
+                converter.handle_cpu_frequency(cpu, polled_at, khz);
+            }
+        }
+    }
+
+    if let Some(allocation_events) = allocation_events {
+        // All processes have quit, so the remaining events are on their way.
+        // Don't wait forever in case a process has passed on its end of the socket.
+        while let Ok(event) = allocation_events.recv_timeout(Duration::from_millis(500)) {
+            pending_allocation_events.push_back(event);
+        }
+        if !is_paused {
+            for event in pending_allocation_events {
+                for converter in converters(&mut converter, &mut flight_recorder) {
+                    converter.handle_allocation_sample::<ConvertRegsNative>(&event.as_sample());
+                }
+            }
+        }
+    }
+
+    if let Some(recorder) = &mut trace_marker_recorder {
+        recorder.stop();
+        recorder.receive_written(&mut pending_trace_markers);
+        if !is_paused {
+            for event in pending_trace_markers {
+                for converter in converters(&mut converter, &mut flight_recorder) {
+                    converter.handle_trace_marker(
+                        event.pid,
+                        event.tid,
+                        event.timestamp,
+                        &event.text,
+                    );
+                }
+            }
+        }
+    }
+    // Restore the ftrace settings.
+    drop(trace_marker_recorder);
+
     if total_lost_events > 0 {
         eprintln!("Lost {total_lost_events} events.");
     }
 
-    converter.finish()
+    if let Some(writer) = perf_data_writer {
+        let path = writer.path().to_owned();
+        match writer.finish() {
+            Ok(()) => eprintln!("Saved perf events to {}", path.display()),
+            Err(error) => eprintln!("Could not write to {}: {error}", path.display()),
+        }
+    }
+
+    match flight_recorder {
+        Some(flight_recorder) => flight_recorder.finish(converter).finish(),
+        None => converter.finish(),
+    }
+}
+
+/// The converters which get the recorded events: the current one, and the
+/// previous generation of the flight recorder.
+fn converters<'a>(
+    converter: &'a mut LiveConverter,
+    flight_recorder: &'a mut Option<FlightRecorder>,
+) -> impl Iterator<Item = &'a mut LiveConverter> {
+    let previous = flight_recorder
+        .as_mut()
+        .and_then(FlightRecorder::previous_mut);
+    std::iter::once(converter).chain(previous)
+}
+
+/// Creates the converter for a new generation of the flight recorder, and tells
+/// it about the open perf events and the processes which `converter` knows.
+fn new_flight_recorder_generation(
+    flight_recorder: &mut FlightRecorder,
+    perf: &PerfGroup,
+    converter: &LiveConverter,
+) -> LiveConverter {
+    let mut next = flight_recorder.new_generation();
+    next.continue_iterations(converter);
+    register_event_ids(perf, &mut next, &mut None);
+    let timestamp = clock_monotonic_nanos();
+    for pid in converter.process_pids() {
+        let _ = register_existing_process_from_proc(pid as u32, timestamp, &mut next, &mut None);
+    }
+    next
+}
+
+/// Handles a request from the main thread to record more processes, or to stop.
+fn handle_sampler_request(
+    request: SamplerRequest,
+    perf: &mut PerfGroup,
+    converter: &mut LiveConverter,
+    flight_recorder: &mut Option<FlightRecorder>,
+    perf_data_writer: &mut Option<PerfDataWriter>,
+    more_processes_reply_sender: &Sender<bool>,
+    should_stop_profiling_once_perf_events_exhausted: &mut bool,
+) {
+    match request {
+        SamplerRequest::StartProfilingAnotherProcess(another_pid, attach_mode) => {
+            match perf.open_process(another_pid, attach_mode) {
+                Ok(_) => {
+                    for converter in converters(converter, flight_recorder) {
+                        register_event_ids(perf, converter, perf_data_writer);
+                        converter.launch_iteration(another_pid as i32);
+                    }
+                    more_processes_reply_sender.send(true).unwrap();
+                }
+                Err(error) => {
+                    eprintln!("Failed to start profiling on subsequent process: {error}");
+                    more_processes_reply_sender.send(false).unwrap();
+                }
+            }
+        }
+        SamplerRequest::AttachToRunningProcess(pid) => {
+            let attached = attach_to_running_process(pid, perf, converter, perf_data_writer);
+            if attached {
+                if let Some(previous) = flight_recorder
+                    .as_mut()
+                    .and_then(FlightRecorder::previous_mut)
+                {
+                    register_event_ids(perf, previous, &mut None);
+                    let timestamp = clock_monotonic_nanos();
+                    for tree_pid in get_process_tree(&[pid]) {
+                        let _ = register_existing_process_from_proc(
+                            tree_pid, timestamp, previous, &mut None,
+                        );
+                    }
+                }
+            }
+            more_processes_reply_sender.send(attached).unwrap();
+        }
+        SamplerRequest::StopProfilingOncePerfEventsExhausted => {
+            *should_stop_profiling_once_perf_events_exhausted = true;
+        }
+    }
+}
+
+/// Passes a record to the handler of the converter for its type.
+#[allow(clippy::too_many_arguments)]
+fn handle_record(
+    converter: &mut LiveConverter,
+    record: &RawEventRecord,
+    parsed_record: EventRecord,
+    attr_index: usize,
+    group_read_values: Option<&GroupReadValues>,
+    interpretation: &EventInterpretation,
+    exec_name_and_cmdline: Option<(String, Vec<String>)>,
+    last_timestamp: u64,
+) {
+    match parsed_record {
+        EventRecord::Sample(e) if attr_index == interpretation.main_event_attr_index => {
+            converter.handle_main_event_sample::<ConvertRegsNative>(&e);
+            if let Some(values) = group_read_values {
+                converter.handle_group_read_values(&e, values);
+            }
+        }
+        EventRecord::Sample(e) if interpretation.sched_switch_attr_index == Some(attr_index) => {
+            converter.handle_sched_switch_sample::<ConvertRegsNative>(&e);
+        }
+        EventRecord::Sample(e) => match interpretation.known_event_indices.get(&attr_index) {
+            Some(KnownEvent::RssStat) => converter.handle_rss_stat_sample::<ConvertRegsNative>(&e),
+            Some(
+                KnownEvent::SyscallEnter
+                | KnownEvent::SyscallExit
+                | KnownEvent::LockContentionBegin
+                | KnownEvent::LockContentionEnd,
+            ) if converter.handles_lock_contention_event(attr_index) => {
+                converter.handle_lock_contention_sample::<ConvertRegsNative>(&e, attr_index);
+                // With `--syscalls`, futex calls also get syscall markers.
+                if converter.handles_syscall_event(attr_index) {
+                    converter.handle_syscall_sample::<ConvertRegsNative>(&e, attr_index)
+                }
+            }
+            Some(KnownEvent::SyscallEnter | KnownEvent::SyscallExit)
+                if converter.handles_syscall_event(attr_index) =>
+            {
+                converter.handle_syscall_sample::<ConvertRegsNative>(&e, attr_index)
+            }
+            Some(KnownEvent::SchedWaking | KnownEvent::SchedWakeup)
+                if converter.handles_wakeup_event(attr_index) =>
+            {
+                converter.handle_sched_wakeup_sample::<ConvertRegsNative>(&e)
+            }
+            Some(KnownEvent::SchedMigrateTask) if converter.has_thread_states() => {
+                converter.handle_sched_migrate_task_sample(&e)
+            }
+            Some(KnownEvent::SchedProcessFork) => {
+                converter.handle_sched_process_fork_sample::<ConvertRegsNative>(&e, attr_index)
+            }
+            Some(KnownEvent::SchedProcessExec) => {
+                converter.handle_sched_process_exec_sample::<ConvertRegsNative>(&e, attr_index)
+            }
+            // The exit statuses come from these events, but they're also recorded
+            // with `--tracepoint`, so they get the same markers as other tracepoints.
+            Some(KnownEvent::SignalGenerate) => {
+                converter.handle_signal_generate_sample(&e, attr_index);
+                converter.handle_other_event_sample::<ConvertRegsNative>(&e, attr_index)
+            }
+            Some(KnownEvent::SignalDeliver) => {
+                converter.handle_signal_deliver_sample(&e, attr_index);
+                converter.handle_other_event_sample::<ConvertRegsNative>(&e, attr_index)
+            }
+            Some(KnownEvent::ExitGroup) => {
+                converter.handle_exit_group_sample(&e, attr_index);
+                // With `--syscalls`, exit_group calls get syscall markers instead.
+                if converter.handles_syscall_event(attr_index) {
+                    converter.handle_syscall_sample::<ConvertRegsNative>(&e, attr_index)
+                } else {
+                    converter.handle_other_event_sample::<ConvertRegsNative>(&e, attr_index)
+                }
+            }
+            Some(
+                event @ (KnownEvent::BlockRqInsert
+                | KnownEvent::BlockRqIssue
+                | KnownEvent::BlockRqComplete),
+            ) => converter.handle_block_rq_sample::<ConvertRegsNative>(&e, event, attr_index),
+            Some(KnownEvent::ProbeEntry | KnownEvent::ProbeReturn) => {
+                converter.handle_probe_sample::<ConvertRegsNative>(&e, attr_index)
+            }
+            Some(KnownEvent::UsdtProbe) => {
+                converter.handle_usdt_sample::<ConvertRegsNative>(&e, attr_index)
+            }
+            Some(event @ (KnownEvent::CpuFrequency | KnownEvent::CpuIdle)) => {
+                converter.handle_cpu_power_sample(&e, event)
+            }
+            _ => converter.handle_other_event_sample::<ConvertRegsNative>(&e, attr_index),
+        },
+        EventRecord::Fork(e) => {
+            converter.handle_fork(e);
+        }
+        EventRecord::Comm(e) => {
+            if e.is_execve {
+                converter.handle_exec(e, record.timestamp(), exec_name_and_cmdline);
+            } else {
+                converter.handle_thread_rename(e, record.timestamp());
+            }
+        }
+        EventRecord::Exit(e) => {
+            converter.handle_exit(e);
+        }
+        EventRecord::Mmap(e) => {
+            converter.handle_mmap(e, last_timestamp);
+        }
+        EventRecord::Mmap2(e) => {
+            converter.handle_mmap2(e, last_timestamp);
+        }
+        EventRecord::ContextSwitch(e) => {
+            let common = match record.common_data() {
+                Ok(common) => common,
+                Err(_) => return,
+            };
+            converter.handle_context_switch(e, common);
+        }
+        EventRecord::Lost(event) => {
+            if let Ok(common) = record.common_data() {
+                converter.handle_lost(event, common);
+            }
+        }
+        EventRecord::Throttle(e) => {
+            if let Ok(common) = record.common_data() {
+                converter.handle_throttle(e, common);
+            }
+        }
+        EventRecord::Unthrottle(e) => {
+            if let Ok(common) = record.common_data() {
+                converter.handle_unthrottle(e, common);
+            }
+        }
+        _ => {}
+    }
 }
 
 pub fn read_string_lossy<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
@@ -692,6 +2161,133 @@ pub fn read_string_lossy<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
     Ok(String::from_utf8_lossy(&data).into_owned())
 }
 
+/// Returns the given pids and the pids of all their descendant processes which
+/// are currently running, parents before their children.
+fn get_process_tree(root_pids: &[u32]) -> Vec<u32> {
+    let mut pids: Vec<u32> = Vec::new();
+    for &pid in root_pids {
+        if !pids.contains(&pid) {
+            pids.push(pid);
+        }
+    }
+
+    // /proc/<pid>/task/<tid>/children is only present if the kernel was built
+    // with CONFIG_PROC_CHILDREN. Otherwise, find the children via the parent
+    // pids in /proc/<pid>/stat.
+    let mut parent_pids = None;
+    let mut i = 0;
+    while i < pids.len() {
+        let children = get_child_pids(pids[i]).unwrap_or_else(|| {
+            let parent_pids = parent_pids.get_or_insert_with(get_parent_pids);
+            let parent = pids[i];
+            parent_pids
+                .iter()
+                .filter(|(_, &ppid)| ppid == parent)
+                .map(|(&pid, _)| pid)
+                .collect()
+        });
+        for child in children {
+            if !pids.contains(&child) {
+                pids.push(child);
+            }
+        }
+        i += 1;
+    }
+    pids
+}
+
+/// Returns the pids of the running processes whose name, or whose executable
+/// name, matches `included_processes`. Kernel threads and samply itself are
+/// never included.
+fn find_matching_processes(included_processes: &IncludedProcesses) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let own_pid = std::process::id();
+    let mut pids: Vec<u32> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_string_lossy().parse::<u32>().ok())
+        .filter(|&pid| pid != own_pid)
+        .filter(|&pid| {
+            // Kernel threads have an empty command line.
+            let Ok((exe_name, _cmdline)) = get_process_cmdline(pid) else {
+                return false;
+            };
+            let comm_name = read_string_lossy(format!("/proc/{pid}/comm")).unwrap_or_default();
+            included_processes.should_include(Some(comm_name.trim_end()), pid)
+                || included_processes.should_include(Some(&exe_name), pid)
+        })
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+/// Returns the pids of the processes in the given cgroup and in its descendant cgroups.
+fn get_cgroup_pids(cgroup_path: &Path) -> Vec<u32> {
+    let mut pids = Vec::new();
+    if let Ok(procs) = read_string_lossy(cgroup_path.join("cgroup.procs")) {
+        pids.extend(
+            procs
+                .lines()
+                .filter_map(|pid| pid.trim().parse::<u32>().ok()),
+        );
+    }
+    if let Ok(entries) = std::fs::read_dir(cgroup_path) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                pids.extend(get_cgroup_pids(&entry.path()));
+            }
+        }
+    }
+    pids
+}
+
+/// Reads the child processes of all threads of the given process from
+/// /proc/<pid>/task/*/children. Returns None if these files aren't available.
+fn get_child_pids(pid: u32) -> Option<Vec<u32>> {
+    let entries = std::fs::read_dir(format!("/proc/{pid}/task")).ok()?;
+    let mut children = Vec::new();
+    let mut found_children_file = false;
+    for entry in entries.flatten() {
+        let Ok(contents) = read_string_lossy(entry.path().join("children")) else {
+            continue;
+        };
+        found_children_file = true;
+        children.extend(
+            contents
+                .split_whitespace()
+                .filter_map(|pid| pid.parse::<u32>().ok()),
+        );
+    }
+    found_children_file.then_some(children)
+}
+
+/// Returns a map of pid to parent pid for all running processes.
+fn get_parent_pids() -> HashMap<u32, u32> {
+    let mut parent_pids = HashMap::new();
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return parent_pids;
+    };
+    for entry in entries.flatten() {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        // The parent pid is the second field after the parenthesized comm name.
+        let Ok(stat) = read_string_lossy(format!("/proc/{pid}/stat")) else {
+            continue;
+        };
+        let Some(ppid) = stat
+            .rfind(')')
+            .and_then(|pos| stat[pos + 1..].split_whitespace().nth(1))
+            .and_then(|ppid| ppid.parse().ok())
+        else {
+            continue;
+        };
+        parent_pids.insert(pid, ppid);
+    }
+    parent_pids
+}
+
 fn get_process_cmdline(pid: u32) -> std::io::Result<(String, Vec<String>)> {
     let path = format!("/proc/{pid}/cmdline");
     let cmdline_bytes = std::fs::read(&path)?;
@@ -18,6 +18,7 @@ macro_rules! flag {
     };
 }
 
+pub const PERF_FLAG_PID_CGROUP: c_ulong = 1 << 2;
 pub const PERF_FLAG_FD_CLOEXEC: c_ulong = 1 << 3;
 
 pub const PERF_TYPE_HARDWARE: u32 = 0;
@@ -51,6 +52,8 @@ pub const PERF_ATTR_FLAG_USE_CLOCKID: u64 = flag!(25);
 pub const PERF_ATTR_FLAG_CONTEX_SWITCH: u64 = flag!(26);
 
 pub const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+pub const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+pub const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
 pub const PERF_COUNT_HW_REF_CPU_CYCLES: u64 = 9;
 
 pub const PERF_COUNT_SW_CPU_CLOCK: u64 = 0;
@@ -91,6 +94,11 @@ pub const PERF_SAMPLE_IDENTIFIER: u64 = 1 << 16;
 pub const PERF_SAMPLE_TRANSACTION: u64 = 1 << 17;
 pub const PERF_SAMPLE_REGS_INTR: u64 = 1 << 18;
 
+pub const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
+pub const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
+pub const PERF_FORMAT_ID: u64 = 1 << 2;
+pub const PERF_FORMAT_GROUP: u64 = 1 << 3;
+
 pub const PERF_REG_X86_AX: u64 = 0;
 pub const PERF_REG_X86_BX: u64 = 1;
 pub const PERF_REG_X86_CX: u64 = 2;
@@ -222,6 +230,7 @@ mod ioctl {
         pub const IOC_SIZEBITS: c_ulong = 14;
         pub const IOC_DIRBITS: c_ulong = 2;
         pub const IOC_NONE: c_ulong = 0;
+        pub const IOC_READ: c_ulong = 2;
     }
 
     #[cfg(any(
@@ -236,6 +245,7 @@ mod ioctl {
         pub const IOC_SIZEBITS: c_ulong = 13;
         pub const IOC_DIRBITS: c_ulong = 3;
         pub const IOC_NONE: c_ulong = 1;
+        pub const IOC_READ: c_ulong = 2;
     }
 
     pub use self::arch::*;
@@ -263,8 +273,15 @@ macro_rules! io {
     };
 }
 
+macro_rules! ior {
+    ($kind:expr, $nr:expr, $size:expr) => {
+        ioc!(ioctl::IOC_READ, $kind, $nr, $size)
+    };
+}
+
 pub const PERF_EVENT_IOC_ENABLE: c_ulong = io!(b'$', 0);
 pub const PERF_EVENT_IOC_DISABLE: c_ulong = io!(b'$', 1);
+pub const PERF_EVENT_IOC_ID: c_ulong = ior!(b'$', 7, std::mem::size_of::<u64>() as c_ulong);
 
 #[repr(C)]
 pub struct PerfEventAttr {
@@ -372,3 +389,13 @@ pub fn sys_perf_event_open(
         ) as c_int
     }
 }
+
+/// The current time in the clock that our perf events use for their timestamps.
+pub fn clock_monotonic_nanos() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
@@ -0,0 +1,328 @@
+//! Reads the text which processes write to the ftrace `trace_marker` file, for
+//! example with atrace-style `B|<pid>|<name>` / `E|<pid>` sections.
+//!
+//! perf doesn't deliver `ftrace:print` samples for `trace_marker` writes, so we
+//! read them from an ftrace ring buffer instead, via `trace_pipe`. So that we
+//! neither change the global ftrace settings nor consume events which other
+//! ftrace users are waiting for, we create our own tracing instance in
+//! `instances/`, which gets a copy of the `trace_marker` writes through the
+//! `copy_trace_marker` option. The instance uses CLOCK_MONOTONIC, so that the
+//! timestamps match the timestamps of the perf events, and has the
+//! `record-tgid` option, so that we know which process each write came from.
+//! The instance is removed when the [`TraceMarkerRecorder`] is dropped.
+//!
+//! The `trace_marker` file is shared by the whole system, so the events contain
+//! the writes from all processes. The converter ignores the writes from
+//! processes which aren't being profiled.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crossbeam_channel::{Receiver, Sender};
+
+use super::sys::clock_monotonic_nanos;
+use super::tracefs::find_tracefs;
+
+/// The prefix of the names of our tracing instances, which is followed by the
+/// pid of the samply process.
+const INSTANCE_NAME_PREFIX: &str = "samply-";
+
+/// A single write to the `trace_marker` file.
+#[derive(Debug, Clone)]
+pub struct TraceMarkerEvent {
+    /// The process of the writing thread, if known.
+    pub pid: Option<i32>,
+    pub tid: i32,
+    /// CLOCK_MONOTONIC, in nanoseconds.
+    pub timestamp: u64,
+    pub text: String,
+}
+
+/// Creates a tracing instance and reads the `trace_marker` writes from it on a
+/// background thread.
+pub struct TraceMarkerRecorder {
+    instance: PathBuf,
+    stop: Arc<AtomicBool>,
+    reader_thread: Option<JoinHandle<()>>,
+    events: Receiver<TraceMarkerEvent>,
+    /// The reader thread holds the lock while it reads from `trace_pipe` and
+    /// passes the events on to `events`.
+    trace_pipe: Option<Arc<Mutex<TracePipeReader>>>,
+}
+
+impl TraceMarkerRecorder {
+    pub fn new() -> io::Result<Self> {
+        let instances = find_tracefs()?.join("instances");
+        remove_stale_instances(&instances);
+
+        let instance = instances.join(format!("{INSTANCE_NAME_PREFIX}{}", std::process::id()));
+        fs::create_dir(&instance)?;
+
+        // From here on, the instance is removed when the recorder is dropped.
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut recorder = TraceMarkerRecorder {
+            instance,
+            stop: Arc::new(AtomicBool::new(false)),
+            reader_thread: None,
+            events: receiver,
+            trace_pipe: None,
+        };
+        if !recorder.instance.join("options/copy_trace_marker").exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "this kernel can't copy trace_marker writes to other tracing instances \
+                 (the copy_trace_marker option is missing)",
+            ));
+        }
+        recorder.write_setting("trace_clock", "mono")?;
+        recorder.write_setting("options/record-tgid", "1")?;
+        recorder.write_setting("options/copy_trace_marker", "1")?;
+
+        let trace_pipe = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(recorder.instance.join("trace_pipe"))?;
+        let trace_pipe = Arc::new(Mutex::new(TracePipeReader {
+            trace_pipe,
+            start_time: clock_monotonic_nanos(),
+            pending_line: Vec::new(),
+        }));
+        recorder.trace_pipe = Some(trace_pipe.clone());
+        let stop = recorder.stop.clone();
+        recorder.reader_thread = Some(thread::spawn(move || {
+            read_trace_pipe(&trace_pipe, &stop, &sender)
+        }));
+
+        Ok(recorder)
+    }
+
+    /// The writes which have been read so far, oldest first.
+    pub fn events(&self) -> &Receiver<TraceMarkerEvent> {
+        &self.events
+    }
+
+    /// Appends all writes which happened before this call to `events`,
+    /// including the ones which the reader thread hasn't picked up yet. Once a
+    /// process has exited, this includes all of its writes.
+    pub fn receive_written(&self, events: &mut VecDeque<TraceMarkerEvent>) {
+        let Some(trace_pipe) = &self.trace_pipe else {
+            return;
+        };
+        let mut trace_pipe = trace_pipe.lock().unwrap();
+        events.extend(self.events.try_iter());
+        trace_pipe.read_available(|event| {
+            events.push_back(event);
+            true
+        });
+    }
+
+    /// Stops the reader thread, once it has read all the writes which are in
+    /// the ftrace buffer at this point.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(reader_thread) = self.reader_thread.take() {
+            let _ = reader_thread.join();
+        }
+    }
+
+    fn write_setting(&self, name: &str, value: &str) -> io::Result<()> {
+        fs::write(self.instance.join(name), value)
+    }
+}
+
+impl Drop for TraceMarkerRecorder {
+    fn drop(&mut self) {
+        // The instance can only be removed once its trace_pipe is closed.
+        self.stop();
+        self.trace_pipe = None;
+        let _ = fs::remove_dir(&self.instance);
+    }
+}
+
+/// Removes the instances which were left behind by samply processes which
+/// didn't exit cleanly.
+fn remove_stale_instances(instances: &Path) {
+    let Ok(entries) = fs::read_dir(instances) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(INSTANCE_NAME_PREFIX))
+            .filter(|pid| pid.parse::<u32>().is_ok())
+        else {
+            continue;
+        };
+        if !Path::new("/proc").join(pid).exists() {
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+}
+
+/// Our end of the instance's `trace_pipe`, which is opened in non-blocking mode.
+struct TracePipeReader {
+    trace_pipe: File,
+    /// Writes from before this time are left over from an earlier session.
+    start_time: u64,
+    /// The start of a line whose end hasn't been read yet.
+    pending_line: Vec<u8>,
+}
+
+impl TracePipeReader {
+    /// Reads until `trace_pipe` is empty, and calls `on_event` for each write.
+    /// Returns false once no more writes can be read, either because of an
+    /// error or because `on_event` returned false.
+    fn read_available(&mut self, mut on_event: impl FnMut(TraceMarkerEvent) -> bool) -> bool {
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            match self.trace_pipe.read(&mut buffer) {
+                Ok(0) => return false,
+                Ok(len) => {
+                    self.pending_line.extend_from_slice(&buffer[..len]);
+                    while let Some(line_end) = memchr::memchr(b'\n', &self.pending_line) {
+                        let line = String::from_utf8_lossy(&self.pending_line[..line_end]);
+                        if let Some(event) = parse_trace_pipe_line(&line) {
+                            if event.timestamp >= self.start_time && !on_event(event) {
+                                return false;
+                            }
+                        }
+                        self.pending_line.drain(..=line_end);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+fn read_trace_pipe(
+    trace_pipe: &Mutex<TracePipeReader>,
+    stop: &AtomicBool,
+    sender: &Sender<TraceMarkerEvent>,
+) {
+    let fd = trace_pipe.lock().unwrap().trace_pipe.as_raw_fd();
+    loop {
+        // Check the stop flag before reading, so that we read everything that
+        // was written before we were asked to stop.
+        let should_stop = stop.load(Ordering::Relaxed);
+        let can_read_more = trace_pipe
+            .lock()
+            .unwrap()
+            .read_available(|event| sender.send(event).is_ok());
+        if !can_read_more || should_stop {
+            break;
+        }
+        // Wait without the lock, so that `receive_written` doesn't have to wait
+        // for us.
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut poll_fd, 1, 100) };
+    }
+}
+
+/// Parses a `trace_marker` write from a line of `trace_pipe` output. With the
+/// `record-tgid` option, the lines look like this:
+///
+/// ```plain
+///            <...>-24070   (-------) [000] ...1.  3019.391980: tracing_mark_write: B|24070|hello
+///      my-thread-24072   (  24070) [003] .....  3019.392102: tracing_mark_write: E|24070
+/// ```
+///
+/// The tgid is only known once the kernel has seen the thread in a context
+/// switch. If it's missing, we look it up in `/proc`.
+fn parse_trace_pipe_line(line: &str) -> Option<TraceMarkerEvent> {
+    let (header, text) = line.split_once(": tracing_mark_write: ")?;
+    let (header, timestamp) = header.rsplit_once(' ')?;
+    let (secs, micros) = timestamp.split_once('.')?;
+    let timestamp = secs.parse::<u64>().ok()? * 1_000_000_000 + micros.parse::<u64>().ok()? * 1000;
+
+    // The comm can contain spaces, dashes and brackets, so we parse from the
+    // right: the cpu is the last bracketed field, optionally preceded by the tgid.
+    let (header, _cpu_and_flags) = header.rsplit_once(" [")?;
+    let header = header.trim_end();
+    let (comm_and_tid, tgid) = match header.strip_suffix(')') {
+        Some(rest) => {
+            let (comm_and_tid, tgid) = rest.rsplit_once('(')?;
+            (comm_and_tid.trim_end(), tgid.trim().parse::<i32>().ok())
+        }
+        None => (header, None),
+    };
+    let (_comm, tid) = comm_and_tid.rsplit_once('-')?;
+    let tid = tid.parse::<i32>().ok()?;
+    let pid = tgid.or_else(|| tgid_from_proc(tid));
+
+    Some(TraceMarkerEvent {
+        pid,
+        tid,
+        timestamp,
+        text: text.to_owned(),
+    })
+}
+
+fn tgid_from_proc(tid: i32) -> Option<i32> {
+    let status = fs::read_to_string(format!("/proc/{tid}/status")).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Tgid:"))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_trace_pipe_line() {
+        let event = parse_trace_pipe_line(
+            "     my-thread-24072   (  24070) [003] .....  3019.392102: tracing_mark_write: E|24070",
+        )
+        .unwrap();
+        assert_eq!(event.pid, Some(24070));
+        assert_eq!(event.tid, 24072);
+        assert_eq!(event.timestamp, 3_019_392_102_000);
+        assert_eq!(event.text, "E|24070");
+
+        let event = parse_trace_pipe_line(
+            "  web content-17 [000] ...1.    12.000001: tracing_mark_write: B|17|a: b",
+        )
+        .unwrap();
+        assert_eq!(event.tid, 17);
+        assert_eq!(event.timestamp, 12_000_001_000);
+        assert_eq!(event.text, "B|17|a: b");
+
+        assert!(parse_trace_pipe_line("  sh-1 [000] ..... 1.000000: sched_switch: x").is_none());
+    }
+
+    /// Needs write access to tracefs, and a kernel with the `copy_trace_marker`
+    /// option.
+    #[test]
+    #[ignore]
+    fn test_receive_written() {
+        let recorder = TraceMarkerRecorder::new().unwrap();
+        let pid = std::process::id() as i32;
+        let text = format!("C|{pid}|test_receive_written|42");
+        fs::write(find_tracefs().unwrap().join("trace_marker"), &text).unwrap();
+
+        // The write must be returned right away, even if the reader thread
+        // hasn't picked it up yet.
+        let mut events = VecDeque::new();
+        recorder.receive_written(&mut events);
+        let event = events.iter().find(|event| event.text == text).unwrap();
+        assert_eq!(event.pid, Some(pid));
+    }
+}
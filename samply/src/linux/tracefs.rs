@@ -0,0 +1,57 @@
+//! Helpers for finding things in tracefs, the file system through which ftrace
+//! is configured.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const TRACEFS_PATHS: [&str; 2] = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+/// Returns the directory where tracefs is mounted.
+pub fn find_tracefs() -> io::Result<PathBuf> {
+    TRACEFS_PATHS
+        .iter()
+        .map(Path::new)
+        .find(|path| path.join("trace_marker").exists())
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "tracefs is not mounted; try `sudo mount -t tracefs tracefs /sys/kernel/tracing`",
+            )
+        })
+}
+
+/// Looks up the ID of a tracepoint, for use as the `config` of a
+/// `PERF_TYPE_TRACEPOINT` perf event. `name` is `<category>:<name>`, e.g.
+/// `kmem:rss_stat`.
+pub fn tracepoint_id(tracefs: &Path, name: &str) -> io::Result<u64> {
+    let (category, event) = name.split_once(':').ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid tracepoint name {name}"),
+        )
+    })?;
+    let id = fs::read_to_string(tracefs.join("events").join(category).join(event).join("id"))?;
+    id.trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid ID for {name}")))
+}
+
+/// Reads the `format` file of a tracepoint, which describes the layout of the
+/// raw data of its samples.
+pub fn tracepoint_format(tracefs: &Path, name: &str) -> io::Result<String> {
+    let (category, event) = name.split_once(':').ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid tracepoint name {name}"),
+        )
+    })?;
+    fs::read_to_string(
+        tracefs
+            .join("events")
+            .join(category)
+            .join(event)
+            .join("format"),
+    )
+}
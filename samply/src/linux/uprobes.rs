@@ -0,0 +1,409 @@
+//! Creating uprobes for `--probe` and `--usdt`, through the uprobe PMU.
+//!
+//! A probe is given as `/path/to/binary:symbol`, which probes the entry and the
+//! return of the function, or as `/path/to/binary:symbol%return`, which only
+//! probes the return. The symbol is looked up with wholesym, and its address is
+//! translated into a file offset, which is what the uprobe PMU expects.
+//!
+//! The events are named like the ones from `perf probe -x`, i.e.
+//! `probe_<binary>:<symbol>` and `probe_<binary>:<symbol>__return`, so that the
+//! converter treats them the same way when importing perf.data files.
+//!
+//! A USDT probe is given as `[/path/to/binary:]provider:name`, and is found in
+//! the `.note.stapsdt` section of the binary. Each site of the probe becomes a
+//! uprobe named `sdt_<provider>:<name>`, like with `perf probe sdt_<provider>:<name>`.
+//! If the probe has a semaphore, the kernel increments it while the uprobe
+//! exists, so that the process evaluates the probe's arguments.
+
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use object::{Object, ObjectSegment};
+use wholesym::samply_symbols::{demangle_any, sdt_probes, SdtArgument};
+use wholesym::{SymbolManager, SymbolManagerConfig};
+
+use super::perf_event::{Tracepoint, Uprobe};
+use super::profiler::ConvertRegsNative;
+use crate::linux_shared::ConvertRegs;
+
+const UPROBE_PMU_PATH: &str = "/sys/bus/event_source/devices/uprobe";
+
+/// The number of bytes of the user stack which are sampled with each USDT probe
+/// hit, for the arguments which are stored on the stack.
+const USDT_ARGUMENT_STACK_SIZE: u32 = 2048;
+
+/// A parsed `--probe` argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeSpec {
+    pub path: PathBuf,
+    pub symbol: String,
+    /// Whether only the return of the function is probed.
+    pub return_only: bool,
+}
+
+impl ProbeSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        // Split at the last colon which isn't part of a `::`, because paths can
+        // contain colons, and symbols like Rust paths contain `::`.
+        let (path, symbol) = spec
+            .match_indices(':')
+            .map(|(index, _)| index)
+            .rfind(|&index| !spec[..index].ends_with(':') && !spec[index + 1..].starts_with(':'))
+            .map(|index| (&spec[..index], &spec[index + 1..]))
+            .ok_or_else(|| format!("Invalid probe {spec}, expected /path/to/binary:symbol"))?;
+        let (symbol, return_only) = match symbol.strip_suffix("%return") {
+            Some(symbol) => (symbol, true),
+            None => (symbol, false),
+        };
+        if path.is_empty() || symbol.is_empty() {
+            return Err(format!(
+                "Invalid probe {spec}, expected /path/to/binary:symbol"
+            ));
+        }
+        Ok(Self {
+            path: PathBuf::from(path),
+            symbol: symbol.to_owned(),
+            return_only,
+        })
+    }
+
+    /// The name of the entry event, e.g. `probe_libc:malloc`. `perf probe`
+    /// uses the file name up to the first dot as the event group.
+    pub fn event_name(&self) -> String {
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let binary: String = file_name
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("probe_{binary}:{}", self.symbol)
+    }
+}
+
+/// The type of the uprobe PMU, and the layout of its `config`.
+struct UprobePmu {
+    pmu_type: u32,
+    /// The config bit for uretprobes.
+    retprobe_config: u64,
+    /// The first config bit of the file offset of a USDT semaphore, if the
+    /// kernel supports semaphores.
+    ref_ctr_offset_shift: Option<u32>,
+}
+
+/// Reads the type of the uprobe PMU, and its config fields, which are given
+/// as `config:<bit>` or `config:<first bit>-<last bit>` in its format directory.
+fn uprobe_pmu() -> io::Result<UprobePmu> {
+    let pmu_path = Path::new(UPROBE_PMU_PATH);
+    let pmu_type = fs::read_to_string(pmu_path.join("type"))
+        .map_err(|error| io::Error::new(error.kind(), "the uprobe PMU is not available"))?;
+    let pmu_type = pmu_type
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid uprobe PMU type"))?;
+    let config_bit = |format: &str| -> Option<u32> {
+        let format = fs::read_to_string(pmu_path.join("format").join(format)).ok()?;
+        let bits = format.trim().strip_prefix("config:")?;
+        bits.split('-').next()?.parse().ok()
+    };
+    let retprobe_bit = config_bit("retprobe").ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Invalid uprobe retprobe format")
+    })?;
+    Ok(UprobePmu {
+        pmu_type,
+        retprobe_config: 1 << retprobe_bit,
+        ref_ctr_offset_shift: config_bit("ref_ctr_offset"),
+    })
+}
+
+/// Finds the file offset of an address in an ELF file, from the segment which
+/// contains it.
+fn address_file_offset(file: &object::File, address: u64) -> Option<u64> {
+    file.segments().find_map(|segment| {
+        let (file_start, file_size) = segment.file_range();
+        let offset = address.checked_sub(segment.address())?;
+        (offset < file_size).then_some(file_start + offset)
+    })
+}
+
+fn canonical_path(path: &Path) -> Result<CString, String> {
+    let path =
+        fs::canonicalize(path).map_err(|error| format!("Could not find {path:?}: {error}"))?;
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| format!("Invalid path {path:?}"))
+}
+
+/// Finds the file offset of a symbol, by looking up its relative address with
+/// wholesym and finding the segment which contains it.
+fn symbol_file_offset(path: &Path, symbol: &str) -> Result<u64, String> {
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|error| format!("Could not start the symbol lookup: {error}"))?;
+    // Mangled names contain the identifiers of the demangled name, so only the
+    // names which contain the last identifier of the symbol, e.g. `run` for
+    // `my_app::run` or `bar` for `foo::bar(int)`, need to be demangled.
+    let identifier = symbol
+        .split(['(', '<'])
+        .next()
+        .and_then(|path| path.rsplit("::").next())
+        .unwrap_or(symbol);
+    let relative_address = rt.block_on(async {
+        let symbol_manager = SymbolManager::with_config(SymbolManagerConfig::new());
+        let symbol_map = symbol_manager
+            .load_symbol_map_for_binary_at_path(path, None)
+            .await
+            .map_err(|error| format!("Could not load the symbols of {path:?}: {error}"))?;
+        let address = symbol_map
+            .iter_symbols()
+            .find(|(_, name)| {
+                name == symbol || (name.contains(identifier) && demangle_any(name) == symbol)
+            })
+            .map(|(address, _)| address);
+        address.ok_or_else(|| format!("Could not find the symbol {symbol} in {path:?}"))
+    })?;
+
+    let data = fs::read(path).map_err(|error| format!("Could not read {path:?}: {error}"))?;
+    let file = object::File::parse(&data[..])
+        .map_err(|error| format!("Could not parse {path:?}: {error}"))?;
+    // Relative addresses are relative to the address of the first segment, see
+    // `samply_symbols::relative_address_base`.
+    let base = file
+        .segments()
+        .next()
+        .map(|segment| segment.address())
+        .unwrap_or(0);
+    let address = base + u64::from(relative_address);
+    address_file_offset(&file, address)
+        .ok_or_else(|| format!("The symbol {symbol} in {path:?} is not in a mapped segment"))
+}
+
+/// Resolves the `--probe` arguments into uprobe events.
+///
+/// The events are opened on each CPU for all processes. The kernel can't
+/// inherit uprobe PMU events: it looks up the path of the binary again, through
+/// a pointer into samply's memory, and new threads of the profiled processes
+/// fail to start when that doesn't work. The converter skips the hits of other
+/// processes.
+pub fn open_uprobes(probes: &[String]) -> Result<Vec<Tracepoint>, String> {
+    if probes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pmu = uprobe_pmu().map_err(|error| error.to_string())?;
+    let mut tracepoints = Vec::new();
+    for probe in probes {
+        let spec = ProbeSpec::parse(probe)?;
+        let offset = symbol_file_offset(&spec.path, &spec.symbol)?;
+        let path = canonical_path(&spec.path)?;
+        let name = spec.event_name();
+        let uprobe = |name: String, config: u64| Tracepoint {
+            name,
+            id: 0,
+            system_wide: true,
+            sample_callchain: true,
+            uprobe: Some(Uprobe {
+                pmu_type: pmu.pmu_type,
+                config,
+                path: path.clone(),
+                offset,
+                argument_regs_mask: 0,
+                argument_stack_size: 0,
+            }),
+        };
+        if !spec.return_only {
+            tracepoints.push(uprobe(name.clone(), 0));
+        }
+        tracepoints.push(uprobe(format!("{name}__return"), pmu.retprobe_config));
+    }
+    Ok(tracepoints)
+}
+
+/// A parsed `--usdt` argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsdtSpec {
+    /// The binary with the probe, or `None` for the launched command.
+    pub path: Option<PathBuf>,
+    pub provider: String,
+    pub name: String,
+}
+
+impl UsdtSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        // Split at the last two colons, because paths can contain colons.
+        let mut parts = spec.rsplitn(3, ':');
+        let (Some(name), Some(provider)) = (parts.next(), parts.next()) else {
+            return Err(format!(
+                "Invalid USDT probe {spec}, expected [/path/to/binary:]provider:name"
+            ));
+        };
+        let path = parts.next();
+        if name.is_empty() || provider.is_empty() || path == Some("") {
+            return Err(format!(
+                "Invalid USDT probe {spec}, expected [/path/to/binary:]provider:name"
+            ));
+        }
+        Ok(Self {
+            path: path.map(PathBuf::from),
+            provider: provider.to_owned(),
+            name: name.to_owned(),
+        })
+    }
+
+    /// The name of the event, e.g. `sdt_libc:setjmp`, as used by `perf probe`.
+    pub fn event_name(&self) -> String {
+        format!("sdt_{}:{}", self.provider, self.name)
+    }
+}
+
+/// A uprobe on one site of a USDT probe, with the spec of the probe's
+/// arguments at that site.
+#[derive(Debug, Clone)]
+pub struct UsdtProbe {
+    pub tracepoint: Tracepoint,
+    pub arguments: Vec<SdtArgument>,
+}
+
+/// Finds the binary of the launched command, for `--usdt` arguments without a
+/// path.
+pub fn launched_binary(command_name: &std::ffi::OsStr) -> Option<PathBuf> {
+    which::which(command_name).ok()
+}
+
+/// Resolves the `--usdt` arguments into a uprobe for each site of each probe.
+/// `default_binary` is used for the probes which aren't given with a path.
+pub fn open_usdt_probes(
+    specs: &[String],
+    default_binary: Option<&Path>,
+) -> Result<Vec<UsdtProbe>, String> {
+    if specs.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pmu = uprobe_pmu().map_err(|error| error.to_string())?;
+    let mut probes = Vec::new();
+    for spec in specs {
+        let spec = UsdtSpec::parse(spec)?;
+        let path = match (&spec.path, default_binary) {
+            (Some(path), _) => path.as_path(),
+            (None, Some(path)) => path,
+            (None, None) => {
+                return Err(format!(
+                    "The USDT probe {}:{} needs a binary, e.g. /path/to/binary:{}:{}",
+                    spec.provider, spec.name, spec.provider, spec.name
+                ))
+            }
+        };
+        let data = fs::read(path).map_err(|error| format!("Could not read {path:?}: {error}"))?;
+        let file = object::File::parse(&data[..])
+            .map_err(|error| format!("Could not parse {path:?}: {error}"))?;
+        let canonical_path = canonical_path(path)?;
+        let sites: Vec<_> = sdt_probes(&file)
+            .into_iter()
+            .filter(|probe| probe.provider == spec.provider && probe.name == spec.name)
+            .collect();
+        if sites.is_empty() {
+            return Err(format!(
+                "Could not find the USDT probe {}:{} in {path:?}",
+                spec.provider, spec.name
+            ));
+        }
+        for site in sites {
+            let offset = address_file_offset(&file, site.address).ok_or_else(|| {
+                format!(
+                    "The USDT probe {}:{} in {path:?} is not in a mapped segment",
+                    spec.provider, spec.name
+                )
+            })?;
+            let mut config = 0;
+            if let Some(semaphore_address) = site.semaphore_address {
+                let shift = pmu.ref_ctr_offset_shift.ok_or_else(|| {
+                    format!(
+                        "The USDT probe {}:{} has a semaphore, which this kernel doesn't support",
+                        spec.provider, spec.name
+                    )
+                })?;
+                let semaphore_offset =
+                    address_file_offset(&file, semaphore_address).ok_or_else(|| {
+                        format!(
+                            "The semaphore of the USDT probe {}:{} in {path:?} is not in a mapped segment",
+                            spec.provider, spec.name
+                        )
+                    })?;
+                config |= semaphore_offset << shift;
+            }
+            let (argument_regs_mask, argument_stack_size) = if site.arguments.is_empty() {
+                (0, 0)
+            } else {
+                (
+                    ConvertRegsNative::argument_regs_mask(),
+                    USDT_ARGUMENT_STACK_SIZE,
+                )
+            };
+            probes.push(UsdtProbe {
+                tracepoint: Tracepoint {
+                    name: spec.event_name(),
+                    id: 0,
+                    system_wide: true,
+                    sample_callchain: true,
+                    uprobe: Some(Uprobe {
+                        pmu_type: pmu.pmu_type,
+                        config,
+                        path: canonical_path.clone(),
+                        offset,
+                        argument_regs_mask,
+                        argument_stack_size,
+                    }),
+                },
+                arguments: site.arguments,
+            });
+        }
+    }
+    Ok(probes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_spec() {
+        let spec = ProbeSpec::parse("/usr/lib/libc.so.6:malloc").unwrap();
+        assert_eq!(spec.path, Path::new("/usr/lib/libc.so.6"));
+        assert_eq!(spec.symbol, "malloc");
+        assert!(!spec.return_only);
+        assert_eq!(spec.event_name(), "probe_libc:malloc");
+
+        let spec = ProbeSpec::parse("./my-app:my_app::run%return").unwrap();
+        assert_eq!(spec.symbol, "my_app::run");
+        assert!(spec.return_only);
+        assert_eq!(spec.event_name(), "probe_my_app:my_app::run");
+
+        let spec = ProbeSpec::parse("/mnt/c:/app:main").unwrap();
+        assert_eq!(spec.path, Path::new("/mnt/c:/app"));
+        assert_eq!(spec.symbol, "main");
+
+        assert!(ProbeSpec::parse("malloc").is_err());
+        assert!(ProbeSpec::parse("/bin/true:").is_err());
+    }
+
+    #[test]
+    fn test_parse_usdt_spec() {
+        let spec = UsdtSpec::parse("/usr/lib/libc.so.6:libc:setjmp").unwrap();
+        assert_eq!(spec.path.as_deref(), Some(Path::new("/usr/lib/libc.so.6")));
+        assert_eq!(spec.provider, "libc");
+        assert_eq!(spec.name, "setjmp");
+        assert_eq!(spec.event_name(), "sdt_libc:setjmp");
+
+        let spec = UsdtSpec::parse("python:function__entry").unwrap();
+        assert_eq!(spec.path, None);
+        assert_eq!(spec.event_name(), "sdt_python:function__entry");
+
+        assert!(UsdtSpec::parse("setjmp").is_err());
+        assert!(UsdtSpec::parse(":libc:setjmp").is_err());
+        assert!(UsdtSpec::parse("libc:").is_err());
+    }
+}
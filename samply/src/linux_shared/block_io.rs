@@ -0,0 +1,418 @@
+//! Block I/O request markers from the `block:block_rq_insert`,
+//! `block:block_rq_issue` and `block:block_rq_complete` tracepoints.
+//!
+//! Each request becomes an interval marker from its issue to the device until
+//! its completion. The marker goes on the thread which submitted the request:
+//! that's the thread of the insert sample if the request was queued, because
+//! queued requests are often issued from a kworker. Requests whose submitter
+//! we don't know, e.g. writeback from a kernel thread which isn't part of the
+//! profile, go on a per-device thread instead.
+//!
+//! Each device also gets counters for the bytes transferred by completed
+//! requests and for the number of requests which are in flight.
+
+use std::collections::{HashMap, VecDeque};
+
+use fxprof_processed_profile::{
+    CounterHandle, MarkerHandle, MarkerTiming, ProcessHandle, Profile, ThreadHandle, Timestamp,
+};
+use linux_perf_data::Endianness;
+
+use super::processes::PID_MAX_LIMIT;
+use super::tracepoint_format::{FieldValue, TracepointFormat};
+use crate::shared::process_sample_data::BlockIoMarker;
+use crate::shared::unresolved_samples::UnresolvedStackHandle;
+
+/// The fields of a `block:block_rq_insert`, `block:block_rq_issue` or
+/// `block:block_rq_complete` sample which we use.
+///
+/// ```
+/// # cat /sys/kernel/tracing/events/block/block_rq_issue/format
+/// name: block_rq_issue
+/// ID: 1145
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:dev_t dev;        offset:8;       size:4; signed:0;
+///         field:sector_t sector;  offset:16;      size:8; signed:0;
+///         field:unsigned int nr_sector;   offset:24;      size:4; signed:0;
+///         field:unsigned int bytes;       offset:28;      size:4; signed:0;
+///         field:unsigned short ioprio;    offset:32;      size:2; signed:0;
+///         field:char rwbs[10];    offset:34;      size:10;        signed:0;
+///         field:char comm[16];    offset:44;      size:16;        signed:0;
+///         field:__data_loc char[] cmd;    offset:60;      size:4; signed:0;
+/// ```
+///
+/// `block_rq_complete` has `int error` instead of `bytes`. The layout differs
+/// between kernel versions, e.g. before Linux 6.3 there was no `ioprio` field
+/// and `rwbs` was 8 bytes long, so the fields are decoded with the format of
+/// the tracepoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockRq {
+    /// The device number, with the major number in the upper 12 bits.
+    pub dev: u32,
+    pub sector: u64,
+    pub nr_sector: u32,
+    /// The `bytes` field of insert and issue samples, or the size from
+    /// `nr_sector` for complete samples.
+    pub bytes: u32,
+    /// The operation and its flags, e.g. "WS" for a synchronous write.
+    pub rwbs: String,
+}
+
+impl BlockRq {
+    pub fn decode(format: &TracepointFormat, raw: &[u8], endian: Endianness) -> Option<Self> {
+        let integer = |name: &str| format.field(name)?.decode_integer(raw, endian);
+        let nr_sector = integer("nr_sector")? as u32;
+        // The `bytes` field is missing from complete samples, so the size
+        // comes from the number of sectors.
+        let bytes = match integer("bytes") {
+            Some(bytes) => bytes as u32,
+            None => nr_sector.saturating_mul(512),
+        };
+        let rwbs = match format.field("rwbs")?.decode(raw, endian)? {
+            FieldValue::String(rwbs) => rwbs,
+            FieldValue::Number(_) => return None,
+        };
+        Some(Self {
+            dev: integer("dev")? as u32,
+            sector: integer("sector")? as u64,
+            nr_sector,
+            bytes,
+            rwbs,
+        })
+    }
+
+    /// The device as "major:minor", like in `/proc/partitions`.
+    pub fn device_name(&self) -> String {
+        format!("{}:{}", self.dev >> 20, self.dev & 0xfffff)
+    }
+
+    /// The name of the operation in `rwbs`.
+    pub fn operation_name(&self) -> &'static str {
+        if self.rwbs.contains('D') {
+            "Discard"
+        } else if self.rwbs.contains('W') {
+            "Write"
+        } else if self.rwbs.contains('R') {
+            "Read"
+        } else if self.rwbs.contains('F') {
+            "Flush"
+        } else {
+            "Block I/O"
+        }
+    }
+}
+
+/// The thread which submitted a block I/O request, with its stack at the time.
+#[derive(Debug, Clone)]
+pub struct BlockIoSubmitter {
+    pub pid: i32,
+    pub thread: ThreadHandle,
+    pub timestamp: Timestamp,
+    pub timestamp_mono: u64,
+    pub stack: UnresolvedStackHandle,
+}
+
+/// A request which was queued and hasn't been issued to the device yet.
+#[derive(Debug, Clone)]
+struct InsertedRequest {
+    inserted_at: Timestamp,
+    submitter: Option<BlockIoSubmitter>,
+}
+
+/// A request which was issued to the device and hasn't completed yet.
+#[derive(Debug, Clone)]
+struct IssuedRequest {
+    rq: BlockRq,
+    /// When the request was queued, or issued if it wasn't queued.
+    submitted_at: Timestamp,
+    issued_at: Timestamp,
+    submitter: Option<BlockIoSubmitter>,
+}
+
+#[derive(Debug)]
+struct Device {
+    thread: Option<ThreadHandle>,
+    throughput_counter: CounterHandle,
+    queue_depth_counter: CounterHandle,
+}
+
+/// The block I/O requests which are in flight, by device and sector.
+#[derive(Debug)]
+pub struct BlockIoState {
+    start_time: Timestamp,
+    /// The process for the per-device threads and counters.
+    process: Option<ProcessHandle>,
+    devices: HashMap<u32, Device>,
+    /// The number of per-device threads, for their synthetic tids.
+    device_thread_count: u32,
+    /// The requests which were queued and haven't been issued.
+    inserted: HashMap<(u32, u64), VecDeque<InsertedRequest>>,
+    issued: HashMap<(u32, u64), VecDeque<IssuedRequest>>,
+}
+
+impl BlockIoState {
+    pub fn new(start_time: Timestamp) -> Self {
+        Self {
+            start_time,
+            process: None,
+            devices: HashMap::new(),
+            device_thread_count: 0,
+            inserted: HashMap::new(),
+            issued: HashMap::new(),
+        }
+    }
+
+    /// Remembers the submitter of a request which was queued.
+    pub fn insert(
+        &mut self,
+        rq: &BlockRq,
+        submitter: Option<BlockIoSubmitter>,
+        timestamp: Timestamp,
+    ) {
+        self.inserted
+            .entry((rq.dev, rq.sector))
+            .or_default()
+            .push_back(InsertedRequest {
+                inserted_at: timestamp,
+                submitter,
+            });
+    }
+
+    /// Records the issue of a request to the device. If the request was
+    /// queued, its submitter is the thread of the insert sample.
+    pub fn issue(
+        &mut self,
+        profile: &mut Profile,
+        rq: BlockRq,
+        submitter: Option<BlockIoSubmitter>,
+        timestamp: Timestamp,
+    ) {
+        let key = (rq.dev, rq.sector);
+        let (submitted_at, submitter) =
+            match self.inserted.get_mut(&key).and_then(VecDeque::pop_front) {
+                Some(inserted) => (inserted.inserted_at, inserted.submitter),
+                None => (timestamp, submitter),
+            };
+        let device = self.device(profile, rq.dev);
+        profile.add_counter_sample(device.queue_depth_counter, timestamp, 1.0, 1);
+        self.issued
+            .entry(key)
+            .or_default()
+            .push_back(IssuedRequest {
+                rq,
+                submitted_at,
+                issued_at: timestamp,
+                submitter,
+            });
+    }
+
+    /// Adds the marker for a completed request, on the thread of its
+    /// submitter or on the device thread. Returns the marker and the
+    /// submitter, so that the submitter's stack can be attached to it.
+    pub fn complete(
+        &mut self,
+        profile: &mut Profile,
+        rq: &BlockRq,
+        timestamp: Timestamp,
+    ) -> Option<(MarkerHandle, BlockIoSubmitter)> {
+        let key = (rq.dev, rq.sector);
+        let issued = self.issued.get_mut(&key)?.pop_front()?;
+        let device = self.device(profile, rq.dev);
+        profile.add_counter_sample(device.queue_depth_counter, timestamp, -1.0, 1);
+        profile.add_counter_sample(
+            device.throughput_counter,
+            timestamp,
+            issued.rq.bytes.into(),
+            1,
+        );
+        let thread = match &issued.submitter {
+            Some(submitter) => submitter.thread,
+            None => self.device_thread(profile, rq.dev),
+        };
+        let marker = BlockIoMarker {
+            name: profile.handle_for_string(issued.rq.operation_name()),
+            device: profile.handle_for_string(&issued.rq.device_name()),
+            operation: profile.handle_for_string(&issued.rq.rwbs),
+            sector: issued.rq.sector,
+            size: issued.rq.bytes,
+            latency: timestamp.millis_since(issued.submitted_at),
+        };
+        let marker_handle = profile.add_marker(
+            thread,
+            MarkerTiming::Interval(issued.issued_at, timestamp),
+            marker,
+        );
+        Some((marker_handle, issued.submitter?))
+    }
+
+    fn process(&mut self, profile: &mut Profile) -> ProcessHandle {
+        *self
+            .process
+            .get_or_insert_with(|| profile.add_process("Block I/O", 0, self.start_time))
+    }
+
+    fn device(&mut self, profile: &mut Profile, dev: u32) -> &mut Device {
+        let process = self.process(profile);
+        self.devices.entry(dev).or_insert_with(|| {
+            let name = format!("{}:{}", dev >> 20, dev & 0xfffff);
+            let throughput_counter = profile.add_counter(
+                process,
+                &format!("Throughput ({name})"),
+                "Block I/O",
+                "The number of bytes transferred by completed block I/O requests",
+            );
+            let queue_depth_counter = profile.add_counter(
+                process,
+                &format!("Queue depth ({name})"),
+                "Block I/O",
+                "The number of block I/O requests which were issued to the device and haven't completed yet",
+            );
+            Device {
+                thread: None,
+                throughput_counter,
+                queue_depth_counter,
+            }
+        })
+    }
+
+    fn device_thread(&mut self, profile: &mut Profile, dev: u32) -> ThreadHandle {
+        let process = self.process(profile);
+        let start_time = self.start_time;
+        // The device threads get tids which can't be the tid of a real thread.
+        let tid = PID_MAX_LIMIT + 1 + self.device_thread_count;
+        let device = self.device(profile, dev);
+        if let Some(thread) = device.thread {
+            return thread;
+        }
+        let thread = profile.add_thread(process, tid, start_time, false);
+        profile.set_thread_name(
+            thread,
+            &format!("Block device {}:{}", dev >> 20, dev & 0xfffff),
+        );
+        device.thread = Some(thread);
+        self.device_thread_count += 1;
+        thread
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    const ISSUE_FORMAT: &str = "\
+name: block_rq_issue
+ID: 1145
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:dev_t dev;\toffset:8;\tsize:4;\tsigned:0;
+\tfield:sector_t sector;\toffset:16;\tsize:8;\tsigned:0;
+\tfield:unsigned int nr_sector;\toffset:24;\tsize:4;\tsigned:0;
+\tfield:unsigned int bytes;\toffset:28;\tsize:4;\tsigned:0;
+\tfield:unsigned short ioprio;\toffset:32;\tsize:2;\tsigned:0;
+\tfield:char rwbs[10];\toffset:34;\tsize:10;\tsigned:0;
+\tfield:char comm[16];\toffset:44;\tsize:16;\tsigned:0;
+\tfield:__data_loc char[] cmd;\toffset:60;\tsize:4;\tsigned:0;
+";
+
+    /// The format of `block_rq_complete` before Linux 6.3, without `ioprio`.
+    const OLD_COMPLETE_FORMAT: &str = "\
+name: block_rq_complete
+ID: 1146
+format:
+\tfield:dev_t dev;\toffset:8;\tsize:4;\tsigned:0;
+\tfield:sector_t sector;\toffset:16;\tsize:8;\tsigned:0;
+\tfield:unsigned int nr_sector;\toffset:24;\tsize:4;\tsigned:0;
+\tfield:int error;\toffset:28;\tsize:4;\tsigned:1;
+\tfield:char rwbs[8];\toffset:32;\tsize:8;\tsigned:0;
+\tfield:__data_loc char[] cmd;\toffset:40;\tsize:4;\tsigned:0;
+";
+
+    fn raw_data(rwbs_offset: usize, rwbs: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 64];
+        data[8..12].copy_from_slice(&((253 << 20) | 1u32).to_le_bytes());
+        data[16..24].copy_from_slice(&2048u64.to_le_bytes());
+        data[24..28].copy_from_slice(&8u32.to_le_bytes());
+        data[28..32].copy_from_slice(&4096u32.to_le_bytes());
+        data[rwbs_offset..rwbs_offset + rwbs.len()].copy_from_slice(rwbs);
+        data
+    }
+
+    #[test]
+    fn test_decode_block_rq() {
+        let format = TracepointFormat::parse(ISSUE_FORMAT).unwrap();
+        let mut data = raw_data(34, b"WS");
+        // IOPRIO_CLASS_BE, level 4
+        data[32..34].copy_from_slice(&0x4004u16.to_le_bytes());
+        let rq = BlockRq::decode(&format, &data, Endianness::LittleEndian).unwrap();
+        assert_eq!(
+            rq,
+            BlockRq {
+                dev: (253 << 20) | 1,
+                sector: 2048,
+                nr_sector: 8,
+                bytes: 4096,
+                rwbs: "WS".to_string(),
+            }
+        );
+        assert_eq!(rq.device_name(), "253:1");
+        assert_eq!(rq.operation_name(), "Write");
+        assert!(BlockRq::decode(&format, &data[..30], Endianness::LittleEndian).is_none());
+
+        // The error field of complete samples isn't a size.
+        let format = TracepointFormat::parse(OLD_COMPLETE_FORMAT).unwrap();
+        let mut data = raw_data(32, b"R");
+        data[28..32].copy_from_slice(&(-5i32).to_le_bytes());
+        let rq = BlockRq::decode(&format, &data, Endianness::LittleEndian).unwrap();
+        assert_eq!(rq.bytes, 4096);
+        assert_eq!(rq.rwbs, "R");
+        assert_eq!(rq.operation_name(), "Read");
+    }
+
+    #[test]
+    fn test_block_io_requests() {
+        let mut profile = test_profile();
+        let mut state = BlockIoState::new(t(0));
+        let rq = BlockRq {
+            dev: 8 << 20,
+            sector: 100,
+            nr_sector: 8,
+            bytes: 4096,
+            rwbs: "W".to_string(),
+        };
+
+        // A request without a known submitter goes on the device thread.
+        state.issue(&mut profile, rq.clone(), None, t(10));
+        assert!(state.complete(&mut profile, &rq, t(20)).is_none());
+        assert!(state.devices[&rq.dev].thread.is_some());
+        assert!(state.complete(&mut profile, &rq, t(30)).is_none());
+
+        // A queued request keeps the submitter from its insert sample.
+        let process = profile.add_process("test", 1, t(0));
+        let thread = profile.add_thread(process, 1, t(0), true);
+        let submitter = BlockIoSubmitter {
+            pid: 1,
+            thread,
+            timestamp: t(40),
+            timestamp_mono: 40,
+            stack: UnresolvedStackHandle::EMPTY,
+        };
+        state.insert(&rq, Some(submitter), t(40));
+        state.issue(&mut profile, rq.clone(), None, t(50));
+        // The latency includes the time in the queue.
+        assert_eq!(state.issued[&(rq.dev, rq.sector)][0].submitted_at, t(40));
+        let (_marker, submitter) = state.complete(&mut profile, &rq, t(60)).unwrap();
+        assert_eq!(submitter.thread, thread);
+        assert!(state.inserted[&(rq.dev, rq.sector)].is_empty());
+        assert!(state.issued[&(rq.dev, rq.sector)].is_empty());
+    }
+}
@@ -2,17 +2,35 @@ use framehop::aarch64::UnwindRegsAarch64;
 use framehop::x86_64::UnwindRegsX86_64;
 use linux_perf_data::linux_perf_event_reader;
 use linux_perf_event_reader::constants::{
-    PERF_REG_ARM64_LR, PERF_REG_ARM64_PC, PERF_REG_ARM64_SP, PERF_REG_ARM64_X29, PERF_REG_X86_BP,
-    PERF_REG_X86_IP, PERF_REG_X86_SP,
+    PERF_REG_ARM64_LR, PERF_REG_ARM64_PC, PERF_REG_ARM64_SP, PERF_REG_ARM64_X29, PERF_REG_X86_AX,
+    PERF_REG_X86_BP, PERF_REG_X86_BX, PERF_REG_X86_CX, PERF_REG_X86_DI, PERF_REG_X86_DX,
+    PERF_REG_X86_IP, PERF_REG_X86_R8, PERF_REG_X86_SI, PERF_REG_X86_SP,
 };
 use linux_perf_event_reader::Regs;
 
+use super::syscalls::{aarch64_syscall_name, i386_syscall_name, x86_64_syscall_name};
+
 pub trait ConvertRegs {
     type UnwindRegs;
     fn convert_regs(regs: &Regs) -> (u64, u64, Self::UnwindRegs);
 
     #[allow(unused)]
     fn regs_mask() -> u64;
+
+    /// Returns the name of a syscall number on this architecture.
+    fn syscall_name(nr: u64) -> Option<&'static str>;
+
+    /// Returns the name of a syscall number of a 32-bit process on this
+    /// architecture.
+    fn compat_syscall_name(nr: u64) -> Option<&'static str>;
+
+    /// The mask of the general purpose registers, which are sampled for USDT
+    /// probes so that their arguments can be read.
+    fn argument_regs_mask() -> u64;
+
+    /// Returns the perf register index for a register name from a USDT argument
+    /// spec, e.g. `edi` or `x0`. Sub-registers map to the full register.
+    fn register_index(name: &str) -> Option<u64>;
 }
 
 pub struct ConvertRegsX86_64;
@@ -29,6 +47,43 @@ impl ConvertRegs for ConvertRegsX86_64 {
     fn regs_mask() -> u64 {
         (1 << PERF_REG_X86_IP) | (1 << PERF_REG_X86_SP) | (1 << PERF_REG_X86_BP)
     }
+
+    fn syscall_name(nr: u64) -> Option<&'static str> {
+        x86_64_syscall_name(nr)
+    }
+
+    fn compat_syscall_name(nr: u64) -> Option<&'static str> {
+        i386_syscall_name(nr)
+    }
+
+    fn argument_regs_mask() -> u64 {
+        // AX to IP, and R8 to R15, without the flags and the segment registers.
+        0x1ff | (0xff << PERF_REG_X86_R8)
+    }
+
+    fn register_index(name: &str) -> Option<u64> {
+        let index = match name {
+            "rax" | "eax" | "ax" | "al" => PERF_REG_X86_AX,
+            "rbx" | "ebx" | "bx" | "bl" => PERF_REG_X86_BX,
+            "rcx" | "ecx" | "cx" | "cl" => PERF_REG_X86_CX,
+            "rdx" | "edx" | "dx" | "dl" => PERF_REG_X86_DX,
+            "rsi" | "esi" | "si" | "sil" => PERF_REG_X86_SI,
+            "rdi" | "edi" | "di" | "dil" => PERF_REG_X86_DI,
+            "rbp" | "ebp" | "bp" | "bpl" => PERF_REG_X86_BP,
+            "rsp" | "esp" | "sp" | "spl" => PERF_REG_X86_SP,
+            "rip" => PERF_REG_X86_IP,
+            _ => {
+                // r8 to r15, and their sub-registers r8d, r8w and r8b.
+                let number = name.strip_prefix('r')?.trim_end_matches(['d', 'w', 'b']);
+                let number: u64 = number.parse().ok()?;
+                if !(8..=15).contains(&number) {
+                    return None;
+                }
+                PERF_REG_X86_R8 + number - 8
+            }
+        };
+        Some(index)
+    }
 }
 
 pub struct ConvertRegsAarch64;
@@ -49,4 +104,31 @@ impl ConvertRegs for ConvertRegsAarch64 {
             | (1 << PERF_REG_ARM64_SP)
             | (1 << PERF_REG_ARM64_X29)
     }
+
+    fn syscall_name(nr: u64) -> Option<&'static str> {
+        aarch64_syscall_name(nr)
+    }
+
+    fn compat_syscall_name(_nr: u64) -> Option<&'static str> {
+        // We don't have the table of the 32-bit ARM syscalls, so these syscalls
+        // are shown with their numbers.
+        None
+    }
+
+    fn argument_regs_mask() -> u64 {
+        // X0 to X30, SP and PC.
+        (1 << (PERF_REG_ARM64_PC + 1)) - 1
+    }
+
+    fn register_index(name: &str) -> Option<u64> {
+        match name {
+            "sp" | "wsp" => Some(PERF_REG_ARM64_SP),
+            "pc" => Some(PERF_REG_ARM64_PC),
+            _ => {
+                let number = name.strip_prefix(['x', 'w'])?;
+                let number: u64 = number.parse().ok()?;
+                (number <= 30).then_some(number)
+            }
+        }
+    }
 }
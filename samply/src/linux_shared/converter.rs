@@ -1,16 +1,17 @@
 use std::collections::HashMap;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use byteorder::LittleEndian;
 use debugid::DebugId;
 use framehop::{ExplicitModuleSectionInfo, FrameAddress, Module, Unwinder};
 use fxprof_processed_profile::{
-    Category, CategoryColor, CategoryHandle, CpuDelta, FrameFlags, LibraryHandle, LibraryInfo,
-    MarkerFieldFlags, MarkerFieldFormat, MarkerTiming, PlatformSpecificReferenceTimestamp, Profile,
-    ReferenceTimestamp, SamplingInterval, StaticSchemaMarker, StaticSchemaMarkerField,
-    StringHandle, SubcategoryHandle, SymbolTable, ThreadHandle,
+    Category, CategoryColor, CategoryHandle, CpuDelta, FrameFlags, FrameHandle, LibraryHandle,
+    LibraryInfo, MarkerFieldFlags, MarkerFieldFormat, MarkerTiming, PausedRangeReason,
+    PlatformSpecificReferenceTimestamp, Profile, ProfileConfiguration, ReferenceTimestamp,
+    SamplingInterval, StaticSchemaMarker, StaticSchemaMarkerField, StringHandle, SubcategoryHandle,
+    SymbolTable, ThreadHandle, Timestamp,
 };
 use linux_perf_data::linux_perf_event_reader::TaskWasPreempted;
 use linux_perf_data::simpleperf_dso_type::{DSO_DEX_FILE, DSO_KERNEL, DSO_KERNEL_MODULE};
@@ -20,25 +21,41 @@ use linux_perf_data::{
 };
 use linux_perf_event_reader::constants::PERF_CONTEXT_MAX;
 use linux_perf_event_reader::{
-    CommOrExecRecord, CommonData, ContextSwitchRecord, ForkOrExitRecord, Mmap2FileId, Mmap2Record,
-    MmapRecord, RawDataU64, SampleRecord,
+    CommOrExecRecord, CommonData, ContextSwitchRecord, ForkOrExitRecord, LostRecord, Mmap2FileId,
+    Mmap2Record, MmapRecord, RawData, RawDataU64, Regs, SampleRecord, ThrottleRecord,
 };
 use memmap2::Mmap;
 use object::{CompressedFileRange, CompressionFormat, Object, ObjectSection};
 use samply_symbols::{debug_id_for_object, DebugIdExt};
-use wholesym::samply_symbols::demangle_any;
+use wholesym::samply_symbols::{demangle_any, SdtArgument};
 use wholesym::{samply_symbols, CodeId, ElfBuildId};
 
 use super::avma_range::AvmaRange;
+use super::block_io::{BlockIoState, BlockIoSubmitter, BlockRq};
 use super::convert_regs::ConvertRegs;
-use super::event_interpretation::{EventInterpretation, OffCpuIndicator};
+use super::cpu_power::{parse_power_event, CpuPowerState};
+use super::event_interpretation::{EventInterpretation, KnownEvent, OffCpuIndicator};
+use super::group_read::GroupReadCounters;
+use super::host_info::HostInfo;
 use super::injected_jit_object::{correct_bad_perf_jit_so_file, jit_function_name};
+use super::iterations::Iterations;
 use super::kernel_symbols::{kernel_module_build_id, KernelSymbols};
+use super::lock_contention::{LockContentionState, LockSample, PendingLockWait};
 use super::mmap_range_or_vec::MmapRangeOrVec;
 use super::pe_mappings::{PeMappings, SuspectedPeMapping};
+use super::probes::{PendingCall, ProbeState};
+use super::process_lifecycle::ProcessLifecycle;
 use super::processes::Processes;
 use super::rss_stat::{RssStat, MM_ANONPAGES, MM_FILEPAGES, MM_SHMEMPAGES, MM_SWAPENTS};
+use super::sched_wakeup::{SchedWakeup, WakeupState};
 use super::svma_file_range::compute_vma_bias;
+use super::syscalls::{PendingSyscall, SyscallMarker, SyscallSample, SyscallState};
+use super::thread_states::{
+    parse_sched_migrate_task, parse_sched_switch, ThreadState, ThreadStates,
+};
+use super::trace_marker::{TraceMarkerState, TraceMarkerText};
+use super::tracepoint_format::{FieldValue, TracepointFormat, TracepointMarkers};
+use super::usdt::UsdtState;
 use super::vdso::VdsoObject;
 use crate::shared::context_switch::{ContextSwitchHandler, OffCpuSampleGroup};
 use crate::shared::jit_category_manager::JitCategoryManager;
@@ -46,8 +63,9 @@ use crate::shared::lib_mappings::{AndroidArtInfo, LibMappingInfo};
 use crate::shared::per_cpu::Cpus;
 use crate::shared::process_name::make_process_name;
 use crate::shared::process_sample_data::{
-    OtherEventMarker, RssStatMarker, RssStatMember, SchedSwitchMarkerOnCpuTrack,
-    SchedSwitchMarkerOnThreadTrack,
+    ExecMarker, LostEventsMarker, OtherEventMarker, RssStatMarker, RssStatMember,
+    SchedSwitchMarkerOnCpuTrack, SchedSwitchMarkerOnThreadTrack, SpawnMarker, SpawnedByMarker,
+    ThrottledMarker,
 };
 use crate::shared::prop_types::ProfileCreationProps;
 use crate::shared::synthetic_jit_library::SyntheticJitLibrary;
@@ -86,6 +104,16 @@ where
     cpus: Option<Cpus>,
     stack_scratch: Vec<StackFrame>,
 
+    /// The timestamp of the most recent sample on each CPU. Lost events are
+    /// assumed to have been lost since this time.
+    last_sample_time_per_cpu: HashMap<u32, u64>,
+
+    /// The start of the current throttling period, per perf event id.
+    throttle_start_per_event: HashMap<u64, ThrottleStart>,
+
+    /// The time at which the user paused recording, if recording is currently paused.
+    pause_start_time: Option<u64>,
+
     /// Whether repeated frames at the base of the stack should be folded
     /// into one frame.
     fold_recursive_prefix: bool,
@@ -107,6 +135,150 @@ where
 
     /// Whether to emit context switch markers.
     should_emit_cswitch_markers: bool,
+
+    /// The open sections and the counters from `trace_marker` writes.
+    trace_markers: TraceMarkerState,
+    group_read_counters: GroupReadCounters,
+
+    /// The pending syscalls of each thread, with `--syscalls`.
+    syscalls: Option<SyscallState>,
+
+    /// The wakeups which are waiting for the switch-in of the woken thread.
+    wakeups: WakeupState,
+
+    /// The current state of each thread, with `--thread-states`.
+    thread_states: Option<ThreadStates>,
+
+    /// The block I/O requests which are in flight.
+    block_io: BlockIoState,
+
+    /// The frequency counters and idle states of the CPUs.
+    cpu_power: CpuPowerState,
+
+    /// The pending lock waits of each thread, with `--lock-contention`.
+    lock_contention: Option<LockContentionState>,
+
+    /// The calls of probed functions which haven't returned yet.
+    probes: ProbeState,
+
+    /// The argument specs of the USDT probes from `--usdt`.
+    usdt: UsdtState,
+
+    /// The formats of the tracepoints without custom handling, for decoding
+    /// their samples into marker fields.
+    tracepoint_markers: TracepointMarkers,
+
+    /// The start of each running process, for its lifetime marker.
+    process_lifecycle: ProcessLifecycle,
+
+    /// The runs of the launched command, if it's run several times.
+    iterations: Option<Iterations>,
+
+    /// The stack of the last sample, shared with the other converters which
+    /// get the same records.
+    shared_sample_stack: Option<Arc<Mutex<SharedSampleStack>>>,
+
+    /// The flow IDs for the markers of wakeups and spawns.
+    flow_ids: FlowIds,
+}
+
+/// Hands out the IDs which link the markers of a flow, e.g. of a wakeup. All
+/// kinds of flows share them, so that their IDs don't collide in the profile.
+#[derive(Debug, Default)]
+pub struct FlowIds {
+    last: u64,
+}
+
+impl FlowIds {
+    pub fn next(&mut self) -> u64 {
+        self.last += 1;
+        self.last
+    }
+}
+
+/// The stack of the last unwound sample. Converters which get the same records,
+/// like the generations of the flight recorder, share it so that each sample is
+/// only unwound once.
+#[derive(Debug, Default)]
+pub struct SharedSampleStack {
+    sample: Option<SampleKey>,
+    frames: Vec<StackFrame>,
+}
+
+/// Identifies a sample for the [`SharedSampleStack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleKey {
+    Record {
+        id: Option<u64>,
+        timestamp: Option<u64>,
+        tid: Option<i32>,
+        ip: Option<u64>,
+    },
+    Allocation {
+        timestamp: u64,
+        pid: i32,
+        address: u64,
+    },
+}
+
+impl SharedSampleStack {
+    /// Puts the stack of `sample` into `stack`. `unwind` is only called if no
+    /// other converter which shares `shared` has unwound the sample yet.
+    fn get_or_unwind(
+        shared: Option<&Mutex<Self>>,
+        sample: SampleKey,
+        stack: &mut Vec<StackFrame>,
+        unwind: impl FnOnce(&mut Vec<StackFrame>),
+    ) {
+        let Some(shared) = shared else {
+            unwind(stack);
+            return;
+        };
+        let mut shared = shared.lock().unwrap();
+        if shared.sample == Some(sample) {
+            stack.clone_from(&shared.frames);
+            return;
+        }
+        unwind(stack);
+        shared.sample = Some(sample);
+        shared.frames.clone_from(stack);
+    }
+}
+
+/// An allocation or deallocation which was sampled in the profiled process, for
+/// example by samply's LD_PRELOAD library.
+#[allow(unused)]
+pub struct AllocationSample<'a> {
+    pub pid: i32,
+    /// CLOCK_MONOTONIC, in nanoseconds.
+    pub timestamp: u64,
+    pub address: u64,
+    /// The number of bytes that this allocation sample stands for, or None
+    /// for a deallocation.
+    pub size: Option<u64>,
+    /// The user registers inside the allocation function, for the register mask
+    /// of `C` in [`Converter::handle_allocation_sample`]. The frame of the
+    /// allocation function itself is left out of the stack.
+    pub regs: Regs<'a>,
+    /// The raw bytes of the user stack, starting at the stack pointer.
+    pub user_stack: &'a [u8],
+}
+
+/// The start of a throttling period, with the pid, tid and CPU of the throttle
+/// record.
+struct ThrottleStart {
+    timestamp: u64,
+    pid_tid: Option<(i32, i32)>,
+    cpu: Option<u32>,
+}
+
+/// The stack of a tracepoint sample, for a marker on the sampled thread.
+struct MarkerStack {
+    pid: i32,
+    thread: ThreadHandle,
+    timestamp: Timestamp,
+    timestamp_mono: u64,
+    stack: UnresolvedStackHandle,
 }
 
 struct SimpleperfConverterData {
@@ -152,6 +324,10 @@ where
         if let Some(linux_version) = linux_version {
             profile.set_os_name(&format!("Linux {linux_version}"));
         }
+        profile.set_configuration(ProfileConfiguration {
+            features: interpretation.event_names.clone(),
+            ..Default::default()
+        });
         let (off_cpu_sampling_interval_ns, off_cpu_weight_per_sample) =
             match &interpretation.sampling_is_time_based {
                 Some(interval_ns) => (*interval_ns, 1),
@@ -170,8 +346,8 @@ where
             ),
         );
 
+        let start_timestamp = timestamp_converter.convert_time(first_sample_time);
         let cpus = if profile_creation_props.create_per_cpu_threads {
-            let start_timestamp = timestamp_converter.convert_time(first_sample_time);
             Some(Cpus::new(start_timestamp, &mut profile))
         } else {
             None
@@ -202,7 +378,31 @@ where
             context_switch_handler: ContextSwitchHandler::new(off_cpu_sampling_interval_ns),
             unresolved_stacks: UnresolvedStacks::default(),
             off_cpu_indicator: interpretation.off_cpu_indicator,
+            syscalls: profile_creation_props
+                .syscalls
+                .then(|| SyscallState::new(&interpretation.event_names)),
+            wakeups: WakeupState::new(&interpretation.event_names),
+            thread_states: profile_creation_props
+                .thread_states
+                .then(ThreadStates::default),
+            block_io: BlockIoState::new(start_timestamp),
+            cpu_power: CpuPowerState::new(start_timestamp),
+            lock_contention: profile_creation_props
+                .lock_contention
+                .then(|| LockContentionState::new(&interpretation.event_names)),
+            probes: ProbeState::new(&interpretation.event_names),
+            usdt: UsdtState::default(),
+            tracepoint_markers: TracepointMarkers::default(),
+            process_lifecycle: ProcessLifecycle::default(),
+            iterations: None,
+            shared_sample_stack: None,
+            flow_ids: FlowIds::default(),
             event_names: interpretation.event_names,
+            group_read_counters: GroupReadCounters::new(
+                interpretation.attr_index_by_event_id,
+                interpretation.instructions_attr_index,
+                interpretation.cycles_attr_index,
+            ),
             kernel_symbols,
             kernel_image_mapping: None,
             simpleperf,
@@ -213,14 +413,58 @@ where
                 .arg_count_to_include_in_process_name,
             cpus,
             stack_scratch: Vec::new(),
+            last_sample_time_per_cpu: HashMap::new(),
+            throttle_start_per_event: HashMap::new(),
+            pause_start_time: None,
             call_chain_return_addresses_are_preadjusted,
             should_emit_jit_markers: profile_creation_props.should_emit_jit_markers,
             should_emit_cswitch_markers: profile_creation_props.should_emit_cswitch_markers,
+            trace_markers: TraceMarkerState::default(),
         }
     }
 
     pub fn finish(mut self) -> Profile {
+        if let Some(pause_start_time) = self.pause_start_time.take() {
+            let start_time = self.timestamp_converter.convert_time(pause_start_time);
+            self.profile
+                .add_paused_range(start_time, None, PausedRangeReason::ProfilerPaused);
+        }
+
+        // Throttling periods which never ended last until the end of the profile.
+        for start in std::mem::take(&mut self.throttle_start_per_event).into_values() {
+            let start_time = self.timestamp_converter.convert_time(start.timestamp);
+            self.add_collection_gap(start_time, None, start.pid_tid, start.cpu, ThrottledMarker);
+        }
+
+        if let Some(syscalls) = self.syscalls.take() {
+            for (syscall, marker) in syscalls.finish(&mut self.profile) {
+                let timing = MarkerTiming::IntervalStart(syscall.start);
+                self.add_syscall_marker(syscall, timing, marker);
+            }
+        }
+
+        if let Some(mut lock_contention) = self.lock_contention.take() {
+            for wait in lock_contention.finish() {
+                let timing = MarkerTiming::IntervalStart(wait.start);
+                self.add_lock_contention_marker(&wait, timing);
+            }
+        }
+
+        for (probe, call) in self.probes.finish() {
+            self.add_probe_marker(probe, &call, MarkerTiming::IntervalStart(call.start));
+        }
+
+        if let Some(thread_states) = self.thread_states.take() {
+            thread_states.finish(&mut self.profile);
+        }
+        std::mem::take(&mut self.process_lifecycle).finish(&mut self.profile);
+        if let Some(iterations) = self.iterations.take() {
+            iterations.finish(&mut self.profile);
+        }
+        self.cpu_power.finish(&mut self.profile);
+
         let mut profile = self.profile;
+        self.trace_markers.finish(&mut profile);
         self.simpleperf
             .jit_app_cache_library
             .finish_and_set_symbol_table(&mut profile);
@@ -241,6 +485,10 @@ where
         self.profile.set_os_name(os_name);
     }
 
+    pub fn set_host_info(&mut self, host_info: &HostInfo) {
+        host_info.add_to_profile(&mut self.profile);
+    }
+
     pub fn handle_main_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
@@ -255,6 +503,9 @@ where
             .timestamp
             .expect("Can't handle samples without timestamps");
         self.current_sample_time = timestamp;
+        if let Some(cpu) = e.cpu {
+            self.last_sample_time_per_cpu.insert(cpu, timestamp);
+        }
 
         let profile_timestamp = self.timestamp_converter.convert_time(timestamp);
 
@@ -273,6 +524,7 @@ where
             stack,
             self.fold_recursive_prefix,
             self.call_chain_return_addresses_are_preadjusted,
+            self.shared_sample_stack.as_deref(),
         );
 
         let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
@@ -285,6 +537,28 @@ where
         thread.last_sample_timestamp = Some(timestamp);
         let thread_handle = thread.profile_thread;
 
+        if let Some(thread_states) = &mut self.thread_states {
+            thread_states.set_state(
+                &mut self.profile,
+                tid,
+                Some(thread_handle),
+                ThreadState::Running,
+                profile_timestamp,
+            );
+        }
+
+        // Without context switch records, the first sample after a wakeup is
+        // the closest we get to the switch-in.
+        if self.off_cpu_indicator != Some(OffCpuIndicator::ContextSwitches) {
+            if let Some((start, marker)) = self.wakeups.switch_in(tid) {
+                self.profile.add_marker(
+                    thread_handle,
+                    MarkerTiming::Interval(start, profile_timestamp),
+                    marker,
+                );
+            }
+        }
+
         // Consume off-cpu time and clear any saved off-CPU stack.
         let off_cpu_sample = self
             .context_switch_handler
@@ -295,6 +569,9 @@ where
             let cpu_delta_ns = self
                 .context_switch_handler
                 .consume_cpu_delta(&mut thread.context_switch_data);
+            let state_frame = self.thread_states.as_ref().and_then(|thread_states| {
+                thread_states.off_cpu_frame(&mut self.profile, tid, thread_handle)
+            });
             process_off_cpu_sample_group(
                 off_cpu_sample,
                 thread_handle,
@@ -302,6 +579,7 @@ where
                 &self.timestamp_converter,
                 self.off_cpu_weight_per_sample,
                 off_cpu_stack,
+                state_frame,
                 &mut process.unresolved_samples,
             );
         }
@@ -404,6 +682,7 @@ where
             stack,
             self.fold_recursive_prefix,
             self.call_chain_return_addresses_are_preadjusted,
+            self.shared_sample_stack.as_deref(),
         );
 
         let stack_index = self
@@ -415,12 +694,39 @@ where
         let timestamp_mono = e
             .timestamp
             .expect("Can't handle context switch without time");
+        if let (Some(thread_states), Some((prev_state, next_tid))) = (
+            &mut self.thread_states,
+            e.raw.and_then(|raw| parse_sched_switch(raw, self.endian)),
+        ) {
+            let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+            let state = ThreadState::from_sched_switch_prev_state(prev_state);
+            thread_states.set_state(
+                &mut self.profile,
+                tid,
+                Some(thread.profile_thread),
+                state,
+                timestamp,
+            );
+            if next_tid != 0 {
+                // We only know the thread handle of the next thread if we've
+                // seen the thread before.
+                thread_states.set_state(
+                    &mut self.profile,
+                    next_tid,
+                    None,
+                    ThreadState::Running,
+                    timestamp,
+                );
+            }
+        }
+
         if self.off_cpu_indicator == Some(OffCpuIndicator::SchedSwitchAndSamples) {
             // Treat this sched_switch sample as a switch-out.
             // Sometimes we have sched_switch samples but no context switch records; for
             // example when using `simpleperf record --trace-offcpu`.
             self.context_switch_handler
                 .handle_switch_out(timestamp_mono, &mut thread.context_switch_data);
+            self.wakeups.switch_out(tid);
         }
 
         if let (Some(cpu_index), Some(cpus)) = (e.cpu, &mut self.cpus) {
@@ -493,66 +799,935 @@ where
             _ => return,
         };
 
-        let delta = rss_stat.size - *prev_size_of_this_member;
-        *prev_size_of_this_member = rss_stat.size;
+        let delta = rss_stat.size - *prev_size_of_this_member;
+        *prev_size_of_this_member = rss_stat.size;
+
+        if rss_stat.member == MM_ANONPAGES {
+            let counter = process.get_or_make_mem_counter(&mut self.profile);
+            self.profile
+                .add_counter_sample(counter, timestamp, delta as f64, 1);
+        }
+
+        process.check_jitdump(
+            &mut self.jit_category_manager,
+            &mut self.profile,
+            &self.timestamp_converter,
+        );
+
+        let stack = &mut self.stack_scratch;
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            stack,
+            self.fold_recursive_prefix,
+            self.call_chain_return_addresses_are_preadjusted,
+            self.shared_sample_stack.as_deref(),
+        );
+        let unresolved_stack = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+        let thread_handle = process.threads.main_thread.profile_thread;
+        let timing = MarkerTiming::Instant(timestamp);
+        let name = match member {
+            RssStatMember::ResidentFileMappingPages => "RSS Stat FILEPAGES",
+            RssStatMember::ResidentAnonymousPages => "RSS Stat ANONPAGES",
+            RssStatMember::AnonymousSwapEntries => "RSS Stat SHMEMPAGES",
+            RssStatMember::ResidentSharedMemoryPages => "RSS Stat SWAPENTS",
+        };
+        let name = self.profile.handle_for_string(name);
+        let marker_handle = self.profile.add_marker(
+            thread_handle,
+            timing,
+            RssStatMarker::new(name, rss_stat.size, delta),
+        );
+        process.unresolved_samples.attach_stack_to_marker(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            marker_handle,
+        );
+    }
+
+    /// Updates the memory counter of the process with the size of its resident
+    /// anonymous memory, in bytes, for when it's read from `/proc` instead of
+    /// from `kmem:rss_stat` samples. Unchanged sizes are skipped.
+    #[allow(unused)]
+    pub fn handle_anon_rss_size(&mut self, pid: i32, timestamp_mono: u64, size: i64) {
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let delta = size - process.prev_mm_anonpages_size;
+        if delta == 0 {
+            return;
+        }
+        process.prev_mm_anonpages_size = size;
+        let counter = process.get_or_make_mem_counter(&mut self.profile);
+        self.profile
+            .add_counter_sample(counter, timestamp, delta as f64, 1);
+    }
+
+    pub fn handle_other_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+    ) {
+        let Some(name) = self.event_names.get(attr_index) else {
+            return;
+        };
+        let tracepoint_marker = e.raw.and_then(|raw| {
+            self.tracepoint_markers.marker(
+                &mut self.profile,
+                attr_index,
+                name,
+                &raw.as_slice(),
+                self.endian,
+            )
+        });
+        let name = self.profile.handle_for_string(name);
+        let marker_stack = self.get_marker_stack::<C>(e);
+        let timing = MarkerTiming::Instant(marker_stack.timestamp);
+        let marker_handle = match tracepoint_marker {
+            Some(marker) => self.profile.add_marker(marker_stack.thread, timing, marker),
+            None => self
+                .profile
+                .add_marker(marker_stack.thread, timing, OtherEventMarker(name)),
+        };
+        let process = self
+            .processes
+            .get_by_pid(marker_stack.pid, &mut self.profile);
+        process.unresolved_samples.attach_stack_to_marker(
+            marker_stack.thread,
+            marker_stack.timestamp,
+            marker_stack.timestamp_mono,
+            marker_stack.stack,
+            marker_handle,
+        );
+    }
+
+    /// Sets the formats of the recorded tracepoints, by tracepoint name, so that
+    /// the samples of tracepoints without custom handling are decoded into
+    /// marker fields.
+    pub fn set_tracepoint_formats(&mut self, formats: &HashMap<String, TracepointFormat>) {
+        self.tracepoint_markers
+            .set_formats(&self.event_names, formats);
+    }
+
+    /// Unwinds the stack of a tracepoint sample, for a marker on the sampled thread.
+    fn get_marker_stack<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) -> MarkerStack {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let timestamp_mono = e
+            .timestamp
+            .expect("Can't handle samples without timestamps");
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.check_jitdump(
+            &mut self.jit_category_manager,
+            &mut self.profile,
+            &self.timestamp_converter,
+        );
+
+        let stack = &mut self.stack_scratch;
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            stack,
+            self.fold_recursive_prefix,
+            self.call_chain_return_addresses_are_preadjusted,
+            self.shared_sample_stack.as_deref(),
+        );
+
+        let thread = match e.tid {
+            Some(tid) => {
+                process
+                    .threads
+                    .get_thread_by_tid(tid, &mut self.profile)
+                    .profile_thread
+            }
+            None => process.threads.main_thread.profile_thread,
+        };
+
+        let stack = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+        MarkerStack {
+            pid,
+            thread,
+            timestamp,
+            timestamp_mono,
+            stack,
+        }
+    }
+
+    /// Whether the samples of this event are paired up into syscall markers,
+    /// with `--syscalls`.
+    pub fn handles_syscall_event(&self, attr_index: usize) -> bool {
+        self.syscalls
+            .as_ref()
+            .is_some_and(|syscalls| syscalls.handles_event(attr_index))
+    }
+
+    pub fn handle_syscall_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+    ) {
+        let (Some(syscalls), Some(raw)) = (&mut self.syscalls, &e.raw) else {
+            return;
+        };
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+        let is_compat = self.processes.get_by_pid(pid, &mut self.profile).is_compat;
+        let Some(sample) = syscalls.parse_sample(
+            &mut self.profile,
+            attr_index,
+            &raw.as_slice(),
+            self.endian,
+            if is_compat {
+                C::compat_syscall_name
+            } else {
+                C::syscall_name
+            },
+        ) else {
+            return;
+        };
+        match sample {
+            SyscallSample::Enter(entry) => {
+                let marker_stack = self.get_marker_stack::<C>(e);
+                let syscall = PendingSyscall {
+                    entry,
+                    pid: marker_stack.pid,
+                    thread: marker_stack.thread,
+                    start: marker_stack.timestamp,
+                    start_mono: marker_stack.timestamp_mono,
+                    stack: marker_stack.stack,
+                };
+                if let Some(syscalls) = &mut self.syscalls {
+                    syscalls.enter(tid, syscall);
+                }
+            }
+            SyscallSample::Exit { nr, ret } => {
+                let timestamp_mono = e
+                    .timestamp
+                    .expect("Can't handle samples without timestamps");
+                let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+                let Some((syscall, marker)) = self
+                    .syscalls
+                    .as_mut()
+                    .and_then(|syscalls| syscalls.exit(&mut self.profile, tid, nr, ret))
+                else {
+                    return;
+                };
+                let timing = MarkerTiming::Interval(syscall.start, timestamp);
+                self.add_syscall_marker(syscall, timing, marker);
+            }
+        }
+    }
+
+    fn add_syscall_marker(
+        &mut self,
+        syscall: PendingSyscall,
+        timing: MarkerTiming,
+        marker: SyscallMarker,
+    ) {
+        let marker_handle = self.profile.add_marker(syscall.thread, timing, marker);
+        let process = self.processes.get_by_pid(syscall.pid, &mut self.profile);
+        process.unresolved_samples.attach_stack_to_marker(
+            syscall.thread,
+            syscall.start,
+            syscall.start_mono,
+            syscall.stack,
+            marker_handle,
+        );
+    }
+
+    /// Whether the samples of this event are paired up into lock contention
+    /// markers, with `--lock-contention`.
+    pub fn handles_lock_contention_event(&self, attr_index: usize) -> bool {
+        self.lock_contention
+            .as_ref()
+            .is_some_and(|lock_contention| lock_contention.handles_event(attr_index))
+    }
+
+    /// Pairs up the begin and end samples of lock waits. Each wait becomes a
+    /// marker on the waiting thread, and a sample on the "Lock contention"
+    /// thread of the process, weighted by the wait time in milliseconds.
+    pub fn handle_lock_contention_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+    ) {
+        let (Some(lock_contention), Some(raw)) = (&self.lock_contention, &e.raw) else {
+            return;
+        };
+        let Some(sample) = lock_contention.parse_sample(attr_index, &raw.as_slice(), self.endian)
+        else {
+            return;
+        };
+        let tid = e.tid.expect("Can't handle samples without tids");
+        match sample {
+            LockSample::Begin(wait) => {
+                let marker_stack = self.get_marker_stack::<C>(e);
+                let wait = PendingLockWait {
+                    wait,
+                    pid: marker_stack.pid,
+                    thread: marker_stack.thread,
+                    start: marker_stack.timestamp,
+                    start_mono: marker_stack.timestamp_mono,
+                    stack: marker_stack.stack,
+                };
+                if let Some(lock_contention) = &mut self.lock_contention {
+                    lock_contention.begin(tid, wait);
+                }
+            }
+            LockSample::DidNotWait(kind) => {
+                if let Some(lock_contention) = &mut self.lock_contention {
+                    lock_contention.end(tid, kind);
+                }
+            }
+            LockSample::End(kind) => {
+                let timestamp_mono = e
+                    .timestamp
+                    .expect("Can't handle samples without timestamps");
+                let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+                let Some(lock_contention) = &mut self.lock_contention else {
+                    return;
+                };
+                let Some(wait) = lock_contention.end(tid, kind) else {
+                    return;
+                };
+                self.add_lock_contention_marker(
+                    &wait,
+                    MarkerTiming::Interval(wait.start, timestamp),
+                );
+
+                let wait_ns = timestamp_mono.saturating_sub(wait.start_mono);
+                if wait_ns == 0 {
+                    return;
+                }
+                let weight = wait_ns as f64 / 1_000_000.0;
+                let Some(lock_contention) = &mut self.lock_contention else {
+                    return;
+                };
+                let process = self.processes.get_by_pid(wait.pid, &mut self.profile);
+                let thread = lock_contention.contention_thread(
+                    &mut self.profile,
+                    process.profile_process,
+                    wait.pid,
+                    wait.start,
+                );
+                let lock_frame =
+                    LockContentionState::lock_frame(&mut self.profile, thread, &wait.wait);
+                process
+                    .unresolved_samples
+                    .add_sample_with_fractional_weight(
+                        thread,
+                        wait.start,
+                        wait.start_mono,
+                        wait.stack,
+                        CpuDelta::ZERO,
+                        weight,
+                        Some(lock_frame),
+                    );
+            }
+        }
+    }
+
+    fn add_lock_contention_marker(&mut self, wait: &PendingLockWait, timing: MarkerTiming) {
+        let marker = LockContentionState::marker(&mut self.profile, &wait.wait);
+        let marker_handle = self.profile.add_marker(wait.thread, timing, marker);
+        let process = self.processes.get_by_pid(wait.pid, &mut self.profile);
+        process.unresolved_samples.attach_stack_to_marker(
+            wait.thread,
+            wait.start,
+            wait.start_mono,
+            wait.stack,
+            marker_handle,
+        );
+    }
+
+    /// Turns the samples of `perf probe` events into markers for the calls of
+    /// the probed function, and counts the calls.
+    pub fn handle_probe_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+    ) {
+        let Some(event) = self.probes.event(attr_index) else {
+            return;
+        };
+        if !self.is_profiled_process(e) {
+            return;
+        }
+        let tid = e.tid.expect("Can't handle samples without tids");
+        let paired = self.probes.has_entry_and_return(event.probe);
+        if paired && event.is_return {
+            let timestamp_mono = e
+                .timestamp
+                .expect("Can't handle samples without timestamps");
+            let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+            if let Some(call) = self.probes.exit(tid, event.probe) {
+                let timing = MarkerTiming::Interval(call.start, timestamp);
+                self.add_probe_marker(event.probe, &call, timing);
+            }
+            return;
+        }
+
+        let marker_stack = self.get_marker_stack::<C>(e);
+        let call = PendingCall {
+            pid: marker_stack.pid,
+            thread: marker_stack.thread,
+            start: marker_stack.timestamp,
+            start_mono: marker_stack.timestamp_mono,
+            stack: marker_stack.stack,
+        };
+        let process = self.processes.get_by_pid(call.pid, &mut self.profile);
+        self.probes.count_call(
+            &mut self.profile,
+            process.profile_process,
+            call.pid,
+            event.probe,
+            call.start,
+        );
+        if paired {
+            self.probes.enter(tid, event.probe, call);
+        } else {
+            self.add_probe_marker(event.probe, &call, MarkerTiming::Instant(call.start));
+        }
+    }
+
+    fn add_probe_marker(&mut self, probe: usize, call: &PendingCall, timing: MarkerTiming) {
+        let marker = self.probes.marker(&mut self.profile, probe);
+        let marker_handle = self.profile.add_marker(call.thread, timing, marker);
+        let process = self.processes.get_by_pid(call.pid, &mut self.profile);
+        process.unresolved_samples.attach_stack_to_marker(
+            call.thread,
+            call.start,
+            call.start_mono,
+            call.stack,
+            marker_handle,
+        );
+    }
+
+    /// Adds a marker for each run of the launched command, which is launched
+    /// several times. With `number_process_names`, the processes of each run
+    /// get its number in their names.
+    #[allow(unused)]
+    pub fn record_iterations(&mut self, number_process_names: bool) {
+        self.iterations = Some(Iterations::new(number_process_names));
+    }
+
+    /// Called when the next run of the launched command is launched as process
+    /// `pid`, before it executes the command.
+    #[allow(unused)]
+    pub fn launch_iteration(&mut self, pid: i32) {
+        if let Some(iterations) = &mut self.iterations {
+            iterations.launch(pid);
+        }
+    }
+
+    /// Continues the iterations of `previous`, for a converter which takes
+    /// over from it. Call this before registering the running processes, so
+    /// that they get the numbers of their iterations.
+    pub fn continue_iterations(&mut self, previous: &Self) {
+        self.iterations = previous.iterations.as_ref().map(Iterations::continued);
+    }
+
+    /// Shares the stacks of unwound samples with other converters which get
+    /// the same records, so that each sample is only unwound once.
+    pub fn share_sample_stacks(&mut self, shared: Arc<Mutex<SharedSampleStack>>) {
+        self.shared_sample_stack = Some(shared);
+    }
+
+    /// The name of process `pid`, with the number of its iteration if needed.
+    fn process_name(&self, pid: i32, name: &str) -> String {
+        match &self.iterations {
+            Some(iterations) => iterations.process_name(pid, name),
+            None => name.to_owned(),
+        }
+    }
+
+    /// Whether the sample is from a process which we know from its records.
+    /// Uprobes are sampled for all processes, because their events can't be
+    /// inherited, so the hits of other processes need to be skipped.
+    fn is_profiled_process(&self, e: &SampleRecord) -> bool {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        self.processes.contains(pid)
+    }
+
+    /// Sets the argument spec of the USDT probe site which is sampled by the
+    /// event at `attr_index`, so that the arguments are added to its markers.
+    pub fn set_usdt_arguments(&mut self, attr_index: usize, arguments: Vec<SdtArgument>) {
+        self.usdt.set_arguments(attr_index, arguments);
+    }
+
+    /// Adds an instant marker with the stack and the arguments for a hit of a
+    /// USDT probe.
+    pub fn handle_usdt_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+    ) {
+        let Some(event_name) = self.event_names.get(attr_index) else {
+            return;
+        };
+        if !self.is_profiled_process(e) {
+            return;
+        }
+        let marker = self.usdt.marker::<C>(
+            &mut self.profile,
+            attr_index,
+            event_name,
+            e.user_regs.as_ref(),
+            e.user_stack,
+        );
+        let marker_stack = self.get_marker_stack::<C>(e);
+        let marker_handle = self.profile.add_marker(
+            marker_stack.thread,
+            MarkerTiming::Instant(marker_stack.timestamp),
+            marker,
+        );
+        let process = self
+            .processes
+            .get_by_pid(marker_stack.pid, &mut self.profile);
+        process.unresolved_samples.attach_stack_to_marker(
+            marker_stack.thread,
+            marker_stack.timestamp,
+            marker_stack.timestamp_mono,
+            marker_stack.stack,
+            marker_handle,
+        );
+    }
+
+    /// Adds a marker with the stack of the spawning thread for a
+    /// `sched:sched_process_fork` sample, and a marker at the start of the new
+    /// thread or process, linked with a flow ID.
+    ///
+    /// The layout of this tracepoint changed in Linux 6.x, so we need its
+    /// format to find the pid of the child. Without it, the sample gets the
+    /// usual tracepoint marker.
+    pub fn handle_sched_process_fork_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+        let Some(FieldValue::Number(child_tid)) = self.tracepoint_field(e, attr_index, "child_pid")
+        else {
+            self.handle_other_event_sample::<C>(e, attr_index);
+            return;
+        };
+        let child_tid = child_tid as i32;
+        let marker_stack = self.get_marker_stack::<C>(e);
+        let flow = self.flow_ids.next();
+
+        // We've already seen the FORK record of the child, so we know whether
+        // it's a thread of this process or the main thread of a new process.
+        let child_pid = if self.processes.contains_thread(pid, child_tid) {
+            pid
+        } else {
+            child_tid
+        };
+        let child_thread = self
+            .processes
+            .get_by_pid(child_pid, &mut self.profile)
+            .threads
+            .get_thread_by_tid(child_tid, &mut self.profile);
+        let (child_thread, child_label) = (child_thread.profile_thread, child_thread.thread_label);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let parent_label = process
+            .threads
+            .get_thread_by_tid(tid, &mut self.profile)
+            .thread_label;
+
+        self.profile.add_marker(
+            child_thread,
+            MarkerTiming::Instant(marker_stack.timestamp),
+            SpawnedByMarker {
+                parent: parent_label,
+                flow,
+            },
+        );
+        let marker_handle = self.profile.add_marker(
+            marker_stack.thread,
+            MarkerTiming::Instant(marker_stack.timestamp),
+            SpawnMarker {
+                child: child_label,
+                flow,
+            },
+        );
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.unresolved_samples.attach_stack_to_marker(
+            marker_stack.thread,
+            marker_stack.timestamp,
+            marker_stack.timestamp_mono,
+            marker_stack.stack,
+            marker_handle,
+        );
+    }
+
+    /// Adds an exec marker with the executed program for a
+    /// `sched:sched_process_exec` sample, or the usual tracepoint marker if the
+    /// program can't be read from the sample.
+    pub fn handle_sched_process_exec_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+    ) {
+        let Some(FieldValue::String(filename)) = self.tracepoint_field(e, attr_index, "filename")
+        else {
+            self.handle_other_event_sample::<C>(e, attr_index);
+            return;
+        };
+        let marker = ExecMarker {
+            filename: self.profile.handle_for_string(&filename),
+        };
+        let marker_stack = self.get_marker_stack::<C>(e);
+        let marker_handle = self.profile.add_marker(
+            marker_stack.thread,
+            MarkerTiming::Instant(marker_stack.timestamp),
+            marker,
+        );
+        let process = self
+            .processes
+            .get_by_pid(marker_stack.pid, &mut self.profile);
+        process.unresolved_samples.attach_stack_to_marker(
+            marker_stack.thread,
+            marker_stack.timestamp,
+            marker_stack.timestamp_mono,
+            marker_stack.stack,
+            marker_handle,
+        );
+    }
+
+    /// Remembers the exit code of the process for a
+    /// `syscalls:sys_enter_exit_group` sample.
+    pub fn handle_exit_group_sample(&mut self, e: &SampleRecord, attr_index: usize) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        if let Some(FieldValue::Number(status)) = self.tracepoint_field(e, attr_index, "error_code")
+        {
+            self.process_lifecycle.set_exit_code(pid, status as i64);
+        }
+    }
+
+    /// Remembers a fatal signal for a `signal:signal_generate` sample, in case
+    /// it kills the process it was sent to.
+    pub fn handle_signal_generate_sample(&mut self, e: &SampleRecord, attr_index: usize) {
+        // TRACE_SIGNAL_IGNORED and TRACE_SIGNAL_OVERFLOW_FAIL
+        const NOT_QUEUED: [i64; 2] = [1, 3];
+        let field = |name| match self.tracepoint_field(e, attr_index, name) {
+            Some(FieldValue::Number(value)) => Some(value as i64),
+            _ => None,
+        };
+        let (Some(signal), Some(tid), Some(result)) = (field("sig"), field("pid"), field("result"))
+        else {
+            return;
+        };
+        if !NOT_QUEUED.contains(&result) {
+            self.process_lifecycle
+                .signal_sent(tid as i32, signal as i32);
+        }
+    }
+
+    /// Remembers the signal which kills the process for a
+    /// `signal:signal_deliver` sample.
+    pub fn handle_signal_deliver_sample(&mut self, e: &SampleRecord, attr_index: usize) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+        let field = |name| match self.tracepoint_field(e, attr_index, name) {
+            Some(FieldValue::Number(value)) => Some(value as i64),
+            _ => None,
+        };
+        let (Some(signal), Some(handler)) = (field("sig"), field("sa_handler")) else {
+            return;
+        };
+        // SIG_DFL is 0.
+        self.process_lifecycle
+            .signal_delivered(pid, tid, signal as i32, handler == 0);
+    }
+
+    fn tracepoint_field(
+        &self,
+        e: &SampleRecord,
+        attr_index: usize,
+        field_name: &str,
+    ) -> Option<FieldValue> {
+        let raw = e.raw?;
+        self.tracepoint_markers
+            .field_value(attr_index, field_name, &raw.as_slice(), self.endian)
+    }
+
+    /// Whether the samples of this event are turned into wakeup markers.
+    pub fn handles_wakeup_event(&self, attr_index: usize) -> bool {
+        self.wakeups.handles_event(attr_index)
+    }
+
+    /// Adds a wakeup marker with the waker's stack to the waking thread. The
+    /// marker for the woken thread is added once that thread is switched in.
+    pub fn handle_sched_wakeup_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+        let Some(wakeup) = e.raw.and_then(|raw| SchedWakeup::parse(raw, self.endian)) else {
+            return;
+        };
+        if let (Some(thread_states), Some(timestamp_mono)) = (&mut self.thread_states, e.timestamp)
+        {
+            let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+            thread_states.wake(&mut self.profile, wakeup.tid, timestamp);
+        }
+        if tid == 0 {
+            // Ignore wakeups from the idle thread, e.g. from timer interrupts.
+            return;
+        }
+        let marker_stack = self.get_marker_stack::<C>(e);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let waker_thread = process
+            .threads
+            .get_thread_by_tid(tid, &mut self.profile)
+            .thread_label;
+        let marker = self.wakeups.wake(
+            &mut self.profile,
+            &mut self.flow_ids,
+            &wakeup,
+            waker_thread,
+            marker_stack.timestamp,
+        );
+        let marker_handle = self.profile.add_marker(
+            marker_stack.thread,
+            MarkerTiming::Instant(marker_stack.timestamp),
+            marker,
+        );
+        process.unresolved_samples.attach_stack_to_marker(
+            marker_stack.thread,
+            marker_stack.timestamp,
+            marker_stack.timestamp_mono,
+            marker_stack.stack,
+            marker_handle,
+        );
+    }
+
+    /// Whether thread state markers are created, with `--thread-states`.
+    pub fn has_thread_states(&self) -> bool {
+        self.thread_states.is_some()
+    }
+
+    pub fn handle_sched_migrate_task_sample(&mut self, e: &SampleRecord) {
+        let (Some(thread_states), Some(raw)) = (&mut self.thread_states, e.raw) else {
+            return;
+        };
+        if let Some(tid) = parse_sched_migrate_task(raw, self.endian) {
+            thread_states.migrate(tid);
+        }
+    }
+
+    /// Pairs up the samples of the block request tracepoints into block I/O
+    /// markers, with the stack of the thread which submitted the request.
+    pub fn handle_block_rq_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        event: &KnownEvent,
+        attr_index: usize,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+        let timestamp_mono = e
+            .timestamp
+            .expect("Can't handle samples without timestamps");
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let (Some(raw), Some(format)) = (e.raw, self.tracepoint_markers.format(attr_index)) else {
+            return;
+        };
+        let Some(rq) = BlockRq::decode(format, &raw.as_slice(), self.endian) else {
+            return;
+        };
+        if let KnownEvent::BlockRqComplete = event {
+            if let Some((marker_handle, submitter)) =
+                self.block_io.complete(&mut self.profile, &rq, timestamp)
+            {
+                let process = self.processes.get_by_pid(submitter.pid, &mut self.profile);
+                process.unresolved_samples.attach_stack_to_marker(
+                    submitter.thread,
+                    submitter.timestamp,
+                    submitter.timestamp_mono,
+                    submitter.stack,
+                    marker_handle,
+                );
+            }
+            return;
+        }
+
+        // Requests from threads we don't know about, e.g. from kernel threads
+        // in a system-wide live recording, go on the device thread, and we
+        // don't create processes for them.
+        let submitter = (tid != 0 && self.processes.contains_thread(pid, tid)).then(|| {
+            let marker_stack = self.get_marker_stack::<C>(e);
+            BlockIoSubmitter {
+                pid: marker_stack.pid,
+                thread: marker_stack.thread,
+                timestamp: marker_stack.timestamp,
+                timestamp_mono: marker_stack.timestamp_mono,
+                stack: marker_stack.stack,
+            }
+        });
+        match event {
+            KnownEvent::BlockRqInsert => self.block_io.insert(&rq, submitter, timestamp),
+            _ => self
+                .block_io
+                .issue(&mut self.profile, rq, submitter, timestamp),
+        }
+    }
+
+    /// Turns `power:cpu_frequency` and `power:cpu_idle` samples into CPU
+    /// frequency counters and idle state markers.
+    pub fn handle_cpu_power_sample(&mut self, e: &SampleRecord, event: &KnownEvent) {
+        let timestamp_mono = e
+            .timestamp
+            .expect("Can't handle samples without timestamps");
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let Some((state, cpu)) = e.raw.and_then(|raw| parse_power_event(raw, self.endian)) else {
+            return;
+        };
+        let cpus = self.cpus.as_mut();
+        match event {
+            KnownEvent::CpuFrequency => {
+                self.cpu_power
+                    .set_frequency(&mut self.profile, cpus, cpu, state, timestamp)
+            }
+            _ => self
+                .cpu_power
+                .set_idle_state(&mut self.profile, cpus, cpu, state, timestamp),
+        }
+    }
+
+    /// Updates the frequency counter of a CPU with its frequency in kHz, for
+    /// when it's read from cpufreq instead of from `power:cpu_frequency`
+    /// samples. Unchanged frequencies are skipped.
+    #[allow(unused)]
+    pub fn handle_cpu_frequency(&mut self, cpu: u32, timestamp_mono: u64, khz: u32) {
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        self.cpu_power
+            .set_frequency(&mut self.profile, self.cpus.as_mut(), cpu, khz, timestamp);
+    }
 
-        if rss_stat.member == MM_ANONPAGES {
-            let counter = process.get_or_make_mem_counter(&mut self.profile);
-            self.profile
-                .add_counter_sample(counter, timestamp, delta as f64, 1);
+    /// Handles an `ftrace:print` sample, i.e. a write to `trace_marker`. The
+    /// text is in the `buf` field.
+    pub fn handle_ftrace_print_sample(&mut self, e: &SampleRecord, attr_index: usize) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+        let timestamp_mono = e
+            .timestamp
+            .expect("Can't handle samples without timestamps");
+        let Some(FieldValue::String(text)) = self.tracepoint_field(e, attr_index, "buf") else {
+            return;
+        };
+        self.add_trace_marker(pid, tid, timestamp_mono, &text);
+    }
+
+    /// Adds the markers or counter values for text which a thread wrote to the
+    /// ftrace `trace_marker` file. `pid` is the process of the writing thread,
+    /// if known; otherwise the pid in the text is used.
+    ///
+    /// The text from processes which aren't being profiled is ignored.
+    #[allow(unused)]
+    pub fn handle_trace_marker(
+        &mut self,
+        pid: Option<i32>,
+        tid: i32,
+        timestamp_mono: u64,
+        text: &str,
+    ) {
+        let Some(pid) = pid.or_else(|| TraceMarkerText::parse(text).0) else {
+            return;
+        };
+        if !self.processes.contains(pid) {
+            return;
         }
+        self.add_trace_marker(pid, tid, timestamp_mono, text);
+    }
 
-        process.check_jitdump(
-            &mut self.jit_category_manager,
+    fn add_trace_marker(&mut self, pid: i32, tid: i32, timestamp_mono: u64, text: &str) {
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let process_handle = process.profile_process;
+        let thread_handle = process
+            .threads
+            .get_thread_by_tid(tid, &mut self.profile)
+            .profile_thread;
+        let (_, text) = TraceMarkerText::parse(text);
+        self.trace_markers.handle(
             &mut self.profile,
-            &self.timestamp_converter,
+            process_handle,
+            thread_handle,
+            pid,
+            tid,
+            timestamp,
+            text,
         );
+    }
 
-        let stack = &mut self.stack_scratch;
-        Self::get_sample_stack::<C>(
-            e,
-            &process.unwinder,
-            &mut self.cache,
-            stack,
-            self.fold_recursive_prefix,
-            self.call_chain_return_addresses_are_preadjusted,
-        );
-        let unresolved_stack = self.unresolved_stacks.convert(stack.iter().rev().cloned());
-        let thread_handle = process.threads.main_thread.profile_thread;
-        let timing = MarkerTiming::Instant(timestamp);
-        let name = match member {
-            RssStatMember::ResidentFileMappingPages => "RSS Stat FILEPAGES",
-            RssStatMember::ResidentAnonymousPages => "RSS Stat ANONPAGES",
-            RssStatMember::AnonymousSwapEntries => "RSS Stat SHMEMPAGES",
-            RssStatMember::ResidentSharedMemoryPages => "RSS Stat SWAPENTS",
+    /// Adds the counter values from the `read_format` group data of a sample,
+    /// see [`split_off_group_read`](super::group_read::split_off_group_read).
+    pub fn handle_group_read_values(&mut self, e: &SampleRecord, values: &[(Option<u64>, u64)]) {
+        let (Some(pid), Some(tid), Some(timestamp_mono)) = (e.pid, e.tid, e.timestamp) else {
+            return;
         };
-        let name = self.profile.handle_for_string(name);
-        let marker_handle = self.profile.add_marker(
-            thread_handle,
-            timing,
-            RssStatMarker::new(name, rss_stat.size, delta),
-        );
-        process.unresolved_samples.attach_stack_to_marker(
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let process_handle = process.profile_process;
+        let thread_handle = process
+            .threads
+            .get_thread_by_tid(tid, &mut self.profile)
+            .profile_thread;
+        self.group_read_counters.handle(
+            &mut self.profile,
+            process_handle,
             thread_handle,
+            pid,
+            tid,
+            e.cpu,
             timestamp,
-            timestamp_mono,
-            unresolved_stack,
-            marker_handle,
+            values,
+            &self.event_names,
         );
     }
 
-    pub fn handle_other_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+    /// Registers the IDs of perf events which were opened after the converter
+    /// was created.
+    #[allow(unused)]
+    pub fn add_event_ids(&mut self, attr_index: usize, ids: &[u64]) {
+        self.group_read_counters.add_event_ids(attr_index, ids);
+    }
+
+    /// Adds an allocation or deallocation sample to the process. Deallocations
+    /// are only added if the allocation at the same address was sampled, and
+    /// they subtract that allocation's size again.
+    #[allow(unused)]
+    pub fn handle_allocation_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
-        e: &SampleRecord,
-        attr_index: usize,
+        e: &AllocationSample,
     ) {
-        let pid = e.pid.expect("Can't handle samples without pids");
-        let timestamp_mono = e
-            .timestamp
-            .expect("Can't handle samples without timestamps");
-        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
-        // let tid = e.tid.expect("Can't handle samples without tids");
-        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        // Don't bring back a process which has already exited.
+        if !self.processes.contains(e.pid) {
+            return;
+        }
+        let timestamp = self.timestamp_converter.convert_time(e.timestamp);
+        let process = self.processes.get_by_pid(e.pid, &mut self.profile);
+
+        let size = match e.size {
+            Some(size) => {
+                process.live_allocations.insert(e.address, size);
+                size as i64
+            }
+            None => match process.live_allocations.remove(&e.address) {
+                Some(size) => -(size as i64),
+                None => return,
+            },
+        };
+
         process.check_jitdump(
             &mut self.jit_category_manager,
             &mut self.profile,
@@ -560,40 +1735,51 @@ where
         );
 
         let stack = &mut self.stack_scratch;
-        Self::get_sample_stack::<C>(
-            e,
-            &process.unwinder,
-            &mut self.cache,
+        let sample = SampleKey::Allocation {
+            timestamp: e.timestamp,
+            pid: e.pid,
+            address: e.address,
+        };
+        let cache = &mut self.cache;
+        SharedSampleStack::get_or_unwind(
+            self.shared_sample_stack.as_deref(),
+            sample,
             stack,
-            self.fold_recursive_prefix,
-            self.call_chain_return_addresses_are_preadjusted,
+            |stack| {
+                stack.truncate(0);
+                let ustack_bytes =
+                    RawDataU64::from_raw_data::<LittleEndian>(RawData::Single(e.user_stack));
+                Self::unwind_user_stack::<C>(
+                    &e.regs,
+                    ustack_bytes,
+                    &process.unwinder,
+                    cache,
+                    stack,
+                );
+            },
         );
-
-        let thread_handle = match e.tid {
-            Some(tid) => {
-                process
-                    .threads
-                    .get_thread_by_tid(tid, &mut self.profile)
-                    .profile_thread
+        if stack.is_empty() {
+            return;
+        }
+        stack.remove(0);
+        if self.fold_recursive_prefix && !stack.is_empty() {
+            let last_frame = *stack.last().unwrap();
+            while stack.len() >= 2 && stack[stack.len() - 2] == last_frame {
+                stack.pop();
             }
-            None => process.threads.main_thread.profile_thread,
-        };
-
-        let unresolved_stack = self.unresolved_stacks.convert(stack.iter().rev().cloned());
-        if let Some(name) = self.event_names.get(attr_index) {
-            let timing = MarkerTiming::Instant(timestamp);
-            let name = self.profile.handle_for_string(name);
-            let marker_handle =
-                self.profile
-                    .add_marker(thread_handle, timing, OtherEventMarker(name));
-            process.unresolved_samples.attach_stack_to_marker(
-                thread_handle,
-                timestamp,
-                timestamp_mono,
-                unresolved_stack,
-                marker_handle,
-            );
         }
+
+        // Allocation samples always go on the main thread, see Profile::add_allocation_sample.
+        let stack_index = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+        process.unresolved_samples.add_allocation_sample(
+            process.threads.main_thread.profile_thread,
+            process.profile_process,
+            timestamp,
+            e.timestamp,
+            stack_index,
+            e.address,
+            size,
+        );
     }
 
     /// Get the stack contained in this sample, and put it into `stack`.
@@ -615,6 +1801,12 @@ where
     ///    bytes on the stack are just copied into the perf.data file, and we
     ///    need to do the unwinding now, based on the register values in
     ///    `e.user_regs` and the raw stack bytes in `e.user_stack`.
+    ///  - With both (samply's "mixed" call graph mode), we unwind with DWARF
+    ///    as well and keep whichever user stack has more frames, because
+    ///    frame pointer walks stop early in code without frame pointers.
+    ///
+    /// If another converter which shares the stacks has already unwound this
+    /// sample, the stack is copied from `shared` instead.
     fn get_sample_stack<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         e: &SampleRecord,
         unwinder: &U,
@@ -622,11 +1814,42 @@ where
         stack: &mut Vec<StackFrame>,
         fold_recursive_prefix: bool,
         call_chain_return_addresses_are_preadjusted: bool,
+        shared: Option<&Mutex<SharedSampleStack>>,
+    ) {
+        let sample = SampleKey::Record {
+            id: e.id,
+            timestamp: e.timestamp,
+            tid: e.tid,
+            ip: e.ip,
+        };
+        SharedSampleStack::get_or_unwind(shared, sample, stack, |stack| {
+            Self::unwind_sample_stack::<C>(
+                e,
+                unwinder,
+                cache,
+                stack,
+                fold_recursive_prefix,
+                call_chain_return_addresses_are_preadjusted,
+            )
+        });
+    }
+
+    /// Unwinds the stack of the sample, see [`Self::get_sample_stack`].
+    fn unwind_sample_stack<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        e: &SampleRecord,
+        unwinder: &U,
+        cache: &mut U::Cache,
+        stack: &mut Vec<StackFrame>,
+        fold_recursive_prefix: bool,
+        call_chain_return_addresses_are_preadjusted: bool,
     ) {
         stack.truncate(0);
 
         // CpuMode::from_misc(e.raw.misc)
 
+        // The index of the first user frame from e.callchain, if any.
+        let mut callchain_user_start = None;
+
         // Get the first fragment of the stack from e.callchain.
         if let Some(callchain) = e.callchain {
             let mut is_first_frame = true;
@@ -646,6 +1869,9 @@ where
                         (false, false) => StackFrame::ReturnAddress(address, mode),
                         (false, true) => StackFrame::AdjustedReturnAddress(address, mode),
                     };
+                if mode == StackMode::User && callchain_user_start.is_none() {
+                    callchain_user_start = Some(stack.len());
+                }
                 stack.push(stack_frame);
 
                 is_first_frame = false;
@@ -654,35 +1880,19 @@ where
 
         // Append the user stack with the help of DWARF unwinding.
         if let (Some(regs), Some((user_stack, _))) = (&e.user_regs, e.user_stack) {
+            let dwarf_start = stack.len();
             let ustack_bytes = RawDataU64::from_raw_data::<LittleEndian>(user_stack);
-            let (pc, sp, regs) = C::convert_regs(regs);
-            let mut read_stack = |addr: u64| {
-                // ustack_bytes has the stack bytes starting from the current stack pointer.
-                let offset = addr.checked_sub(sp).ok_or(())?;
-                let index = usize::try_from(offset / 8).map_err(|_| ())?;
-                ustack_bytes.get(index).ok_or(())
-            };
-
-            // Unwind.
-            let mut frames = unwinder.iter_frames(pc, regs, cache, &mut read_stack);
-            loop {
-                let frame = match frames.next() {
-                    Ok(Some(frame)) => frame,
-                    Ok(None) => break,
-                    Err(_) => {
-                        stack.push(StackFrame::TruncatedStackMarker);
-                        break;
-                    }
-                };
-                let stack_frame = match frame {
-                    FrameAddress::InstructionPointer(addr) => {
-                        StackFrame::InstructionPointer(addr, StackMode::User)
-                    }
-                    FrameAddress::ReturnAddress(addr) => {
-                        StackFrame::ReturnAddress(addr.into(), StackMode::User)
-                    }
-                };
-                stack.push(stack_frame);
+            Self::unwind_user_stack::<C>(regs, ustack_bytes, unwinder, cache, stack);
+
+            // If the callchain already had a user stack, only keep the longer of the two.
+            if let Some(callchain_user_start) = callchain_user_start {
+                let callchain_user_len = dwarf_start - callchain_user_start;
+                let dwarf_len = stack.len() - dwarf_start;
+                if dwarf_len > callchain_user_len {
+                    stack.drain(callchain_user_start..dwarf_start);
+                } else {
+                    stack.truncate(dwarf_start);
+                }
             }
         }
 
@@ -698,6 +1908,47 @@ where
         }
     }
 
+    /// Unwinds the user stack from the register values in `regs` and the raw
+    /// stack bytes in `ustack_bytes`, which start at the stack pointer, and
+    /// appends the frames to `stack`, ordered from callee-most to caller-most.
+    fn unwind_user_stack<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        regs: &Regs,
+        ustack_bytes: RawDataU64,
+        unwinder: &U,
+        cache: &mut U::Cache,
+        stack: &mut Vec<StackFrame>,
+    ) {
+        let (pc, sp, regs) = C::convert_regs(regs);
+        let mut read_stack = |addr: u64| {
+            // ustack_bytes has the stack bytes starting from the current stack pointer.
+            let offset = addr.checked_sub(sp).ok_or(())?;
+            let index = usize::try_from(offset / 8).map_err(|_| ())?;
+            ustack_bytes.get(index).ok_or(())
+        };
+
+        // Unwind.
+        let mut frames = unwinder.iter_frames(pc, regs, cache, &mut read_stack);
+        loop {
+            let frame = match frames.next() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(_) => {
+                    stack.push(StackFrame::TruncatedStackMarker);
+                    break;
+                }
+            };
+            let stack_frame = match frame {
+                FrameAddress::InstructionPointer(addr) => {
+                    StackFrame::InstructionPointer(addr, StackMode::User)
+                }
+                FrameAddress::ReturnAddress(addr) => {
+                    StackFrame::ReturnAddress(addr.into(), StackMode::User)
+                }
+            };
+            stack.push(stack_frame);
+        }
+    }
+
     pub fn handle_mmap(&mut self, e: MmapRecord, timestamp: u64) {
         let mut path = e.path.as_slice();
         self.add_mmap_marker(e.pid, e.tid, &path, timestamp);
@@ -747,8 +1998,42 @@ where
     }
 
     pub fn handle_mmap2(&mut self, e: Mmap2Record, timestamp: u64) {
+        self.remember_mapping(&e);
+        self.add_mmap_marker(e.pid, e.tid, &e.path.as_slice(), timestamp);
+        self.add_mmap2_mapping(e, timestamp);
+    }
+
+    /// Called for the mappings of a running process which were read from
+    /// /proc, rather than recorded. The mappings which the converter already
+    /// knows about are skipped, and no markers are added, because these
+    /// mappings can have been created at any time before `timestamp`.
+    ///
+    /// Returns whether the mapping was new.
+    pub fn handle_existing_mmap2(&mut self, e: Mmap2Record, timestamp: u64) -> bool {
+        let is_new = self.remember_mapping(&e);
+        if is_new {
+            self.add_mmap2_mapping(e, timestamp);
+        }
+        is_new
+    }
+
+    /// Returns whether the mapping is new to its process.
+    fn remember_mapping(&mut self, e: &Mmap2Record) -> bool {
+        if e.pid <= 0 {
+            return true;
+        }
+        let process = self.processes.get_by_pid(e.pid, &mut self.profile);
+        let key = (
+            e.address,
+            e.length,
+            e.page_offset,
+            e.path.as_slice().to_vec(),
+        );
+        process.known_mappings.insert(key)
+    }
+
+    fn add_mmap2_mapping(&mut self, e: Mmap2Record, timestamp: u64) {
         let path = e.path.as_slice();
-        self.add_mmap_marker(e.pid, e.tid, &path, timestamp);
 
         if self.check_jitdump_or_marker_file(&path, e.pid, e.tid) {
             // Not a DSO.
@@ -841,6 +2126,112 @@ where
         false
     }
 
+    /// Called when the user pauses recording.
+    #[allow(unused)]
+    pub fn handle_pause(&mut self, timestamp: u64) {
+        self.pause_start_time.get_or_insert(timestamp);
+    }
+
+    /// Called when the user resumes recording after [`Converter::handle_pause`].
+    #[allow(unused)]
+    pub fn handle_resume(&mut self, timestamp: u64) {
+        let Some(pause_start_time) = self.pause_start_time.take() else {
+            return;
+        };
+        let start_time = self.timestamp_converter.convert_time(pause_start_time);
+        let end_time = self
+            .timestamp_converter
+            .convert_time(timestamp.max(pause_start_time));
+        self.profile.add_paused_range(
+            start_time,
+            Some(end_time),
+            PausedRangeReason::ProfilerPaused,
+        );
+    }
+
+    /// Called for PERF_RECORD_LOST, which the kernel emits when the ring buffer
+    /// was full and events had to be dropped.
+    pub fn handle_lost(&mut self, e: LostRecord, common: CommonData) {
+        let end_time = common.timestamp.unwrap_or(self.current_sample_time);
+        let start_time = common
+            .cpu
+            .and_then(|cpu| self.last_sample_time_per_cpu.get(&cpu).copied())
+            .unwrap_or(self.current_sample_time)
+            .min(end_time);
+        self.add_collection_gap(
+            self.timestamp_converter.convert_time(start_time),
+            Some(self.timestamp_converter.convert_time(end_time)),
+            common.pid.zip(common.tid),
+            common.cpu,
+            LostEventsMarker { count: e.count },
+        );
+    }
+
+    /// Called for PERF_RECORD_THROTTLE, which the kernel emits when it stops
+    /// sampling because sampling took up too much CPU time.
+    pub fn handle_throttle(&mut self, e: ThrottleRecord, common: CommonData) {
+        self.throttle_start_per_event
+            .entry(e.id)
+            .or_insert(ThrottleStart {
+                timestamp: e.timestamp,
+                pid_tid: common.pid.zip(common.tid),
+                cpu: common.cpu,
+            });
+    }
+
+    /// Called for PERF_RECORD_UNTHROTTLE, which ends a throttling period.
+    pub fn handle_unthrottle(&mut self, e: ThrottleRecord, common: CommonData) {
+        let Some(start) = self.throttle_start_per_event.remove(&e.id) else {
+            return;
+        };
+        let end_time = e.timestamp.max(start.timestamp);
+        let cpu = start.cpu.or(common.cpu);
+        self.add_collection_gap(
+            self.timestamp_converter.convert_time(start.timestamp),
+            Some(self.timestamp_converter.convert_time(end_time)),
+            start.pid_tid,
+            cpu,
+            ThrottledMarker,
+        );
+    }
+
+    /// Records a time range during which samples are missing, as a paused range
+    /// and a marker on the affected thread, and as a marker on the affected CPU.
+    /// Lost events and throttling are per CPU, so they don't pause the collection
+    /// of the other threads, unlike a profile-wide paused range.
+    ///
+    /// An `end_time` of `None` means that the gap lasted until the end of the
+    /// profile.
+    fn add_collection_gap<M: StaticSchemaMarker + Clone>(
+        &mut self,
+        start_time: Timestamp,
+        end_time: Option<Timestamp>,
+        pid_tid: Option<(i32, i32)>,
+        cpu: Option<u32>,
+        marker: M,
+    ) {
+        let timing = match end_time {
+            Some(end_time) => MarkerTiming::Interval(start_time, end_time),
+            None => MarkerTiming::IntervalStart(start_time),
+        };
+        if let Some((pid, tid)) = pid_tid.filter(|(_, tid)| *tid != 0) {
+            let process = self.processes.get_by_pid(pid, &mut self.profile);
+            let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+            self.profile.add_thread_paused_range(
+                thread.profile_thread,
+                start_time,
+                end_time,
+                PausedRangeReason::CollectorPaused,
+            );
+            self.profile
+                .add_marker(thread.profile_thread, timing.clone(), marker.clone());
+        }
+        if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, cpu) {
+            let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
+            self.profile.add_marker(cpu.thread_handle, timing, marker);
+        }
+    }
+
     pub fn handle_context_switch(&mut self, e: ContextSwitchRecord, common: CommonData) {
         let pid = common.pid.expect("Can't handle samples without pids");
         let tid = common.tid.expect("Can't handle samples without tids");
@@ -856,6 +2247,24 @@ where
 
         match e {
             ContextSwitchRecord::In { .. } => {
+                let profile_timestamp = self.timestamp_converter.convert_time(timestamp);
+                if let Some((start, marker)) = self.wakeups.switch_in(tid) {
+                    self.profile.add_marker(
+                        thread.profile_thread,
+                        MarkerTiming::Interval(start, profile_timestamp),
+                        marker,
+                    );
+                }
+                if let Some(thread_states) = &mut self.thread_states {
+                    thread_states.set_state(
+                        &mut self.profile,
+                        tid,
+                        Some(thread.profile_thread),
+                        ThreadState::Running,
+                        profile_timestamp,
+                    );
+                }
+
                 // Consume off-cpu time and clear the saved off-CPU stack.
                 let off_cpu_sample = self
                     .context_switch_handler
@@ -866,6 +2275,9 @@ where
                     let cpu_delta_ns = self
                         .context_switch_handler
                         .consume_cpu_delta(&mut thread.context_switch_data);
+                    let state_frame = self.thread_states.as_ref().and_then(|thread_states| {
+                        thread_states.off_cpu_frame(&mut self.profile, tid, thread.profile_thread)
+                    });
                     process_off_cpu_sample_group(
                         off_cpu_sample,
                         thread.profile_thread,
@@ -873,6 +2285,7 @@ where
                         &self.timestamp_converter,
                         self.off_cpu_weight_per_sample,
                         off_cpu_stack,
+                        state_frame,
                         &mut process.unresolved_samples,
                     );
                 }
@@ -932,6 +2345,23 @@ where
                 }
             }
             ContextSwitchRecord::Out { preempted, .. } => {
+                self.wakeups.switch_out(tid);
+                if let Some(thread_states) = &mut self.thread_states {
+                    // A preceding sched_switch sample has a more precise state.
+                    if matches!(thread_states.state(tid), None | Some(ThreadState::Running)) {
+                        let state = match preempted {
+                            TaskWasPreempted::Yes => ThreadState::Runnable,
+                            TaskWasPreempted::No => ThreadState::Sleeping,
+                        };
+                        thread_states.set_state(
+                            &mut self.profile,
+                            tid,
+                            Some(thread.profile_thread),
+                            state,
+                            self.timestamp_converter.convert_time(timestamp),
+                        );
+                    }
+                }
                 self.context_switch_handler
                     .handle_switch_out(timestamp, &mut thread.context_switch_data);
                 if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, Some(common.cpu.unwrap())) {
@@ -972,6 +2402,9 @@ where
             }
             let parent_process_name = parent_process.name.clone();
             let fork_data = parent_process.clone_fork_data();
+            if let Some(iterations) = &mut self.iterations {
+                iterations.fork(e.ppid, e.pid);
+            }
             let child_process = self.processes.recycle_or_get_new(
                 e.pid,
                 parent_process_name,
@@ -979,6 +2412,13 @@ where
                 &mut self.profile,
             );
             child_process.adopt_fork_data_from_parent(fork_data);
+            let name = process_name_handle(&mut self.profile, child_process.name.as_deref(), e.pid);
+            self.process_lifecycle.start(
+                e.pid,
+                child_process.threads.main_thread.profile_thread,
+                name,
+                start_time,
+            );
         } else {
             // New thread within the same process.
             // eprintln!("New thread: pid={}, old_tid={}, new_tid={}", e.pid, e.ptid, e.tid);
@@ -999,7 +2439,21 @@ where
     pub fn handle_exit(&mut self, e: ForkOrExitRecord) {
         let is_main = e.pid == e.tid;
         let end_time = self.timestamp_converter.convert_time(e.timestamp);
+        self.wakeups.thread_exit(e.tid);
+        self.process_lifecycle.thread_exit(e.tid);
         if is_main {
+            let mut main_thread = None;
+            if self.processes.contains(e.pid) {
+                let process = self.processes.get_by_pid(e.pid, &mut self.profile);
+                let thread = process.threads.main_thread.profile_thread;
+                let name = process_name_handle(&mut self.profile, process.name.as_deref(), e.pid);
+                self.process_lifecycle
+                    .exit(&mut self.profile, e.pid, thread, name, end_time);
+                main_thread = Some(thread);
+            }
+            if let Some(iterations) = &mut self.iterations {
+                iterations.exit(&mut self.profile, e.pid, main_thread, end_time);
+            }
             self.processes.remove(
                 e.pid,
                 end_time,
@@ -1045,6 +2499,7 @@ where
         } else {
             comm_name.clone()
         };
+        let name = self.process_name(e.pid, &name);
 
         // eprintln!("Process execve: pid={}, tid={}, new name: {}", e.pid, e.tid, name);
 
@@ -1057,12 +2512,18 @@ where
                 &mut self.jit_category_manager,
                 &self.timestamp_converter,
             );
-            self.processes.recycle_or_get_new(
+            let process = self.processes.recycle_or_get_new(
                 e.pid,
                 Some(name.to_string()),
                 timestamp,
                 &mut self.profile,
             );
+            let thread = process.threads.main_thread.profile_thread;
+            let name = self.profile.handle_for_string(&name);
+            self.process_lifecycle.start(e.pid, thread, name, timestamp);
+            if let Some(iterations) = &mut self.iterations {
+                iterations.exec(e.pid, thread, timestamp);
+            }
         } else {
             eprintln!(
                 "Unexpected is_execve on non-main thread! pid: {}, tid: {}",
@@ -1096,8 +2557,11 @@ where
 
         if is_main {
             // eprintln!("Process rename: pid={}, new name: {}", e.pid, name);
+            let name = self.process_name(e.pid, &name);
             self.processes
-                .rename_process(e.pid, timestamp, name.to_string(), &mut self.profile);
+                .rename_process(e.pid, timestamp, name.clone(), &mut self.profile);
+            let name = self.profile.handle_for_string(&name);
+            self.process_lifecycle.rename(e.pid, name);
         } else {
             // eprintln!("Thread rename: pid={}, tid={}, new name: {}", e.pid, e.tid, name);
             let process = self.processes.get_by_pid(e.pid, &mut self.profile);
@@ -1122,6 +2586,10 @@ where
         let process_handle = process.profile_process;
 
         let name = make_process_name(exe_name, args, self.arg_count_to_include_in_process_name);
+        let name = match &self.iterations {
+            Some(iterations) => iterations.process_name(pid, &name),
+            None => name,
+        };
         self.profile.set_process_name(process_handle, &name);
         process.name = Some(name.to_owned());
 
@@ -1132,6 +2600,33 @@ where
         self.profile.set_process_start_time(process_handle, time);
     }
 
+    /// Whether we have already seen this process, e.g. from a FORK or COMM record.
+    #[allow(unused)]
+    pub fn has_process(&self, pid: i32) -> bool {
+        self.processes.contains(pid)
+    }
+
+    /// The pids of the processes which haven't exited yet.
+    #[allow(unused)]
+    pub fn process_pids(&self) -> Vec<i32> {
+        self.processes.pids().collect()
+    }
+
+    /// The marker files which the running processes have announced so far.
+    #[allow(unused)]
+    pub fn marker_file_paths(&self) -> Vec<PathBuf> {
+        self.processes
+            .marker_file_paths()
+            .map(Path::to_owned)
+            .collect()
+    }
+
+    /// Whether we have already seen this thread.
+    #[allow(unused)]
+    pub fn has_thread(&self, pid: i32, tid: i32) -> bool {
+        self.processes.contains_thread(pid, tid)
+    }
+
     #[allow(unused)]
     pub fn register_existing_thread(&mut self, pid: i32, tid: i32, name: &str) {
         let is_main = pid == tid;
@@ -1447,6 +2942,10 @@ where
                 return;
             }
 
+            if file.format() == object::BinaryFormat::Elf {
+                process.is_compat = !file.is_64();
+            }
+
             let module_section_info =
                 Self::module_section_info_with_object(Some(mmap.clone()), &file);
             let Some(library_info) =
@@ -1814,6 +3313,7 @@ impl SimpleperfSymbolTables {
 //     dbg!(jit_function_name(&file));
 // }
 
+#[allow(clippy::too_many_arguments)]
 fn process_off_cpu_sample_group(
     off_cpu_sample: OffCpuSampleGroup,
     thread_handle: ThreadHandle,
@@ -1821,6 +3321,7 @@ fn process_off_cpu_sample_group(
     timestamp_converter: &TimestampConverter,
     off_cpu_weight_per_sample: i32,
     off_cpu_stack: UnresolvedStackHandle,
+    state_frame: Option<FrameHandle>,
     samples: &mut UnresolvedSamples,
 ) {
     let OffCpuSampleGroup {
@@ -1835,14 +3336,14 @@ fn process_off_cpu_sample_group(
     let weight = off_cpu_weight_per_sample;
     let stack = off_cpu_stack;
     let profile_timestamp = timestamp_converter.convert_time(begin_timestamp);
-    samples.add_sample(
+    samples.add_sample_with_leaf_frame(
         thread_handle,
         profile_timestamp,
         begin_timestamp,
         stack,
         cpu_delta,
         weight,
-        None,
+        state_frame,
     );
 
     if sample_count > 1 {
@@ -1850,18 +3351,26 @@ fn process_off_cpu_sample_group(
         let cpu_delta = CpuDelta::from_nanos(0);
         let weight = i32::try_from(sample_count - 1).unwrap_or(0) * off_cpu_weight_per_sample;
         let profile_timestamp = timestamp_converter.convert_time(end_timestamp);
-        samples.add_sample(
+        samples.add_sample_with_leaf_frame(
             thread_handle,
             profile_timestamp,
             begin_timestamp,
             stack,
             cpu_delta,
             weight,
-            None,
+            state_frame,
         );
     }
 }
 
+/// The name of a process for its markers, or `<pid>` if we don't know it.
+fn process_name_handle(profile: &mut Profile, name: Option<&str>, pid: i32) -> StringHandle {
+    match name {
+        Some(name) => profile.handle_for_string(name),
+        None => profile.handle_for_string(&format!("<{pid}>")),
+    }
+}
+
 /// Returns true for paths such as the following:
 ///  - "/data/local/tmp/perf.data_jit_app_cache:1039560-1040440"
 ///  - "./TemporaryFile-osHvVs" (used by older versions of simpleperf, e.g. on Android 11)
@@ -0,0 +1,213 @@
+//! CPU frequency counters and idle state markers, from the `power:cpu_frequency`
+//! and `power:cpu_idle` tracepoints.
+//!
+//! Each CPU gets a frequency counter, and a marker for each time it spends in
+//! an idle state. Both go on the per-CPU threads if we have them, and on
+//! threads of their own otherwise.
+
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use fxprof_processed_profile::{
+    CounterHandle, MarkerTiming, ProcessHandle, Profile, ThreadHandle, Timestamp,
+};
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+use crate::shared::per_cpu::Cpus;
+use crate::shared::process_sample_data::CpuIdleMarker;
+
+/// The `state` of a `power:cpu_idle` sample when the CPU leaves its idle state.
+const PWR_EVENT_EXIT: u32 = u32::MAX;
+
+/// The `state` and `cpu_id` fields of a `power:cpu_frequency` or `power:cpu_idle`
+/// sample. The state is the frequency in kHz, or the index of the idle state.
+///
+/// ```
+/// # cat /sys/kernel/tracing/events/power/cpu_idle/format
+/// name: cpu_idle
+/// ID: 485
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:u32 state;        offset:8;       size:4; signed:0;
+///         field:u32 cpu_id;       offset:12;      size:4; signed:0;
+///
+/// print fmt: "state=%lu cpu_id=%lu", (unsigned long)REC->state, (unsigned long)REC->cpu_id
+/// ```
+pub fn parse_power_event(data: RawData, endian: Endianness) -> Option<(u32, u32)> {
+    let data = data.as_slice();
+    let state = data.get(8..12)?;
+    let cpu_id = data.get(12..16)?;
+    Some(match endian {
+        Endianness::LittleEndian => (
+            LittleEndian::read_u32(state),
+            LittleEndian::read_u32(cpu_id),
+        ),
+        Endianness::BigEndian => (BigEndian::read_u32(state), BigEndian::read_u32(cpu_id)),
+    })
+}
+
+/// The current frequency and idle state of each CPU.
+#[derive(Debug)]
+pub struct CpuPowerState {
+    start_time: Timestamp,
+    /// The process for our own per-CPU threads, if we don't have per-CPU
+    /// threads from `--per-cpu-threads`.
+    process: Option<ProcessHandle>,
+    threads: HashMap<u32, ThreadHandle>,
+    /// The frequency counter of each CPU, with the last frequency in kHz.
+    frequency_counters: HashMap<u32, (CounterHandle, u32)>,
+    /// The idle state which each CPU is currently in, with its start time.
+    idle_states: HashMap<u32, (ThreadHandle, u32, Timestamp)>,
+}
+
+impl CpuPowerState {
+    pub fn new(start_time: Timestamp) -> Self {
+        Self {
+            start_time,
+            process: None,
+            threads: HashMap::new(),
+            frequency_counters: HashMap::new(),
+            idle_states: HashMap::new(),
+        }
+    }
+
+    /// Updates the frequency counter of the CPU. Unchanged frequencies, e.g.
+    /// from polling cpufreq, are skipped.
+    pub fn set_frequency(
+        &mut self,
+        profile: &mut Profile,
+        cpus: Option<&mut Cpus>,
+        cpu: u32,
+        khz: u32,
+        timestamp: Timestamp,
+    ) {
+        let process = match cpus {
+            Some(cpus) => cpus.process_handle(),
+            None => self.process(profile),
+        };
+        let (counter, prev_khz) = self.frequency_counters.entry(cpu).or_insert_with(|| {
+            let counter = profile.add_counter(
+                process,
+                &format!("CPU {cpu} frequency"),
+                "CPU power",
+                &format!("The frequency of CPU {cpu}, in MHz"),
+            );
+            (counter, 0)
+        });
+        if khz == *prev_khz {
+            return;
+        }
+        let delta_mhz = (f64::from(khz) - f64::from(*prev_khz)) / 1000.0;
+        profile.add_counter_sample(*counter, timestamp, delta_mhz, 1);
+        *prev_khz = khz;
+    }
+
+    /// Handles a `power:cpu_idle` sample. A CPU which enters an idle state
+    /// leaves its previous idle state, if it was in one.
+    pub fn set_idle_state(
+        &mut self,
+        profile: &mut Profile,
+        cpus: Option<&mut Cpus>,
+        cpu: u32,
+        state: u32,
+        timestamp: Timestamp,
+    ) {
+        if let Some((thread, previous_state, start)) = self.idle_states.remove(&cpu) {
+            profile.add_marker(
+                thread,
+                MarkerTiming::Interval(start, timestamp),
+                CpuIdleMarker {
+                    state: previous_state,
+                },
+            );
+        }
+        if state == PWR_EVENT_EXIT {
+            return;
+        }
+        let thread = match cpus {
+            Some(cpus) => cpus.get_mut(cpu as usize, profile).thread_handle,
+            None => self.thread(profile, cpu),
+        };
+        self.idle_states.insert(cpu, (thread, state, timestamp));
+    }
+
+    /// Adds the markers for the CPUs which are still idle at the end of the
+    /// profile.
+    pub fn finish(self, profile: &mut Profile) {
+        for (thread, state, start) in self.idle_states.into_values() {
+            profile.add_marker(
+                thread,
+                MarkerTiming::IntervalStart(start),
+                CpuIdleMarker { state },
+            );
+        }
+    }
+
+    fn process(&mut self, profile: &mut Profile) -> ProcessHandle {
+        *self
+            .process
+            .get_or_insert_with(|| profile.add_process("CPU power", 0, self.start_time))
+    }
+
+    fn thread(&mut self, profile: &mut Profile, cpu: u32) -> ThreadHandle {
+        let process = self.process(profile);
+        let start_time = self.start_time;
+        *self.threads.entry(cpu).or_insert_with(|| {
+            let thread = profile.add_thread(process, cpu, start_time, false);
+            profile.set_thread_name(thread, &format!("CPU {cpu}"));
+            thread
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    #[test]
+    fn test_parse_power_event() {
+        let mut data = vec![0; 16];
+        data[8..12].copy_from_slice(&2_400_000u32.to_le_bytes());
+        data[12..16].copy_from_slice(&3u32.to_le_bytes());
+        assert_eq!(
+            parse_power_event(RawData::Single(&data), Endianness::LittleEndian),
+            Some((2_400_000, 3))
+        );
+        assert!(
+            parse_power_event(RawData::Single(&data[..12]), Endianness::LittleEndian).is_none()
+        );
+    }
+
+    #[test]
+    fn test_cpu_power_state() {
+        let mut profile = test_profile();
+        let mut state = CpuPowerState::new(t(0));
+
+        state.set_frequency(&mut profile, None, 1, 2_400_000, t(10));
+        state.set_frequency(&mut profile, None, 1, 2_400_000, t(20));
+        assert_eq!(state.frequency_counters[&1].1, 2_400_000);
+
+        // Leaving an idle state without entering one is ignored.
+        state.set_idle_state(&mut profile, None, 1, PWR_EVENT_EXIT, t(30));
+        assert!(state.idle_states.is_empty());
+        state.set_idle_state(&mut profile, None, 1, 2, t(40));
+        state.set_idle_state(&mut profile, None, 1, 1, t(50));
+        assert_eq!(state.idle_states[&1].1, 1);
+        state.set_idle_state(&mut profile, None, 1, PWR_EVENT_EXIT, t(60));
+        assert!(state.idle_states.is_empty());
+        assert_eq!(state.threads.len(), 1);
+
+        // With per-CPU threads, the markers go on those.
+        let mut cpus = Cpus::new(t(0), &mut profile);
+        state.set_idle_state(&mut profile, Some(&mut cpus), 0, 1, t(70));
+        let cpu_thread = cpus.get_mut(0, &mut profile).thread_handle;
+        assert_eq!(state.idle_states[&0].0, cpu_thread);
+        assert_eq!(state.threads.len(), 1);
+    }
+}
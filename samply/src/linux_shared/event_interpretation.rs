@@ -2,15 +2,81 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 
 use linux_perf_data::{linux_perf_event_reader, AttributeDescription};
-use linux_perf_event_reader::{AttrFlags, PerfEventType, SamplingPolicy, SoftwareCounterType};
+use linux_perf_event_reader::{
+    AttrFlags, HardwareEventId, PerfEventType, SamplingPolicy, SoftwareCounterType,
+};
+
+use super::probes::is_probe_event_name;
+use super::usdt::is_usdt_event_name;
 
 #[derive(Debug, Clone)]
 pub enum KnownEvent {
     RssStat,
-    MmapEnter,
-    MmapExit,
-    MprotectEnter,
     PageFault,
+    FtracePrint,
+    /// `raw_syscalls:sys_enter` or a `syscalls:sys_enter_<name>` tracepoint.
+    SyscallEnter,
+    /// `raw_syscalls:sys_exit` or a `syscalls:sys_exit_<name>` tracepoint.
+    SyscallExit,
+    SchedWaking,
+    SchedWakeup,
+    SchedMigrateTask,
+    SchedProcessFork,
+    SchedProcessExec,
+    /// `syscalls:sys_enter_exit_group`, for the exit codes of processes.
+    ExitGroup,
+    /// `signal:signal_generate`, for the signals which killed processes.
+    SignalGenerate,
+    /// `signal:signal_deliver`, for the signals which killed processes.
+    SignalDeliver,
+    BlockRqInsert,
+    BlockRqIssue,
+    BlockRqComplete,
+    CpuFrequency,
+    CpuIdle,
+    LockContentionBegin,
+    LockContentionEnd,
+    /// The entry of a `perf probe` probe, e.g. `probe_libc:malloc`.
+    ProbeEntry,
+    /// The return of a `perf probe` probe, e.g. `probe_libc:malloc__return`.
+    ProbeReturn,
+    /// A USDT probe, e.g. `sdt_libc:setjmp`.
+    UsdtProbe,
+}
+
+impl KnownEvent {
+    /// Returns the known event for a tracepoint name, e.g. `kmem:rss_stat`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let event = match name {
+            "kmem:rss_stat" => KnownEvent::RssStat,
+            "exceptions:page_fault_user" => KnownEvent::PageFault,
+            "ftrace:print" => KnownEvent::FtracePrint,
+            "raw_syscalls:sys_enter" => KnownEvent::SyscallEnter,
+            "raw_syscalls:sys_exit" => KnownEvent::SyscallExit,
+            "sched:sched_waking" => KnownEvent::SchedWaking,
+            "sched:sched_wakeup" => KnownEvent::SchedWakeup,
+            "sched:sched_migrate_task" => KnownEvent::SchedMigrateTask,
+            "sched:sched_process_fork" => KnownEvent::SchedProcessFork,
+            "sched:sched_process_exec" => KnownEvent::SchedProcessExec,
+            "syscalls:sys_enter_exit_group" => KnownEvent::ExitGroup,
+            "signal:signal_generate" => KnownEvent::SignalGenerate,
+            "signal:signal_deliver" => KnownEvent::SignalDeliver,
+            "block:block_rq_insert" => KnownEvent::BlockRqInsert,
+            "block:block_rq_issue" => KnownEvent::BlockRqIssue,
+            "block:block_rq_complete" => KnownEvent::BlockRqComplete,
+            "power:cpu_frequency" => KnownEvent::CpuFrequency,
+            "power:cpu_idle" => KnownEvent::CpuIdle,
+            "lock:contention_begin" => KnownEvent::LockContentionBegin,
+            "lock:contention_end" => KnownEvent::LockContentionEnd,
+            _ if name.starts_with("syscalls:sys_enter_") => KnownEvent::SyscallEnter,
+            _ if name.starts_with("syscalls:sys_exit_") => KnownEvent::SyscallExit,
+            _ if is_probe_event_name(name) && name.ends_with("__return") => KnownEvent::ProbeReturn,
+            _ if is_probe_event_name(name) => KnownEvent::ProbeEntry,
+            _ if is_usdt_event_name(name) => KnownEvent::UsdtProbe,
+            _ => return None,
+        };
+        Some(event)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -32,6 +98,11 @@ pub struct EventInterpretation {
     pub sched_switch_attr_index: Option<usize>,
     pub known_event_indices: HashMap<usize, KnownEvent>,
     pub event_names: Vec<String>,
+    /// The attr index of each perf event ID, for matching the values in
+    /// `read_format` group data to their events.
+    pub attr_index_by_event_id: HashMap<u64, usize>,
+    pub instructions_attr_index: Option<usize>,
+    pub cycles_attr_index: Option<usize>,
 }
 
 impl EventInterpretation {
@@ -71,24 +142,14 @@ impl EventInterpretation {
             (false, Some(_)) => Some(OffCpuIndicator::SchedSwitchAndSamples),
             _ => None,
         };
-        let mut known_event_indices = HashMap::new();
-
-        let known_events = [
-            ("kmem:rss_stat", KnownEvent::RssStat),
-            ("exceptions:page_fault_user", KnownEvent::PageFault),
-            ("syscalls:sys_enter_mprotect", KnownEvent::MprotectEnter),
-            ("syscalls:sys_enter_mmap", KnownEvent::MmapEnter),
-            ("syscalls:sys_exit_mmap", KnownEvent::MmapExit),
-        ];
-
-        for (event_name, event) in known_events {
-            let index = attrs
-                .iter()
-                .position(|attr_desc| attr_desc.name.as_deref() == Some(event_name));
-            if let Some(index) = index {
-                known_event_indices.insert(index, event);
-            }
-        }
+        let known_event_indices = attrs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, attr_desc)| {
+                let event = KnownEvent::from_name(attr_desc.name.as_deref()?)?;
+                Some((index, event))
+            })
+            .collect();
 
         let event_names = attrs
             .iter()
@@ -101,6 +162,26 @@ impl EventInterpretation {
             })
             .collect();
 
+        let attr_index_by_event_id = attrs
+            .iter()
+            .enumerate()
+            .flat_map(|(attr_index, attr_desc)| {
+                attr_desc.event_ids.iter().map(move |id| (*id, attr_index))
+            })
+            .collect();
+        let instructions_attr_index = attrs.iter().position(|attr_desc| {
+            matches!(
+                attr_desc.attr.type_,
+                PerfEventType::Hardware(HardwareEventId::Instructions, _)
+            )
+        });
+        let cycles_attr_index = attrs.iter().position(|attr_desc| {
+            matches!(
+                attr_desc.attr.type_,
+                PerfEventType::Hardware(HardwareEventId::CpuCycles, _)
+            )
+        });
+
         Self {
             main_event_attr_index,
             main_event_name,
@@ -109,6 +190,9 @@ impl EventInterpretation {
             sched_switch_attr_index,
             known_event_indices,
             event_names,
+            attr_index_by_event_id,
+            instructions_attr_index,
+            cycles_attr_index,
         }
     }
 }
@@ -0,0 +1,333 @@
+//! Counter tracks from the values of counting events, which are read into the
+//! samples of the sampling event with `PERF_SAMPLE_READ` and `PERF_FORMAT_GROUP`,
+//! e.g. with `perf record -e '{cycles,instructions,cache-misses}:S'` or with
+//! `samply record --hw-counters`.
+
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use fxprof_processed_profile::{
+    CounterHandle, MarkerTiming, ProcessHandle, Profile, ThreadHandle, Timestamp,
+};
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::{RawData, RawEventRecord, ReadFormat, RecordType, SampleFormat};
+
+use crate::shared::process_sample_data::IpcMarker;
+
+/// The counter values from the `read_format` group data of a sample, as pairs
+/// of event ID and value. The event ID is only present with `PERF_FORMAT_ID`.
+pub type GroupReadValues = Vec<(Option<u64>, u64)>;
+
+/// Takes the `read_format` group data out of a sample record, and returns the
+/// record without it, plus the counter values.
+///
+/// linux-perf-event-reader doesn't parse the group layout of `read_format`
+/// correctly, and misreads all the sample fields which follow it. So we parse it
+/// ourselves, and copy the rest of the record into `buffer` without it.
+///
+/// Returns `None` for records without group data.
+pub fn split_off_group_read<'a>(
+    record: &RawEventRecord,
+    buffer: &'a mut Vec<u8>,
+) -> Option<(RawEventRecord<'a>, GroupReadValues)> {
+    let parse_info = &record.parse_info;
+    if record.record_type != RecordType::SAMPLE
+        || !parse_info.sample_format.contains(SampleFormat::READ)
+        || !parse_info.read_format.contains(ReadFormat::GROUP)
+    {
+        return None;
+    }
+
+    // The fields which come before the read_format data are all 8 bytes large.
+    let read_offset = parse_info
+        .sample_format
+        .intersection(
+            SampleFormat::IDENTIFIER
+                | SampleFormat::IP
+                | SampleFormat::TID
+                | SampleFormat::TIME
+                | SampleFormat::ADDR
+                | SampleFormat::ID
+                | SampleFormat::STREAM_ID
+                | SampleFormat::CPU
+                | SampleFormat::PERIOD,
+        )
+        .bits()
+        .count_ones() as usize
+        * 8;
+
+    buffer.clear();
+    buffer.extend_from_slice(&record.data.as_slice());
+
+    let read_u64 = |offset: usize| -> Option<u64> {
+        let bytes = buffer.get(offset..offset + 8)?;
+        Some(match parse_info.endian {
+            Endianness::LittleEndian => LittleEndian::read_u64(bytes),
+            Endianness::BigEndian => BigEndian::read_u64(bytes),
+        })
+    };
+
+    // struct read_format {
+    //     u64 nr;
+    //     { u64 time_enabled; } && PERF_FORMAT_TOTAL_TIME_ENABLED
+    //     { u64 time_running; } && PERF_FORMAT_TOTAL_TIME_RUNNING
+    //     { u64 value;
+    //       { u64 id;         } && PERF_FORMAT_ID
+    //       { u64 lost;       } && PERF_FORMAT_LOST
+    //     } cntr[nr];
+    // };
+    let read_format = parse_info.read_format;
+    let nr = read_u64(read_offset)? as usize;
+    let mut offset = read_offset + 8;
+    if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
+        offset += 8;
+    }
+    if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
+        offset += 8;
+    }
+    let has_id = read_format.contains(ReadFormat::ID);
+    // PERF_FORMAT_LOST isn't known to linux-perf-event-reader.
+    let has_lost = read_format.bits() & (1 << 4) != 0;
+    let mut values = Vec::with_capacity(nr.min(64));
+    for _ in 0..nr {
+        let value = read_u64(offset)?;
+        offset += 8;
+        let id = if has_id {
+            offset += 8;
+            Some(read_u64(offset - 8)?)
+        } else {
+            None
+        };
+        if has_lost {
+            offset += 8;
+        }
+        values.push((id, value));
+    }
+    if offset > buffer.len() {
+        return None;
+    }
+
+    buffer.drain(read_offset..offset);
+    let mut parse_info = record.parse_info;
+    parse_info.sample_format.remove(SampleFormat::READ);
+    let record = RawEventRecord {
+        record_type: record.record_type,
+        misc: record.misc,
+        data: RawData::Single(&buffer[..]),
+        parse_info,
+    };
+    Some((record, values))
+}
+
+/// Turns the counter values in samples into per-process counter tracks, and
+/// adds IPC (instructions per cycle) markers to the threads if both
+/// instructions and cycles are counted.
+#[derive(Debug, Default)]
+pub struct GroupReadCounters {
+    /// The attr index of each event ID.
+    attr_index_by_event_id: HashMap<u64, usize>,
+    instructions_attr_index: Option<usize>,
+    cycles_attr_index: Option<usize>,
+    /// The most recent value of each perf event, by tid, cpu and event ID. Each
+    /// perf event counts separately, e.g. per CPU, or per inherited thread.
+    last_values: HashMap<(i32, Option<u32>, u64), u64>,
+    /// The time of the previous sample with counter values, by tid and cpu.
+    last_sample_times: HashMap<(i32, Option<u32>), Timestamp>,
+    /// The counter track for each pid and attr index.
+    counters: HashMap<(i32, usize), CounterHandle>,
+}
+
+impl GroupReadCounters {
+    pub fn new(
+        attr_index_by_event_id: HashMap<u64, usize>,
+        instructions_attr_index: Option<usize>,
+        cycles_attr_index: Option<usize>,
+    ) -> Self {
+        Self {
+            attr_index_by_event_id,
+            instructions_attr_index,
+            cycles_attr_index,
+            ..Default::default()
+        }
+    }
+
+    /// Registers the IDs of newly opened perf events.
+    pub fn add_event_ids(&mut self, attr_index: usize, ids: &[u64]) {
+        for &id in ids {
+            self.attr_index_by_event_id.insert(id, attr_index);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn handle(
+        &mut self,
+        profile: &mut Profile,
+        process: ProcessHandle,
+        thread: ThreadHandle,
+        pid: i32,
+        tid: i32,
+        cpu: Option<u32>,
+        timestamp: Timestamp,
+        values: &[(Option<u64>, u64)],
+        event_names: &[String],
+    ) {
+        let mut instructions_delta = None;
+        let mut cycles_delta = None;
+        for &(id, value) in values {
+            // Values without a known ID, e.g. the value of the sampling event
+            // itself, are skipped.
+            let Some(id) = id else { continue };
+            let Some(&attr_index) = self.attr_index_by_event_id.get(&id) else {
+                continue;
+            };
+            let last_value = self.last_values.insert((tid, cpu, id), value).unwrap_or(0);
+            let delta = value.saturating_sub(last_value);
+            if Some(attr_index) == self.instructions_attr_index {
+                instructions_delta = Some(delta);
+            }
+            if Some(attr_index) == self.cycles_attr_index {
+                cycles_delta = Some(delta);
+            }
+            let counter = *self.counters.entry((pid, attr_index)).or_insert_with(|| {
+                let name = event_names
+                    .get(attr_index)
+                    .map(String::as_str)
+                    .unwrap_or("<unknown event>");
+                profile.add_counter(
+                    process,
+                    name,
+                    "Hardware counters",
+                    &format!("The number of {name}, counted by perf"),
+                )
+            });
+            profile.add_counter_sample(counter, timestamp, delta as f64, 1);
+        }
+
+        // The IPC is a ratio, so it can't be a counter, whose values are added
+        // up over time ranges. Instead, each interval between two samples of the
+        // thread gets a marker, and the markers are drawn as a graph.
+        let last_sample_time = self.last_sample_times.insert((tid, cpu), timestamp);
+        if let (Some(instructions), Some(cycles), Some(start)) =
+            (instructions_delta, cycles_delta, last_sample_time)
+        {
+            if cycles != 0 {
+                profile.add_marker(
+                    thread,
+                    MarkerTiming::Interval(start, timestamp),
+                    IpcMarker {
+                        instructions,
+                        cycles,
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::{
+        BranchSampleFormat, EventRecord, RecordIdParseInfo, RecordParseInfo,
+    };
+
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    #[test]
+    fn test_ipc_markers() {
+        let mut profile = test_profile();
+        let process = profile.add_process("test", 1, t(0));
+        let thread = profile.add_thread(process, 1, t(0), true);
+        let event_names = ["cycles".to_owned(), "instructions".to_owned()];
+        let mut counters = GroupReadCounters::new(HashMap::new(), Some(1), Some(0));
+        counters.add_event_ids(0, &[7]);
+        counters.add_event_ids(1, &[8]);
+
+        let mut handle = |time, values: &[(Option<u64>, u64)]| {
+            counters.handle(
+                &mut profile,
+                process,
+                thread,
+                1,
+                1,
+                Some(0),
+                t(time),
+                values,
+                &event_names,
+            )
+        };
+        handle(10, &[(Some(7), 1000), (Some(8), 500)]);
+        handle(20, &[(Some(7), 3000), (Some(8), 4500)]);
+
+        let profile = serde_json::to_value(&profile).unwrap();
+        let counter_names: Vec<_> = profile["counters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|counter| counter["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(counter_names, ["cycles", "instructions"]);
+        // Only the second sample has a previous sample to measure from.
+        let markers = &profile["threads"][0]["markers"];
+        assert_eq!(markers["length"], 1);
+        assert_eq!(markers["data"][0]["instructions"], 4000.0);
+        assert_eq!(markers["data"][0]["cycles"], 2000.0);
+        assert_eq!(markers["data"][0]["ipc"], 2.0);
+    }
+
+    #[test]
+    fn test_split_off_group_read() {
+        let sample_format =
+            SampleFormat::IP | SampleFormat::TID | SampleFormat::READ | SampleFormat::CALLCHAIN;
+        let parse_info = RecordParseInfo {
+            endian: Endianness::LittleEndian,
+            sample_format,
+            branch_sample_format: BranchSampleFormat::empty(),
+            read_format: ReadFormat::GROUP | ReadFormat::ID,
+            common_data_offset_from_end: None,
+            sample_regs_user: 0,
+            user_regs_count: 0,
+            sample_regs_intr: 0,
+            intr_regs_count: 0,
+            id_parse_info: RecordIdParseInfo {
+                nonsample_record_id_offset_from_end: None,
+                sample_record_id_offset_from_start: None,
+            },
+            nonsample_record_time_offset_from_end: None,
+            sample_record_time_offset_from_start: None,
+        };
+        let words: [u64; 11] = [
+            0x1234,                // ip
+            (12 << 32) | 11,       // pid, tid
+            2,                     // nr
+            1000,                  // value of the sampling event
+            7,                     // its id
+            500,                   // value of the counting event
+            8,                     // its id
+            2,                     // callchain length
+            0x1234,                // callchain
+            0x5678,                //
+            0xdead_beef_dead_beef, // trailing bytes
+        ];
+        let data: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let record = RawEventRecord {
+            record_type: RecordType::SAMPLE,
+            misc: 0,
+            data: RawData::Single(&data),
+            parse_info,
+        };
+
+        let mut buffer = Vec::new();
+        let (record, values) = split_off_group_read(&record, &mut buffer).unwrap();
+        assert_eq!(values, vec![(Some(7), 1000), (Some(8), 500)]);
+        let EventRecord::Sample(sample) = record.parse().unwrap() else {
+            panic!("expected a sample")
+        };
+        assert_eq!(sample.ip, Some(0x1234));
+        assert_eq!(sample.pid, Some(11));
+        assert_eq!(sample.tid, Some(12));
+        let callchain = sample.callchain.unwrap();
+        assert_eq!(callchain.len(), 2);
+        assert_eq!(callchain.get(1), Some(0x5678));
+    }
+}
@@ -0,0 +1,42 @@
+//! Information about the machine which a profile was recorded on, for the
+//! profile's metadata. It comes from /proc and uname when recording, and from
+//! the feature headers of the perf.data file when importing.
+
+use fxprof_processed_profile::Profile;
+
+#[derive(Debug, Clone, Default)]
+pub struct HostInfo {
+    pub cpu_name: Option<String>,
+    pub physical_cpus: Option<u32>,
+    pub logical_cpus: Option<u32>,
+    /// The total memory in bytes.
+    pub main_memory: Option<u64>,
+    /// The command line of the recording.
+    pub arguments: Option<String>,
+    /// Labeled values for the "Host" section of the metadata, such as the
+    /// hostname and the kernel release.
+    pub extra: Vec<(&'static str, String)>,
+}
+
+impl HostInfo {
+    pub fn add_to_profile(&self, profile: &mut Profile) {
+        if let Some(cpu_name) = &self.cpu_name {
+            profile.set_cpu_name(cpu_name);
+        }
+        if let Some(physical_cpus) = self.physical_cpus {
+            profile.set_physical_cpu_count(physical_cpus);
+        }
+        if let Some(logical_cpus) = self.logical_cpus {
+            profile.set_logical_cpu_count(logical_cpus);
+        }
+        if let Some(main_memory) = self.main_memory {
+            profile.set_main_memory(main_memory);
+        }
+        if let Some(arguments) = &self.arguments {
+            profile.set_arguments(arguments);
+        }
+        for (label, value) in &self.extra {
+            profile.add_extra_info_string("Host", label, value);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! The runs of the launched command when recording with `--iteration-count`.
+//!
+//! Each launched process gets the number of its iteration, and so do the
+//! processes which it forks. The iteration spans from the exec of the launched
+//! process until its exit, and is shown as a marker on its main thread.
+
+use std::collections::{HashMap, HashSet};
+
+use fxprof_processed_profile::{MarkerTiming, Profile, ThreadHandle, Timestamp};
+
+use crate::shared::process_sample_data::IterationMarker;
+
+#[derive(Debug)]
+pub struct Iterations {
+    /// Whether the iteration is appended to the names of its processes, to
+    /// keep them apart. Otherwise the processes of all iterations are merged.
+    number_process_names: bool,
+    /// The number of iterations which have been launched so far.
+    count: u32,
+    /// The iteration of each process which belongs to one, by pid.
+    iteration_by_pid: HashMap<i32, u32>,
+    /// The pids of the launched processes which haven't executed yet.
+    pending_pids: Vec<i32>,
+    /// The main thread and the start time of the running iterations, by the
+    /// pid of their launched process.
+    running: HashMap<i32, (ThreadHandle, Timestamp)>,
+    /// The pids of the launched processes whose iteration was started before
+    /// this profile, by the previous generation of the flight recorder.
+    continued: HashSet<i32>,
+}
+
+impl Iterations {
+    pub fn new(number_process_names: bool) -> Self {
+        Self {
+            number_process_names,
+            count: 0,
+            iteration_by_pid: HashMap::new(),
+            pending_pids: Vec::new(),
+            running: HashMap::new(),
+            continued: HashSet::new(),
+        }
+    }
+
+    /// Returns the iterations for a new profile which continues this one. The
+    /// running iterations end in the new profile without a start.
+    pub fn continued(&self) -> Self {
+        Self {
+            number_process_names: self.number_process_names,
+            count: self.count,
+            iteration_by_pid: self.iteration_by_pid.clone(),
+            pending_pids: self.pending_pids.clone(),
+            running: HashMap::new(),
+            continued: self
+                .running
+                .keys()
+                .chain(&self.continued)
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Called when the next iteration is launched as process `pid`, before it
+    /// executes the command.
+    pub fn launch(&mut self, pid: i32) {
+        self.count += 1;
+        self.iteration_by_pid.insert(pid, self.count);
+        self.pending_pids.push(pid);
+    }
+
+    /// Called when process `child_pid` is forked from process `parent_pid`.
+    pub fn fork(&mut self, parent_pid: i32, child_pid: i32) {
+        if let Some(&iteration) = self.iteration_by_pid.get(&parent_pid) {
+            self.iteration_by_pid.insert(child_pid, iteration);
+        }
+    }
+
+    /// Called when process `pid` executes a program. The first exec of a
+    /// launched process starts its iteration.
+    pub fn exec(&mut self, pid: i32, thread: ThreadHandle, timestamp: Timestamp) {
+        if let Some(index) = self.pending_pids.iter().position(|&p| p == pid) {
+            self.pending_pids.swap_remove(index);
+            self.running.insert(pid, (thread, timestamp));
+        }
+    }
+
+    /// Returns the name for a process of an iteration, which gets the number
+    /// of the iteration if the iterations are kept apart.
+    pub fn process_name(&self, pid: i32, name: &str) -> String {
+        match self.iteration_by_pid.get(&pid) {
+            Some(iteration) if self.number_process_names => {
+                format!("{name} (iteration {iteration})")
+            }
+            _ => name.to_owned(),
+        }
+    }
+
+    /// Called when process `pid` exits. The exit of a launched process ends
+    /// its iteration. `main_thread` is used for the marker if the iteration
+    /// was started before this profile.
+    pub fn exit(
+        &mut self,
+        profile: &mut Profile,
+        pid: i32,
+        main_thread: Option<ThreadHandle>,
+        timestamp: Timestamp,
+    ) {
+        let Some(iteration) = self.iteration_by_pid.remove(&pid) else {
+            return;
+        };
+        let (thread, timing) = match self.running.remove(&pid) {
+            Some((thread, start)) => (thread, MarkerTiming::Interval(start, timestamp)),
+            None => match (self.continued.remove(&pid), main_thread) {
+                (true, Some(thread)) => (thread, MarkerTiming::IntervalEnd(timestamp)),
+                _ => return,
+            },
+        };
+        profile.add_marker(thread, timing, IterationMarker { iteration, pid });
+    }
+
+    /// Adds the markers of the iterations which were still running at the end
+    /// of the profile.
+    pub fn finish(self, profile: &mut Profile) {
+        for (pid, (thread, start)) in self.running {
+            let iteration = self.iteration_by_pid[&pid];
+            profile.add_marker(
+                thread,
+                MarkerTiming::IntervalStart(start),
+                IterationMarker { iteration, pid },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    #[test]
+    fn test_iterations() {
+        let mut profile = test_profile();
+        let process = profile.add_process("test", 100, t(0));
+        let thread = profile.add_thread(process, 100, t(0), true);
+
+        let mut iterations = Iterations::new(true);
+        iterations.launch(100);
+        iterations.fork(100, 101);
+        iterations.fork(50, 102);
+        assert_eq!(iterations.process_name(100, "sh"), "sh (iteration 1)");
+        assert_eq!(iterations.process_name(101, "ls"), "ls (iteration 1)");
+        assert_eq!(iterations.process_name(102, "ls"), "ls");
+
+        // Only the first exec of the launched process starts the iteration.
+        iterations.exec(100, thread, t(1));
+        iterations.exec(100, thread, t(2));
+        iterations.exec(101, thread, t(2));
+        assert_eq!(iterations.running.len(), 1);
+        assert_eq!(iterations.running[&100].1, t(1));
+
+        iterations.exit(&mut profile, 101, Some(thread), t(3));
+        iterations.exit(&mut profile, 100, Some(thread), t(4));
+        assert!(iterations.running.is_empty());
+
+        iterations.launch(200);
+        iterations.exec(200, thread, t(5));
+        assert_eq!(iterations.iteration_by_pid[&200], 2);
+
+        // A new generation of the flight recorder keeps the numbers, and ends
+        // the running iteration without a start.
+        iterations.launch(300);
+        let mut continued = iterations.continued();
+        assert_eq!(continued.process_name(300, "sh"), "sh (iteration 3)");
+        assert!(continued.running.is_empty());
+        continued.exec(300, thread, t(6));
+        assert_eq!(continued.running[&300].0, thread);
+        continued.launch(400);
+        assert_eq!(continued.iteration_by_pid[&400], 4);
+        continued.exit(&mut profile, 200, Some(thread), t(7));
+        assert!(continued.continued.is_empty());
+        continued.finish(&mut profile);
+        iterations.finish(&mut profile);
+
+        let merged = Iterations::new(false);
+        assert_eq!(merged.process_name(100, "sh"), "sh");
+    }
+}
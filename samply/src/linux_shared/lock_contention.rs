@@ -0,0 +1,372 @@
+//! Lock contention markers and samples, with `--lock-contention`.
+//!
+//! Futex waits come from the `syscalls:sys_enter_futex` / `syscalls:sys_exit_futex`
+//! tracepoints, and contended kernel locks come from the `lock:contention_begin`
+//! / `lock:contention_end` tracepoints, which exist since Linux 5.19.
+//!
+//! Each wait becomes an interval marker on the waiting thread, with the lock
+//! address and the stack at the start of the wait. Each wait also becomes a
+//! sample on a "Lock contention" thread of the process, with the waiting stack
+//! below a frame for the lock, and the wait time in milliseconds as its weight.
+//! This way, the call tree of that thread shows which locks and which call
+//! sites cost the most time.
+
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use fxprof_processed_profile::{
+    Category, CategoryColor, FrameFlags, FrameHandle, ProcessHandle, Profile, ThreadHandle,
+    Timestamp, WeightType,
+};
+use linux_perf_data::Endianness;
+
+use super::processes::PID_MAX_LIMIT;
+use super::syscalls::parse_syscall_raw_data;
+use crate::shared::process_sample_data::LockContentionMarker;
+use crate::shared::unresolved_samples::UnresolvedStackHandle;
+
+/// The lock contention tracepoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockEvent {
+    /// `syscalls:sys_enter_futex`
+    FutexEnter,
+    /// `syscalls:sys_exit_futex`
+    FutexExit,
+    /// `lock:contention_begin`
+    ContentionBegin,
+    /// `lock:contention_end`
+    ContentionEnd,
+}
+
+impl LockEvent {
+    fn from_event_name(name: &str) -> Option<Self> {
+        let event = match name {
+            "syscalls:sys_enter_futex" => LockEvent::FutexEnter,
+            "syscalls:sys_exit_futex" => LockEvent::FutexExit,
+            "lock:contention_begin" => LockEvent::ContentionBegin,
+            "lock:contention_end" => LockEvent::ContentionEnd,
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+/// Whether a wait is in the futex syscall or in the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockKind {
+    Futex,
+    Kernel,
+}
+
+/// The lock which a thread started waiting for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockWait {
+    pub kind: LockKind,
+    pub address: u64,
+    /// The futex operation, e.g. "FUTEX_WAIT_PRIVATE", or the kind of kernel
+    /// lock, e.g. "mutex".
+    pub operation: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockSample {
+    Begin(LockWait),
+    End(LockKind),
+    /// The futex call returned without waiting, because the futex value had
+    /// already changed.
+    DidNotWait(LockKind),
+}
+
+/// A wait which hasn't ended yet.
+#[derive(Debug, Clone)]
+pub struct PendingLockWait {
+    pub wait: LockWait,
+    pub pid: i32,
+    pub thread: ThreadHandle,
+    pub start: Timestamp,
+    pub start_mono: u64,
+    pub stack: UnresolvedStackHandle,
+}
+
+/// The return value of a futex wait whose futex value didn't match.
+const EAGAIN: i64 = 11;
+
+const FUTEX_PRIVATE_FLAG: u64 = 128;
+const FUTEX_CLOCK_REALTIME: u64 = 256;
+
+/// Returns the name of a futex operation which waits, or None for operations
+/// which don't, e.g. `FUTEX_WAKE`.
+fn futex_wait_operation_name(op: u64) -> Option<String> {
+    let name = match op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME) {
+        0 => "FUTEX_WAIT",
+        6 => "FUTEX_LOCK_PI",
+        9 => "FUTEX_WAIT_BITSET",
+        11 => "FUTEX_WAIT_REQUEUE_PI",
+        13 => "FUTEX_LOCK_PI2",
+        _ => return None,
+    };
+    Some(if op & FUTEX_PRIVATE_FLAG != 0 {
+        format!("{name}_PRIVATE")
+    } else {
+        name.to_owned()
+    })
+}
+
+/// The kind of kernel lock, from the `LCB_F_*` flags of `lock:contention_begin`.
+fn kernel_lock_kind(flags: u32) -> String {
+    const LCB_F_SPIN: u32 = 1 << 0;
+    const LCB_F_READ: u32 = 1 << 1;
+    const LCB_F_WRITE: u32 = 1 << 2;
+    const LCB_F_RT: u32 = 1 << 3;
+    const LCB_F_PERCPU: u32 = 1 << 4;
+    const LCB_F_MUTEX: u32 = 1 << 5;
+    let kind = if flags & LCB_F_MUTEX != 0 {
+        "mutex"
+    } else if flags & LCB_F_RT != 0 {
+        "rtmutex"
+    } else if flags & LCB_F_PERCPU != 0 {
+        "percpu-rwsem"
+    } else if flags & LCB_F_SPIN != 0 {
+        "spinlock"
+    } else {
+        "rwsem"
+    };
+    if flags & LCB_F_READ != 0 {
+        format!("{kind}:R")
+    } else if flags & LCB_F_WRITE != 0 {
+        format!("{kind}:W")
+    } else {
+        kind.to_owned()
+    }
+}
+
+/// Parses the `lock_addr` and `flags` fields of a `lock:contention_begin`
+/// sample.
+///
+/// ```
+/// # cat /sys/kernel/tracing/events/lock/contention_begin/format
+/// name: contention_begin
+/// ID: 434
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:void * lock_addr; offset:8;       size:8; signed:0;
+///         field:unsigned int flags;       offset:16;      size:4; signed:0;
+/// ```
+fn parse_contention_begin(data: &[u8], endian: Endianness) -> Option<(u64, u32)> {
+    let lock_addr = data.get(8..16)?;
+    let flags = data.get(16..20)?;
+    Some(match endian {
+        Endianness::LittleEndian => (
+            LittleEndian::read_u64(lock_addr),
+            LittleEndian::read_u32(flags),
+        ),
+        Endianness::BigEndian => (BigEndian::read_u64(lock_addr), BigEndian::read_u32(flags)),
+    })
+}
+
+/// Pairs up the begin and end samples of the waits of each thread.
+#[derive(Debug)]
+pub struct LockContentionState {
+    events: HashMap<usize, LockEvent>,
+    /// The futex wait and the kernel lock wait of each thread, by tid. A
+    /// thread can wait for a kernel lock inside of the futex syscall.
+    pending: HashMap<(i32, LockKind), PendingLockWait>,
+    /// The "Lock contention" thread of each process, by pid.
+    threads: HashMap<i32, ThreadHandle>,
+}
+
+impl LockContentionState {
+    pub fn new(event_names: &[String]) -> Self {
+        Self {
+            events: event_names
+                .iter()
+                .enumerate()
+                .filter_map(|(index, name)| Some((index, LockEvent::from_event_name(name)?)))
+                .collect(),
+            pending: HashMap::new(),
+            threads: HashMap::new(),
+        }
+    }
+
+    /// Whether the samples of this event are turned into lock contention markers.
+    pub fn handles_event(&self, attr_index: usize) -> bool {
+        self.events.contains_key(&attr_index)
+    }
+
+    /// Parses the raw data of a sample. Returns None for futex operations
+    /// which don't wait, e.g. `FUTEX_WAKE`.
+    pub fn parse_sample(
+        &self,
+        attr_index: usize,
+        raw: &[u8],
+        endian: Endianness,
+    ) -> Option<LockSample> {
+        let sample = match self.events.get(&attr_index)? {
+            LockEvent::FutexEnter => {
+                let (_nr, args) = parse_syscall_raw_data(raw, false, endian)?;
+                let (&uaddr, &op) = (args.first()?, args.get(1)?);
+                LockSample::Begin(LockWait {
+                    kind: LockKind::Futex,
+                    address: uaddr,
+                    operation: futex_wait_operation_name(op)?,
+                })
+            }
+            LockEvent::FutexExit => {
+                let (_nr, values) = parse_syscall_raw_data(raw, false, endian)?;
+                if *values.first()? as i64 == -EAGAIN {
+                    LockSample::DidNotWait(LockKind::Futex)
+                } else {
+                    LockSample::End(LockKind::Futex)
+                }
+            }
+            LockEvent::ContentionBegin => {
+                let (lock_addr, flags) = parse_contention_begin(raw, endian)?;
+                LockSample::Begin(LockWait {
+                    kind: LockKind::Kernel,
+                    address: lock_addr,
+                    operation: kernel_lock_kind(flags),
+                })
+            }
+            LockEvent::ContentionEnd => LockSample::End(LockKind::Kernel),
+        };
+        Some(sample)
+    }
+
+    pub fn begin(&mut self, tid: i32, wait: PendingLockWait) {
+        self.pending.insert((tid, wait.wait.kind), wait);
+    }
+
+    /// Returns the wait which the thread stopped waiting for, unless the wait
+    /// started before recording started.
+    pub fn end(&mut self, tid: i32, kind: LockKind) -> Option<PendingLockWait> {
+        self.pending.remove(&(tid, kind))
+    }
+
+    /// Returns the waits which haven't ended by the end of the profile.
+    pub fn finish(&mut self) -> Vec<PendingLockWait> {
+        self.pending.drain().map(|(_, wait)| wait).collect()
+    }
+
+    /// Returns the marker for a wait.
+    pub fn marker(profile: &mut Profile, wait: &LockWait) -> LockContentionMarker {
+        let name = match wait.kind {
+            LockKind::Futex => "Futex wait",
+            LockKind::Kernel => "Kernel lock contention",
+        };
+        LockContentionMarker {
+            name: profile.handle_for_string(name),
+            address: profile.handle_for_string(&format!("{:#x}", wait.address)),
+            operation: profile.handle_for_string(&wait.operation),
+        }
+    }
+
+    /// Returns the "Lock contention" thread of the process.
+    pub fn contention_thread(
+        &mut self,
+        profile: &mut Profile,
+        process: ProcessHandle,
+        pid: i32,
+        start_time: Timestamp,
+    ) -> ThreadHandle {
+        *self.threads.entry(pid).or_insert_with(|| {
+            // The tid can't be the tid of a real thread.
+            let tid = PID_MAX_LIMIT + pid as u32;
+            let thread = profile.add_thread(process, tid, start_time, false);
+            profile.set_thread_name(thread, "Lock contention");
+            profile.set_thread_samples_weight_type(thread, WeightType::TracingMs);
+            thread
+        })
+    }
+
+    /// Returns the root frame of the samples for waits for this lock.
+    pub fn lock_frame(profile: &mut Profile, thread: ThreadHandle, wait: &LockWait) -> FrameHandle {
+        let category = profile.handle_for_category(Category("Lock contention", CategoryColor::Red));
+        let label = match wait.kind {
+            LockKind::Futex => format!("futex {:#x}", wait.address),
+            LockKind::Kernel => format!("{} {:#x}", wait.operation, wait.address),
+        };
+        let label = profile.handle_for_string(&label);
+        profile.handle_for_frame_with_label(thread, label, category, FrameFlags::empty())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event_names() -> Vec<String> {
+        [
+            "cpu-clock",
+            "syscalls:sys_enter_futex",
+            "syscalls:sys_exit_futex",
+            "lock:contention_begin",
+            "lock:contention_end",
+        ]
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect()
+    }
+
+    #[test]
+    fn test_parse_futex_samples() {
+        let state = LockContentionState::new(&event_names());
+        assert!(!state.handles_event(0));
+        assert!(state.handles_event(1));
+
+        let mut enter = vec![0u8; 64];
+        enter[8..12].copy_from_slice(&202i32.to_le_bytes());
+        enter[16..24].copy_from_slice(&0x7f00_1234u64.to_le_bytes());
+        enter[24..32].copy_from_slice(&(FUTEX_PRIVATE_FLAG | 9).to_le_bytes());
+        assert_eq!(
+            state.parse_sample(1, &enter, Endianness::LittleEndian),
+            Some(LockSample::Begin(LockWait {
+                kind: LockKind::Futex,
+                address: 0x7f00_1234,
+                operation: "FUTEX_WAIT_BITSET_PRIVATE".to_string(),
+            }))
+        );
+
+        // FUTEX_WAKE doesn't wait.
+        enter[24..32].copy_from_slice(&(FUTEX_PRIVATE_FLAG | 1).to_le_bytes());
+        assert_eq!(
+            state.parse_sample(1, &enter, Endianness::LittleEndian),
+            None
+        );
+
+        let mut exit = vec![0u8; 24];
+        exit[8..12].copy_from_slice(&202i32.to_le_bytes());
+        assert_eq!(
+            state.parse_sample(2, &exit, Endianness::LittleEndian),
+            Some(LockSample::End(LockKind::Futex))
+        );
+
+        // A futex wait which returns -EAGAIN didn't wait.
+        exit[16..24].copy_from_slice(&(-EAGAIN).to_le_bytes());
+        assert_eq!(
+            state.parse_sample(2, &exit, Endianness::LittleEndian),
+            Some(LockSample::DidNotWait(LockKind::Futex))
+        );
+    }
+
+    #[test]
+    fn test_parse_contention_samples() {
+        let state = LockContentionState::new(&event_names());
+        let mut begin = vec![0u8; 20];
+        begin[8..16].copy_from_slice(&0xffff_8880_0000_1000u64.to_le_bytes());
+        begin[16..20].copy_from_slice(&(1u32 << 5).to_le_bytes());
+        assert_eq!(
+            state.parse_sample(3, &begin, Endianness::LittleEndian),
+            Some(LockSample::Begin(LockWait {
+                kind: LockKind::Kernel,
+                address: 0xffff_8880_0000_1000,
+                operation: "mutex".to_string(),
+            }))
+        );
+        assert_eq!(kernel_lock_kind(1 << 2), "rwsem:W");
+        assert_eq!(kernel_lock_kind((1 << 0) | (1 << 1)), "spinlock:R");
+    }
+}
@@ -1,23 +1,44 @@
 mod avma_range;
+mod block_io;
 mod convert_regs;
 mod converter;
+mod cpu_power;
 mod event_interpretation;
+mod group_read;
+mod host_info;
 mod injected_jit_object;
+mod iterations;
 mod kernel_symbols;
+mod lock_contention;
 mod mmap_range_or_vec;
 mod object_rewriter;
 mod pe_mappings;
+mod probes;
 mod process;
+mod process_lifecycle;
 mod process_threads;
 mod processes;
 mod rss_stat;
+mod sched_wakeup;
 mod svma_file_range;
+mod syscalls;
+#[cfg(test)]
+mod test_util;
 mod thread;
+mod thread_states;
+mod trace_marker;
+mod tracepoint_format;
+mod usdt;
 #[allow(unused)]
 pub mod vdso;
 
 pub use convert_regs::{ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64};
-pub use converter::Converter;
+pub use converter::{AllocationSample, Converter, SharedSampleStack};
 #[allow(unused)]
 pub use event_interpretation::{EventInterpretation, KnownEvent, OffCpuIndicator};
+pub use group_read::{split_off_group_read, GroupReadValues};
+pub use host_info::HostInfo;
+#[allow(unused)]
+pub use kernel_symbols::build_id_from_notes_section_data;
 pub use mmap_range_or_vec::MmapRangeOrVec;
+pub use tracepoint_format::{parse_tracing_data, write_tracing_data, TracepointFormat};
@@ -0,0 +1,218 @@
+//! Markers and call counters for `perf probe` events, e.g. the uprobes from
+//! `--probe`.
+//!
+//! The entry of a probed function is an event like `probe_libc:malloc`, and its
+//! return is `probe_libc:malloc__return`. If both were recorded, the entry and
+//! the return of each call are paired up into an interval marker with the stack
+//! at the entry. Otherwise, each hit becomes an instant marker. Each process
+//! also gets a counter of the calls of each probed function.
+
+use std::collections::HashMap;
+
+use fxprof_processed_profile::{CounterHandle, ProcessHandle, Profile, ThreadHandle, Timestamp};
+
+use crate::shared::process_sample_data::ProbeMarker;
+use crate::shared::unresolved_samples::UnresolvedStackHandle;
+
+/// Whether an event is a `perf probe` event, i.e. a kprobe in the `probe`
+/// group or a uprobe in a `probe_<binary>` group.
+pub fn is_probe_event_name(name: &str) -> bool {
+    name.split_once(':')
+        .is_some_and(|(group, _)| group == "probe" || group.starts_with("probe_"))
+}
+
+#[derive(Debug, Clone)]
+struct Probe {
+    /// The event name of the entry, e.g. `probe_libc:malloc`.
+    name: String,
+    has_entry: bool,
+    has_return: bool,
+}
+
+/// A probe hit which is waiting for its return.
+#[derive(Debug, Clone)]
+pub struct PendingCall {
+    pub pid: i32,
+    pub thread: ThreadHandle,
+    pub start: Timestamp,
+    pub start_mono: u64,
+    pub stack: UnresolvedStackHandle,
+}
+
+/// A probe hit, see [`ProbeState::event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeEvent {
+    pub probe: usize,
+    pub is_return: bool,
+}
+
+/// Pairs up the entries and returns of the calls of each thread.
+#[derive(Debug, Default)]
+pub struct ProbeState {
+    probes: Vec<Probe>,
+    events: HashMap<usize, ProbeEvent>,
+    /// The calls which haven't returned yet, by tid and probe. Recursive calls
+    /// are nested.
+    pending: HashMap<(i32, usize), Vec<PendingCall>>,
+    /// The call counter of each probe, by pid and probe.
+    counters: HashMap<(i32, usize), CounterHandle>,
+}
+
+impl ProbeState {
+    pub fn new(event_names: &[String]) -> Self {
+        let mut state = Self::default();
+        for (attr_index, name) in event_names.iter().enumerate() {
+            if !is_probe_event_name(name) {
+                continue;
+            }
+            let (entry_name, is_return) = match name.strip_suffix("__return") {
+                Some(entry_name) => (entry_name, true),
+                None => (name.as_str(), false),
+            };
+            let probe = match state.probes.iter().position(|p| p.name == entry_name) {
+                Some(probe) => probe,
+                None => {
+                    state.probes.push(Probe {
+                        name: entry_name.to_owned(),
+                        has_entry: false,
+                        has_return: false,
+                    });
+                    state.probes.len() - 1
+                }
+            };
+            if is_return {
+                state.probes[probe].has_return = true;
+            } else {
+                state.probes[probe].has_entry = true;
+            }
+            state
+                .events
+                .insert(attr_index, ProbeEvent { probe, is_return });
+        }
+        state
+    }
+
+    pub fn event(&self, attr_index: usize) -> Option<ProbeEvent> {
+        self.events.get(&attr_index).copied()
+    }
+
+    /// Whether the calls of this probe are paired up into interval markers.
+    pub fn has_entry_and_return(&self, probe: usize) -> bool {
+        let probe = &self.probes[probe];
+        probe.has_entry && probe.has_return
+    }
+
+    pub fn enter(&mut self, tid: i32, probe: usize, call: PendingCall) {
+        self.pending.entry((tid, probe)).or_default().push(call);
+    }
+
+    /// Returns the innermost pending call of the probe on this thread, unless it
+    /// was entered before recording started.
+    pub fn exit(&mut self, tid: i32, probe: usize) -> Option<PendingCall> {
+        self.pending.get_mut(&(tid, probe))?.pop()
+    }
+
+    /// Returns the calls which haven't returned by the end of the profile.
+    pub fn finish(&mut self) -> Vec<(usize, PendingCall)> {
+        self.pending
+            .drain()
+            .flat_map(|((_, probe), calls)| calls.into_iter().map(move |call| (probe, call)))
+            .collect()
+    }
+
+    pub fn marker(&self, profile: &mut Profile, probe: usize) -> ProbeMarker {
+        let name = &self.probes[probe].name;
+        let function = name.split_once(':').map_or(name.as_str(), |(_, f)| f);
+        ProbeMarker {
+            name: profile.handle_for_string(function),
+            probe: profile.handle_for_string(name),
+        }
+    }
+
+    /// Counts a call in the call counter of the probe for this process.
+    pub fn count_call(
+        &mut self,
+        profile: &mut Profile,
+        process: ProcessHandle,
+        pid: i32,
+        probe: usize,
+        timestamp: Timestamp,
+    ) {
+        let name = &self.probes[probe].name;
+        let counter = *self.counters.entry((pid, probe)).or_insert_with(|| {
+            profile.add_counter(
+                process,
+                &format!("{name} calls"),
+                "Probes",
+                &format!("The number of calls of {name}"),
+            )
+        });
+        profile.add_counter_sample(counter, timestamp, 1.0, 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    #[test]
+    fn test_probe_events() {
+        let event_names: Vec<String> = [
+            "cycles",
+            "probe_libc:malloc",
+            "probe_libc:malloc__return",
+            "probe_app:run__return",
+            "probe:do_sys_openat2",
+            "sched:sched_switch",
+        ]
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect();
+        let state = ProbeState::new(&event_names);
+        assert_eq!(state.event(0), None);
+        assert_eq!(state.event(5), None);
+        assert_eq!(
+            state.event(2),
+            Some(ProbeEvent {
+                probe: 0,
+                is_return: true
+            })
+        );
+        assert!(state.has_entry_and_return(0));
+        assert_eq!(state.event(3).unwrap().probe, 1);
+        assert!(!state.has_entry_and_return(1));
+        assert!(!state.has_entry_and_return(2));
+        assert!(!is_probe_event_name("probes:foo"));
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let mut profile = test_profile();
+        let process = profile.add_process("test", 1, t(0));
+        let thread = profile.add_thread(process, 1, t(0), true);
+        let call = |start| PendingCall {
+            pid: 1,
+            thread,
+            start: t(start),
+            start_mono: start,
+            stack: UnresolvedStackHandle::EMPTY,
+        };
+
+        let event_names: Vec<String> = ["cycles", "probe_app:fib", "probe_app:fib__return"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+        let mut state = ProbeState::new(&event_names);
+        // A return without an entry, e.g. from before recording started, is ignored.
+        assert!(state.exit(1, 0).is_none());
+        state.enter(1, 0, call(10));
+        state.enter(1, 0, call(20));
+        assert_eq!(state.exit(1, 0).unwrap().start_mono, 20);
+        assert_eq!(state.exit(1, 0).unwrap().start_mono, 10);
+        state.enter(1, 0, call(30));
+        let unfinished = state.finish();
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(unfinished[0].1.start_mono, 30);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 use framehop::Unwinder;
@@ -38,11 +39,23 @@ pub struct Process<U> {
     pub prev_mm_swapents_size: i64,
     pub prev_mm_shmempages_size: i64,
     pub mem_counter: Option<CounterHandle>,
+    /// The sizes of the sampled allocations which haven't been freed yet, by address.
+    pub live_allocations: HashMap<u64, u64>,
+    /// The address, length, file offset and path of each mapping which has been
+    /// seen, so that catching up on the mappings from /proc skips them.
+    pub known_mappings: HashSet<(u64, u64, u64, Vec<u8>)>,
+    /// Whether the process runs 32-bit code on a 64-bit kernel, which we know
+    /// from the class of the ELF files it maps. Its raw syscall numbers are the
+    /// numbers of the 32-bit ABI.
+    pub is_compat: bool,
 }
 
 pub struct ProcessForkData<U> {
     unwinder: U,
     lib_mapping_ops: LibMappingOpQueue,
+    live_allocations: HashMap<u64, u64>,
+    known_mappings: HashSet<(u64, u64, u64, Vec<u8>)>,
+    is_compat: bool,
 }
 
 impl<U> Process<U>
@@ -85,6 +98,9 @@ where
             prev_mm_swapents_size: 0,
             prev_mm_shmempages_size: 0,
             mem_counter: None,
+            live_allocations: HashMap::new(),
+            known_mappings: HashSet::new(),
+            is_compat: false,
         }
     }
 
@@ -93,6 +109,9 @@ where
         ProcessForkData {
             unwinder: self.unwinder.clone(),
             lib_mapping_ops: self.lib_mapping_ops.clone(),
+            live_allocations: self.live_allocations.clone(),
+            known_mappings: self.known_mappings.clone(),
+            is_compat: self.is_compat,
         }
     }
 
@@ -100,6 +119,9 @@ where
     pub fn adopt_fork_data_from_parent(&mut self, fork_data: ProcessForkData<U>) {
         self.unwinder = fork_data.unwinder;
         self.lib_mapping_ops = fork_data.lib_mapping_ops;
+        self.live_allocations = fork_data.live_allocations;
+        self.known_mappings = fork_data.known_mappings;
+        self.is_compat = fork_data.is_compat;
     }
 
     pub fn rename_with_recycling(
@@ -181,6 +203,12 @@ where
             .push((thread, path.to_owned(), lookup_dirs));
     }
 
+    pub fn marker_file_paths(&self) -> impl Iterator<Item = &Path> + '_ {
+        self.marker_file_paths
+            .iter()
+            .map(|(_thread, path, _lookup_dirs)| path.as_path())
+    }
+
     pub fn notify_dead(&mut self, end_time: Timestamp, profile: &mut Profile) {
         self.threads.notify_process_dead(end_time, profile);
         profile.set_process_end_time(self.profile_process, end_time);
@@ -0,0 +1,293 @@
+//! Spawn markers from `sched:sched_process_fork` samples, and a lifetime
+//! marker for each process.
+//!
+//! The `sched_process_fork` tracepoint fires in the context of the thread which
+//! calls `clone` or `fork`, so its sample has the stack of the spawning thread.
+//! It becomes a "Spawned" marker on that thread, and a "Spawned by" marker at
+//! the start of the new thread, linked with a flow ID.
+//!
+//! The lifetime of a process lasts from its FORK record, or from its last exec,
+//! until its EXIT record. The exit status isn't part of the EXIT record, so we
+//! take the exit code from `syscalls:sys_enter_exit_group` samples, and the
+//! signal which killed the process from `signal:signal_deliver` samples, if
+//! they were recorded.
+//!
+//! A fatal signal whose default action doesn't dump core is turned into a
+//! SIGKILL for all threads of the process before it's delivered, so
+//! `signal_deliver` only has the original signal for the core dumping ones. We
+//! remember the fatal signals from `signal:signal_generate` samples to find
+//! the original signal, but these are only recorded if the sender is recorded
+//! too. A Ctrl+C from the terminal is only known as "killed".
+//!
+//! When a thread calls `exit_group` or `execve`, the other threads of its
+//! process get a SIGKILL too, so a signal doesn't replace an exit code, and an
+//! exec forgets about the signal.
+
+use std::collections::HashMap;
+
+use fxprof_processed_profile::{MarkerTiming, Profile, StringHandle, ThreadHandle, Timestamp};
+
+use crate::shared::process_sample_data::ProcessLifetimeMarker;
+
+/// The start of a process which hasn't exited yet.
+#[derive(Debug, Clone)]
+struct ProcessStart {
+    /// The main thread of the process.
+    thread: ThreadHandle,
+    name: StringHandle,
+    timestamp: Timestamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitStatus {
+    /// The status of an `exit_group` call, truncated to 8 bits.
+    Code(u8),
+    /// Killed by this signal.
+    Signal(i32),
+    /// Killed by a signal which was turned into a SIGKILL before we saw it.
+    Killed,
+}
+
+#[derive(Debug, Default)]
+pub struct ProcessLifecycle {
+    /// The running processes, by pid.
+    processes: HashMap<i32, ProcessStart>,
+    /// How each process which called `exit_group` or got a fatal signal is
+    /// going to exit, by pid.
+    exit_statuses: HashMap<i32, ExitStatus>,
+    /// The last fatal signal which was sent to each thread, by tid. Signals
+    /// which are sent to a process are sent to its main thread.
+    sent_signals: HashMap<i32, i32>,
+}
+
+impl ProcessLifecycle {
+    /// Called when a process is forked or executes a new program. An exec
+    /// restarts the lifetime of the process, because the process is shown with
+    /// the name of the new program from then on.
+    pub fn start(
+        &mut self,
+        pid: i32,
+        thread: ThreadHandle,
+        name: StringHandle,
+        timestamp: Timestamp,
+    ) {
+        self.processes.insert(
+            pid,
+            ProcessStart {
+                thread,
+                name,
+                timestamp,
+            },
+        );
+        self.exit_statuses.remove(&pid);
+    }
+
+    pub fn rename(&mut self, pid: i32, name: StringHandle) {
+        if let Some(process) = self.processes.get_mut(&pid) {
+            process.name = name;
+        }
+    }
+
+    /// Called for an `exit_group` call. The status is truncated to 8 bits, like
+    /// the exit code which the parent sees.
+    pub fn set_exit_code(&mut self, pid: i32, status: i64) {
+        self.exit_statuses
+            .insert(pid, ExitStatus::Code(status as u8));
+    }
+
+    /// Called for a `signal_generate` sample which wasn't ignored. `tid` is
+    /// the thread which the signal was sent to.
+    pub fn signal_sent(&mut self, tid: i32, signal: i32) {
+        if is_fatal_by_default(signal) {
+            self.sent_signals.insert(tid, signal);
+        }
+    }
+
+    /// Called for a `signal_deliver` sample in the thread `tid` of the process
+    /// `pid`. `default_handler` is whether the signal has the default action.
+    pub fn signal_delivered(&mut self, pid: i32, tid: i32, signal: i32, default_handler: bool) {
+        if !default_handler || !is_fatal_by_default(signal) {
+            return;
+        }
+        let status = if signal == SIGKILL {
+            match self
+                .sent_signals
+                .get(&tid)
+                .or_else(|| self.sent_signals.get(&pid))
+            {
+                Some(&signal) => ExitStatus::Signal(signal),
+                None => ExitStatus::Killed,
+            }
+        } else {
+            ExitStatus::Signal(signal)
+        };
+        self.exit_statuses.entry(pid).or_insert(status);
+    }
+
+    /// Called for the EXIT record of each thread.
+    pub fn thread_exit(&mut self, tid: i32) {
+        self.sent_signals.remove(&tid);
+    }
+
+    /// Adds the lifetime marker for an exited process. `thread` and `name` are
+    /// used if we haven't seen the start of the process, e.g. because it was
+    /// already running when the recording started.
+    pub fn exit(
+        &mut self,
+        profile: &mut Profile,
+        pid: i32,
+        thread: ThreadHandle,
+        name: StringHandle,
+        timestamp: Timestamp,
+    ) {
+        let status = match self.exit_statuses.remove(&pid) {
+            Some(ExitStatus::Code(exit_code)) => format!("exit code {exit_code}"),
+            Some(ExitStatus::Signal(signal)) => match signal_name(signal) {
+                Some(name) => format!("killed by {name}"),
+                None => format!("killed by signal {signal}"),
+            },
+            Some(ExitStatus::Killed) => "killed".to_owned(),
+            None => "exited".to_owned(),
+        };
+        let status = profile.handle_for_string(&status);
+        let (thread, timing, name) = match self.processes.remove(&pid) {
+            Some(start) => (
+                start.thread,
+                MarkerTiming::Interval(start.timestamp, timestamp),
+                start.name,
+            ),
+            None => (thread, MarkerTiming::IntervalEnd(timestamp), name),
+        };
+        profile.add_marker(thread, timing, ProcessLifetimeMarker { name, pid, status });
+    }
+
+    /// Adds the lifetime markers of the processes which were still running at
+    /// the end of the profile.
+    pub fn finish(self, profile: &mut Profile) {
+        let status = profile.handle_for_string("running");
+        for (pid, start) in self.processes {
+            let marker = ProcessLifetimeMarker {
+                name: start.name,
+                pid,
+                status,
+            };
+            profile.add_marker(
+                start.thread,
+                MarkerTiming::IntervalStart(start.timestamp),
+                marker,
+            );
+        }
+    }
+}
+
+const SIGKILL: i32 = 9;
+
+/// Whether the default action of the signal terminates the process, with the
+/// signal numbers of Linux.
+fn is_fatal_by_default(signal: i32) -> bool {
+    // SIGCHLD, SIGCONT, the stop signals, SIGURG and SIGWINCH don't.
+    matches!(signal, 1..=64) && !matches!(signal, 17..=23 | 28)
+}
+
+/// The name of a signal, with the signal numbers of Linux.
+fn signal_name(signal: i32) -> Option<&'static str> {
+    let name = match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        10 => "SIGUSR1",
+        11 => "SIGSEGV",
+        12 => "SIGUSR2",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        16 => "SIGSTKFLT",
+        24 => "SIGXCPU",
+        25 => "SIGXFSZ",
+        26 => "SIGVTALRM",
+        27 => "SIGPROF",
+        29 => "SIGIO",
+        30 => "SIGPWR",
+        31 => "SIGSYS",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    #[test]
+    fn test_process_lifetimes() {
+        let mut profile = test_profile();
+        let process = profile.add_process("test", 100, t(0));
+        let thread = profile.add_thread(process, 100, t(0), true);
+        let old_name = profile.handle_for_string("sh");
+        let new_name = profile.handle_for_string("ls");
+
+        let mut lifecycle = ProcessLifecycle::default();
+
+        // An exec restarts the lifetime, and forgets about the exit code of
+        // an `exit_group` call which didn't exit the process.
+        lifecycle.start(100, thread, old_name, t(1));
+        lifecycle.set_exit_code(100, 3);
+        lifecycle.start(100, thread, new_name, t(2));
+        assert_eq!(lifecycle.processes[&100].timestamp, t(2));
+        assert!(lifecycle.exit_statuses.is_empty());
+
+        lifecycle.set_exit_code(100, -1);
+        assert_eq!(lifecycle.exit_statuses[&100], ExitStatus::Code(255));
+        // The other threads are killed after an `exit_group` call.
+        lifecycle.signal_delivered(100, 102, 9, true);
+        assert_eq!(lifecycle.exit_statuses[&100], ExitStatus::Code(255));
+        lifecycle.exit(&mut profile, 100, thread, old_name, t(5));
+        assert!(lifecycle.processes.is_empty());
+        assert!(lifecycle.exit_statuses.is_empty());
+
+        lifecycle.start(101, thread, new_name, t(6));
+        lifecycle.rename(101, old_name);
+        assert_eq!(lifecycle.processes[&101].name, old_name);
+        lifecycle.finish(&mut profile);
+    }
+
+    #[test]
+    fn test_exit_signals() {
+        let mut lifecycle = ProcessLifecycle::default();
+
+        // Handled signals and signals which don't terminate are ignored.
+        lifecycle.signal_delivered(100, 100, 15, false);
+        lifecycle.signal_delivered(100, 100, 17, true);
+        assert!(lifecycle.exit_statuses.is_empty());
+
+        // Core dumping signals are delivered as themselves.
+        lifecycle.signal_delivered(100, 100, 11, true);
+        lifecycle.signal_delivered(100, 101, 9, true);
+        assert_eq!(lifecycle.exit_statuses[&100], ExitStatus::Signal(11));
+
+        // Other fatal signals are delivered as SIGKILL.
+        lifecycle.signal_sent(200, 13);
+        lifecycle.signal_sent(200, 17);
+        lifecycle.signal_delivered(200, 200, 9, true);
+        assert_eq!(lifecycle.exit_statuses[&200], ExitStatus::Signal(13));
+        lifecycle.signal_sent(300, 15);
+        lifecycle.signal_delivered(300, 301, 9, true);
+        assert_eq!(lifecycle.exit_statuses[&300], ExitStatus::Signal(15));
+        lifecycle.signal_delivered(400, 400, 9, true);
+        assert_eq!(lifecycle.exit_statuses[&400], ExitStatus::Killed);
+
+        lifecycle.thread_exit(200);
+        lifecycle.thread_exit(300);
+        assert!(lifecycle.sent_signals.is_empty());
+
+        assert_eq!(signal_name(6), Some("SIGABRT"));
+        assert_eq!(signal_name(40), None);
+    }
+}
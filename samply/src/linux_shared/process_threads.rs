@@ -160,6 +160,10 @@ impl ProcessThreads {
         (self.thread_recycler, main_thread_recycling_data)
     }
 
+    pub fn contains_tid(&self, tid: i32) -> bool {
+        tid == self.pid || self.threads_by_tid.contains_key(&tid)
+    }
+
     pub fn get_thread_by_tid(&mut self, tid: i32, profile: &mut Profile) -> &mut Thread {
         if tid == self.pid {
             return &mut self.main_thread;
@@ -1,5 +1,6 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::path::Path;
 
 use framehop::Unwinder;
 use fxprof_processed_profile::{Category, CategoryColor, Profile, Timestamp};
@@ -13,6 +14,11 @@ use crate::shared::recycling::{ProcessRecycler, ProcessRecyclingData, ThreadRecy
 use crate::shared::timestamp_converter::TimestampConverter;
 use crate::shared::unresolved_samples::UnresolvedStacks;
 
+/// The largest pid that Linux can hand out, `PID_MAX_LIMIT` on 64-bit systems.
+/// Threads in the profile which don't correspond to a real thread get tids
+/// above this limit, so that they don't collide with the tids of real threads.
+pub const PID_MAX_LIMIT: u32 = 4 * 1024 * 1024;
+
 pub struct Processes<U>
 where
     U: Unwinder + Default,
@@ -174,6 +180,27 @@ where
         })
     }
 
+    pub fn pids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.processes_by_pid.keys().copied()
+    }
+
+    /// The marker files of the running processes.
+    pub fn marker_file_paths(&self) -> impl Iterator<Item = &Path> + '_ {
+        self.processes_by_pid
+            .values()
+            .flat_map(|process| process.marker_file_paths())
+    }
+
+    pub fn contains(&self, pid: i32) -> bool {
+        self.processes_by_pid.contains_key(&pid)
+    }
+
+    pub fn contains_thread(&self, pid: i32, tid: i32) -> bool {
+        self.processes_by_pid
+            .get(&pid)
+            .is_some_and(|process| process.threads.contains_tid(tid))
+    }
+
     pub fn remove(
         &mut self,
         pid: i32,
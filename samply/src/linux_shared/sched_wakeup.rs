@@ -0,0 +1,248 @@
+//! Wakeup markers from `sched:sched_waking` or `sched:sched_wakeup` samples.
+//!
+//! Each wakeup becomes a marker on the waking thread, with the waker's stack,
+//! and an interval marker on the woken thread, from the wakeup until the woken
+//! thread is switched in. The two markers are linked with a flow ID.
+//!
+//! The woken thread is switched in when we see its CONTEXT_SWITCH record. If
+//! we don't have those, e.g. with `perf record -e sched:sched_switch`, we use
+//! the next sample on the woken thread instead.
+//!
+//! When recording, the tracepoint is only opened for the recorded processes,
+//! like the sampling events, so we only see the wakeups which they perform.
+//! Recording it system-wide would sample the waker stacks of every wakeup on
+//! the machine.
+
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use fxprof_processed_profile::{Profile, StringHandle, Timestamp};
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+use super::converter::FlowIds;
+use crate::shared::process_sample_data::{WakeupMarker, WokenMarker};
+
+/// The woken thread of a `sched:sched_waking` or `sched:sched_wakeup` sample.
+///
+/// ```
+/// # cat /sys/kernel/tracing/events/sched/sched_waking/format
+/// name: sched_waking
+/// ID: 318
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:char comm[16];    offset:8;       size:16;        signed:0;
+///         field:pid_t pid;        offset:24;      size:4; signed:1;
+///         field:int prio; offset:28;      size:4; signed:1;
+///         field:int target_cpu;   offset:32;      size:4; signed:1;
+///
+/// print fmt: "comm=%s pid=%d prio=%d target_cpu=%03d", REC->comm, REC->pid, REC->prio, REC->target_cpu
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchedWakeup {
+    pub tid: i32,
+    pub comm: String,
+}
+
+impl SchedWakeup {
+    pub fn parse(data: RawData, endian: Endianness) -> Option<Self> {
+        let data = data.as_slice();
+        let comm = data.get(8..24)?;
+        let comm_len = memchr::memchr(b'\0', comm).unwrap_or(comm.len());
+        let comm = String::from_utf8_lossy(&comm[..comm_len]).into_owned();
+        let tid = data.get(24..28)?;
+        let tid = match endian {
+            Endianness::LittleEndian => LittleEndian::read_i32(tid),
+            Endianness::BigEndian => BigEndian::read_i32(tid),
+        };
+        Some(Self { tid, comm })
+    }
+}
+
+/// A wakeup whose woken thread hasn't been switched in yet.
+#[derive(Debug, Clone)]
+struct PendingWakeup {
+    timestamp: Timestamp,
+    waker_thread: StringHandle,
+    flow: u64,
+}
+
+/// Pending wakeups which are older than this are dropped. The woken thread
+/// of a system-wide `sched_waking` sample may not be one of the profiled
+/// threads, so we never see it being switched in.
+const MAX_PENDING_AGE_MS: f64 = 1000.0;
+
+/// The wakeups which are waiting for the switch-in of their woken thread.
+#[derive(Debug, Default)]
+pub struct WakeupState {
+    /// The attr index of the wakeup tracepoint. If both `sched_waking` and
+    /// `sched_wakeup` were recorded, we use `sched_waking`, which fires in the
+    /// context of the waker.
+    attr_index: Option<usize>,
+    /// The pending wakeup of each woken thread, by tid.
+    pending: HashMap<i32, PendingWakeup>,
+    /// When we last dropped the pending wakeups which are too old.
+    last_eviction: Option<Timestamp>,
+}
+
+impl WakeupState {
+    pub fn new(event_names: &[String]) -> Self {
+        let position = |name: &str| event_names.iter().position(|n| n == name);
+        Self {
+            attr_index: position("sched:sched_waking").or_else(|| position("sched:sched_wakeup")),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the samples of this event are turned into wakeup markers.
+    pub fn handles_event(&self, attr_index: usize) -> bool {
+        self.attr_index == Some(attr_index)
+    }
+
+    /// Records a wakeup and returns the marker for the waking thread. If the
+    /// woken thread has already been woken and hasn't run since, the first
+    /// wakeup is kept, and this marker gets the same flow ID.
+    pub fn wake(
+        &mut self,
+        profile: &mut Profile,
+        flow_ids: &mut FlowIds,
+        wakeup: &SchedWakeup,
+        waker_thread: StringHandle,
+        timestamp: Timestamp,
+    ) -> WakeupMarker {
+        self.evict_old_wakeups(timestamp);
+        let pending = self
+            .pending
+            .entry(wakeup.tid)
+            .or_insert_with(|| PendingWakeup {
+                timestamp,
+                waker_thread,
+                flow: flow_ids.next(),
+            });
+        let woken_thread =
+            profile.handle_for_string(&format!("{} (tid: {})", wakeup.comm, wakeup.tid));
+        WakeupMarker {
+            woken_thread,
+            flow: pending.flow,
+        }
+    }
+
+    /// Called when a thread is switched in. Returns the start time and the
+    /// marker for the woken thread, if the thread was woken.
+    pub fn switch_in(&mut self, tid: i32) -> Option<(Timestamp, WokenMarker)> {
+        let pending = self.pending.remove(&tid)?;
+        let marker = WokenMarker {
+            waker_thread: pending.waker_thread,
+            flow: pending.flow,
+        };
+        Some((pending.timestamp, marker))
+    }
+
+    /// Called when a thread is switched out. A wakeup of a thread which was
+    /// still running, e.g. because it was just about to go to sleep, is
+    /// dropped, because it isn't followed by a switch-in.
+    pub fn switch_out(&mut self, tid: i32) {
+        self.pending.remove(&tid);
+    }
+
+    /// Called when a thread exits. Its pending wakeup, if any, won't be
+    /// followed by a switch-in.
+    pub fn thread_exit(&mut self, tid: i32) {
+        self.pending.remove(&tid);
+    }
+
+    /// Drops the pending wakeups which are older than [`MAX_PENDING_AGE_MS`].
+    /// This runs at most once per age limit, so that each wakeup sample
+    /// doesn't have to look at all pending wakeups.
+    fn evict_old_wakeups(&mut self, now: Timestamp) {
+        let last_eviction = *self.last_eviction.get_or_insert(now);
+        if now.millis_since(last_eviction) < MAX_PENDING_AGE_MS {
+            return;
+        }
+        self.pending
+            .retain(|_, pending| now.millis_since(pending.timestamp) < MAX_PENDING_AGE_MS);
+        self.last_eviction = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    #[test]
+    fn test_parse_sched_wakeup() {
+        let mut data = vec![0; 36];
+        data[8..14].copy_from_slice(b"worker");
+        data[24..28].copy_from_slice(&1234i32.to_le_bytes());
+        let wakeup = SchedWakeup::parse(RawData::Single(&data), Endianness::LittleEndian).unwrap();
+        assert_eq!(
+            wakeup,
+            SchedWakeup {
+                tid: 1234,
+                comm: "worker".to_string()
+            }
+        );
+        assert!(
+            SchedWakeup::parse(RawData::Single(&data[..20]), Endianness::LittleEndian).is_none()
+        );
+    }
+
+    #[test]
+    fn test_wakeup_flows() {
+        let mut profile = test_profile();
+        let event_names: Vec<String> = ["cpu-clock", "sched:sched_wakeup", "sched:sched_waking"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+        let mut state = WakeupState::new(&event_names);
+        let mut flow_ids = FlowIds::default();
+        assert!(state.handles_event(2));
+        assert!(!state.handles_event(1));
+
+        let waker = profile.handle_for_string("main (pid: 1, tid: 1)");
+        let wakeup = SchedWakeup {
+            tid: 2,
+            comm: "worker".to_string(),
+        };
+        let first = state.wake(&mut profile, &mut flow_ids, &wakeup, waker, t(10));
+        let second = state.wake(&mut profile, &mut flow_ids, &wakeup, waker, t(20));
+        assert_eq!(first.flow, second.flow);
+
+        let (start, woken) = state.switch_in(2).unwrap();
+        assert_eq!(start, t(10));
+        assert_eq!(woken.flow, first.flow);
+        assert!(state.switch_in(2).is_none());
+
+        // Other flows, e.g. of spawns, get their IDs from the same sequence.
+        let spawn_flow = flow_ids.next();
+        assert_ne!(spawn_flow, first.flow);
+
+        // A wakeup of a running thread is dropped when the thread is switched out.
+        let third = state.wake(&mut profile, &mut flow_ids, &wakeup, waker, t(30));
+        assert_ne!(third.flow, first.flow);
+        assert_ne!(third.flow, spawn_flow);
+        state.switch_out(2);
+        assert!(state.switch_in(2).is_none());
+
+        // Wakeups of threads which exit are dropped.
+        state.wake(&mut profile, &mut flow_ids, &wakeup, waker, t(40));
+        state.thread_exit(2);
+        assert!(state.switch_in(2).is_none());
+
+        // Wakeups of threads which are never switched in are dropped once
+        // they're too old.
+        let other = SchedWakeup {
+            tid: 3,
+            comm: "other".to_string(),
+        };
+        state.wake(&mut profile, &mut flow_ids, &wakeup, waker, t(100_000_000));
+        state.wake(&mut profile, &mut flow_ids, &other, waker, t(1_500_000_000));
+        assert!(state.switch_in(2).is_none());
+        assert!(state.switch_in(3).is_some());
+    }
+}
@@ -0,0 +1,2463 @@
+//! Syscall markers from the `raw_syscalls:sys_enter` / `raw_syscalls:sys_exit`
+//! tracepoints, or from the per-syscall `syscalls:sys_enter_<name>` /
+//! `syscalls:sys_exit_<name>` tracepoints, with `--syscalls`.
+//!
+//! The enter and exit samples of each thread are paired up into an interval
+//! marker per syscall, with the arguments, the return value and the stack at
+//! entry. Each syscall gets its own marker type, so that the arguments can be
+//! shown with their names, like in strace.
+
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use fxprof_processed_profile::{
+    Category, CategoryColor, CategoryHandle, Marker, MarkerFieldFlags, MarkerFieldFormat,
+    MarkerLocations, MarkerTypeHandle, Profile, RuntimeSchemaMarkerField,
+    RuntimeSchemaMarkerSchema, StringHandle, ThreadHandle, Timestamp,
+};
+use linux_perf_data::Endianness;
+
+use crate::shared::unresolved_samples::UnresolvedStackHandle;
+
+/// The syscall tracepoints which are paired up into markers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SyscallEvent {
+    /// `raw_syscalls:sys_enter`, which has the syscall number and all six
+    /// argument registers.
+    RawEnter,
+    /// `raw_syscalls:sys_exit`
+    RawExit,
+    /// `syscalls:sys_enter_<name>`, which only has the arguments of this syscall.
+    Enter(String),
+    /// `syscalls:sys_exit_<name>`
+    Exit(String),
+}
+
+impl SyscallEvent {
+    fn from_event_name(name: &str) -> Option<Self> {
+        let event = match name {
+            "raw_syscalls:sys_enter" => SyscallEvent::RawEnter,
+            "raw_syscalls:sys_exit" => SyscallEvent::RawExit,
+            _ => {
+                if let Some(syscall) = name.strip_prefix("syscalls:sys_enter_") {
+                    SyscallEvent::Enter(syscall.to_owned())
+                } else if let Some(syscall) = name.strip_prefix("syscalls:sys_exit_") {
+                    SyscallEvent::Exit(syscall.to_owned())
+                } else {
+                    return None;
+                }
+            }
+        };
+        Some(event)
+    }
+
+    fn is_raw(&self) -> bool {
+        matches!(self, SyscallEvent::RawEnter | SyscallEvent::RawExit)
+    }
+}
+
+/// The syscall number and arguments of a sys_enter sample.
+///
+/// ```plain
+/// # cat /sys/kernel/tracing/events/raw_syscalls/sys_enter/format
+/// name: sys_enter
+/// ID: 443
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:long id;  offset:8;       size:8; signed:1;
+///         field:unsigned long args[6];    offset:16;      size:48;        signed:0;
+///
+/// # cat /sys/kernel/tracing/events/syscalls/sys_enter_openat/format
+/// [...]
+///         field:int __syscall_nr; offset:8;       size:4; signed:1;
+///         field:int dfd;  offset:16;      size:8; signed:0;
+///         field:const char * filename;    offset:24;      size:8; signed:0;
+///         field:int flags;        offset:32;      size:8; signed:0;
+///         field:umode_t mode;     offset:40;      size:8; signed:0;
+/// ```
+///
+/// The sys_exit tracepoints have the return value at offset 16 instead.
+pub fn parse_syscall_raw_data(
+    data: &[u8],
+    is_raw: bool,
+    endian: Endianness,
+) -> Option<(i64, Vec<u64>)> {
+    match endian {
+        Endianness::LittleEndian => parse_syscall_raw_data_impl::<LittleEndian>(data, is_raw),
+        Endianness::BigEndian => parse_syscall_raw_data_impl::<BigEndian>(data, is_raw),
+    }
+}
+
+fn parse_syscall_raw_data_impl<O: ByteOrder>(data: &[u8], is_raw: bool) -> Option<(i64, Vec<u64>)> {
+    let nr = if is_raw {
+        O::read_i64(data.get(8..16)?)
+    } else {
+        i64::from(O::read_i32(data.get(8..12)?))
+    };
+    // The raw data is padded, so that it ends on an 8 byte boundary together
+    // with its u32 size. The padding is shorter than a value.
+    let values = data
+        .get(16..)?
+        .chunks_exact(8)
+        .take(6)
+        .map(O::read_u64)
+        .collect();
+    Some((nr, values))
+}
+
+/// Formats an argument like strace does for numbers: small values, and small
+/// negative values like `AT_FDCWD`, in decimal, and anything else in hex.
+fn format_syscall_arg(value: u64) -> String {
+    // Negative int arguments only have the lower 32 bits set in the register.
+    let signed = if value >> 32 == 0 {
+        i64::from(value as u32 as i32)
+    } else {
+        value as i64
+    };
+    if (-4096..=0xffff).contains(&signed) {
+        signed.to_string()
+    } else {
+        format!("{value:#x}")
+    }
+}
+
+/// Returns the name of the error for return values from -4095 to -1.
+fn errno_name(ret: i64) -> Option<&'static str> {
+    let errno = usize::try_from(ret.checked_neg()?).ok()?;
+    match errno {
+        512 => Some("ERESTARTSYS"),
+        513 => Some("ERESTARTNOINTR"),
+        514 => Some("ERESTARTNOHAND"),
+        515 => Some("ENOIOCTLCMD"),
+        516 => Some("ERESTART_RESTARTBLOCK"),
+        _ => ERRNO_NAMES
+            .get(errno)
+            .copied()
+            .filter(|name| !name.is_empty()),
+    }
+}
+
+fn lookup_syscall_name(table: &'static [(u32, &'static str)], nr: u64) -> Option<&'static str> {
+    let nr = u32::try_from(nr).ok()?;
+    let index = table.binary_search_by_key(&nr, |&(nr, _)| nr).ok()?;
+    Some(table[index].1)
+}
+
+pub fn x86_64_syscall_name(nr: u64) -> Option<&'static str> {
+    lookup_syscall_name(X86_64_SYSCALLS, nr)
+}
+
+pub fn i386_syscall_name(nr: u64) -> Option<&'static str> {
+    lookup_syscall_name(I386_SYSCALLS, nr)
+}
+
+pub fn aarch64_syscall_name(nr: u64) -> Option<&'static str> {
+    lookup_syscall_name(AARCH64_SYSCALLS, nr)
+}
+
+fn syscall_arg_names(name: &str) -> Option<&'static [&'static str]> {
+    let index = SYSCALL_ARG_NAMES
+        .binary_search_by_key(&name, |&(name, _)| name)
+        .ok()?;
+    Some(SYSCALL_ARG_NAMES[index].1)
+}
+
+/// The syscall number, name and arguments of a sys_enter sample.
+#[derive(Debug, Clone)]
+pub struct SyscallEntry {
+    nr: i64,
+    name: StringHandle,
+    marker_type: MarkerTypeHandle,
+    args: Vec<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyscallSample {
+    Enter(SyscallEntry),
+    Exit { nr: i64, ret: i64 },
+}
+
+/// A syscall which has been entered but hasn't returned yet.
+#[derive(Debug, Clone)]
+pub struct PendingSyscall {
+    pub entry: SyscallEntry,
+    pub pid: i32,
+    pub thread: ThreadHandle,
+    pub start: Timestamp,
+    pub start_mono: u64,
+    /// The user stack at entry.
+    pub stack: UnresolvedStackHandle,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SyscallMarkerType {
+    handle: MarkerTypeHandle,
+    arg_count: usize,
+    /// The marker type for the syscalls which hadn't returned by the end of the
+    /// profile, which has no return value and error fields. Registered on demand.
+    unfinished_handle: Option<MarkerTypeHandle>,
+}
+
+/// Pairs up the syscall enter and exit samples of each thread.
+#[derive(Debug)]
+pub struct SyscallState {
+    /// The syscall tracepoints, by attr index. If both the raw tracepoints and
+    /// per-syscall tracepoints were recorded, only the raw ones are used, so
+    /// that each syscall gets one marker. Per-syscall tracepoints are only used
+    /// if both the enter and the exit tracepoint of the syscall were recorded.
+    events: HashMap<usize, SyscallEvent>,
+    /// The syscall which each thread is currently in, by tid.
+    pending: HashMap<i32, PendingSyscall>,
+    /// The marker type for each syscall name.
+    marker_types: HashMap<String, SyscallMarkerType>,
+    category: Option<CategoryHandle>,
+}
+
+impl SyscallState {
+    pub fn new(event_names: &[String]) -> Self {
+        let mut events: HashMap<usize, SyscallEvent> = event_names
+            .iter()
+            .enumerate()
+            .filter_map(|(index, name)| Some((index, SyscallEvent::from_event_name(name)?)))
+            .collect();
+        if events.values().any(SyscallEvent::is_raw) {
+            events.retain(|_, event| event.is_raw());
+        } else {
+            let recorded: Vec<SyscallEvent> = events.values().cloned().collect();
+            events.retain(|_, event| {
+                let counterpart = match event {
+                    SyscallEvent::Enter(name) => SyscallEvent::Exit(name.clone()),
+                    SyscallEvent::Exit(name) => SyscallEvent::Enter(name.clone()),
+                    _ => return false,
+                };
+                recorded.contains(&counterpart)
+            });
+        }
+        Self {
+            events,
+            pending: HashMap::new(),
+            marker_types: HashMap::new(),
+            category: None,
+        }
+    }
+
+    /// Whether the samples of this event are paired up into syscall markers.
+    pub fn handles_event(&self, attr_index: usize) -> bool {
+        self.events.contains_key(&attr_index)
+    }
+
+    /// Parses the raw data of a sample. Returns None if the samples of this
+    /// event aren't paired up into syscall markers. The raw syscall numbers are
+    /// looked up with `syscall_name`.
+    pub fn parse_sample(
+        &mut self,
+        profile: &mut Profile,
+        attr_index: usize,
+        raw: &[u8],
+        endian: Endianness,
+        syscall_name: fn(u64) -> Option<&'static str>,
+    ) -> Option<SyscallSample> {
+        let event = self.events.get(&attr_index)?;
+        let (nr, mut values) = parse_syscall_raw_data(raw, event.is_raw(), endian)?;
+        let name = match event {
+            SyscallEvent::RawEnter => match syscall_name(nr as u64) {
+                Some(name) => name.to_owned(),
+                None => format!("syscall_{nr}"),
+            },
+            SyscallEvent::Enter(name) => name.clone(),
+            SyscallEvent::RawExit | SyscallEvent::Exit(_) => {
+                let ret = *values.first()? as i64;
+                return Some(SyscallSample::Exit { nr, ret });
+            }
+        };
+        let marker_type = self.marker_type(profile, &name, values.len());
+        values.truncate(marker_type.arg_count);
+        Some(SyscallSample::Enter(SyscallEntry {
+            nr,
+            name: profile.handle_for_string(&name),
+            marker_type: marker_type.handle,
+            args: values,
+        }))
+    }
+
+    pub fn enter(&mut self, tid: i32, syscall: PendingSyscall) {
+        self.pending.insert(tid, syscall);
+    }
+
+    /// Returns the syscall which the thread returned from, and its marker,
+    /// unless the thread entered the syscall before recording started.
+    pub fn exit(
+        &mut self,
+        profile: &mut Profile,
+        tid: i32,
+        nr: i64,
+        ret: i64,
+    ) -> Option<(PendingSyscall, SyscallMarker)> {
+        let syscall = self.pending.remove(&tid)?;
+        if syscall.entry.nr != nr {
+            // We missed the exit of the previous syscall or the enter of this one.
+            return None;
+        }
+        let marker = SyscallMarker::new(profile, &syscall.entry, Some(ret));
+        Some((syscall, marker))
+    }
+
+    /// Returns the syscalls which were still running at the end of the profile,
+    /// e.g. blocking reads, except for the syscalls which never return.
+    pub fn finish(mut self, profile: &mut Profile) -> Vec<(PendingSyscall, SyscallMarker)> {
+        let exit = profile.handle_for_string("exit");
+        let exit_group = profile.handle_for_string("exit_group");
+        let pending = std::mem::take(&mut self.pending);
+        pending
+            .into_values()
+            .filter(|syscall| syscall.entry.name != exit && syscall.entry.name != exit_group)
+            .map(|mut syscall| {
+                syscall.entry.marker_type = self.unfinished_marker_type(profile, &syscall.entry);
+                let marker = SyscallMarker::new(profile, &syscall.entry, None);
+                (syscall, marker)
+            })
+            .collect()
+    }
+
+    fn unfinished_marker_type(
+        &mut self,
+        profile: &mut Profile,
+        entry: &SyscallEntry,
+    ) -> MarkerTypeHandle {
+        let (name, marker_type) = self
+            .marker_types
+            .iter()
+            .find(|(_, marker_type)| marker_type.handle == entry.marker_type)
+            .map(|(name, marker_type)| (name.clone(), *marker_type))
+            .expect("The marker type of each syscall entry is registered");
+        if let Some(handle) = marker_type.unfinished_handle {
+            return handle;
+        }
+        let handle = self.register_marker_type(profile, &name, marker_type.arg_count, false);
+        if let Some(marker_type) = self.marker_types.get_mut(&name) {
+            marker_type.unfinished_handle = Some(handle);
+        }
+        handle
+    }
+
+    fn marker_type(
+        &mut self,
+        profile: &mut Profile,
+        name: &str,
+        max_arg_count: usize,
+    ) -> SyscallMarkerType {
+        if let Some(marker_type) = self.marker_types.get(name) {
+            return *marker_type;
+        }
+        let arg_count = syscall_arg_names(name)
+            .map_or(max_arg_count, |arg_names| arg_names.len())
+            .min(max_arg_count);
+        let marker_type = SyscallMarkerType {
+            handle: self.register_marker_type(profile, name, arg_count, true),
+            arg_count,
+            unfinished_handle: None,
+        };
+        self.marker_types.insert(name.to_owned(), marker_type);
+        marker_type
+    }
+
+    /// Registers a marker type for a syscall with the given number of arguments.
+    /// Markers of syscalls which have returned also have the return value and
+    /// the error.
+    fn register_marker_type(
+        &mut self,
+        profile: &mut Profile,
+        name: &str,
+        arg_count: usize,
+        returned: bool,
+    ) -> MarkerTypeHandle {
+        let arg_names: Vec<String> = match syscall_arg_names(name) {
+            Some(arg_names) => arg_names
+                .iter()
+                .map(|arg_name| arg_name.trim_start_matches('_').to_owned())
+                .collect(),
+            None => (0..arg_count).map(|i| format!("arg{i}")).collect(),
+        };
+        let arg_names = &arg_names[..arg_count];
+
+        let category = *self.category.get_or_insert_with(|| {
+            profile.handle_for_category(Category("Syscalls", CategoryColor::Orange))
+        });
+        let mut fields = vec![RuntimeSchemaMarkerField {
+            key: "syscall".into(),
+            label: "Syscall".into(),
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        }];
+        fields.extend(
+            arg_names
+                .iter()
+                .enumerate()
+                .map(|(i, arg_name)| RuntimeSchemaMarkerField {
+                    key: format!("arg{i}"),
+                    label: arg_name.clone(),
+                    format: MarkerFieldFormat::String,
+                    flags: MarkerFieldFlags::SEARCHABLE,
+                }),
+        );
+        if returned {
+            fields.push(RuntimeSchemaMarkerField {
+                key: "ret".into(),
+                label: "Return value".into(),
+                format: MarkerFieldFormat::Integer,
+                flags: MarkerFieldFlags::empty(),
+            });
+            fields.push(RuntimeSchemaMarkerField {
+                key: "error".into(),
+                label: "Error".into(),
+                format: MarkerFieldFormat::String,
+                flags: MarkerFieldFlags::SEARCHABLE,
+            });
+        }
+
+        let args_label = (0..arg_names.len())
+            .map(|i| format!("{{marker.data.arg{i}}}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        // Like strace, syscalls which haven't returned are shown with `= ?`.
+        let (type_name, description, label) = if returned {
+            (
+                format!("Syscall {name}"),
+                format!(
+                    "Emitted for each {name} syscall, from entry to exit, with the stack at entry."
+                ),
+                format!("{name}({args_label}) = {{marker.data.ret}} {{marker.data.error}}"),
+            )
+        } else {
+            (
+                format!("Unfinished syscall {name}"),
+                format!(
+                    "Emitted for each {name} syscall which hadn't returned by the end of the profile, with the stack at entry."
+                ),
+                format!("{name}({args_label}) = ?"),
+            )
+        };
+        profile.register_marker_type(RuntimeSchemaMarkerSchema {
+            type_name,
+            category,
+            description: Some(description),
+            locations: MarkerLocations::MARKER_CHART | MarkerLocations::MARKER_TABLE,
+            chart_label: Some(format!("({args_label})")),
+            tooltip_label: Some(label.clone()),
+            table_label: Some(label),
+            fields,
+            graphs: Vec::new(),
+        })
+    }
+}
+
+/// An interval marker for a syscall, from entry to exit.
+#[derive(Debug, Clone)]
+pub struct SyscallMarker {
+    marker_type: MarkerTypeHandle,
+    name: StringHandle,
+    args: Vec<StringHandle>,
+    /// None if the syscall hadn't returned by the end of the profile, in which
+    /// case the marker type has no return value and error fields.
+    ret: Option<i64>,
+    error: StringHandle,
+}
+
+impl SyscallMarker {
+    fn new(profile: &mut Profile, syscall: &SyscallEntry, ret: Option<i64>) -> Self {
+        let args = syscall
+            .args
+            .iter()
+            .map(|&arg| profile.handle_for_string(&format_syscall_arg(arg)))
+            .collect();
+        let error = profile.handle_for_string(ret.and_then(errno_name).unwrap_or_default());
+        Self {
+            marker_type: syscall.marker_type,
+            name: syscall.name,
+            args,
+            ret,
+            error,
+        }
+    }
+}
+
+impl Marker for SyscallMarker {
+    fn marker_type(&self, _profile: &mut Profile) -> MarkerTypeHandle {
+        self.marker_type
+    }
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn string_field_value(&self, field_index: u32) -> StringHandle {
+        // The fields are the syscall name, the arguments, the return value and the error.
+        match field_index as usize {
+            0 => self.name,
+            i if i <= self.args.len() => self.args[i - 1],
+            i if i == self.args.len() + 2 => self.error,
+            _ => unreachable!(),
+        }
+    }
+
+    fn number_field_value(&self, field_index: u32) -> f64 {
+        assert_eq!(field_index as usize, self.args.len() + 1);
+        self.ret
+            .expect("Only the markers of returned syscalls have a return value field")
+            as f64
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// The syscall numbers on x86_64, from `arch/x86/entry/syscalls/syscall_64.tbl`.
+const X86_64_SYSCALLS: &[(u32, &str)] = &[
+    (0, "read"),
+    (1, "write"),
+    (2, "open"),
+    (3, "close"),
+    (4, "stat"),
+    (5, "fstat"),
+    (6, "lstat"),
+    (7, "poll"),
+    (8, "lseek"),
+    (9, "mmap"),
+    (10, "mprotect"),
+    (11, "munmap"),
+    (12, "brk"),
+    (13, "rt_sigaction"),
+    (14, "rt_sigprocmask"),
+    (15, "rt_sigreturn"),
+    (16, "ioctl"),
+    (17, "pread64"),
+    (18, "pwrite64"),
+    (19, "readv"),
+    (20, "writev"),
+    (21, "access"),
+    (22, "pipe"),
+    (23, "select"),
+    (24, "sched_yield"),
+    (25, "mremap"),
+    (26, "msync"),
+    (27, "mincore"),
+    (28, "madvise"),
+    (29, "shmget"),
+    (30, "shmat"),
+    (31, "shmctl"),
+    (32, "dup"),
+    (33, "dup2"),
+    (34, "pause"),
+    (35, "nanosleep"),
+    (36, "getitimer"),
+    (37, "alarm"),
+    (38, "setitimer"),
+    (39, "getpid"),
+    (40, "sendfile"),
+    (41, "socket"),
+    (42, "connect"),
+    (43, "accept"),
+    (44, "sendto"),
+    (45, "recvfrom"),
+    (46, "sendmsg"),
+    (47, "recvmsg"),
+    (48, "shutdown"),
+    (49, "bind"),
+    (50, "listen"),
+    (51, "getsockname"),
+    (52, "getpeername"),
+    (53, "socketpair"),
+    (54, "setsockopt"),
+    (55, "getsockopt"),
+    (56, "clone"),
+    (57, "fork"),
+    (58, "vfork"),
+    (59, "execve"),
+    (60, "exit"),
+    (61, "wait4"),
+    (62, "kill"),
+    (63, "uname"),
+    (64, "semget"),
+    (65, "semop"),
+    (66, "semctl"),
+    (67, "shmdt"),
+    (68, "msgget"),
+    (69, "msgsnd"),
+    (70, "msgrcv"),
+    (71, "msgctl"),
+    (72, "fcntl"),
+    (73, "flock"),
+    (74, "fsync"),
+    (75, "fdatasync"),
+    (76, "truncate"),
+    (77, "ftruncate"),
+    (78, "getdents"),
+    (79, "getcwd"),
+    (80, "chdir"),
+    (81, "fchdir"),
+    (82, "rename"),
+    (83, "mkdir"),
+    (84, "rmdir"),
+    (85, "creat"),
+    (86, "link"),
+    (87, "unlink"),
+    (88, "symlink"),
+    (89, "readlink"),
+    (90, "chmod"),
+    (91, "fchmod"),
+    (92, "chown"),
+    (93, "fchown"),
+    (94, "lchown"),
+    (95, "umask"),
+    (96, "gettimeofday"),
+    (97, "getrlimit"),
+    (98, "getrusage"),
+    (99, "sysinfo"),
+    (100, "times"),
+    (101, "ptrace"),
+    (102, "getuid"),
+    (103, "syslog"),
+    (104, "getgid"),
+    (105, "setuid"),
+    (106, "setgid"),
+    (107, "geteuid"),
+    (108, "getegid"),
+    (109, "setpgid"),
+    (110, "getppid"),
+    (111, "getpgrp"),
+    (112, "setsid"),
+    (113, "setreuid"),
+    (114, "setregid"),
+    (115, "getgroups"),
+    (116, "setgroups"),
+    (117, "setresuid"),
+    (118, "getresuid"),
+    (119, "setresgid"),
+    (120, "getresgid"),
+    (121, "getpgid"),
+    (122, "setfsuid"),
+    (123, "setfsgid"),
+    (124, "getsid"),
+    (125, "capget"),
+    (126, "capset"),
+    (127, "rt_sigpending"),
+    (128, "rt_sigtimedwait"),
+    (129, "rt_sigqueueinfo"),
+    (130, "rt_sigsuspend"),
+    (131, "sigaltstack"),
+    (132, "utime"),
+    (133, "mknod"),
+    (134, "uselib"),
+    (135, "personality"),
+    (136, "ustat"),
+    (137, "statfs"),
+    (138, "fstatfs"),
+    (139, "sysfs"),
+    (140, "getpriority"),
+    (141, "setpriority"),
+    (142, "sched_setparam"),
+    (143, "sched_getparam"),
+    (144, "sched_setscheduler"),
+    (145, "sched_getscheduler"),
+    (146, "sched_get_priority_max"),
+    (147, "sched_get_priority_min"),
+    (148, "sched_rr_get_interval"),
+    (149, "mlock"),
+    (150, "munlock"),
+    (151, "mlockall"),
+    (152, "munlockall"),
+    (153, "vhangup"),
+    (154, "modify_ldt"),
+    (155, "pivot_root"),
+    (156, "_sysctl"),
+    (157, "prctl"),
+    (158, "arch_prctl"),
+    (159, "adjtimex"),
+    (160, "setrlimit"),
+    (161, "chroot"),
+    (162, "sync"),
+    (163, "acct"),
+    (164, "settimeofday"),
+    (165, "mount"),
+    (166, "umount2"),
+    (167, "swapon"),
+    (168, "swapoff"),
+    (169, "reboot"),
+    (170, "sethostname"),
+    (171, "setdomainname"),
+    (172, "iopl"),
+    (173, "ioperm"),
+    (175, "init_module"),
+    (176, "delete_module"),
+    (179, "quotactl"),
+    (180, "nfsservctl"),
+    (181, "getpmsg"),
+    (182, "putpmsg"),
+    (183, "afs_syscall"),
+    (184, "tuxcall"),
+    (185, "security"),
+    (186, "gettid"),
+    (187, "readahead"),
+    (188, "setxattr"),
+    (189, "lsetxattr"),
+    (190, "fsetxattr"),
+    (191, "getxattr"),
+    (192, "lgetxattr"),
+    (193, "fgetxattr"),
+    (194, "listxattr"),
+    (195, "llistxattr"),
+    (196, "flistxattr"),
+    (197, "removexattr"),
+    (198, "lremovexattr"),
+    (199, "fremovexattr"),
+    (200, "tkill"),
+    (201, "time"),
+    (202, "futex"),
+    (203, "sched_setaffinity"),
+    (204, "sched_getaffinity"),
+    (205, "set_thread_area"),
+    (206, "io_setup"),
+    (207, "io_destroy"),
+    (208, "io_getevents"),
+    (209, "io_submit"),
+    (210, "io_cancel"),
+    (211, "get_thread_area"),
+    (212, "lookup_dcookie"),
+    (213, "epoll_create"),
+    (214, "epoll_ctl_old"),
+    (215, "epoll_wait_old"),
+    (216, "remap_file_pages"),
+    (217, "getdents64"),
+    (218, "set_tid_address"),
+    (219, "restart_syscall"),
+    (220, "semtimedop"),
+    (221, "fadvise64"),
+    (222, "timer_create"),
+    (223, "timer_settime"),
+    (224, "timer_gettime"),
+    (225, "timer_getoverrun"),
+    (226, "timer_delete"),
+    (227, "clock_settime"),
+    (228, "clock_gettime"),
+    (229, "clock_getres"),
+    (230, "clock_nanosleep"),
+    (231, "exit_group"),
+    (232, "epoll_wait"),
+    (233, "epoll_ctl"),
+    (234, "tgkill"),
+    (235, "utimes"),
+    (236, "vserver"),
+    (237, "mbind"),
+    (238, "set_mempolicy"),
+    (239, "get_mempolicy"),
+    (240, "mq_open"),
+    (241, "mq_unlink"),
+    (242, "mq_timedsend"),
+    (243, "mq_timedreceive"),
+    (244, "mq_notify"),
+    (245, "mq_getsetattr"),
+    (246, "kexec_load"),
+    (247, "waitid"),
+    (248, "add_key"),
+    (249, "request_key"),
+    (250, "keyctl"),
+    (251, "ioprio_set"),
+    (252, "ioprio_get"),
+    (253, "inotify_init"),
+    (254, "inotify_add_watch"),
+    (255, "inotify_rm_watch"),
+    (256, "migrate_pages"),
+    (257, "openat"),
+    (258, "mkdirat"),
+    (259, "mknodat"),
+    (260, "fchownat"),
+    (261, "futimesat"),
+    (262, "newfstatat"),
+    (263, "unlinkat"),
+    (264, "renameat"),
+    (265, "linkat"),
+    (266, "symlinkat"),
+    (267, "readlinkat"),
+    (268, "fchmodat"),
+    (269, "faccessat"),
+    (270, "pselect6"),
+    (271, "ppoll"),
+    (272, "unshare"),
+    (273, "set_robust_list"),
+    (274, "get_robust_list"),
+    (275, "splice"),
+    (276, "tee"),
+    (277, "sync_file_range"),
+    (278, "vmsplice"),
+    (279, "move_pages"),
+    (280, "utimensat"),
+    (281, "epoll_pwait"),
+    (282, "signalfd"),
+    (283, "timerfd_create"),
+    (284, "eventfd"),
+    (285, "fallocate"),
+    (286, "timerfd_settime"),
+    (287, "timerfd_gettime"),
+    (288, "accept4"),
+    (289, "signalfd4"),
+    (290, "eventfd2"),
+    (291, "epoll_create1"),
+    (292, "dup3"),
+    (293, "pipe2"),
+    (294, "inotify_init1"),
+    (295, "preadv"),
+    (296, "pwritev"),
+    (297, "rt_tgsigqueueinfo"),
+    (298, "perf_event_open"),
+    (299, "recvmmsg"),
+    (300, "fanotify_init"),
+    (301, "fanotify_mark"),
+    (302, "prlimit64"),
+    (303, "name_to_handle_at"),
+    (304, "open_by_handle_at"),
+    (305, "clock_adjtime"),
+    (306, "syncfs"),
+    (307, "sendmmsg"),
+    (308, "setns"),
+    (309, "getcpu"),
+    (310, "process_vm_readv"),
+    (311, "process_vm_writev"),
+    (312, "kcmp"),
+    (313, "finit_module"),
+    (314, "sched_setattr"),
+    (315, "sched_getattr"),
+    (316, "renameat2"),
+    (317, "seccomp"),
+    (318, "getrandom"),
+    (319, "memfd_create"),
+    (320, "kexec_file_load"),
+    (321, "bpf"),
+    (322, "execveat"),
+    (323, "userfaultfd"),
+    (324, "membarrier"),
+    (325, "mlock2"),
+    (326, "copy_file_range"),
+    (327, "preadv2"),
+    (328, "pwritev2"),
+    (329, "pkey_mprotect"),
+    (330, "pkey_alloc"),
+    (331, "pkey_free"),
+    (332, "statx"),
+    (334, "rseq"),
+    (424, "pidfd_send_signal"),
+    (425, "io_uring_setup"),
+    (426, "io_uring_enter"),
+    (427, "io_uring_register"),
+    (428, "open_tree"),
+    (429, "move_mount"),
+    (430, "fsopen"),
+    (431, "fsconfig"),
+    (432, "fsmount"),
+    (433, "fspick"),
+    (434, "pidfd_open"),
+    (435, "clone3"),
+    (436, "close_range"),
+    (437, "openat2"),
+    (438, "pidfd_getfd"),
+    (439, "faccessat2"),
+    (440, "process_madvise"),
+    (441, "epoll_pwait2"),
+    (442, "mount_setattr"),
+    (443, "quotactl_fd"),
+    (444, "landlock_create_ruleset"),
+    (445, "landlock_add_rule"),
+    (446, "landlock_restrict_self"),
+    (447, "memfd_secret"),
+    (448, "process_mrelease"),
+    (449, "futex_waitv"),
+    (450, "set_mempolicy_home_node"),
+    (451, "cachestat"),
+    (452, "fchmodat2"),
+    (453, "map_shadow_stack"),
+    (454, "futex_wake"),
+    (455, "futex_wait"),
+    (456, "futex_requeue"),
+    (457, "statmount"),
+    (458, "listmount"),
+    (459, "lsm_get_self_attr"),
+    (460, "lsm_set_self_attr"),
+    (461, "lsm_list_modules"),
+    (462, "mseal"),
+];
+
+/// The syscall numbers of 32-bit processes on x86_64, from
+/// `arch/x86/entry/syscalls/syscall_32.tbl`.
+const I386_SYSCALLS: &[(u32, &str)] = &[
+    (0, "restart_syscall"),
+    (1, "exit"),
+    (2, "fork"),
+    (3, "read"),
+    (4, "write"),
+    (5, "open"),
+    (6, "close"),
+    (7, "waitpid"),
+    (8, "creat"),
+    (9, "link"),
+    (10, "unlink"),
+    (11, "execve"),
+    (12, "chdir"),
+    (13, "time"),
+    (14, "mknod"),
+    (15, "chmod"),
+    (16, "lchown"),
+    (17, "break"),
+    (18, "oldstat"),
+    (19, "lseek"),
+    (20, "getpid"),
+    (21, "mount"),
+    (22, "umount"),
+    (23, "setuid"),
+    (24, "getuid"),
+    (25, "stime"),
+    (26, "ptrace"),
+    (27, "alarm"),
+    (28, "oldfstat"),
+    (29, "pause"),
+    (30, "utime"),
+    (31, "stty"),
+    (32, "gtty"),
+    (33, "access"),
+    (34, "nice"),
+    (35, "ftime"),
+    (36, "sync"),
+    (37, "kill"),
+    (38, "rename"),
+    (39, "mkdir"),
+    (40, "rmdir"),
+    (41, "dup"),
+    (42, "pipe"),
+    (43, "times"),
+    (44, "prof"),
+    (45, "brk"),
+    (46, "setgid"),
+    (47, "getgid"),
+    (48, "signal"),
+    (49, "geteuid"),
+    (50, "getegid"),
+    (51, "acct"),
+    (52, "umount2"),
+    (53, "lock"),
+    (54, "ioctl"),
+    (55, "fcntl"),
+    (56, "mpx"),
+    (57, "setpgid"),
+    (58, "ulimit"),
+    (59, "oldolduname"),
+    (60, "umask"),
+    (61, "chroot"),
+    (62, "ustat"),
+    (63, "dup2"),
+    (64, "getppid"),
+    (65, "getpgrp"),
+    (66, "setsid"),
+    (67, "sigaction"),
+    (68, "sgetmask"),
+    (69, "ssetmask"),
+    (70, "setreuid"),
+    (71, "setregid"),
+    (72, "sigsuspend"),
+    (73, "sigpending"),
+    (74, "sethostname"),
+    (75, "setrlimit"),
+    (76, "getrlimit"),
+    (77, "getrusage"),
+    (78, "gettimeofday"),
+    (79, "settimeofday"),
+    (80, "getgroups"),
+    (81, "setgroups"),
+    (82, "select"),
+    (83, "symlink"),
+    (84, "oldlstat"),
+    (85, "readlink"),
+    (86, "uselib"),
+    (87, "swapon"),
+    (88, "reboot"),
+    (89, "readdir"),
+    (90, "mmap"),
+    (91, "munmap"),
+    (92, "truncate"),
+    (93, "ftruncate"),
+    (94, "fchmod"),
+    (95, "fchown"),
+    (96, "getpriority"),
+    (97, "setpriority"),
+    (98, "profil"),
+    (99, "statfs"),
+    (100, "fstatfs"),
+    (101, "ioperm"),
+    (102, "socketcall"),
+    (103, "syslog"),
+    (104, "setitimer"),
+    (105, "getitimer"),
+    (106, "stat"),
+    (107, "lstat"),
+    (108, "fstat"),
+    (109, "olduname"),
+    (110, "iopl"),
+    (111, "vhangup"),
+    (112, "idle"),
+    (113, "vm86old"),
+    (114, "wait4"),
+    (115, "swapoff"),
+    (116, "sysinfo"),
+    (117, "ipc"),
+    (118, "fsync"),
+    (119, "sigreturn"),
+    (120, "clone"),
+    (121, "setdomainname"),
+    (122, "uname"),
+    (123, "modify_ldt"),
+    (124, "adjtimex"),
+    (125, "mprotect"),
+    (126, "sigprocmask"),
+    (127, "create_module"),
+    (128, "init_module"),
+    (129, "delete_module"),
+    (130, "get_kernel_syms"),
+    (131, "quotactl"),
+    (132, "getpgid"),
+    (133, "fchdir"),
+    (134, "bdflush"),
+    (135, "sysfs"),
+    (136, "personality"),
+    (137, "afs_syscall"),
+    (138, "setfsuid"),
+    (139, "setfsgid"),
+    (140, "_llseek"),
+    (141, "getdents"),
+    (142, "_newselect"),
+    (143, "flock"),
+    (144, "msync"),
+    (145, "readv"),
+    (146, "writev"),
+    (147, "getsid"),
+    (148, "fdatasync"),
+    (149, "_sysctl"),
+    (150, "mlock"),
+    (151, "munlock"),
+    (152, "mlockall"),
+    (153, "munlockall"),
+    (154, "sched_setparam"),
+    (155, "sched_getparam"),
+    (156, "sched_setscheduler"),
+    (157, "sched_getscheduler"),
+    (158, "sched_yield"),
+    (159, "sched_get_priority_max"),
+    (160, "sched_get_priority_min"),
+    (161, "sched_rr_get_interval"),
+    (162, "nanosleep"),
+    (163, "mremap"),
+    (164, "setresuid"),
+    (165, "getresuid"),
+    (166, "vm86"),
+    (167, "query_module"),
+    (168, "poll"),
+    (169, "nfsservctl"),
+    (170, "setresgid"),
+    (171, "getresgid"),
+    (172, "prctl"),
+    (173, "rt_sigreturn"),
+    (174, "rt_sigaction"),
+    (175, "rt_sigprocmask"),
+    (176, "rt_sigpending"),
+    (177, "rt_sigtimedwait"),
+    (178, "rt_sigqueueinfo"),
+    (179, "rt_sigsuspend"),
+    (180, "pread64"),
+    (181, "pwrite64"),
+    (182, "chown"),
+    (183, "getcwd"),
+    (184, "capget"),
+    (185, "capset"),
+    (186, "sigaltstack"),
+    (187, "sendfile"),
+    (188, "getpmsg"),
+    (189, "putpmsg"),
+    (190, "vfork"),
+    (191, "ugetrlimit"),
+    (192, "mmap2"),
+    (193, "truncate64"),
+    (194, "ftruncate64"),
+    (195, "stat64"),
+    (196, "lstat64"),
+    (197, "fstat64"),
+    (198, "lchown32"),
+    (199, "getuid32"),
+    (200, "getgid32"),
+    (201, "geteuid32"),
+    (202, "getegid32"),
+    (203, "setreuid32"),
+    (204, "setregid32"),
+    (205, "getgroups32"),
+    (206, "setgroups32"),
+    (207, "fchown32"),
+    (208, "setresuid32"),
+    (209, "getresuid32"),
+    (210, "setresgid32"),
+    (211, "getresgid32"),
+    (212, "chown32"),
+    (213, "setuid32"),
+    (214, "setgid32"),
+    (215, "setfsuid32"),
+    (216, "setfsgid32"),
+    (217, "pivot_root"),
+    (218, "mincore"),
+    (219, "madvise"),
+    (220, "getdents64"),
+    (221, "fcntl64"),
+    (224, "gettid"),
+    (225, "readahead"),
+    (226, "setxattr"),
+    (227, "lsetxattr"),
+    (228, "fsetxattr"),
+    (229, "getxattr"),
+    (230, "lgetxattr"),
+    (231, "fgetxattr"),
+    (232, "listxattr"),
+    (233, "llistxattr"),
+    (234, "flistxattr"),
+    (235, "removexattr"),
+    (236, "lremovexattr"),
+    (237, "fremovexattr"),
+    (238, "tkill"),
+    (239, "sendfile64"),
+    (240, "futex"),
+    (241, "sched_setaffinity"),
+    (242, "sched_getaffinity"),
+    (243, "set_thread_area"),
+    (244, "get_thread_area"),
+    (245, "io_setup"),
+    (246, "io_destroy"),
+    (247, "io_getevents"),
+    (248, "io_submit"),
+    (249, "io_cancel"),
+    (250, "fadvise64"),
+    (252, "exit_group"),
+    (253, "lookup_dcookie"),
+    (254, "epoll_create"),
+    (255, "epoll_ctl"),
+    (256, "epoll_wait"),
+    (257, "remap_file_pages"),
+    (258, "set_tid_address"),
+    (259, "timer_create"),
+    (260, "timer_settime"),
+    (261, "timer_gettime"),
+    (262, "timer_getoverrun"),
+    (263, "timer_delete"),
+    (264, "clock_settime"),
+    (265, "clock_gettime"),
+    (266, "clock_getres"),
+    (267, "clock_nanosleep"),
+    (268, "statfs64"),
+    (269, "fstatfs64"),
+    (270, "tgkill"),
+    (271, "utimes"),
+    (272, "fadvise64_64"),
+    (273, "vserver"),
+    (274, "mbind"),
+    (275, "get_mempolicy"),
+    (276, "set_mempolicy"),
+    (277, "mq_open"),
+    (278, "mq_unlink"),
+    (279, "mq_timedsend"),
+    (280, "mq_timedreceive"),
+    (281, "mq_notify"),
+    (282, "mq_getsetattr"),
+    (283, "kexec_load"),
+    (284, "waitid"),
+    (286, "add_key"),
+    (287, "request_key"),
+    (288, "keyctl"),
+    (289, "ioprio_set"),
+    (290, "ioprio_get"),
+    (291, "inotify_init"),
+    (292, "inotify_add_watch"),
+    (293, "inotify_rm_watch"),
+    (294, "migrate_pages"),
+    (295, "openat"),
+    (296, "mkdirat"),
+    (297, "mknodat"),
+    (298, "fchownat"),
+    (299, "futimesat"),
+    (300, "fstatat64"),
+    (301, "unlinkat"),
+    (302, "renameat"),
+    (303, "linkat"),
+    (304, "symlinkat"),
+    (305, "readlinkat"),
+    (306, "fchmodat"),
+    (307, "faccessat"),
+    (308, "pselect6"),
+    (309, "ppoll"),
+    (310, "unshare"),
+    (311, "set_robust_list"),
+    (312, "get_robust_list"),
+    (313, "splice"),
+    (314, "sync_file_range"),
+    (315, "tee"),
+    (316, "vmsplice"),
+    (317, "move_pages"),
+    (318, "getcpu"),
+    (319, "epoll_pwait"),
+    (320, "utimensat"),
+    (321, "signalfd"),
+    (322, "timerfd_create"),
+    (323, "eventfd"),
+    (324, "fallocate"),
+    (325, "timerfd_settime"),
+    (326, "timerfd_gettime"),
+    (327, "signalfd4"),
+    (328, "eventfd2"),
+    (329, "epoll_create1"),
+    (330, "dup3"),
+    (331, "pipe2"),
+    (332, "inotify_init1"),
+    (333, "preadv"),
+    (334, "pwritev"),
+    (335, "rt_tgsigqueueinfo"),
+    (336, "perf_event_open"),
+    (337, "recvmmsg"),
+    (338, "fanotify_init"),
+    (339, "fanotify_mark"),
+    (340, "prlimit64"),
+    (341, "name_to_handle_at"),
+    (342, "open_by_handle_at"),
+    (343, "clock_adjtime"),
+    (344, "syncfs"),
+    (345, "sendmmsg"),
+    (346, "setns"),
+    (347, "process_vm_readv"),
+    (348, "process_vm_writev"),
+    (349, "kcmp"),
+    (350, "finit_module"),
+    (351, "sched_setattr"),
+    (352, "sched_getattr"),
+    (353, "renameat2"),
+    (354, "seccomp"),
+    (355, "getrandom"),
+    (356, "memfd_create"),
+    (357, "bpf"),
+    (358, "execveat"),
+    (359, "socket"),
+    (360, "socketpair"),
+    (361, "bind"),
+    (362, "connect"),
+    (363, "listen"),
+    (364, "accept4"),
+    (365, "getsockopt"),
+    (366, "setsockopt"),
+    (367, "getsockname"),
+    (368, "getpeername"),
+    (369, "sendto"),
+    (370, "sendmsg"),
+    (371, "recvfrom"),
+    (372, "recvmsg"),
+    (373, "shutdown"),
+    (374, "userfaultfd"),
+    (375, "membarrier"),
+    (376, "mlock2"),
+    (377, "copy_file_range"),
+    (378, "preadv2"),
+    (379, "pwritev2"),
+    (380, "pkey_mprotect"),
+    (381, "pkey_alloc"),
+    (382, "pkey_free"),
+    (383, "statx"),
+    (384, "arch_prctl"),
+    (385, "io_pgetevents"),
+    (386, "rseq"),
+    (393, "semget"),
+    (394, "semctl"),
+    (395, "shmget"),
+    (396, "shmctl"),
+    (397, "shmat"),
+    (398, "shmdt"),
+    (399, "msgget"),
+    (400, "msgsnd"),
+    (401, "msgrcv"),
+    (402, "msgctl"),
+    (403, "clock_gettime64"),
+    (404, "clock_settime64"),
+    (405, "clock_adjtime64"),
+    (406, "clock_getres_time64"),
+    (407, "clock_nanosleep_time64"),
+    (408, "timer_gettime64"),
+    (409, "timer_settime64"),
+    (410, "timerfd_gettime64"),
+    (411, "timerfd_settime64"),
+    (412, "utimensat_time64"),
+    (413, "pselect6_time64"),
+    (414, "ppoll_time64"),
+    (416, "io_pgetevents_time64"),
+    (417, "recvmmsg_time64"),
+    (418, "mq_timedsend_time64"),
+    (419, "mq_timedreceive_time64"),
+    (420, "semtimedop_time64"),
+    (421, "rt_sigtimedwait_time64"),
+    (422, "futex_time64"),
+    (423, "sched_rr_get_interval_time64"),
+    (424, "pidfd_send_signal"),
+    (425, "io_uring_setup"),
+    (426, "io_uring_enter"),
+    (427, "io_uring_register"),
+    (428, "open_tree"),
+    (429, "move_mount"),
+    (430, "fsopen"),
+    (431, "fsconfig"),
+    (432, "fsmount"),
+    (433, "fspick"),
+    (434, "pidfd_open"),
+    (435, "clone3"),
+    (436, "close_range"),
+    (437, "openat2"),
+    (438, "pidfd_getfd"),
+    (439, "faccessat2"),
+    (440, "process_madvise"),
+    (441, "epoll_pwait2"),
+    (442, "mount_setattr"),
+    (443, "quotactl_fd"),
+    (444, "landlock_create_ruleset"),
+    (445, "landlock_add_rule"),
+    (446, "landlock_restrict_self"),
+    (447, "memfd_secret"),
+    (448, "process_mrelease"),
+    (449, "futex_waitv"),
+    (450, "set_mempolicy_home_node"),
+    (451, "cachestat"),
+    (452, "fchmodat2"),
+    (454, "futex_wake"),
+    (455, "futex_wait"),
+    (456, "futex_requeue"),
+    (457, "statmount"),
+    (458, "listmount"),
+    (459, "lsm_get_self_attr"),
+    (460, "lsm_set_self_attr"),
+    (461, "lsm_list_modules"),
+    (462, "mseal"),
+];
+
+/// The syscall numbers on aarch64, from `include/uapi/asm-generic/unistd.h`.
+const AARCH64_SYSCALLS: &[(u32, &str)] = &[
+    (0, "io_setup"),
+    (1, "io_destroy"),
+    (2, "io_submit"),
+    (3, "io_cancel"),
+    (4, "io_getevents"),
+    (5, "setxattr"),
+    (6, "lsetxattr"),
+    (7, "fsetxattr"),
+    (8, "getxattr"),
+    (9, "lgetxattr"),
+    (10, "fgetxattr"),
+    (11, "listxattr"),
+    (12, "llistxattr"),
+    (13, "flistxattr"),
+    (14, "removexattr"),
+    (15, "lremovexattr"),
+    (16, "fremovexattr"),
+    (17, "getcwd"),
+    (18, "lookup_dcookie"),
+    (19, "eventfd2"),
+    (20, "epoll_create1"),
+    (21, "epoll_ctl"),
+    (22, "epoll_pwait"),
+    (23, "dup"),
+    (24, "dup3"),
+    (25, "fcntl"),
+    (26, "inotify_init1"),
+    (27, "inotify_add_watch"),
+    (28, "inotify_rm_watch"),
+    (29, "ioctl"),
+    (30, "ioprio_set"),
+    (31, "ioprio_get"),
+    (32, "flock"),
+    (33, "mknodat"),
+    (34, "mkdirat"),
+    (35, "unlinkat"),
+    (36, "symlinkat"),
+    (37, "linkat"),
+    (39, "umount2"),
+    (40, "mount"),
+    (41, "pivot_root"),
+    (42, "nfsservctl"),
+    (43, "statfs"),
+    (44, "fstatfs"),
+    (45, "truncate"),
+    (46, "ftruncate"),
+    (47, "fallocate"),
+    (48, "faccessat"),
+    (49, "chdir"),
+    (50, "fchdir"),
+    (51, "chroot"),
+    (52, "fchmod"),
+    (53, "fchmodat"),
+    (54, "fchownat"),
+    (55, "fchown"),
+    (56, "openat"),
+    (57, "close"),
+    (58, "vhangup"),
+    (59, "pipe2"),
+    (60, "quotactl"),
+    (61, "getdents64"),
+    (62, "lseek"),
+    (63, "read"),
+    (64, "write"),
+    (65, "readv"),
+    (66, "writev"),
+    (67, "pread64"),
+    (68, "pwrite64"),
+    (69, "preadv"),
+    (70, "pwritev"),
+    (71, "sendfile"),
+    (72, "pselect6"),
+    (73, "ppoll"),
+    (74, "signalfd4"),
+    (75, "vmsplice"),
+    (76, "splice"),
+    (77, "tee"),
+    (78, "readlinkat"),
+    (79, "newfstatat"),
+    (80, "fstat"),
+    (81, "sync"),
+    (82, "fsync"),
+    (83, "fdatasync"),
+    (85, "timerfd_create"),
+    (86, "timerfd_settime"),
+    (87, "timerfd_gettime"),
+    (88, "utimensat"),
+    (89, "acct"),
+    (90, "capget"),
+    (91, "capset"),
+    (92, "personality"),
+    (93, "exit"),
+    (94, "exit_group"),
+    (95, "waitid"),
+    (96, "set_tid_address"),
+    (97, "unshare"),
+    (98, "futex"),
+    (99, "set_robust_list"),
+    (100, "get_robust_list"),
+    (101, "nanosleep"),
+    (102, "getitimer"),
+    (103, "setitimer"),
+    (104, "kexec_load"),
+    (105, "init_module"),
+    (106, "delete_module"),
+    (107, "timer_create"),
+    (108, "timer_gettime"),
+    (109, "timer_getoverrun"),
+    (110, "timer_settime"),
+    (111, "timer_delete"),
+    (112, "clock_settime"),
+    (113, "clock_gettime"),
+    (114, "clock_getres"),
+    (115, "clock_nanosleep"),
+    (116, "syslog"),
+    (117, "ptrace"),
+    (118, "sched_setparam"),
+    (119, "sched_setscheduler"),
+    (120, "sched_getscheduler"),
+    (121, "sched_getparam"),
+    (122, "sched_setaffinity"),
+    (123, "sched_getaffinity"),
+    (124, "sched_yield"),
+    (125, "sched_get_priority_max"),
+    (126, "sched_get_priority_min"),
+    (127, "sched_rr_get_interval"),
+    (128, "restart_syscall"),
+    (129, "kill"),
+    (130, "tkill"),
+    (131, "tgkill"),
+    (132, "sigaltstack"),
+    (133, "rt_sigsuspend"),
+    (134, "rt_sigaction"),
+    (135, "rt_sigprocmask"),
+    (136, "rt_sigpending"),
+    (137, "rt_sigtimedwait"),
+    (138, "rt_sigqueueinfo"),
+    (139, "rt_sigreturn"),
+    (140, "setpriority"),
+    (141, "getpriority"),
+    (142, "reboot"),
+    (143, "setregid"),
+    (144, "setgid"),
+    (145, "setreuid"),
+    (146, "setuid"),
+    (147, "setresuid"),
+    (148, "getresuid"),
+    (149, "setresgid"),
+    (150, "getresgid"),
+    (151, "setfsuid"),
+    (152, "setfsgid"),
+    (153, "times"),
+    (154, "setpgid"),
+    (155, "getpgid"),
+    (156, "getsid"),
+    (157, "setsid"),
+    (158, "getgroups"),
+    (159, "setgroups"),
+    (160, "uname"),
+    (161, "sethostname"),
+    (162, "setdomainname"),
+    (165, "getrusage"),
+    (166, "umask"),
+    (167, "prctl"),
+    (168, "getcpu"),
+    (169, "gettimeofday"),
+    (170, "settimeofday"),
+    (171, "adjtimex"),
+    (172, "getpid"),
+    (173, "getppid"),
+    (174, "getuid"),
+    (175, "geteuid"),
+    (176, "getgid"),
+    (177, "getegid"),
+    (178, "gettid"),
+    (179, "sysinfo"),
+    (180, "mq_open"),
+    (181, "mq_unlink"),
+    (182, "mq_timedsend"),
+    (183, "mq_timedreceive"),
+    (184, "mq_notify"),
+    (185, "mq_getsetattr"),
+    (186, "msgget"),
+    (187, "msgctl"),
+    (188, "msgrcv"),
+    (189, "msgsnd"),
+    (190, "semget"),
+    (191, "semctl"),
+    (192, "semtimedop"),
+    (193, "semop"),
+    (194, "shmget"),
+    (195, "shmctl"),
+    (196, "shmat"),
+    (197, "shmdt"),
+    (198, "socket"),
+    (199, "socketpair"),
+    (200, "bind"),
+    (201, "listen"),
+    (202, "accept"),
+    (203, "connect"),
+    (204, "getsockname"),
+    (205, "getpeername"),
+    (206, "sendto"),
+    (207, "recvfrom"),
+    (208, "setsockopt"),
+    (209, "getsockopt"),
+    (210, "shutdown"),
+    (211, "sendmsg"),
+    (212, "recvmsg"),
+    (213, "readahead"),
+    (214, "brk"),
+    (215, "munmap"),
+    (216, "mremap"),
+    (217, "add_key"),
+    (218, "request_key"),
+    (219, "keyctl"),
+    (220, "clone"),
+    (221, "execve"),
+    (222, "mmap"),
+    (223, "fadvise64"),
+    (224, "swapon"),
+    (225, "swapoff"),
+    (226, "mprotect"),
+    (227, "msync"),
+    (228, "mlock"),
+    (229, "munlock"),
+    (230, "mlockall"),
+    (231, "munlockall"),
+    (232, "mincore"),
+    (233, "madvise"),
+    (234, "remap_file_pages"),
+    (235, "mbind"),
+    (236, "get_mempolicy"),
+    (237, "set_mempolicy"),
+    (238, "migrate_pages"),
+    (239, "move_pages"),
+    (240, "rt_tgsigqueueinfo"),
+    (241, "perf_event_open"),
+    (242, "accept4"),
+    (243, "recvmmsg"),
+    (260, "wait4"),
+    (261, "prlimit64"),
+    (262, "fanotify_init"),
+    (263, "fanotify_mark"),
+    (264, "name_to_handle_at"),
+    (265, "open_by_handle_at"),
+    (266, "clock_adjtime"),
+    (267, "syncfs"),
+    (268, "setns"),
+    (269, "sendmmsg"),
+    (270, "process_vm_readv"),
+    (271, "process_vm_writev"),
+    (272, "kcmp"),
+    (273, "finit_module"),
+    (274, "sched_setattr"),
+    (275, "sched_getattr"),
+    (276, "renameat2"),
+    (277, "seccomp"),
+    (278, "getrandom"),
+    (279, "memfd_create"),
+    (280, "bpf"),
+    (281, "execveat"),
+    (282, "userfaultfd"),
+    (283, "membarrier"),
+    (284, "mlock2"),
+    (285, "copy_file_range"),
+    (286, "preadv2"),
+    (287, "pwritev2"),
+    (288, "pkey_mprotect"),
+    (289, "pkey_alloc"),
+    (290, "pkey_free"),
+    (291, "statx"),
+    (293, "rseq"),
+    (294, "kexec_file_load"),
+    (424, "pidfd_send_signal"),
+    (425, "io_uring_setup"),
+    (426, "io_uring_enter"),
+    (427, "io_uring_register"),
+    (428, "open_tree"),
+    (429, "move_mount"),
+    (430, "fsopen"),
+    (431, "fsconfig"),
+    (432, "fsmount"),
+    (433, "fspick"),
+    (434, "pidfd_open"),
+    (435, "clone3"),
+    (436, "close_range"),
+    (437, "openat2"),
+    (438, "pidfd_getfd"),
+    (439, "faccessat2"),
+    (440, "process_madvise"),
+    (441, "epoll_pwait2"),
+    (442, "mount_setattr"),
+    (443, "quotactl_fd"),
+    (444, "landlock_create_ruleset"),
+    (445, "landlock_add_rule"),
+    (446, "landlock_restrict_self"),
+    (447, "memfd_secret"),
+    (448, "process_mrelease"),
+    (449, "futex_waitv"),
+    (450, "set_mempolicy_home_node"),
+    (451, "cachestat"),
+    (452, "fchmodat2"),
+    (454, "futex_wake"),
+    (455, "futex_wait"),
+    (456, "futex_requeue"),
+    (457, "statmount"),
+    (458, "listmount"),
+    (459, "lsm_get_self_attr"),
+    (460, "lsm_set_self_attr"),
+    (461, "lsm_list_modules"),
+    (462, "mseal"),
+];
+
+/// The argument names of each syscall, sorted by syscall name.
+const SYSCALL_ARG_NAMES: &[(&str, &[&str])] = &[
+    ("_sysctl", &["args"]),
+    ("accept", &["fd", "upeer_sockaddr", "upeer_addrlen"]),
+    (
+        "accept4",
+        &["fd", "upeer_sockaddr", "upeer_addrlen", "flags"],
+    ),
+    ("access", &["filename", "mode"]),
+    ("acct", &["name"]),
+    (
+        "add_key",
+        &["_type", "_description", "_payload", "plen", "ringid"],
+    ),
+    ("adjtimex", &["txc_p"]),
+    ("alarm", &["seconds"]),
+    ("arch_prctl", &["option", "arg2"]),
+    ("bind", &["fd", "umyaddr", "addrlen"]),
+    ("bpf", &["cmd", "uattr", "size"]),
+    ("brk", &["brk"]),
+    ("cachestat", &["fd", "cstat_range", "cstat", "flags"]),
+    ("capget", &["header", "dataptr"]),
+    ("capset", &["header", "data"]),
+    ("chdir", &["filename"]),
+    ("chmod", &["filename", "mode"]),
+    ("chown", &["filename", "user", "group"]),
+    ("chroot", &["filename"]),
+    ("clock_adjtime", &["which_clock", "utx"]),
+    ("clock_getres", &["which_clock", "tp"]),
+    ("clock_gettime", &["which_clock", "tp"]),
+    ("clock_nanosleep", &["which_clock", "flags", "rqtp", "rmtp"]),
+    ("clock_settime", &["which_clock", "tp"]),
+    (
+        "clone",
+        &[
+            "clone_flags",
+            "newsp",
+            "parent_tidptr",
+            "child_tidptr",
+            "tls",
+        ],
+    ),
+    ("clone3", &["uargs", "size"]),
+    ("close", &["fd"]),
+    ("close_range", &["fd", "max_fd", "flags"]),
+    ("connect", &["fd", "uservaddr", "addrlen"]),
+    (
+        "copy_file_range",
+        &["fd_in", "off_in", "fd_out", "off_out", "len", "flags"],
+    ),
+    ("creat", &["pathname", "mode"]),
+    ("delete_module", &["name_user", "flags"]),
+    ("dup", &["fildes"]),
+    ("dup2", &["oldfd", "newfd"]),
+    ("dup3", &["oldfd", "newfd", "flags"]),
+    ("epoll_create", &["size"]),
+    ("epoll_create1", &["flags"]),
+    ("epoll_ctl", &["epfd", "op", "fd", "event"]),
+    (
+        "epoll_pwait",
+        &[
+            "epfd",
+            "events",
+            "maxevents",
+            "timeout",
+            "sigmask",
+            "sigsetsize",
+        ],
+    ),
+    (
+        "epoll_pwait2",
+        &[
+            "epfd",
+            "events",
+            "maxevents",
+            "timeout",
+            "sigmask",
+            "sigsetsize",
+        ],
+    ),
+    ("epoll_wait", &["epfd", "events", "maxevents", "timeout"]),
+    ("eventfd", &["count"]),
+    ("eventfd2", &["count", "flags"]),
+    ("execve", &["filename", "argv", "envp"]),
+    ("execveat", &["fd", "filename", "argv", "envp", "flags"]),
+    ("exit", &["error_code"]),
+    ("exit_group", &["error_code"]),
+    ("faccessat", &["dfd", "filename", "mode"]),
+    ("faccessat2", &["dfd", "filename", "mode", "flags"]),
+    ("fadvise64", &["fd", "offset", "len", "advice"]),
+    ("fallocate", &["fd", "mode", "offset", "len"]),
+    ("fanotify_init", &["flags", "event_f_flags"]),
+    (
+        "fanotify_mark",
+        &["fanotify_fd", "flags", "mask", "dfd", "pathname"],
+    ),
+    ("fchdir", &["fd"]),
+    ("fchmod", &["fd", "mode"]),
+    ("fchmodat", &["dfd", "filename", "mode"]),
+    ("fchmodat2", &["dfd", "filename", "mode", "flags"]),
+    ("fchown", &["fd", "user", "group"]),
+    ("fchownat", &["dfd", "filename", "user", "group", "flag"]),
+    ("fcntl", &["fd", "cmd", "arg"]),
+    ("fdatasync", &["fd"]),
+    ("fgetxattr", &["fd", "name", "value", "size"]),
+    ("finit_module", &["fd", "uargs", "flags"]),
+    ("flistxattr", &["fd", "list", "size"]),
+    ("flock", &["fd", "cmd"]),
+    ("fork", &[]),
+    ("fremovexattr", &["fd", "name"]),
+    ("fsconfig", &["fd", "cmd", "_key", "_value", "aux"]),
+    ("fsetxattr", &["fd", "name", "value", "size", "flags"]),
+    ("fsmount", &["fs_fd", "flags", "attr_flags"]),
+    ("fsopen", &["_fs_name", "flags"]),
+    ("fspick", &["dfd", "path", "flags"]),
+    ("fstat", &["fd", "statbuf"]),
+    ("fstatfs", &["fd", "buf"]),
+    ("fsync", &["fd"]),
+    ("ftruncate", &["fd", "length"]),
+    ("futex", &["uaddr", "op", "val", "utime", "uaddr2", "val3"]),
+    (
+        "futex_requeue",
+        &["waiters", "flags", "nr_wake", "nr_requeue"],
+    ),
+    (
+        "futex_wait",
+        &["uaddr", "val", "mask", "flags", "timeout", "clockid"],
+    ),
+    (
+        "futex_waitv",
+        &["waiters", "nr_futexes", "flags", "timeout", "clockid"],
+    ),
+    ("futex_wake", &["uaddr", "mask", "nr", "flags"]),
+    ("futimesat", &["dfd", "filename", "utimes"]),
+    (
+        "get_mempolicy",
+        &["policy", "nmask", "maxnode", "addr", "flags"],
+    ),
+    ("get_robust_list", &["pid", "head_ptr", "len_ptr"]),
+    ("get_thread_area", &["u_info"]),
+    ("getcpu", &["cpup", "nodep", "unused"]),
+    ("getcwd", &["buf", "size"]),
+    ("getdents", &["fd", "dirent", "count"]),
+    ("getdents64", &["fd", "dirent", "count"]),
+    ("getegid", &[]),
+    ("geteuid", &[]),
+    ("getgid", &[]),
+    ("getgroups", &["gidsetsize", "grouplist"]),
+    ("getitimer", &["which", "value"]),
+    ("getpeername", &["fd", "usockaddr", "usockaddr_len"]),
+    ("getpgid", &["pid"]),
+    ("getpgrp", &[]),
+    ("getpid", &[]),
+    ("getppid", &[]),
+    ("getpriority", &["which", "who"]),
+    ("getrandom", &["ubuf", "len", "flags"]),
+    ("getresgid", &["rgidp", "egidp", "sgidp"]),
+    ("getresuid", &["ruidp", "euidp", "suidp"]),
+    ("getrlimit", &["resource", "rlim"]),
+    ("getrusage", &["who", "ru"]),
+    ("getsid", &["pid"]),
+    ("getsockname", &["fd", "usockaddr", "usockaddr_len"]),
+    (
+        "getsockopt",
+        &["fd", "level", "optname", "optval", "optlen"],
+    ),
+    ("gettid", &[]),
+    ("gettimeofday", &["tv", "tz"]),
+    ("getuid", &[]),
+    ("getxattr", &["pathname", "name", "value", "size"]),
+    ("init_module", &["umod", "len", "uargs"]),
+    ("inotify_add_watch", &["fd", "pathname", "mask"]),
+    ("inotify_init", &[]),
+    ("inotify_init1", &["flags"]),
+    ("inotify_rm_watch", &["fd", "wd"]),
+    ("io_cancel", &["ctx_id", "iocb", "result"]),
+    ("io_destroy", &["ctx"]),
+    (
+        "io_getevents",
+        &["ctx_id", "min_nr", "nr", "events", "timeout"],
+    ),
+    ("io_setup", &["nr_events", "ctxp"]),
+    ("io_submit", &["ctx_id", "nr", "iocbpp"]),
+    (
+        "io_uring_enter",
+        &["fd", "to_submit", "min_complete", "flags", "argp", "argsz"],
+    ),
+    ("io_uring_register", &["fd", "opcode", "arg", "nr_args"]),
+    ("io_uring_setup", &["entries", "params"]),
+    ("ioctl", &["fd", "cmd", "arg"]),
+    ("ioperm", &["from", "num", "turn_on"]),
+    ("iopl", &["level"]),
+    ("ioprio_get", &["which", "who"]),
+    ("ioprio_set", &["which", "who", "ioprio"]),
+    ("kcmp", &["pid1", "pid2", "type", "idx1", "idx2"]),
+    (
+        "kexec_file_load",
+        &[
+            "kernel_fd",
+            "initrd_fd",
+            "cmdline_len",
+            "cmdline_ptr",
+            "flags",
+        ],
+    ),
+    ("kexec_load", &["entry", "nr_segments", "segments", "flags"]),
+    ("keyctl", &["option", "arg2", "arg3", "arg4", "arg5"]),
+    ("kill", &["pid", "sig"]),
+    (
+        "landlock_add_rule",
+        &["ruleset_fd", "rule_type", "rule_attr", "flags"],
+    ),
+    ("landlock_create_ruleset", &["attr", "size", "flags"]),
+    ("landlock_restrict_self", &["ruleset_fd", "flags"]),
+    ("lchown", &["filename", "user", "group"]),
+    ("lgetxattr", &["pathname", "name", "value", "size"]),
+    ("link", &["oldname", "newname"]),
+    (
+        "linkat",
+        &["olddfd", "oldname", "newdfd", "newname", "flags"],
+    ),
+    ("listen", &["fd", "backlog"]),
+    ("listmount", &["req", "mnt_ids", "nr_mnt_ids", "flags"]),
+    ("listxattr", &["pathname", "list", "size"]),
+    ("llistxattr", &["pathname", "list", "size"]),
+    ("lookup_dcookie", &["cookie64", "buf", "len"]),
+    ("lremovexattr", &["pathname", "name"]),
+    ("lseek", &["fd", "offset", "whence"]),
+    ("lsetxattr", &["pathname", "name", "value", "size", "flags"]),
+    ("lsm_get_self_attr", &["attr", "ctx", "size", "flags"]),
+    ("lsm_list_modules", &["ids", "size", "flags"]),
+    ("lsm_set_self_attr", &["attr", "ctx", "size", "flags"]),
+    ("lstat", &["filename", "statbuf"]),
+    ("madvise", &["start", "len_in", "behavior"]),
+    ("map_shadow_stack", &["addr", "size", "flags"]),
+    (
+        "mbind",
+        &["start", "len", "mode", "nmask", "maxnode", "flags"],
+    ),
+    ("membarrier", &["cmd", "flags", "cpu_id"]),
+    ("memfd_create", &["uname", "flags"]),
+    ("memfd_secret", &["flags"]),
+    (
+        "migrate_pages",
+        &["pid", "maxnode", "old_nodes", "new_nodes"],
+    ),
+    ("mincore", &["start", "len", "vec"]),
+    ("mkdir", &["pathname", "mode"]),
+    ("mkdirat", &["dfd", "pathname", "mode"]),
+    ("mknod", &["filename", "mode", "dev"]),
+    ("mknodat", &["dfd", "filename", "mode", "dev"]),
+    ("mlock", &["start", "len"]),
+    ("mlock2", &["start", "len", "flags"]),
+    ("mlockall", &["flags"]),
+    ("mmap", &["addr", "len", "prot", "flags", "fd", "off"]),
+    ("modify_ldt", &["func", "ptr", "bytecount"]),
+    ("mount", &["dev_name", "dir_name", "type", "flags", "data"]),
+    ("mount_setattr", &["dfd", "path", "flags", "uattr", "usize"]),
+    (
+        "move_mount",
+        &[
+            "from_dfd",
+            "from_pathname",
+            "to_dfd",
+            "to_pathname",
+            "flags",
+        ],
+    ),
+    (
+        "move_pages",
+        &["pid", "nr_pages", "pages", "nodes", "status", "flags"],
+    ),
+    ("mprotect", &["start", "len", "prot"]),
+    ("mq_getsetattr", &["mqdes", "u_mqstat", "u_omqstat"]),
+    ("mq_notify", &["mqdes", "u_notification"]),
+    ("mq_open", &["u_name", "oflag", "mode", "u_attr"]),
+    (
+        "mq_timedreceive",
+        &[
+            "mqdes",
+            "u_msg_ptr",
+            "msg_len",
+            "u_msg_prio",
+            "u_abs_timeout",
+        ],
+    ),
+    (
+        "mq_timedsend",
+        &["mqdes", "u_msg_ptr", "msg_len", "msg_prio", "u_abs_timeout"],
+    ),
+    ("mq_unlink", &["u_name"]),
+    (
+        "mremap",
+        &["addr", "old_len", "new_len", "flags", "new_addr"],
+    ),
+    ("mseal", &["start", "len", "flags"]),
+    ("msgctl", &["msqid", "cmd", "buf"]),
+    ("msgget", &["key", "msgflg"]),
+    ("msgrcv", &["msqid", "msgp", "msgsz", "msgtyp", "msgflg"]),
+    ("msgsnd", &["msqid", "msgp", "msgsz", "msgflg"]),
+    ("msync", &["start", "len", "flags"]),
+    ("munlock", &["start", "len"]),
+    ("munlockall", &[]),
+    ("munmap", &["addr", "len"]),
+    (
+        "name_to_handle_at",
+        &["dfd", "name", "handle", "mnt_id", "flag"],
+    ),
+    ("nanosleep", &["rqtp", "rmtp"]),
+    ("newfstatat", &["dfd", "filename", "statbuf", "flag"]),
+    ("open", &["filename", "flags", "mode"]),
+    ("open_by_handle_at", &["mountdirfd", "handle", "flags"]),
+    ("open_tree", &["dfd", "filename", "flags"]),
+    ("openat", &["dfd", "filename", "flags", "mode"]),
+    ("openat2", &["dfd", "filename", "how", "usize"]),
+    ("pause", &[]),
+    (
+        "perf_event_open",
+        &["attr_uptr", "pid", "cpu", "group_fd", "flags"],
+    ),
+    ("personality", &["personality"]),
+    ("pidfd_getfd", &["pidfd", "fd", "flags"]),
+    ("pidfd_open", &["pid", "flags"]),
+    ("pidfd_send_signal", &["pidfd", "sig", "info", "flags"]),
+    ("pipe", &["fildes"]),
+    ("pipe2", &["fildes", "flags"]),
+    ("pivot_root", &["new_root", "put_old"]),
+    ("pkey_alloc", &["flags", "init_val"]),
+    ("pkey_free", &["pkey"]),
+    ("pkey_mprotect", &["start", "len", "prot", "pkey"]),
+    ("poll", &["ufds", "nfds", "timeout_msecs"]),
+    ("ppoll", &["ufds", "nfds", "tsp", "sigmask", "sigsetsize"]),
+    ("prctl", &["option", "arg2", "arg3", "arg4", "arg5"]),
+    ("pread64", &["fd", "buf", "count", "pos"]),
+    ("preadv", &["fd", "vec", "vlen", "pos_l", "pos_h"]),
+    ("preadv2", &["fd", "vec", "vlen", "pos_l", "pos_h", "flags"]),
+    ("prlimit64", &["pid", "resource", "new_rlim", "old_rlim"]),
+    (
+        "process_madvise",
+        &["pidfd", "vec", "vlen", "behavior", "flags"],
+    ),
+    ("process_mrelease", &["pidfd", "flags"]),
+    (
+        "process_vm_readv",
+        &["pid", "lvec", "liovcnt", "rvec", "riovcnt", "flags"],
+    ),
+    (
+        "process_vm_writev",
+        &["pid", "lvec", "liovcnt", "rvec", "riovcnt", "flags"],
+    ),
+    ("pselect6", &["n", "inp", "outp", "exp", "tsp", "sig"]),
+    ("ptrace", &["request", "pid", "addr", "data"]),
+    ("pwrite64", &["fd", "buf", "count", "pos"]),
+    ("pwritev", &["fd", "vec", "vlen", "pos_l", "pos_h"]),
+    (
+        "pwritev2",
+        &["fd", "vec", "vlen", "pos_l", "pos_h", "flags"],
+    ),
+    ("quotactl", &["cmd", "special", "id", "addr"]),
+    ("quotactl_fd", &["fd", "cmd", "id", "addr"]),
+    ("read", &["fd", "buf", "count"]),
+    ("readahead", &["fd", "offset", "count"]),
+    ("readlink", &["path", "buf", "bufsiz"]),
+    ("readlinkat", &["dfd", "pathname", "buf", "bufsiz"]),
+    ("readv", &["fd", "vec", "vlen"]),
+    ("reboot", &["magic1", "magic2", "cmd", "arg"]),
+    (
+        "recvfrom",
+        &["fd", "ubuf", "size", "flags", "addr", "addr_len"],
+    ),
+    ("recvmmsg", &["fd", "mmsg", "vlen", "flags", "timeout"]),
+    ("recvmsg", &["fd", "msg", "flags"]),
+    (
+        "remap_file_pages",
+        &["start", "size", "prot", "pgoff", "flags"],
+    ),
+    ("removexattr", &["pathname", "name"]),
+    ("rename", &["oldname", "newname"]),
+    ("renameat", &["olddfd", "oldname", "newdfd", "newname"]),
+    (
+        "renameat2",
+        &["olddfd", "oldname", "newdfd", "newname", "flags"],
+    ),
+    (
+        "request_key",
+        &["_type", "_description", "_callout_info", "destringid"],
+    ),
+    ("restart_syscall", &[]),
+    ("rmdir", &["pathname"]),
+    ("rseq", &["rseq", "rseq_len", "flags", "sig"]),
+    ("rt_sigaction", &["sig", "act", "oact", "sigsetsize"]),
+    ("rt_sigpending", &["uset", "sigsetsize"]),
+    ("rt_sigprocmask", &["how", "nset", "oset", "sigsetsize"]),
+    ("rt_sigqueueinfo", &["pid", "sig", "uinfo"]),
+    ("rt_sigreturn", &[]),
+    ("rt_sigsuspend", &["unewset", "sigsetsize"]),
+    ("rt_sigtimedwait", &["uthese", "uinfo", "uts", "sigsetsize"]),
+    ("rt_tgsigqueueinfo", &["tgid", "pid", "sig", "uinfo"]),
+    ("sched_get_priority_max", &["policy"]),
+    ("sched_get_priority_min", &["policy"]),
+    ("sched_getaffinity", &["pid", "len", "user_mask_ptr"]),
+    ("sched_getattr", &["pid", "uattr", "usize", "flags"]),
+    ("sched_getparam", &["pid", "param"]),
+    ("sched_getscheduler", &["pid"]),
+    ("sched_rr_get_interval", &["pid", "interval"]),
+    ("sched_setaffinity", &["pid", "len", "user_mask_ptr"]),
+    ("sched_setattr", &["pid", "uattr", "flags"]),
+    ("sched_setparam", &["pid", "param"]),
+    ("sched_setscheduler", &["pid", "policy", "param"]),
+    ("sched_yield", &[]),
+    ("seccomp", &["op", "flags", "uargs"]),
+    ("select", &["n", "inp", "outp", "exp", "tvp"]),
+    ("semctl", &["semid", "semnum", "cmd", "arg"]),
+    ("semget", &["key", "nsems", "semflg"]),
+    ("semop", &["semid", "tsops", "nsops"]),
+    ("semtimedop", &["semid", "tsops", "nsops", "timeout"]),
+    ("sendfile", &["out_fd", "in_fd", "offset", "count"]),
+    ("sendmmsg", &["fd", "mmsg", "vlen", "flags"]),
+    ("sendmsg", &["fd", "msg", "flags"]),
+    (
+        "sendto",
+        &["fd", "buff", "len", "flags", "addr", "addr_len"],
+    ),
+    ("set_mempolicy", &["mode", "nmask", "maxnode"]),
+    (
+        "set_mempolicy_home_node",
+        &["start", "len", "home_node", "flags"],
+    ),
+    ("set_robust_list", &["head", "len"]),
+    ("set_thread_area", &["u_info"]),
+    ("set_tid_address", &["tidptr"]),
+    ("setdomainname", &["name", "len"]),
+    ("setfsgid", &["gid"]),
+    ("setfsuid", &["uid"]),
+    ("setgid", &["gid"]),
+    ("setgroups", &["gidsetsize", "grouplist"]),
+    ("sethostname", &["name", "len"]),
+    ("setitimer", &["which", "value", "ovalue"]),
+    ("setns", &["fd", "flags"]),
+    ("setpgid", &["pid", "pgid"]),
+    ("setpriority", &["which", "who", "niceval"]),
+    ("setregid", &["rgid", "egid"]),
+    ("setresgid", &["rgid", "egid", "sgid"]),
+    ("setresuid", &["ruid", "euid", "suid"]),
+    ("setreuid", &["ruid", "euid"]),
+    ("setrlimit", &["resource", "rlim"]),
+    ("setsid", &[]),
+    (
+        "setsockopt",
+        &["fd", "level", "optname", "optval", "optlen"],
+    ),
+    ("settimeofday", &["tv", "tz"]),
+    ("setuid", &["uid"]),
+    ("setxattr", &["pathname", "name", "value", "size", "flags"]),
+    ("shmat", &["shmid", "shmaddr", "shmflg"]),
+    ("shmctl", &["shmid", "cmd", "buf"]),
+    ("shmdt", &["shmaddr"]),
+    ("shmget", &["key", "size", "shmflg"]),
+    ("shutdown", &["fd", "how"]),
+    ("sigaltstack", &["uss", "uoss"]),
+    ("signalfd", &["ufd", "user_mask", "sizemask"]),
+    ("signalfd4", &["ufd", "user_mask", "sizemask", "flags"]),
+    ("socket", &["family", "type", "protocol"]),
+    ("socketpair", &["family", "type", "protocol", "usockvec"]),
+    (
+        "splice",
+        &["fd_in", "off_in", "fd_out", "off_out", "len", "flags"],
+    ),
+    ("stat", &["filename", "statbuf"]),
+    ("statfs", &["pathname", "buf"]),
+    ("statmount", &["req", "buf", "bufsize", "flags"]),
+    ("statx", &["dfd", "filename", "flags", "mask", "buffer"]),
+    ("swapoff", &["specialfile"]),
+    ("swapon", &["specialfile", "swap_flags"]),
+    ("symlink", &["oldname", "newname"]),
+    ("symlinkat", &["oldname", "newdfd", "newname"]),
+    ("sync", &[]),
+    ("sync_file_range", &["fd", "offset", "nbytes", "flags"]),
+    ("syncfs", &["fd"]),
+    ("sysfs", &["option", "arg1", "arg2"]),
+    ("sysinfo", &["info"]),
+    ("syslog", &["type", "buf", "len"]),
+    ("tee", &["fdin", "fdout", "len", "flags"]),
+    ("tgkill", &["tgid", "pid", "sig"]),
+    ("time", &["tloc"]),
+    (
+        "timer_create",
+        &["which_clock", "timer_event_spec", "created_timer_id"],
+    ),
+    ("timer_delete", &["timer_id"]),
+    ("timer_getoverrun", &["timer_id"]),
+    ("timer_gettime", &["timer_id", "setting"]),
+    (
+        "timer_settime",
+        &["timer_id", "flags", "new_setting", "old_setting"],
+    ),
+    ("timerfd_create", &["clockid", "flags"]),
+    ("timerfd_gettime", &["ufd", "otmr"]),
+    ("timerfd_settime", &["ufd", "flags", "utmr", "otmr"]),
+    ("times", &["tbuf"]),
+    ("tkill", &["pid", "sig"]),
+    ("truncate", &["path", "length"]),
+    ("umask", &["mask"]),
+    ("umount2", &["name", "flags"]),
+    ("uname", &["name"]),
+    ("unlink", &["pathname"]),
+    ("unlinkat", &["dfd", "pathname", "flag"]),
+    ("unshare", &["unshare_flags"]),
+    ("uselib", &["library"]),
+    ("userfaultfd", &["flags"]),
+    ("ustat", &["dev", "ubuf"]),
+    ("utime", &["filename", "times"]),
+    ("utimensat", &["dfd", "filename", "utimes", "flags"]),
+    ("utimes", &["filename", "utimes"]),
+    ("vfork", &[]),
+    ("vhangup", &[]),
+    ("vmsplice", &["fd", "uiov", "nr_segs", "flags"]),
+    ("wait4", &["upid", "stat_addr", "options", "ru"]),
+    ("waitid", &["which", "upid", "infop", "options", "ru"]),
+    ("write", &["fd", "buf", "count"]),
+    ("writev", &["fd", "vec", "vlen"]),
+];
+
+/// The names of the errno values, from `include/uapi/asm-generic/errno-base.h`
+/// and `errno.h`. 41 and 58 are unused.
+const ERRNO_NAMES: &[&str] = &[
+    "",
+    "EPERM",
+    "ENOENT",
+    "ESRCH",
+    "EINTR",
+    "EIO",
+    "ENXIO",
+    "E2BIG",
+    "ENOEXEC",
+    "EBADF",
+    "ECHILD",
+    "EAGAIN",
+    "ENOMEM",
+    "EACCES",
+    "EFAULT",
+    "ENOTBLK",
+    "EBUSY",
+    "EEXIST",
+    "EXDEV",
+    "ENODEV",
+    "ENOTDIR",
+    "EISDIR",
+    "EINVAL",
+    "ENFILE",
+    "EMFILE",
+    "ENOTTY",
+    "ETXTBSY",
+    "EFBIG",
+    "ENOSPC",
+    "ESPIPE",
+    "EROFS",
+    "EMLINK",
+    "EPIPE",
+    "EDOM",
+    "ERANGE",
+    "EDEADLK",
+    "ENAMETOOLONG",
+    "ENOLCK",
+    "ENOSYS",
+    "ENOTEMPTY",
+    "ELOOP",
+    "",
+    "ENOMSG",
+    "EIDRM",
+    "ECHRNG",
+    "EL2NSYNC",
+    "EL3HLT",
+    "EL3RST",
+    "ELNRNG",
+    "EUNATCH",
+    "ENOCSI",
+    "EL2HLT",
+    "EBADE",
+    "EBADR",
+    "EXFULL",
+    "ENOANO",
+    "EBADRQC",
+    "EBADSLT",
+    "",
+    "EBFONT",
+    "ENOSTR",
+    "ENODATA",
+    "ETIME",
+    "ENOSR",
+    "ENONET",
+    "ENOPKG",
+    "EREMOTE",
+    "ENOLINK",
+    "EADV",
+    "ESRMNT",
+    "ECOMM",
+    "EPROTO",
+    "EMULTIHOP",
+    "EDOTDOT",
+    "EBADMSG",
+    "EOVERFLOW",
+    "ENOTUNIQ",
+    "EBADFD",
+    "EREMCHG",
+    "ELIBACC",
+    "ELIBBAD",
+    "ELIBSCN",
+    "ELIBMAX",
+    "ELIBEXEC",
+    "EILSEQ",
+    "ERESTART",
+    "ESTRPIPE",
+    "EUSERS",
+    "ENOTSOCK",
+    "EDESTADDRREQ",
+    "EMSGSIZE",
+    "EPROTOTYPE",
+    "ENOPROTOOPT",
+    "EPROTONOSUPPORT",
+    "ESOCKTNOSUPPORT",
+    "EOPNOTSUPP",
+    "EPFNOSUPPORT",
+    "EAFNOSUPPORT",
+    "EADDRINUSE",
+    "EADDRNOTAVAIL",
+    "ENETDOWN",
+    "ENETUNREACH",
+    "ENETRESET",
+    "ECONNABORTED",
+    "ECONNRESET",
+    "ENOBUFS",
+    "EISCONN",
+    "ENOTCONN",
+    "ESHUTDOWN",
+    "ETOOMANYREFS",
+    "ETIMEDOUT",
+    "ECONNREFUSED",
+    "EHOSTDOWN",
+    "EHOSTUNREACH",
+    "EALREADY",
+    "EINPROGRESS",
+    "ESTALE",
+    "EUCLEAN",
+    "ENOTNAM",
+    "ENAVAIL",
+    "EISNAM",
+    "EREMOTEIO",
+    "EDQUOT",
+    "ENOMEDIUM",
+    "EMEDIUMTYPE",
+    "ECANCELED",
+    "ENOKEY",
+    "EKEYEXPIRED",
+    "EKEYREVOKED",
+    "EKEYREJECTED",
+    "EOWNERDEAD",
+    "ENOTRECOVERABLE",
+    "ERFKILL",
+    "EHWPOISON",
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    fn raw_data(nr: u64, values: &[u64]) -> Vec<u8> {
+        let mut data = vec![0; 8];
+        data.extend_from_slice(&nr.to_le_bytes());
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        // The padding after the u32 size.
+        data.extend_from_slice(&[0; 4]);
+        data
+    }
+
+    #[test]
+    fn test_syscall_tables() {
+        for table in [X86_64_SYSCALLS, I386_SYSCALLS, AARCH64_SYSCALLS] {
+            assert!(table.windows(2).all(|w| w[0].0 < w[1].0));
+        }
+        assert!(SYSCALL_ARG_NAMES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(x86_64_syscall_name(0), Some("read"));
+        assert_eq!(x86_64_syscall_name(257), Some("openat"));
+        assert_eq!(i386_syscall_name(5), Some("open"));
+        assert_eq!(i386_syscall_name(295), Some("openat"));
+        assert_eq!(aarch64_syscall_name(56), Some("openat"));
+        assert_eq!(aarch64_syscall_name(1000), None);
+        assert_eq!(
+            syscall_arg_names("openat"),
+            Some(&["dfd", "filename", "flags", "mode"][..])
+        );
+    }
+
+    #[test]
+    fn test_format_syscall_values() {
+        assert_eq!(format_syscall_arg(3), "3");
+        assert_eq!(format_syscall_arg(-100i64 as u64), "-100");
+        assert_eq!(format_syscall_arg(0xffff_ff9c), "-100");
+        assert_eq!(format_syscall_arg(0x8000_0000), "0x80000000");
+        assert_eq!(format_syscall_arg(0x7ffd_1234_5678), "0x7ffd12345678");
+        assert_eq!(errno_name(-11), Some("EAGAIN"));
+        assert_eq!(errno_name(-512), Some("ERESTARTSYS"));
+        assert_eq!(errno_name(-41), None);
+        assert_eq!(errno_name(4096), None);
+    }
+
+    #[test]
+    fn test_syscall_events() {
+        let event_names = [
+            "cycles",
+            "syscalls:sys_enter_mmap",
+            "syscalls:sys_exit_mmap",
+            "syscalls:sys_enter_mprotect",
+        ]
+        .map(ToOwned::to_owned);
+        let state = SyscallState::new(&event_names);
+        assert_eq!(
+            (0..4).map(|i| state.handles_event(i)).collect::<Vec<_>>(),
+            [false, true, true, false]
+        );
+
+        let event_names = [
+            "raw_syscalls:sys_enter",
+            "raw_syscalls:sys_exit",
+            "syscalls:sys_enter_mmap",
+            "syscalls:sys_exit_mmap",
+        ]
+        .map(ToOwned::to_owned);
+        let state = SyscallState::new(&event_names);
+        assert_eq!(
+            (0..4).map(|i| state.handles_event(i)).collect::<Vec<_>>(),
+            [true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn test_pair_syscall_samples() {
+        let mut profile = test_profile();
+        let process = profile.add_process("test", 12, t(0));
+        let thread = profile.add_thread(process, 12, t(0), true);
+        let event_names =
+            ["cycles", "raw_syscalls:sys_enter", "raw_syscalls:sys_exit"].map(ToOwned::to_owned);
+        let mut state = SyscallState::new(&event_names);
+        assert!(state
+            .parse_sample(
+                &mut profile,
+                0,
+                &raw_data(0, &[]),
+                Endianness::LittleEndian,
+                x86_64_syscall_name
+            )
+            .is_none());
+
+        let enter = raw_data(0, &[3, 0x7ffd_0000_0000, 4096, 0, 0, 0]);
+        let Some(SyscallSample::Enter(entry)) = state.parse_sample(
+            &mut profile,
+            1,
+            &enter,
+            Endianness::LittleEndian,
+            x86_64_syscall_name,
+        ) else {
+            panic!("expected a syscall entry")
+        };
+        assert_eq!(entry.args, vec![3, 0x7ffd_0000_0000, 4096]);
+        state.enter(
+            13,
+            PendingSyscall {
+                entry,
+                pid: 12,
+                thread,
+                start: t(1_000_000),
+                start_mono: 1_000_000,
+                stack: UnresolvedStackHandle::EMPTY,
+            },
+        );
+
+        let exit = raw_data(0, &[-11i64 as u64]);
+        let Some(SyscallSample::Exit { nr, ret }) = state.parse_sample(
+            &mut profile,
+            2,
+            &exit,
+            Endianness::LittleEndian,
+            x86_64_syscall_name,
+        ) else {
+            panic!("expected a syscall exit")
+        };
+        assert_eq!((nr, ret), (0, -11));
+        assert!(state.exit(&mut profile, 12, nr, ret).is_none());
+        let (syscall, marker) = state.exit(&mut profile, 13, nr, ret).unwrap();
+        assert_eq!(syscall.start_mono, 1_000_000);
+        assert_eq!(marker.args.len(), 3);
+        assert_eq!(marker.ret, Some(-11));
+        assert_eq!(marker.error, profile.handle_for_string("EAGAIN"));
+        assert_eq!(marker.name, profile.handle_for_string("read"));
+    }
+}
@@ -0,0 +1,17 @@
+//! Fixtures for the tests of the converter's modules.
+
+use fxprof_processed_profile::{Profile, ReferenceTimestamp, SamplingInterval, Timestamp};
+
+/// An empty profile which starts at the Unix epoch.
+pub fn test_profile() -> Profile {
+    Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+        SamplingInterval::from_millis(1),
+    )
+}
+
+/// A timestamp, in nanoseconds since the start of the profile.
+pub fn t(nanos: u64) -> Timestamp {
+    Timestamp::from_nanos_since_reference(nanos)
+}
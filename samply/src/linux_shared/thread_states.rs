@@ -0,0 +1,296 @@
+//! A per-thread state timeline, with `--thread-states`.
+//!
+//! Context switch records only tell us whether a thread is on or off the CPU.
+//! The `prev_state` of `sched:sched_switch` samples tells us why a thread went
+//! off-CPU: it was preempted, it's sleeping, or it's in an uninterruptible
+//! wait, e.g. for disk I/O or a kernel lock. Wakeups turn a sleeping
+//! thread into a runnable one, which is waiting for a CPU until it's switched
+//! in. `sched:sched_migrate_task` samples count how often a runnable thread was
+//! moved to a different CPU.
+//!
+//! Each state becomes an interval marker on the thread. The off-CPU samples of
+//! the thread get a label frame with the state in which the thread went off-CPU,
+//! so that the call tree can be filtered by the reason for the wait.
+
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use fxprof_processed_profile::{
+    Category, CategoryColor, FrameFlags, FrameHandle, MarkerTiming, Profile, ThreadHandle,
+    Timestamp,
+};
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+use crate::shared::process_sample_data::ThreadStateMarker;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Running,
+    /// Waiting for a CPU, after being preempted or woken up.
+    Runnable,
+    /// An interruptible sleep, e.g. waiting for a futex or in `poll`.
+    Sleeping,
+    /// An uninterruptible sleep ("D"), e.g. waiting for disk I/O or for a
+    /// kernel lock.
+    Uninterruptible,
+}
+
+impl ThreadState {
+    /// Interprets the `prev_state` field of a `sched:sched_switch` sample.
+    ///
+    /// The value is a bit from `TASK_REPORT`, e.g. 0x1 for `TASK_INTERRUPTIBLE`
+    /// ("S") and 0x2 for `TASK_UNINTERRUPTIBLE` ("D"), or `TASK_REPORT_MAX` if
+    /// the thread was preempted. Older kernels report 0 for preempted threads.
+    pub fn from_sched_switch_prev_state(prev_state: u64) -> Self {
+        const TASK_UNINTERRUPTIBLE: u64 = 0x2;
+        const TASK_REPORT_MAX: u64 = 0x100;
+        if prev_state == 0 || prev_state & TASK_REPORT_MAX != 0 {
+            ThreadState::Runnable
+        } else if prev_state & TASK_UNINTERRUPTIBLE != 0 {
+            ThreadState::Uninterruptible
+        } else {
+            // TASK_INTERRUPTIBLE, or stopped, traced, dead, or an idle kernel thread.
+            ThreadState::Sleeping
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ThreadState::Running => "Running",
+            ThreadState::Runnable => "Runnable",
+            ThreadState::Sleeping => "Sleeping",
+            ThreadState::Uninterruptible => "Uninterruptible",
+        }
+    }
+}
+
+/// The `prev_state` and `next_pid` fields of a `sched:sched_switch` sample.
+///
+/// ```
+/// # cat /sys/kernel/tracing/events/sched/sched_switch/format
+/// name: sched_switch
+/// ID: 316
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:char prev_comm[16];       offset:8;       size:16;        signed:0;
+///         field:pid_t prev_pid;   offset:24;      size:4; signed:1;
+///         field:int prev_prio;    offset:28;      size:4; signed:1;
+///         field:long prev_state;  offset:32;      size:8; signed:1;
+///         field:char next_comm[16];       offset:40;      size:16;        signed:0;
+///         field:pid_t next_pid;   offset:56;      size:4; signed:1;
+///         field:int next_prio;    offset:60;      size:4; signed:1;
+/// ```
+pub fn parse_sched_switch(data: RawData, endian: Endianness) -> Option<(u64, i32)> {
+    let data = data.as_slice();
+    let prev_state = data.get(32..40)?;
+    let next_pid = data.get(56..60)?;
+    Some(match endian {
+        Endianness::LittleEndian => (
+            LittleEndian::read_u64(prev_state),
+            LittleEndian::read_i32(next_pid),
+        ),
+        Endianness::BigEndian => (
+            BigEndian::read_u64(prev_state),
+            BigEndian::read_i32(next_pid),
+        ),
+    })
+}
+
+/// The migrated thread of a `sched:sched_migrate_task` sample, which has the
+/// same `comm` and `pid` fields as `sched:sched_waking`.
+pub fn parse_sched_migrate_task(data: RawData, endian: Endianness) -> Option<i32> {
+    let data = data.as_slice();
+    let pid = data.get(24..28)?;
+    Some(match endian {
+        Endianness::LittleEndian => LittleEndian::read_i32(pid),
+        Endianness::BigEndian => BigEndian::read_i32(pid),
+    })
+}
+
+#[derive(Debug, Clone)]
+struct CurrentState {
+    thread: ThreadHandle,
+    state: ThreadState,
+    start: Timestamp,
+    /// The state in which the thread went off-CPU, for its off-CPU samples.
+    off_cpu_state: Option<ThreadState>,
+    migrations: u32,
+}
+
+/// The current state of each thread, by tid.
+#[derive(Debug, Default)]
+pub struct ThreadStates {
+    current: HashMap<i32, CurrentState>,
+}
+
+impl ThreadStates {
+    /// Moves the thread into a new state, and adds a marker for the previous
+    /// state. The thread handle is only needed if the thread is new to us.
+    pub fn set_state(
+        &mut self,
+        profile: &mut Profile,
+        tid: i32,
+        thread: Option<ThreadHandle>,
+        state: ThreadState,
+        timestamp: Timestamp,
+    ) {
+        let current = match self.current.get_mut(&tid) {
+            Some(current) => current,
+            None => {
+                let Some(thread) = thread else { return };
+                self.current.insert(
+                    tid,
+                    CurrentState {
+                        thread,
+                        state,
+                        start: timestamp,
+                        off_cpu_state: None,
+                        migrations: 0,
+                    },
+                );
+                return;
+            }
+        };
+        if current.state == state {
+            return;
+        }
+        Self::add_marker(
+            profile,
+            current,
+            MarkerTiming::Interval(current.start, timestamp),
+        );
+        if current.state == ThreadState::Running {
+            current.off_cpu_state = Some(state);
+        }
+        current.state = state;
+        current.start = timestamp;
+        current.migrations = 0;
+    }
+
+    /// Returns the current state of the thread, if known.
+    pub fn state(&self, tid: i32) -> Option<ThreadState> {
+        self.current.get(&tid).map(|current| current.state)
+    }
+
+    /// A wakeup turns a sleeping or uninterruptible thread into a runnable one.
+    pub fn wake(&mut self, profile: &mut Profile, tid: i32, timestamp: Timestamp) {
+        if matches!(
+            self.state(tid),
+            Some(ThreadState::Sleeping | ThreadState::Uninterruptible)
+        ) {
+            self.set_state(profile, tid, None, ThreadState::Runnable, timestamp);
+        }
+    }
+
+    pub fn migrate(&mut self, tid: i32) {
+        if let Some(current) = self.current.get_mut(&tid) {
+            if current.state == ThreadState::Runnable {
+                current.migrations += 1;
+            }
+        }
+    }
+
+    /// Returns a label frame for the off-CPU samples of the thread, with the
+    /// state in which the thread went off-CPU most recently.
+    pub fn off_cpu_frame(
+        &self,
+        profile: &mut Profile,
+        tid: i32,
+        thread: ThreadHandle,
+    ) -> Option<FrameHandle> {
+        let state = self.current.get(&tid)?.off_cpu_state?;
+        let category = profile.handle_for_category(Category("Off-CPU", CategoryColor::Gray));
+        let subcategory = profile.handle_for_subcategory(category, state.name());
+        let label = profile.handle_for_string(state.name());
+        Some(profile.handle_for_frame_with_label(thread, label, subcategory, FrameFlags::empty()))
+    }
+
+    /// Adds the markers for the current states, which last until the end of
+    /// the profile.
+    pub fn finish(self, profile: &mut Profile) {
+        for current in self.current.into_values() {
+            Self::add_marker(
+                profile,
+                &current,
+                MarkerTiming::IntervalStart(current.start),
+            );
+        }
+    }
+
+    fn add_marker(profile: &mut Profile, current: &CurrentState, timing: MarkerTiming) {
+        let name = profile.handle_for_string(current.state.name());
+        profile.add_marker(
+            current.thread,
+            timing,
+            ThreadStateMarker {
+                name,
+                migrations: current.migrations,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::linux_shared::test_util::{t, test_profile};
+
+    #[test]
+    fn test_sched_switch_prev_state() {
+        use ThreadState::*;
+        assert_eq!(ThreadState::from_sched_switch_prev_state(0), Runnable);
+        assert_eq!(ThreadState::from_sched_switch_prev_state(0x100), Runnable);
+        assert_eq!(ThreadState::from_sched_switch_prev_state(0x1), Sleeping);
+        assert_eq!(
+            ThreadState::from_sched_switch_prev_state(0x2),
+            Uninterruptible
+        );
+        assert_eq!(ThreadState::from_sched_switch_prev_state(0x80), Sleeping);
+
+        let mut data = vec![0; 64];
+        data[32..40].copy_from_slice(&2u64.to_le_bytes());
+        data[56..60].copy_from_slice(&42i32.to_le_bytes());
+        assert_eq!(
+            parse_sched_switch(RawData::Single(&data), Endianness::LittleEndian),
+            Some((2, 42))
+        );
+    }
+
+    #[test]
+    fn test_thread_states() {
+        let mut profile = test_profile();
+        let process = profile.add_process("test", 1, t(0));
+        let thread = profile.add_thread(process, 1, t(0), true);
+
+        let mut states = ThreadStates::default();
+        // Threads we haven't seen yet are ignored without a thread handle.
+        states.wake(&mut profile, 1, t(0));
+        states.set_state(&mut profile, 1, None, ThreadState::Running, t(0));
+        assert_eq!(states.state(1), None);
+
+        states.set_state(&mut profile, 1, Some(thread), ThreadState::Running, t(10));
+        assert!(states.off_cpu_frame(&mut profile, 1, thread).is_none());
+        states.set_state(&mut profile, 1, None, ThreadState::Uninterruptible, t(20));
+        states.wake(&mut profile, 1, t(30));
+        assert_eq!(states.state(1), Some(ThreadState::Runnable));
+        states.migrate(1);
+        states.set_state(&mut profile, 1, None, ThreadState::Running, t(40));
+        assert_eq!(states.state(1), Some(ThreadState::Running));
+        // The off-CPU samples are labeled with the state in which the thread
+        // went off-CPU, not with the state before the switch-in.
+        assert_eq!(
+            states.current[&1].off_cpu_state,
+            Some(ThreadState::Uninterruptible)
+        );
+        assert!(states.off_cpu_frame(&mut profile, 1, thread).is_some());
+
+        // Waking a running thread doesn't change its state.
+        states.wake(&mut profile, 1, t(50));
+        assert_eq!(states.state(1), Some(ThreadState::Running));
+    }
+}
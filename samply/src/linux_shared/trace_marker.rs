@@ -0,0 +1,261 @@
+//! Markers and counters from text which is written to the ftrace `trace_marker`
+//! file, for example by Android's atrace.
+//!
+//! Text in the atrace format is turned into markers and counters:
+//!
+//!  - `B|<pid>|<name>` begins a section on the writing thread.
+//!  - `E|<pid>` ends the most recently begun section on the writing thread.
+//!  - `S|<pid>|<name>|<cookie>` and `F|<pid>|<name>|<cookie>` begin and end an
+//!    asynchronous section, which can end on a different thread.
+//!  - `C|<pid>|<name>|<value>` sets the value of a counter of the process.
+//!  - `I|<pid>|<name>` is an instant event.
+//!
+//! Any other text becomes an instant marker with the text as its name.
+
+use std::collections::HashMap;
+
+use fxprof_processed_profile::{
+    CounterHandle, MarkerTiming, ProcessHandle, Profile, ThreadHandle, Timestamp,
+};
+
+use crate::shared::process_sample_data::FtraceMarker;
+
+/// The parsed text of a single `trace_marker` write.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceMarkerText<'a> {
+    Begin { name: &'a str },
+    End,
+    AsyncBegin { name: &'a str, cookie: i64 },
+    AsyncEnd { name: &'a str, cookie: i64 },
+    Counter { name: &'a str, value: f64 },
+    Instant { name: &'a str },
+}
+
+impl<'a> TraceMarkerText<'a> {
+    /// Parses the text. Returns the pid from the atrace syntax, if present.
+    pub fn parse(text: &'a str) -> (Option<i32>, Self) {
+        let text = text.trim_end_matches(['\n', '\0']);
+        Self::parse_atrace(text).unwrap_or((None, TraceMarkerText::Instant { name: text }))
+    }
+
+    fn parse_atrace(text: &'a str) -> Option<(Option<i32>, Self)> {
+        let (kind, rest) = text.split_once('|').unwrap_or((text, ""));
+        let (pid, args) = rest.split_once('|').unwrap_or((rest, ""));
+        let pid = pid.parse::<i32>().ok();
+        let parsed = match kind {
+            // Section names may contain '|', so they extend to the end of the text.
+            "B" if !args.is_empty() => TraceMarkerText::Begin { name: args },
+            "E" => TraceMarkerText::End,
+            "S" | "F" => {
+                let (name, cookie) = args.rsplit_once('|')?;
+                let cookie = cookie.trim().parse().ok()?;
+                if kind == "S" {
+                    TraceMarkerText::AsyncBegin { name, cookie }
+                } else {
+                    TraceMarkerText::AsyncEnd { name, cookie }
+                }
+            }
+            "C" => {
+                let (name, value) = args.split_once('|')?;
+                // Newer atrace versions append a category after the value.
+                let value = value.split('|').next()?.trim().parse().ok()?;
+                TraceMarkerText::Counter { name, value }
+            }
+            "I" if !args.is_empty() => TraceMarkerText::Instant { name: args },
+            _ => return None,
+        };
+        Some((pid, parsed))
+    }
+}
+
+/// The sections and counters from `trace_marker` writes, across all processes.
+#[derive(Debug, Default)]
+pub struct TraceMarkerState {
+    /// The sections which are currently open on each thread, innermost last.
+    open_sections: HashMap<i32, Vec<(ThreadHandle, Timestamp, String)>>,
+    /// The asynchronous sections which are currently open, by pid, name and cookie.
+    open_async_sections: HashMap<(i32, String, i64), (ThreadHandle, Timestamp)>,
+    /// The counter for each pid and counter name, and its current value.
+    counters: HashMap<(i32, String), (CounterHandle, f64)>,
+}
+
+impl TraceMarkerState {
+    #[allow(clippy::too_many_arguments)]
+    pub fn handle(
+        &mut self,
+        profile: &mut Profile,
+        process: ProcessHandle,
+        thread: ThreadHandle,
+        pid: i32,
+        tid: i32,
+        timestamp: Timestamp,
+        text: TraceMarkerText,
+    ) {
+        match text {
+            TraceMarkerText::Begin { name } => {
+                self.open_sections.entry(tid).or_default().push((
+                    thread,
+                    timestamp,
+                    name.to_owned(),
+                ));
+            }
+            TraceMarkerText::End => {
+                let Some((thread, start, name)) =
+                    self.open_sections.get_mut(&tid).and_then(|s| s.pop())
+                else {
+                    return;
+                };
+                let name = profile.handle_for_string(&name);
+                profile.add_marker(
+                    thread,
+                    MarkerTiming::Interval(start, timestamp),
+                    FtraceMarker(name),
+                );
+            }
+            TraceMarkerText::AsyncBegin { name, cookie } => {
+                self.open_async_sections
+                    .insert((pid, name.to_owned(), cookie), (thread, timestamp));
+            }
+            TraceMarkerText::AsyncEnd { name, cookie } => {
+                let Some((thread, start)) =
+                    self.open_async_sections
+                        .remove(&(pid, name.to_owned(), cookie))
+                else {
+                    return;
+                };
+                let name = profile.handle_for_string(name);
+                profile.add_marker(
+                    thread,
+                    MarkerTiming::Interval(start, timestamp),
+                    FtraceMarker(name),
+                );
+            }
+            TraceMarkerText::Counter { name, value } => {
+                let (counter, prev_value) = self
+                    .counters
+                    .entry((pid, name.to_owned()))
+                    .or_insert_with(|| {
+                        let counter = profile.add_counter(
+                            process,
+                            name,
+                            "Trace marker",
+                            "A counter which was written to the ftrace trace_marker file",
+                        );
+                        (counter, 0.0)
+                    });
+                profile.add_counter_sample(*counter, timestamp, value - *prev_value, 1);
+                *prev_value = value;
+            }
+            TraceMarkerText::Instant { name } => {
+                let name = profile.handle_for_string(name);
+                profile.add_marker(thread, MarkerTiming::Instant(timestamp), FtraceMarker(name));
+            }
+        }
+    }
+
+    /// Adds the sections which were never ended, as markers which last until
+    /// the end of the profile.
+    pub fn finish(self, profile: &mut Profile) {
+        for (thread, start, name) in self.open_sections.into_values().flatten() {
+            let name = profile.handle_for_string(&name);
+            profile.add_marker(
+                thread,
+                MarkerTiming::IntervalStart(start),
+                FtraceMarker(name),
+            );
+        }
+        for ((_pid, name, _cookie), (thread, start)) in self.open_async_sections {
+            let name = profile.handle_for_string(&name);
+            profile.add_marker(
+                thread,
+                MarkerTiming::IntervalStart(start),
+                FtraceMarker(name),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_trace_marker_text() {
+        assert_eq!(
+            TraceMarkerText::parse("B|1234|Load page\n"),
+            (Some(1234), TraceMarkerText::Begin { name: "Load page" })
+        );
+        assert_eq!(
+            TraceMarkerText::parse("B|1234|Draw|frame 7"),
+            (
+                Some(1234),
+                TraceMarkerText::Begin {
+                    name: "Draw|frame 7"
+                }
+            )
+        );
+        assert_eq!(
+            TraceMarkerText::parse("E|1234"),
+            (Some(1234), TraceMarkerText::End)
+        );
+        assert_eq!(TraceMarkerText::parse("E"), (None, TraceMarkerText::End));
+        assert_eq!(
+            TraceMarkerText::parse("S|1234|Fetch|42"),
+            (
+                Some(1234),
+                TraceMarkerText::AsyncBegin {
+                    name: "Fetch",
+                    cookie: 42
+                }
+            )
+        );
+        assert_eq!(
+            TraceMarkerText::parse("F|1234|Fetch|42"),
+            (
+                Some(1234),
+                TraceMarkerText::AsyncEnd {
+                    name: "Fetch",
+                    cookie: 42
+                }
+            )
+        );
+        assert_eq!(
+            TraceMarkerText::parse("C|1234|queue length|17"),
+            (
+                Some(1234),
+                TraceMarkerText::Counter {
+                    name: "queue length",
+                    value: 17.0
+                }
+            )
+        );
+        assert_eq!(
+            TraceMarkerText::parse("C|1234|queue length|-2.5|category"),
+            (
+                Some(1234),
+                TraceMarkerText::Counter {
+                    name: "queue length",
+                    value: -2.5
+                }
+            )
+        );
+        assert_eq!(
+            TraceMarkerText::parse("C|1234|queue length|lots"),
+            (
+                None,
+                TraceMarkerText::Instant {
+                    name: "C|1234|queue length|lots"
+                }
+            )
+        );
+        assert_eq!(
+            TraceMarkerText::parse("hello world\n"),
+            (
+                None,
+                TraceMarkerText::Instant {
+                    name: "hello world"
+                }
+            )
+        );
+    }
+}
@@ -0,0 +1,734 @@
+//! Decoding the raw data of tracepoint samples with the tracepoint's format
+//! description, so that tracepoints without custom handling get markers with
+//! their fields.
+//!
+//! The format of each tracepoint is in `events/<system>/<name>/format` in
+//! tracefs, and perf.data files from `perf record` contain the formats of the
+//! recorded tracepoints in the `HEADER_TRACING_DATA` feature section. Each
+//! tracepoint gets its own marker type, with a field for each of its fields.
+
+use std::collections::HashMap;
+
+use fxprof_processed_profile::{
+    Category, CategoryColor, CategoryHandle, Marker, MarkerFieldFlags, MarkerFieldFormat,
+    MarkerLocations, MarkerTypeHandle, Profile, RuntimeSchemaMarkerField,
+    RuntimeSchemaMarkerSchema, StringHandle,
+};
+use linux_perf_data::Endianness;
+
+/// The magic bytes at the start of the tracing data.
+const TRACING_DATA_MAGIC: &[u8] = b"\x17\x08\x44tracing";
+
+/// The parsed `format` file of a tracepoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracepointFormat {
+    /// The name of the tracepoint, without the system, e.g. `sched_process_exec`.
+    pub name: String,
+    /// The fields of the tracepoint, without the `common_*` fields which every
+    /// tracepoint has.
+    pub fields: Vec<TracepointField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracepointField {
+    pub name: String,
+    pub kind: FieldKind,
+    pub offset: usize,
+    pub size: usize,
+    pub signed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// An integer of 1, 2, 4 or 8 bytes.
+    Integer,
+    /// A pointer, which is shown in hex.
+    Pointer,
+    /// A fixed-size string, e.g. `char comm[16]`.
+    CharArray,
+    /// A fixed-size array of integers, e.g. `unsigned long args[6]`.
+    Array {
+        count: usize,
+    },
+    /// A field whose layout we don't know, e.g. an array whose length is a
+    /// macro, which is shown as hex bytes.
+    Bytes,
+    /// A variable-size field, e.g. `__data_loc char[] name`. The field itself is
+    /// a `u32` with the offset of the data in the low 16 bits and its length in
+    /// the high 16 bits. For `__rel_loc` fields, the offset is relative to the
+    /// end of the field.
+    DynamicString {
+        relative: bool,
+    },
+    DynamicArray {
+        relative: bool,
+    },
+}
+
+/// The decoded value of a field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Number(f64),
+    String(String),
+}
+
+impl TracepointFormat {
+    /// Parses the contents of a tracepoint's `format` file.
+    pub fn parse(format: &str) -> Option<Self> {
+        let mut name = None;
+        let mut fields = Vec::new();
+        for line in format.lines() {
+            let line = line.trim();
+            if let Some(n) = line.strip_prefix("name:") {
+                name = Some(n.trim().to_owned());
+            } else if line.starts_with("field:") {
+                if let Some(field) = TracepointField::parse(line) {
+                    if !field.name.starts_with("common_") {
+                        fields.push(field);
+                    }
+                }
+            }
+        }
+        Some(Self {
+            name: name?,
+            fields,
+        })
+    }
+
+    /// The field with this name, if the tracepoint has it.
+    pub fn field(&self, name: &str) -> Option<&TracepointField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+impl TracepointField {
+    /// Parses a line like
+    /// `field:char prev_comm[16]; offset:8; size:16; signed:0;`.
+    fn parse(line: &str) -> Option<Self> {
+        let mut declaration = None;
+        let mut offset = None;
+        let mut size = None;
+        let mut signed = false;
+        for part in line.split(';').map(str::trim) {
+            if let Some(d) = part.strip_prefix("field:") {
+                declaration = Some(d.trim());
+            } else if let Some(o) = part.strip_prefix("offset:") {
+                offset = o.parse().ok();
+            } else if let Some(s) = part.strip_prefix("size:") {
+                size = s.parse().ok();
+            } else if let Some(s) = part.strip_prefix("signed:") {
+                signed = s == "1";
+            }
+        }
+        let declaration = declaration?;
+        let size: usize = size?;
+
+        // The name is the last word of the declaration, without the array brackets.
+        let (type_name, name) = declaration.rsplit_once(' ')?;
+        let (name, array_len) = match name.split_once('[') {
+            Some((name, len)) => (name, Some(len.trim_end_matches(']'))),
+            None => (name, None),
+        };
+        let kind = if let Some(type_name) = type_name.strip_prefix("__data_loc ") {
+            dynamic_kind(type_name, false)
+        } else if let Some(type_name) = type_name.strip_prefix("__rel_loc ") {
+            dynamic_kind(type_name, true)
+        } else if let Some(array_len) = array_len {
+            // Only `char` arrays are strings. `unsigned char` and `u8` arrays
+            // are usually binary data, e.g. addresses.
+            if is_char_type(type_name) {
+                FieldKind::CharArray
+            } else {
+                // The length can be a macro, e.g. `TASK_COMM_LEN`. Then we
+                // don't know the size of the elements.
+                match array_len.parse::<usize>() {
+                    Ok(count) if count > 0 && size % count == 0 && is_int_size(size / count) => {
+                        FieldKind::Array { count }
+                    }
+                    _ => FieldKind::Bytes,
+                }
+            }
+        } else if type_name.ends_with('*') || name.starts_with('*') {
+            FieldKind::Pointer
+        } else if is_int_size(size) {
+            FieldKind::Integer
+        } else {
+            FieldKind::Bytes
+        };
+        Some(Self {
+            name: name.trim_start_matches('*').to_owned(),
+            kind,
+            offset: offset?,
+            size,
+            signed,
+        })
+    }
+
+    /// Decodes the value of an integer field from the raw data of a sample.
+    pub fn decode_integer(&self, raw: &[u8], endian: Endianness) -> Option<i64> {
+        let data = raw.get(self.offset..self.offset.checked_add(self.size)?)?;
+        read_int(data, self.signed, endian)
+    }
+
+    /// Decodes the value of the field from the raw data of a sample.
+    pub fn decode(&self, raw: &[u8], endian: Endianness) -> Option<FieldValue> {
+        let data = raw.get(self.offset..self.offset.checked_add(self.size)?)?;
+        let value = match self.kind {
+            FieldKind::Integer => {
+                let value = read_int(data, self.signed, endian)?;
+                FieldValue::Number(value as f64)
+            }
+            FieldKind::Pointer => {
+                let value = read_int(data, false, endian)?;
+                FieldValue::String(format!("{:#x}", value as u64))
+            }
+            // A size of zero is a flexible array at the end of the data, e.g.
+            // `char buf[]` of `ftrace:print`.
+            FieldKind::CharArray if self.size == 0 => {
+                FieldValue::String(c_string(raw.get(self.offset..)?))
+            }
+            FieldKind::CharArray => FieldValue::String(c_string(data)),
+            FieldKind::Array { count } => {
+                let elements: Option<Vec<String>> = data
+                    .chunks_exact(self.size / count)
+                    .map(|element| {
+                        let value = read_int(element, self.signed, endian)?;
+                        Some(format_integer(value))
+                    })
+                    .collect();
+                FieldValue::String(format!("[{}]", elements?.join(", ")))
+            }
+            FieldKind::Bytes => FieldValue::String(hex_bytes(data)),
+            FieldKind::DynamicString { relative } | FieldKind::DynamicArray { relative } => {
+                let location = read_int(data, false, endian)? as u32;
+                let mut start = (location & 0xffff) as usize;
+                if relative {
+                    start += self.offset + self.size;
+                }
+                let len = (location >> 16) as usize;
+                let data = raw.get(start..start.checked_add(len)?)?;
+                match self.kind {
+                    FieldKind::DynamicString { .. } => FieldValue::String(c_string(data)),
+                    _ => FieldValue::String(hex_bytes(data)),
+                }
+            }
+        };
+        Some(value)
+    }
+
+    fn is_number(&self) -> bool {
+        self.kind == FieldKind::Integer
+    }
+}
+
+/// Whether the type is `char`, as opposed to `unsigned char` or `u8`.
+fn is_char_type(type_name: &str) -> bool {
+    matches!(
+        type_name.trim_start_matches("const "),
+        "char" | "signed char"
+    )
+}
+
+/// Whether an integer of this size can be read with [`read_int`].
+fn is_int_size(size: usize) -> bool {
+    matches!(size, 1 | 2 | 4 | 8)
+}
+
+fn dynamic_kind(type_name: &str, relative: bool) -> FieldKind {
+    if is_char_type(type_name.trim_end_matches("[]")) {
+        FieldKind::DynamicString { relative }
+    } else {
+        FieldKind::DynamicArray { relative }
+    }
+}
+
+fn read_int(data: &[u8], signed: bool, endian: Endianness) -> Option<i64> {
+    let mut bytes = [0; 8];
+    let len = data.len();
+    if !is_int_size(len) {
+        return None;
+    }
+    let value = match endian {
+        Endianness::LittleEndian => {
+            bytes[..len].copy_from_slice(data);
+            u64::from_le_bytes(bytes)
+        }
+        Endianness::BigEndian => {
+            bytes[8 - len..].copy_from_slice(data);
+            u64::from_be_bytes(bytes)
+        }
+    };
+    let shift = 64 - 8 * len as u32;
+    Some(if signed {
+        ((value << shift) as i64) >> shift
+    } else {
+        value as i64
+    })
+}
+
+fn format_integer(value: i64) -> String {
+    if (-4096..=0xffff).contains(&value) {
+        value.to_string()
+    } else {
+        format!("{:#x}", value as u64)
+    }
+}
+
+fn hex_bytes(data: &[u8]) -> String {
+    data.iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn c_string(data: &[u8]) -> String {
+    let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..len]).into_owned()
+}
+
+/// Parses the tracepoint formats from the tracing data in the
+/// `HEADER_TRACING_DATA` section of a perf.data file, as written by perf's
+/// `tracing_data_get`. Returns the formats by tracepoint name, e.g.
+/// `sched:sched_process_exec`.
+pub fn parse_tracing_data(data: &[u8]) -> HashMap<String, TracepointFormat> {
+    let mut formats = HashMap::new();
+    let _ = read_tracing_data_formats(data, &mut formats);
+    formats
+}
+
+/// A cursor over the tracing data. The integers in the tracing data have the
+/// endianness of the recording machine, which is stored in the header.
+struct TracingDataReader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> TracingDataReader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (bytes, rest) = (self.data.get(..len)?, self.data.get(len..)?);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn c_string(&mut self) -> Option<&'a [u8]> {
+        let len = self.data.iter().position(|&b| b == 0)?;
+        let s = self.bytes(len)?;
+        self.bytes(1)?;
+        Some(s)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let bytes = self.bytes(8)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    /// Reads a u64 size followed by that many bytes.
+    fn sized(&mut self) -> Option<&'a [u8]> {
+        let size = self.u64()?;
+        self.bytes(usize::try_from(size).ok()?)
+    }
+}
+
+fn read_tracing_data_formats(
+    data: &[u8],
+    formats: &mut HashMap<String, TracepointFormat>,
+) -> Option<()> {
+    let mut reader = TracingDataReader {
+        data: data.strip_prefix(TRACING_DATA_MAGIC)?,
+        big_endian: false,
+    };
+    let _version = reader.c_string()?;
+    reader.big_endian = reader.bytes(1)?[0] != 0;
+    let _long_size = reader.bytes(1)?;
+    let _page_size = reader.u32()?;
+
+    // The header_page and header_event files, each with their name.
+    for name in [&b"header_page"[..], b"header_event"] {
+        if reader.c_string()? != name {
+            return None;
+        }
+        reader.sized()?;
+    }
+
+    // The formats of the ftrace events, which aren't recorded by perf.
+    let ftrace_count = reader.u32()?;
+    for _ in 0..ftrace_count {
+        reader.sized()?;
+    }
+
+    let system_count = reader.u32()?;
+    for _ in 0..system_count {
+        let system = String::from_utf8_lossy(reader.c_string()?).into_owned();
+        let event_count = reader.u32()?;
+        for _ in 0..event_count {
+            let format = String::from_utf8_lossy(reader.sized()?);
+            if let Some(format) = TracepointFormat::parse(&format) {
+                formats.insert(format!("{system}:{}", format.name), format);
+            }
+        }
+    }
+    Some(())
+}
+
+/// Creates the tracing data for the `HEADER_TRACING_DATA` section of a
+/// perf.data file, from the tracefs `format` files of the recorded tracepoints,
+/// given as (tracepoint name, format file contents).
+pub fn write_tracing_data(
+    page_size: u32,
+    header_page: &[u8],
+    header_event: &[u8],
+    formats: &[(String, String)],
+) -> Vec<u8> {
+    let big_endian = cfg!(target_endian = "big");
+    let u32_bytes = |value: u32| {
+        if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    };
+    let u64_bytes = |value: u64| {
+        if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    };
+
+    let mut data = TRACING_DATA_MAGIC.to_vec();
+    data.extend_from_slice(b"0.6\0");
+    data.push(u8::from(big_endian));
+    data.push(std::mem::size_of::<usize>() as u8);
+    data.extend_from_slice(&u32_bytes(page_size));
+    for (name, contents) in [("header_page", header_page), ("header_event", header_event)] {
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        data.extend_from_slice(&u64_bytes(contents.len() as u64));
+        data.extend_from_slice(contents);
+    }
+
+    // No ftrace formats.
+    data.extend_from_slice(&u32_bytes(0));
+
+    let mut systems: Vec<(&str, Vec<&str>)> = Vec::new();
+    for (name, format) in formats {
+        let Some((system, _)) = name.split_once(':') else {
+            continue;
+        };
+        match systems.iter_mut().find(|(s, _)| *s == system) {
+            Some((_, system_formats)) => system_formats.push(format),
+            None => systems.push((system, vec![format])),
+        }
+    }
+    data.extend_from_slice(&u32_bytes(systems.len() as u32));
+    for (system, system_formats) in systems {
+        data.extend_from_slice(system.as_bytes());
+        data.push(0);
+        data.extend_from_slice(&u32_bytes(system_formats.len() as u32));
+        for format in system_formats {
+            data.extend_from_slice(&u64_bytes(format.len() as u64));
+            data.extend_from_slice(format.as_bytes());
+        }
+    }
+
+    // No kallsyms, no printk formats, and no saved cmdlines.
+    data.extend_from_slice(&u32_bytes(0));
+    data.extend_from_slice(&u32_bytes(0));
+    data.extend_from_slice(&u64_bytes(0));
+    data
+}
+
+/// The marker types for the tracepoints whose format is known, by attr index.
+#[derive(Debug, Default)]
+pub struct TracepointMarkers {
+    formats: HashMap<usize, (TracepointFormat, Option<MarkerTypeHandle>)>,
+    category: Option<CategoryHandle>,
+}
+
+impl TracepointMarkers {
+    /// Uses the formats for the events whose names are in `formats`.
+    pub fn set_formats(
+        &mut self,
+        event_names: &[String],
+        formats: &HashMap<String, TracepointFormat>,
+    ) {
+        for (attr_index, name) in event_names.iter().enumerate() {
+            if let Some(format) = formats.get(name) {
+                self.formats.insert(attr_index, (format.clone(), None));
+            }
+        }
+    }
+
+    /// Decodes a single field of a sample, if the format of the tracepoint is
+    /// known. This is used for tracepoints whose layout differs between kernel
+    /// versions.
+    pub fn field_value(
+        &self,
+        attr_index: usize,
+        field_name: &str,
+        raw: &[u8],
+        endian: Endianness,
+    ) -> Option<FieldValue> {
+        self.format(attr_index)?
+            .field(field_name)?
+            .decode(raw, endian)
+    }
+
+    /// The format of the tracepoint with this attr index, if it's known.
+    pub fn format(&self, attr_index: usize) -> Option<&TracepointFormat> {
+        self.formats.get(&attr_index).map(|(format, _)| format)
+    }
+
+    /// Decodes a sample into a marker with the tracepoint's fields, if the
+    /// format of the tracepoint is known. Samples whose raw data doesn't have
+    /// all the fields, e.g. because it's truncated, don't become markers.
+    pub fn marker(
+        &mut self,
+        profile: &mut Profile,
+        attr_index: usize,
+        event_name: &str,
+        raw: &[u8],
+        endian: Endianness,
+    ) -> Option<TracepointMarker> {
+        let (format, marker_type) = self.formats.get_mut(&attr_index)?;
+        let marker_type = *marker_type.get_or_insert_with(|| {
+            let category = *self.category.get_or_insert_with(|| {
+                profile.handle_for_category(Category("Tracepoints", CategoryColor::Gray))
+            });
+            register_marker_type(profile, category, event_name, format)
+        });
+        let fields = format
+            .fields
+            .iter()
+            .map(|field| {
+                Some(match field.decode(raw, endian)? {
+                    FieldValue::Number(value) => MarkerFieldValue::Number(value),
+                    FieldValue::String(value) => {
+                        MarkerFieldValue::String(profile.handle_for_string(&value))
+                    }
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(TracepointMarker {
+            marker_type,
+            name: profile.handle_for_string(event_name),
+            fields,
+        })
+    }
+}
+
+fn register_marker_type(
+    profile: &mut Profile,
+    category: CategoryHandle,
+    event_name: &str,
+    format: &TracepointFormat,
+) -> MarkerTypeHandle {
+    let fields = format
+        .fields
+        .iter()
+        .map(|field| RuntimeSchemaMarkerField {
+            key: field.name.clone(),
+            label: field.name.clone(),
+            format: if field.is_number() {
+                MarkerFieldFormat::Integer
+            } else {
+                MarkerFieldFormat::String
+            },
+            flags: MarkerFieldFlags::SEARCHABLE,
+        })
+        .collect();
+    let label = format
+        .fields
+        .iter()
+        .map(|field| format!("{0}={{marker.data.{0}}}", field.name))
+        .collect::<Vec<_>>()
+        .join(" ");
+    profile.register_marker_type(RuntimeSchemaMarkerSchema {
+        type_name: format!("Tracepoint {event_name}"),
+        category,
+        description: Some(format!(
+            "Emitted for each sample of the {event_name} tracepoint, with its fields."
+        )),
+        locations: MarkerLocations::MARKER_CHART | MarkerLocations::MARKER_TABLE,
+        chart_label: Some(label.clone()),
+        tooltip_label: Some(format!("{{marker.name}} {label}")),
+        table_label: Some(format!("{{marker.name}} {label}")),
+        fields,
+        graphs: Vec::new(),
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum MarkerFieldValue {
+    Number(f64),
+    String(StringHandle),
+}
+
+/// An instant marker for a tracepoint sample, with the decoded fields.
+#[derive(Debug, Clone)]
+pub struct TracepointMarker {
+    marker_type: MarkerTypeHandle,
+    name: StringHandle,
+    fields: Vec<MarkerFieldValue>,
+}
+
+impl Marker for TracepointMarker {
+    fn marker_type(&self, _profile: &mut Profile) -> MarkerTypeHandle {
+        self.marker_type
+    }
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn string_field_value(&self, field_index: u32) -> StringHandle {
+        match self.fields[field_index as usize] {
+            MarkerFieldValue::String(value) => value,
+            MarkerFieldValue::Number(_) => unreachable!(),
+        }
+    }
+
+    fn number_field_value(&self, field_index: u32) -> f64 {
+        match self.fields[field_index as usize] {
+            MarkerFieldValue::Number(value) => value,
+            MarkerFieldValue::String(_) => unreachable!(),
+        }
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCHED_PROCESS_EXEC: &str = "name: sched_process_exec
+ID: 365
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:__data_loc char[] filename;\toffset:8;\tsize:4;\tsigned:0;
+\tfield:pid_t pid;\toffset:12;\tsize:4;\tsigned:1;
+\tfield:pid_t old_pid;\toffset:16;\tsize:4;\tsigned:1;
+
+print fmt: \"filename=%s pid=%d old_pid=%d\", __get_str(filename), REC->pid, REC->old_pid
+";
+
+    #[test]
+    fn test_parse_format() {
+        let format = TracepointFormat::parse(SCHED_PROCESS_EXEC).unwrap();
+        assert_eq!(format.name, "sched_process_exec");
+        let names: Vec<_> = format.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["filename", "pid", "old_pid"]);
+        assert_eq!(
+            format.fields[0].kind,
+            FieldKind::DynamicString { relative: false }
+        );
+        assert_eq!(format.fields[1].kind, FieldKind::Integer);
+        assert!(format.fields[1].signed);
+
+        let field = |line| TracepointField::parse(line).unwrap();
+        let comm = field("field:char prev_comm[16];\toffset:8;\tsize:16;\tsigned:0;");
+        assert_eq!(
+            (comm.name.as_str(), comm.kind),
+            ("prev_comm", FieldKind::CharArray)
+        );
+        let args = field("field:unsigned long args[6];\toffset:16;\tsize:48;\tsigned:0;");
+        assert_eq!(args.kind, FieldKind::Array { count: 6 });
+        let saddr = field("field:__u8 saddr[4];\toffset:8;\tsize:4;\tsigned:0;");
+        assert_eq!(saddr.kind, FieldKind::Array { count: 4 });
+        let data = field("field:unsigned char data[16];\toffset:8;\tsize:16;\tsigned:0;");
+        assert_eq!(data.kind, FieldKind::Array { count: 16 });
+        let comm = field("field:char comm[TASK_COMM_LEN];\toffset:8;\tsize:16;\tsigned:0;");
+        assert_eq!(comm.kind, FieldKind::CharArray);
+        let ids = field("field:u32 ids[NR_IDS];\toffset:8;\tsize:12;\tsigned:0;");
+        assert_eq!(ids.kind, FieldKind::Bytes);
+        let ptr = field("field:const void * ptr;\toffset:8;\tsize:8;\tsigned:0;");
+        assert_eq!((ptr.name.as_str(), ptr.kind), ("ptr", FieldKind::Pointer));
+    }
+
+    #[test]
+    fn test_decode_fields() {
+        let format = TracepointFormat::parse(SCHED_PROCESS_EXEC).unwrap();
+        let mut raw = vec![0; 20];
+        // filename at offset 20 with length 10, including the NUL terminator.
+        raw[8..12].copy_from_slice(&((10u32 << 16) | 20).to_le_bytes());
+        raw[12..16].copy_from_slice(&1234i32.to_le_bytes());
+        raw[16..20].copy_from_slice(&(-1i32).to_le_bytes());
+        raw.extend_from_slice(b"/bin/true\0");
+        let endian = Endianness::LittleEndian;
+        let values: Vec<_> = format
+            .fields
+            .iter()
+            .map(|field| field.decode(&raw, endian).unwrap())
+            .collect();
+        assert_eq!(
+            values,
+            [
+                FieldValue::String("/bin/true".into()),
+                FieldValue::Number(1234.0),
+                FieldValue::Number(-1.0),
+            ]
+        );
+        // Truncated raw data.
+        assert_eq!(format.fields[2].decode(&raw[..18], endian), None);
+    }
+
+    #[test]
+    fn test_decode_flexible_char_array() {
+        let format = TracepointFormat::parse(
+            "name: print
+ID: 5
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:unsigned long ip;\toffset:8;\tsize:8;\tsigned:0;
+\tfield:char buf[];\toffset:16;\tsize:0;\tsigned:0;
+",
+        )
+        .unwrap();
+        let mut raw = vec![0; 16];
+        raw.extend_from_slice(b"B|1|hello\n\0\0\0");
+        assert_eq!(
+            format.fields[1].decode(&raw, Endianness::LittleEndian),
+            Some(FieldValue::String("B|1|hello\n".into()))
+        );
+    }
+
+    #[test]
+    fn test_tracing_data_roundtrip() {
+        let formats = [(
+            "sched:sched_process_exec".to_owned(),
+            SCHED_PROCESS_EXEC.to_owned(),
+        )];
+        let data = write_tracing_data(4096, b"page", b"event", &formats);
+        let parsed = parse_tracing_data(&data);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(
+            parsed["sched:sched_process_exec"],
+            TracepointFormat::parse(SCHED_PROCESS_EXEC).unwrap()
+        );
+        assert!(parse_tracing_data(b"garbage").is_empty());
+    }
+}
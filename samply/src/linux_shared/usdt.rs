@@ -0,0 +1,252 @@
+//! Markers for USDT probes, from the uprobes of `--usdt` or from `perf probe`
+//! events on SDT probes, which are named `sdt_<provider>:<name>`.
+//!
+//! Each hit becomes an instant marker with the stack. When recording with
+//! `--usdt`, the arguments of the probe are read from the sampled registers
+//! and the top of the sampled user stack, as described by the probe's argument
+//! spec, and are shown as marker fields. Each probe gets its own marker type,
+//! because the number of arguments differs between probes.
+
+use std::collections::HashMap;
+
+use fxprof_processed_profile::{
+    Category, CategoryColor, CategoryHandle, Marker, MarkerFieldFlags, MarkerFieldFormat,
+    MarkerLocations, MarkerTypeHandle, Profile, RuntimeSchemaMarkerField,
+    RuntimeSchemaMarkerSchema, StringHandle,
+};
+use linux_perf_data::linux_perf_event_reader::{RawData, Regs};
+use wholesym::samply_symbols::{SdtArgument, SdtArgumentLocation};
+
+use super::convert_regs::ConvertRegs;
+
+/// Whether an event is the uprobe of a USDT probe.
+pub fn is_usdt_event_name(name: &str) -> bool {
+    name.split_once(':')
+        .is_some_and(|(group, _)| group.starts_with("sdt_"))
+}
+
+/// The argument specs of the USDT probes, and their marker types.
+#[derive(Debug, Default)]
+pub struct UsdtState {
+    /// The arguments of each probe site, by attr index. Each site of a probe
+    /// is a separate event, because the arguments can live in different
+    /// registers at each site.
+    arguments: HashMap<usize, Vec<SdtArgument>>,
+    /// The marker type and the argument count of each probe, by event name.
+    marker_types: HashMap<String, (MarkerTypeHandle, usize)>,
+    category: Option<CategoryHandle>,
+}
+
+impl UsdtState {
+    pub fn set_arguments(&mut self, attr_index: usize, arguments: Vec<SdtArgument>) {
+        self.arguments.insert(attr_index, arguments);
+    }
+
+    /// Creates the marker for a hit of the probe with the event name `event_name`,
+    /// decoding its arguments if their spec is known.
+    pub fn marker<C: ConvertRegs>(
+        &mut self,
+        profile: &mut Profile,
+        attr_index: usize,
+        event_name: &str,
+        regs: Option<&Regs>,
+        user_stack: Option<(RawData, u64)>,
+    ) -> UsdtMarker {
+        let stack = user_stack.map(|(data, size)| {
+            let data = data.as_slice();
+            let size = (size as usize).min(data.len());
+            data[..size].to_vec()
+        });
+        // The schema of a probe is created for its first hit. Other sites of
+        // the same probe should have the same number of arguments.
+        let site_arg_count = self.arguments.get(&attr_index).map_or(0, Vec::len);
+        let (marker_type, arg_count) = self.marker_type(profile, event_name, site_arg_count);
+        let arguments = self
+            .arguments
+            .get(&attr_index)
+            .map_or(&[][..], Vec::as_slice);
+        let args = (0..arg_count)
+            .map(|i| {
+                let value = arguments
+                    .get(i)
+                    .and_then(|argument| read_argument::<C>(argument, regs, stack.as_deref()));
+                let value = value.map_or_else(|| "?".to_owned(), format_argument);
+                profile.handle_for_string(&value)
+            })
+            .collect();
+        let probe = event_name.strip_prefix("sdt_").unwrap_or(event_name);
+        UsdtMarker {
+            marker_type,
+            name: profile.handle_for_string(probe),
+            args,
+        }
+    }
+
+    fn marker_type(
+        &mut self,
+        profile: &mut Profile,
+        event_name: &str,
+        arg_count: usize,
+    ) -> (MarkerTypeHandle, usize) {
+        if let Some(marker_type) = self.marker_types.get(event_name) {
+            return *marker_type;
+        }
+
+        let category = *self.category.get_or_insert_with(|| {
+            profile.handle_for_category(Category("USDT", CategoryColor::Magenta))
+        });
+        let fields = (1..=arg_count)
+            .map(|i| RuntimeSchemaMarkerField {
+                key: format!("arg{i}"),
+                label: format!("arg{i}"),
+                format: MarkerFieldFormat::String,
+                flags: MarkerFieldFlags::SEARCHABLE,
+            })
+            .collect();
+        let args_label = (1..=arg_count)
+            .map(|i| format!("{{marker.data.arg{i}}}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let label = format!("{{marker.name}}({args_label})");
+        let handle = profile.register_marker_type(RuntimeSchemaMarkerSchema {
+            type_name: format!("USDT {event_name}"),
+            category,
+            description: Some(format!(
+                "Emitted for each hit of the USDT probe {event_name}."
+            )),
+            locations: MarkerLocations::MARKER_CHART | MarkerLocations::MARKER_TABLE,
+            chart_label: Some(format!("({args_label})")),
+            tooltip_label: Some(label.clone()),
+            table_label: Some(label),
+            fields,
+            graphs: Vec::new(),
+        });
+        self.marker_types
+            .insert(event_name.to_owned(), (handle, arg_count));
+        (handle, arg_count)
+    }
+}
+
+/// Reads the value of an argument from the registers or the user stack of the
+/// sample, truncated and sign-extended to the argument's size.
+fn read_argument<C: ConvertRegs>(
+    argument: &SdtArgument,
+    regs: Option<&Regs>,
+    stack: Option<&[u8]>,
+) -> Option<i64> {
+    let size = usize::from(argument.size.clamp(1, 8));
+    let register = |name: &str| regs?.get(C::register_index(name)?);
+    let value = match &argument.location {
+        SdtArgumentLocation::Constant(value) => *value as u64,
+        SdtArgumentLocation::Register(name) => register(name)?,
+        SdtArgumentLocation::Memory { base, offset } => {
+            // Only the memory on the sampled part of the stack can be read.
+            let address = register(base)?.wrapping_add(*offset as u64);
+            let sp = regs?.get(C::register_index("sp")?)?;
+            let start = usize::try_from(address.checked_sub(sp)?).ok()?;
+            let bytes = stack?.get(start..start.checked_add(size)?)?;
+            let mut buf = [0; 8];
+            buf[..size].copy_from_slice(bytes);
+            if cfg!(target_endian = "little") {
+                u64::from_le_bytes(buf)
+            } else {
+                u64::from_be_bytes(buf) >> (64 - 8 * size)
+            }
+        }
+        SdtArgumentLocation::Unknown(_) => return None,
+    };
+    let shift = 64 - 8 * size;
+    Some(if argument.signed {
+        ((value << shift) as i64) >> shift
+    } else {
+        ((value << shift) >> shift) as i64
+    })
+}
+
+fn format_argument(value: i64) -> String {
+    if (-4096..=0xffff).contains(&value) {
+        value.to_string()
+    } else {
+        format!("{:#x}", value as u64)
+    }
+}
+
+/// An instant marker for a hit of a USDT probe.
+#[derive(Debug, Clone)]
+pub struct UsdtMarker {
+    marker_type: MarkerTypeHandle,
+    name: StringHandle,
+    args: Vec<StringHandle>,
+}
+
+impl Marker for UsdtMarker {
+    fn marker_type(&self, _profile: &mut Profile) -> MarkerTypeHandle {
+        self.marker_type
+    }
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn string_field_value(&self, field_index: u32) -> StringHandle {
+        self.args[field_index as usize]
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+    use linux_perf_data::linux_perf_event_reader::RawDataU64;
+
+    use super::*;
+    use crate::linux_shared::ConvertRegsX86_64;
+
+    #[test]
+    fn test_read_arguments() {
+        // AX, DI and SP are sampled.
+        let mask = 1 | (1 << 5) | (1 << 7);
+        let mut reg_bytes = Vec::new();
+        for value in [0xffff_ffff_ffff_fffe_u64, 0x1_0000_002a, 0x7ff0_0000] {
+            reg_bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let regs = Regs::new(
+            mask,
+            RawDataU64::from_raw_data::<LittleEndian>(RawData::Single(&reg_bytes)),
+        );
+        let mut stack = vec![0; 32];
+        stack[16..24].copy_from_slice(&1234u64.to_le_bytes());
+
+        let read = |size: u8, signed: bool, location: SdtArgumentLocation| {
+            let argument = SdtArgument {
+                size,
+                signed,
+                location,
+            };
+            read_argument::<ConvertRegsX86_64>(&argument, Some(&regs), Some(&stack))
+        };
+        let register = |name: &str| SdtArgumentLocation::Register(name.into());
+        let stack_slot = |offset| SdtArgumentLocation::Memory {
+            base: "rsp".into(),
+            offset,
+        };
+        assert_eq!(read(4, true, register("eax")), Some(-2));
+        assert_eq!(read(4, false, register("eax")), Some(0xffff_fffe));
+        assert_eq!(read(4, true, register("edi")), Some(42));
+        assert_eq!(read(8, false, register("rdi")), Some(0x1_0000_002a));
+        assert_eq!(read(8, false, stack_slot(16)), Some(1234));
+        assert_eq!(read(8, false, SdtArgumentLocation::Constant(7)), Some(7));
+        // Registers which weren't sampled, and memory outside of the sampled stack.
+        assert_eq!(read(8, false, register("r9")), None);
+        assert_eq!(read(8, false, stack_slot(64)), None);
+        assert_eq!(format_argument(-2), "-2");
+        assert_eq!(format_argument(0x7ff0_0000), "0x7ff00000");
+    }
+}
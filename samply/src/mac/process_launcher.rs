@@ -7,7 +7,7 @@ use std::os::unix::prelude::OsStrExt;
 use std::path::PathBuf;
 use std::process::{Child, Command, ExitStatus};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use flate2::write::GzDecoder;
 use mach2::port::{mach_port_t, MACH_PORT_NULL};
@@ -18,6 +18,7 @@ use tempfile::tempdir;
 use super::mach_ipc::{BlockingMode, OsIpcMultiShotServer};
 pub use super::mach_ipc::{MachError, OsIpcSender};
 use crate::shared::ctrl_c::CtrlC;
+use crate::shared::iteration_times::IterationTimes;
 
 pub trait RootTaskRunner {
     fn run_root_task(&mut self) -> Result<ExitStatus, MachError>;
@@ -38,8 +39,11 @@ impl RootTaskRunner for TaskLauncher {
         // to all processes in the foreground process group).
         let mut ctrl_c_receiver = CtrlC::observe_oneshot();
 
+        let mut iteration_times = IterationTimes::new(self.iteration_count);
+        let start = Instant::now();
         let mut root_child = self.launch_child();
         let mut exit_status = root_child.wait().expect("couldn't wait for child");
+        iteration_times.add(start.elapsed());
 
         for i in 2..=self.iteration_count {
             if !self.ignore_exit_code && !exit_status.success() {
@@ -49,9 +53,12 @@ impl RootTaskRunner for TaskLauncher {
                 break;
             }
             eprintln!("Running iteration {i} of {}...", self.iteration_count);
+            let start = Instant::now();
             let mut root_child = self.launch_child();
             exit_status = root_child.wait().expect("couldn't wait for child");
+            iteration_times.add(start.elapsed());
         }
+        iteration_times.print_summary();
 
         // From now on, we want to terminate if the user presses Ctrl+C.
         ctrl_c_receiver.close();
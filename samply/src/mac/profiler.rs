@@ -30,7 +30,21 @@ pub fn run(
             eprintln!("You can only profile processes which you launch via samply, or attach to via --pid.");
             std::process::exit(1)
         }
-        RecordingMode::Pid(pid) => Box::new(ExistingProcessRunner::new(pid, &mut task_accepter)),
+        RecordingMode::ProcessName { .. } => {
+            eprintln!("Error: Attaching to processes by name is not supported on macOS.");
+            std::process::exit(1)
+        }
+        RecordingMode::Cgroup(_) => {
+            eprintln!("Error: cgroups are only supported on Linux.");
+            std::process::exit(1)
+        }
+        RecordingMode::Pid(pids) => {
+            let [pid] = pids[..] else {
+                eprintln!("Error: Attaching to multiple processes is not supported on macOS.");
+                std::process::exit(1)
+            };
+            Box::new(ExistingProcessRunner::new(pid, &mut task_accepter))
+        }
         RecordingMode::Launch(process_launch_props) => {
             let ProcessLaunchProps {
                 mut env_vars,
@@ -38,6 +52,7 @@ pub fn run(
                 args,
                 iteration_count,
                 ignore_exit_code,
+                iteration_processes: _,
             } = process_launch_props;
 
             let task_launcher = if profile_creation_props.coreclr.any_enabled() {
@@ -50,6 +50,7 @@ fn main() {
     match opt.action {
         cli::Action::Load(load_args) => do_load_action(load_args),
         cli::Action::Import(import_args) => do_import_action(import_args),
+        cli::Action::ListUsdt(list_usdt_args) => do_list_usdt_action(list_usdt_args),
 
         #[cfg(any(
             target_os = "android",
@@ -115,6 +116,42 @@ fn do_import_action(import_args: cli::ImportArgs) {
     }
 }
 
+fn do_list_usdt_action(list_usdt_args: cli::ListUsdtArgs) {
+    let path = &list_usdt_args.file;
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Could not read file {path:?}: {err}");
+            std::process::exit(1)
+        }
+    };
+    let file = match object::File::parse(&data[..]) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Could not parse file {path:?}: {err}");
+            std::process::exit(1)
+        }
+    };
+    let probes = wholesym::samply_symbols::sdt_probes(&file);
+    if probes.is_empty() {
+        eprintln!("No USDT probes found in {path:?}.");
+        return;
+    }
+    for probe in probes {
+        let mut line = format!(
+            "{}:{} at {:#x}, {} arguments",
+            probe.provider,
+            probe.name,
+            probe.address,
+            probe.arguments.len()
+        );
+        if let Some(semaphore_address) = probe.semaphore_address {
+            line.push_str(&format!(", semaphore at {semaphore_address:#x}"));
+        }
+        println!("{line}");
+    }
+}
+
 #[cfg(any(
     target_os = "android",
     target_os = "macos",
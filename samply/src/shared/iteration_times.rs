@@ -0,0 +1,80 @@
+//! The wall-clock times of the runs of `--iteration-count`, which are printed
+//! while recording, so that noisy runs stand out before the profile is opened.
+
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct IterationTimes {
+    iteration_count: u32,
+    times: Vec<Duration>,
+}
+
+impl IterationTimes {
+    pub fn new(iteration_count: u32) -> Self {
+        Self {
+            iteration_count,
+            times: Vec::new(),
+        }
+    }
+
+    /// Records the time of the next iteration, and prints it if the command is
+    /// run more than once.
+    pub fn add(&mut self, time: Duration) {
+        self.times.push(time);
+        if self.iteration_count > 1 {
+            eprintln!(
+                "Iteration {} of {} took {:.3}s.",
+                self.times.len(),
+                self.iteration_count,
+                time.as_secs_f64()
+            );
+        }
+    }
+
+    /// Prints the spread of the times, if more than one iteration was run.
+    pub fn print_summary(&self) {
+        if let Some(summary) = self.summary() {
+            eprintln!("{summary}");
+        }
+    }
+
+    fn summary(&self) -> Option<String> {
+        if self.times.len() < 2 {
+            return None;
+        }
+        let mut times: Vec<f64> = self.times.iter().map(Duration::as_secs_f64).collect();
+        times.sort_by(f64::total_cmp);
+        let n = times.len();
+        let median = if n % 2 == 0 {
+            (times[n / 2 - 1] + times[n / 2]) / 2.0
+        } else {
+            times[n / 2]
+        };
+        let mean = times.iter().sum::<f64>() / n as f64;
+        let std_dev = (times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / n as f64).sqrt();
+        Some(format!(
+            "Iteration times over {n} runs: min {:.3}s, median {median:.3}s, max {:.3}s, mean {mean:.3}s ± {std_dev:.3}s",
+            times[0],
+            times[n - 1],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut times = IterationTimes::new(4);
+        times.add(Duration::from_millis(1000));
+        assert_eq!(times.summary(), None);
+        times.add(Duration::from_millis(1400));
+        times.add(Duration::from_millis(1100));
+        times.add(Duration::from_millis(1300));
+        assert_eq!(
+            times.summary().unwrap(),
+            "Iteration times over 4 runs: min 1.000s, median 1.200s, max 1.400s, mean 1.200s ± 0.158s"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 pub mod context_switch;
 pub mod ctrl_c;
 pub mod included_processes;
+pub mod iteration_times;
 pub mod jit_category_manager;
 pub mod jit_function_add_marker;
 pub mod jit_function_recycler;
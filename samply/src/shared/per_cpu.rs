@@ -129,6 +129,10 @@ impl Cpus {
         }
     }
 
+    pub fn process_handle(&self) -> ProcessHandle {
+        self.process_handle
+    }
+
     pub fn combined_thread_handle(&self) -> ThreadHandle {
         self.combined_thread_handle
     }
@@ -1,6 +1,7 @@
 use fxprof_processed_profile::{
-    LibMappings, MarkerFieldFlags, MarkerFieldFormat, MarkerTiming, Profile, StaticSchemaMarker,
-    StaticSchemaMarkerField, StringHandle, SubcategoryHandle, ThreadHandle, Timestamp,
+    GraphColor, LibMappings, MarkerFieldFlags, MarkerFieldFormat, MarkerGraphType, MarkerTiming,
+    Profile, StaticSchemaMarker, StaticSchemaMarkerField, StaticSchemaMarkerGraph, StringHandle,
+    SubcategoryHandle, ThreadHandle, Timestamp,
 };
 
 use super::lib_mappings::{LibMappingInfo, LibMappingOpQueue, LibMappingsHierarchy};
@@ -8,7 +9,8 @@ use super::stack_converter::StackConverter;
 use super::stack_depth_limiting_frame_iter::StackDepthLimitingFrameIter;
 use super::types::StackFrame;
 use super::unresolved_samples::{
-    SampleData, SampleOrMarker, UnresolvedSampleOrMarker, UnresolvedSamples, UnresolvedStacks,
+    AllocationData, FractionalWeightSampleData, SampleData, SampleOrMarker,
+    UnresolvedSampleOrMarker, UnresolvedSamples, UnresolvedStacks,
 };
 
 #[derive(Debug, Clone)]
@@ -90,6 +92,7 @@ impl ProcessSampleData {
                 stack,
                 sample_or_marker,
                 extra_label_frame,
+                extra_leaf_frame,
                 ..
             } = sample;
 
@@ -100,6 +103,7 @@ impl ProcessSampleData {
                 stack_frame_scratch_buf,
                 &lib_mappings_hierarchy,
                 extra_label_frame,
+                extra_leaf_frame,
             );
             let mut frames =
                 StackDepthLimitingFrameIter::new(profile, frames, thread_handle, user_category);
@@ -109,9 +113,28 @@ impl ProcessSampleData {
                 SampleOrMarker::Sample(SampleData { cpu_delta, weight }) => {
                     profile.add_sample(thread_handle, timestamp, stack_handle, cpu_delta, weight);
                 }
+                SampleOrMarker::FractionalWeightSample(FractionalWeightSampleData {
+                    cpu_delta,
+                    weight,
+                }) => {
+                    profile.add_sample_with_fractional_weight(
+                        thread_handle,
+                        timestamp,
+                        stack_handle,
+                        cpu_delta,
+                        weight,
+                    );
+                }
                 SampleOrMarker::MarkerHandle(mh) => {
                     profile.set_marker_stack(thread_handle, mh, stack_handle);
                 }
+                SampleOrMarker::Allocation(AllocationData {
+                    process,
+                    address,
+                    size,
+                }) => {
+                    profile.add_allocation_sample(process, timestamp, stack_handle, address, size);
+                }
             }
         }
 
@@ -218,6 +241,75 @@ impl StaticSchemaMarker for OtherEventMarker {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct LostEventsMarker {
+    pub count: u64,
+}
+
+impl StaticSchemaMarker for LostEventsMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "LostEvents";
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "Emitted when the kernel dropped events because samply didn't read them quickly enough. Spans the time since the last sample before the loss.",
+    );
+
+    const CHART_LABEL: Option<&'static str> = Some("Lost {marker.data.count} events");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Lost {marker.data.count} events");
+    const TABLE_LABEL: Option<&'static str> = Some("Lost {marker.data.count} events");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[StaticSchemaMarkerField {
+        key: "count",
+        label: "Lost events",
+        format: MarkerFieldFormat::Integer,
+        flags: MarkerFieldFlags::SEARCHABLE,
+    }];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Lost events")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        unreachable!()
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        self.count as f64
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ThrottledMarker;
+
+impl StaticSchemaMarker for ThrottledMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Throttled";
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "Emitted while the kernel throttled sampling because the sampling interrupts took too much CPU time.",
+    );
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Throttled")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        unreachable!()
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UserTimingMarker(pub StringHandle);
 
@@ -255,6 +347,36 @@ impl StaticSchemaMarker for UserTimingMarker {
     }
 }
 
+/// A marker for text which was written to the ftrace `trace_marker` file.
+#[derive(Debug, Clone)]
+pub struct FtraceMarker(pub StringHandle);
+
+impl StaticSchemaMarker for FtraceMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "TraceMarker";
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "Emitted for text which was written to the ftrace trace_marker file, for example for atrace sections.",
+    );
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[];
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.0
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        unreachable!()
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
 pub struct SchedSwitchMarkerOnCpuTrack;
 
 impl StaticSchemaMarker for SchedSwitchMarkerOnCpuTrack {
@@ -317,6 +439,701 @@ impl StaticSchemaMarker for SchedSwitchMarkerOnThreadTrack {
     }
 }
 
+/// Emitted on the waking thread for each wakeup, with the waker's stack.
+pub struct WakeupMarker {
+    /// The name and tid of the woken thread.
+    pub woken_thread: StringHandle,
+    pub flow: u64,
+}
+
+impl StaticSchemaMarker for WakeupMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Wakeup";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted when this thread wakes up another thread.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.woken}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Wakeup of {marker.data.woken}");
+    const TABLE_LABEL: Option<&'static str> = Some("Wakeup of {marker.data.woken}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "woken",
+            label: "Woken thread",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "flow",
+            label: "Flow",
+            format: MarkerFieldFormat::Flow,
+            flags: MarkerFieldFlags::empty(),
+        },
+    ];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Wakeup")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.woken_thread
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        self.flow
+    }
+}
+
+/// Emitted on the woken thread, from the wakeup until the thread is switched in.
+pub struct WokenMarker {
+    /// The label of the waking thread.
+    pub waker_thread: StringHandle,
+    pub flow: u64,
+}
+
+impl StaticSchemaMarker for WokenMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Woken";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Lasts from the wakeup of this thread until it starts running.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.waker}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Woken by {marker.data.waker}");
+    const TABLE_LABEL: Option<&'static str> = Some("Woken by {marker.data.waker}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "waker",
+            label: "Waking thread",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "flow",
+            label: "Flow",
+            format: MarkerFieldFormat::Flow,
+            flags: MarkerFieldFlags::empty(),
+        },
+    ];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Woken")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.waker_thread
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        self.flow
+    }
+}
+
+/// Emitted for each state of a thread with `--thread-states`: running, runnable,
+/// sleeping or in an uninterruptible wait.
+pub struct ThreadStateMarker {
+    pub name: StringHandle,
+    /// How often the thread was moved to a different CPU while it was runnable.
+    pub migrations: u32,
+}
+
+impl StaticSchemaMarker for ThreadStateMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "ThreadState";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("The scheduler state of the thread, from sched_switch samples and wakeups.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.name}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[StaticSchemaMarkerField {
+        key: "migrations",
+        label: "CPU migrations",
+        format: MarkerFieldFormat::Integer,
+        flags: MarkerFieldFlags::empty(),
+    }];
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        unreachable!()
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        self.migrations.into()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// A block I/O request, from its issue to the device until its completion.
+#[derive(Debug, Clone)]
+pub struct BlockIoMarker {
+    pub name: StringHandle,
+    /// The device as "major:minor".
+    pub device: StringHandle,
+    /// The `rwbs` field of the tracepoint, e.g. "WS" for a synchronous write.
+    pub operation: StringHandle,
+    pub sector: u64,
+    pub size: u32,
+    /// The time from the submission of the request, i.e. its insert into the
+    /// queue or its issue if it wasn't queued, until its completion, in
+    /// milliseconds.
+    pub latency: f64,
+}
+
+impl StaticSchemaMarker for BlockIoMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "BlockIo";
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "A block I/O request, from the block_rq_issue tracepoint until the block_rq_complete tracepoint.",
+    );
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.operation} {marker.data.size}");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("{marker.name}: {marker.data.size} on {marker.data.device} in {marker.data.latency}");
+    const TABLE_LABEL: Option<&'static str> = Some(
+        "{marker.name} {marker.data.operation}, {marker.data.size} at sector {marker.data.sector} on {marker.data.device}",
+    );
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "device",
+            label: "Device",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "operation",
+            label: "Operation",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "sector",
+            label: "Sector",
+            format: MarkerFieldFormat::Integer,
+            flags: MarkerFieldFlags::empty(),
+        },
+        StaticSchemaMarkerField {
+            key: "size",
+            label: "Size",
+            format: MarkerFieldFormat::Bytes,
+            flags: MarkerFieldFlags::empty(),
+        },
+        StaticSchemaMarkerField {
+            key: "latency",
+            label: "Latency",
+            format: MarkerFieldFormat::Duration,
+            flags: MarkerFieldFlags::empty(),
+        },
+    ];
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn string_field_value(&self, field_index: u32) -> StringHandle {
+        match field_index {
+            0 => self.device,
+            1 => self.operation,
+            _ => unreachable!(),
+        }
+    }
+
+    fn number_field_value(&self, field_index: u32) -> f64 {
+        match field_index {
+            2 => self.sector as f64,
+            3 => self.size.into(),
+            4 => self.latency,
+            _ => unreachable!(),
+        }
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// The time which a CPU spent in an idle state, from `power:cpu_idle` samples.
+#[derive(Debug, Clone)]
+pub struct CpuIdleMarker {
+    /// The index of the idle state, e.g. 1 for C1 with the intel_idle driver.
+    pub state: u32,
+}
+
+impl StaticSchemaMarker for CpuIdleMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "CpuIdle";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("The time which the CPU spent in an idle state (C-state).");
+
+    const CHART_LABEL: Option<&'static str> = Some("C{marker.data.state}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Idle state {marker.data.state}");
+    const TABLE_LABEL: Option<&'static str> = Some("Idle state {marker.data.state}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[StaticSchemaMarkerField {
+        key: "state",
+        label: "Idle state",
+        format: MarkerFieldFormat::Integer,
+        flags: MarkerFieldFlags::empty(),
+    }];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("CPU idle")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        unreachable!()
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        self.state.into()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// The instructions and cycles of a thread between two samples, from the
+/// values of counting events. Drawn as a graph of the instructions per cycle.
+#[derive(Debug, Clone)]
+pub struct IpcMarker {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+impl StaticSchemaMarker for IpcMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "IPC";
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "The instructions per cycle of the thread since its previous sample, counted by perf.",
+    );
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.ipc}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("IPC: {marker.data.ipc}");
+    const TABLE_LABEL: Option<&'static str> = Some(
+        "IPC: {marker.data.ipc} ({marker.data.instructions} instructions, {marker.data.cycles} cycles)",
+    );
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "instructions",
+            label: "Instructions",
+            format: MarkerFieldFormat::Integer,
+            flags: MarkerFieldFlags::empty(),
+        },
+        StaticSchemaMarkerField {
+            key: "cycles",
+            label: "Cycles",
+            format: MarkerFieldFormat::Integer,
+            flags: MarkerFieldFlags::empty(),
+        },
+        StaticSchemaMarkerField {
+            key: "ipc",
+            label: "Instructions per cycle",
+            format: MarkerFieldFormat::Decimal,
+            flags: MarkerFieldFlags::empty(),
+        },
+    ];
+
+    const GRAPHS: &'static [StaticSchemaMarkerGraph] = &[StaticSchemaMarkerGraph {
+        key: "ipc",
+        graph_type: MarkerGraphType::Line,
+        color: Some(GraphColor::Teal),
+    }];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("IPC")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        unreachable!()
+    }
+
+    fn number_field_value(&self, field_index: u32) -> f64 {
+        match field_index {
+            0 => self.instructions as f64,
+            1 => self.cycles as f64,
+            2 => self.instructions as f64 / self.cycles as f64,
+            _ => unreachable!(),
+        }
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// A wait for a contended lock, either a futex wait or a contended kernel lock.
+#[derive(Debug, Clone)]
+pub struct LockContentionMarker {
+    pub name: StringHandle,
+    /// The address of the lock, in hex.
+    pub address: StringHandle,
+    /// The futex operation, e.g. "FUTEX_WAIT_PRIVATE", or the kind of kernel
+    /// lock, e.g. "mutex".
+    pub operation: StringHandle,
+}
+
+impl StaticSchemaMarker for LockContentionMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "LockContention";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("The time which a thread spent waiting for a contended lock.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.address}");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("{marker.name}: {marker.data.operation} {marker.data.address}");
+    const TABLE_LABEL: Option<&'static str> =
+        Some("{marker.name}: {marker.data.operation} {marker.data.address}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "address",
+            label: "Lock address",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "operation",
+            label: "Operation",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+    ];
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn string_field_value(&self, field_index: u32) -> StringHandle {
+        match field_index {
+            0 => self.address,
+            1 => self.operation,
+            _ => unreachable!(),
+        }
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// A call of a function which was probed with `perf probe` or `--probe`.
+#[derive(Debug, Clone)]
+pub struct ProbeMarker {
+    /// The probed function.
+    pub name: StringHandle,
+    /// The event name of the probe, e.g. `probe_libc:malloc`.
+    pub probe: StringHandle,
+}
+
+impl StaticSchemaMarker for ProbeMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Probe";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("A call of a probed function, from its entry until its return.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.name} ({marker.data.probe})");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[StaticSchemaMarkerField {
+        key: "probe",
+        label: "Probe",
+        format: MarkerFieldFormat::String,
+        flags: MarkerFieldFlags::SEARCHABLE,
+    }];
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.probe
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// Emitted on a thread when it creates a thread or process, with its stack.
+#[derive(Debug, Clone)]
+pub struct SpawnMarker {
+    /// The label of the new thread, or of the main thread of the new process.
+    pub child: StringHandle,
+    pub flow: u64,
+}
+
+impl StaticSchemaMarker for SpawnMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Spawn";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted when this thread creates a thread or forks a process.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.child}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Spawned {marker.data.child}");
+    const TABLE_LABEL: Option<&'static str> = Some("Spawned {marker.data.child}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "child",
+            label: "Spawned thread",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "flow",
+            label: "Flow",
+            format: MarkerFieldFormat::Flow,
+            flags: MarkerFieldFlags::empty(),
+        },
+    ];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Spawned")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.child
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        self.flow
+    }
+}
+
+/// Emitted at the start of a thread or process, linked to the spawn marker on
+/// the thread which created it.
+#[derive(Debug, Clone)]
+pub struct SpawnedByMarker {
+    /// The label of the thread which created this thread or process.
+    pub parent: StringHandle,
+    pub flow: u64,
+}
+
+impl StaticSchemaMarker for SpawnedByMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "SpawnedBy";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted when this thread or process is created by another thread.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.parent}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Spawned by {marker.data.parent}");
+    const TABLE_LABEL: Option<&'static str> = Some("Spawned by {marker.data.parent}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "parent",
+            label: "Spawning thread",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "flow",
+            label: "Flow",
+            format: MarkerFieldFormat::Flow,
+            flags: MarkerFieldFlags::empty(),
+        },
+    ];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Spawned by")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.parent
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        self.flow
+    }
+}
+
+/// Emitted on the main thread of a process when it executes a new program.
+#[derive(Debug, Clone)]
+pub struct ExecMarker {
+    /// The path of the executed program.
+    pub filename: StringHandle,
+}
+
+impl StaticSchemaMarker for ExecMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Exec";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted when the process executes a new program.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.filename}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("exec {marker.data.filename}");
+    const TABLE_LABEL: Option<&'static str> = Some("exec {marker.data.filename}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[StaticSchemaMarkerField {
+        key: "filename",
+        label: "Program",
+        format: MarkerFieldFormat::FilePath,
+        flags: MarkerFieldFlags::SEARCHABLE,
+    }];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Exec")
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.filename
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// The lifetime of a process, from its fork or exec until its exit.
+#[derive(Debug, Clone)]
+pub struct ProcessLifetimeMarker {
+    /// The name of the process.
+    pub name: StringHandle,
+    pub pid: i32,
+    /// How the process ended, e.g. "exit code 1", or "running" if it was still
+    /// running at the end of the profile.
+    pub status: StringHandle,
+}
+
+impl StaticSchemaMarker for ProcessLifetimeMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "ProcessLifetime";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("The lifetime of a process, from its fork or exec until its exit.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.name} ({marker.data.status})");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("{marker.name} (pid {marker.data.pid}): {marker.data.status}");
+    const TABLE_LABEL: Option<&'static str> =
+        Some("{marker.name} (pid {marker.data.pid}): {marker.data.status}");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "pid",
+            label: "pid",
+            format: MarkerFieldFormat::Integer,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "status",
+            label: "Exit status",
+            format: MarkerFieldFormat::String,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+    ];
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.status
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        self.pid.into()
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
+/// One run of the launched command when recording with `--iteration-count`,
+/// from its exec until its exit.
+#[derive(Debug, Clone)]
+pub struct IterationMarker {
+    /// The 1-based number of the iteration.
+    pub iteration: u32,
+    /// The pid of the launched process.
+    pub pid: i32,
+}
+
+impl StaticSchemaMarker for IterationMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Iteration";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("One run of the launched command, when it was run several times.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.name} (pid {marker.data.pid})");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.name} (pid {marker.data.pid})");
+
+    const FIELDS: &'static [StaticSchemaMarkerField] = &[
+        StaticSchemaMarkerField {
+            key: "iteration",
+            label: "Iteration",
+            format: MarkerFieldFormat::Integer,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+        StaticSchemaMarkerField {
+            key: "pid",
+            label: "pid",
+            format: MarkerFieldFormat::Integer,
+            flags: MarkerFieldFlags::SEARCHABLE,
+        },
+    ];
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string(&format!("Iteration {}", self.iteration))
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        unreachable!()
+    }
+
+    fn number_field_value(&self, field_index: u32) -> f64 {
+        match field_index {
+            0 => self.iteration.into(),
+            1 => self.pid.into(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn flow_field_value(&self, _field_index: u32) -> u64 {
+        unreachable!()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SimpleMarker(pub StringHandle);
 
@@ -41,6 +41,83 @@ pub struct RecordingProps {
     pub browsers: bool,
     #[allow(dead_code)]
     pub keep_etl: bool,
+    /// Save the raw perf events to this perf.data file (Linux only).
+    #[allow(dead_code)]
+    pub perf_data_path: Option<PathBuf>,
+    /// How user stacks are collected (Linux only).
+    #[allow(dead_code)]
+    pub call_graph: CallGraphMode,
+    /// The number of bytes of user stack to copy per sample, for DWARF unwinding (Linux only).
+    #[allow(dead_code)]
+    pub stack_size: u32,
+    /// Start with recording paused, until resumed with SIGUSR2 (Linux only).
+    #[allow(dead_code)]
+    pub start_paused: bool,
+    /// Record native allocations of launched processes, sampling one allocation
+    /// per this many bytes (Linux only).
+    #[allow(dead_code)]
+    pub allocation_sampling_interval: Option<u64>,
+    /// Record the text which is written to the ftrace trace_marker file (Linux only).
+    #[allow(dead_code)]
+    pub trace_markers: bool,
+    /// Count instructions and cache misses alongside the sampled CPU cycles, and
+    /// add them as counter tracks (Linux only).
+    #[allow(dead_code)]
+    pub hardware_counters: bool,
+    /// Record the memory usage, page faults and mmap/mprotect calls of the
+    /// recorded processes (Linux only).
+    #[allow(dead_code)]
+    pub memory: bool,
+    /// Record which threads wake up which other threads (Linux only).
+    #[allow(dead_code)]
+    pub wakeups: bool,
+    /// Record the stacks at which threads and processes are created (Linux only).
+    #[allow(dead_code)]
+    pub spawns: bool,
+    /// Record the block I/O requests of all processes (Linux only).
+    #[allow(dead_code)]
+    pub block_io: bool,
+    /// Record the frequencies and idle states of the CPUs (Linux only).
+    #[allow(dead_code)]
+    pub cpu_power: bool,
+    /// Additional tracepoints to sample, as `system:name` (Linux only).
+    #[allow(dead_code)]
+    pub tracepoints: Vec<String>,
+    /// Functions to probe with uprobes, as `/path/to/binary:symbol` or
+    /// `/path/to/binary:symbol%return` (Linux only).
+    #[allow(dead_code)]
+    pub probes: Vec<String>,
+    /// USDT probes to record, as `[/path/to/binary:]provider:name` (Linux only).
+    #[allow(dead_code)]
+    pub usdt: Vec<String>,
+    /// Keep recording with bounded memory, and only write the most recent
+    /// events when asked to (Linux only).
+    #[allow(dead_code)]
+    pub flight_recorder: Option<FlightRecorderProps>,
+}
+
+/// When and how the flight recorder writes a profile of the most recent events.
+#[derive(Debug, Clone)]
+pub struct FlightRecorderProps {
+    /// The minimum duration of the recording which each dump covers.
+    pub window: Duration,
+    /// A Unix socket which accepts `dump` commands.
+    pub socket_path: Option<PathBuf>,
+    /// Write a dump when a marker file gets a line which contains this string.
+    pub trigger: Option<String>,
+}
+
+/// How user stacks are collected when recording on Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallGraphMode {
+    /// The kernel walks the user stack using frame pointers.
+    FramePointer,
+    /// The raw user stack bytes are copied into each sample and unwound by us,
+    /// using the unwind information from the binaries.
+    Dwarf,
+    /// Both of the above. The frame pointer stack is used unless DWARF
+    /// unwinding finds more frames, e.g. in code without frame pointers.
+    Mixed,
 }
 
 /// Which process(es) to record.
@@ -48,8 +125,16 @@ pub struct RecordingProps {
 pub enum RecordingMode {
     /// Record all processes, system-wide.
     All,
-    /// Record just a single process (and its children).
-    Pid(u32),
+    /// Record the given processes (and their children).
+    Pid(Vec<u32>),
+    /// Record the running processes whose names match (and their children).
+    ProcessName {
+        included_processes: IncludedProcesses,
+        /// Also record matching processes which are started during recording.
+        include_new_processes: bool,
+    },
+    /// Record all processes in a cgroup.
+    Cgroup(PathBuf),
     /// Launch a process, and record just that process (and its children).
     Launch(ProcessLaunchProps),
 }
@@ -60,6 +145,8 @@ impl RecordingMode {
         match self {
             RecordingMode::All => true,
             RecordingMode::Pid(_) => true,
+            RecordingMode::ProcessName { .. } => true,
+            RecordingMode::Cgroup(_) => true,
             RecordingMode::Launch(_) => false,
         }
     }
@@ -101,6 +188,15 @@ pub struct ProfileCreationProps {
     /// Whether to emit context switch markers.
     #[allow(dead_code)]
     pub should_emit_cswitch_markers: bool,
+    /// Pair up syscall enter and exit tracepoints into syscall markers (Linux only).
+    #[allow(dead_code)]
+    pub syscalls: bool,
+    /// Add a timeline of the thread states from scheduler tracepoints (Linux only).
+    #[allow(dead_code)]
+    pub thread_states: bool,
+    /// Add markers and samples for futex waits and contended kernel locks (Linux only).
+    #[allow(dead_code)]
+    pub lock_contention: bool,
 }
 
 impl ProfileCreationProps {
@@ -119,6 +215,21 @@ pub struct ProcessLaunchProps {
     pub args: Vec<OsString>,
     pub iteration_count: u32,
     pub ignore_exit_code: bool,
+    /// How the processes of the iterations appear in the profile (Linux only).
+    #[allow(dead_code)]
+    pub iteration_processes: IterationProcesses,
+}
+
+/// How the processes of the runs of a command which is launched several times
+/// appear in the profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IterationProcesses {
+    /// Each run has its own processes, which have the number of the run in
+    /// their names.
+    Separate,
+    /// The processes of all runs are merged by name, so that the call tree
+    /// aggregates the runs.
+    Merged,
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +49,7 @@ struct ConvertedStackIterD<I: Iterator<Item = SecondPassFrameInfo>> {
     inner: I,
     thread: ThreadHandle,
     pending_frame_handle: Option<FrameHandle>,
+    last_frame_handle: Option<FrameHandle>,
     js_name_for_baseline_interpreter: Option<JsName>,
 }
 
@@ -206,12 +207,15 @@ impl<I: Iterator<Item = SecondPassFrameInfo>> ConvertedStackIterD<I> {
         if let Some(pending_frame_handle) = self.pending_frame_handle.take() {
             return Some(pending_frame_handle);
         }
-        let SecondPassFrameInfo {
+        let Some(SecondPassFrameInfo {
             location,
             category,
             js_frame,
             ..
-        } = self.inner.next()?;
+        }) = self.inner.next()
+        else {
+            return self.last_frame_handle.take();
+        };
 
         let mut frame_flags = FrameFlags::empty();
 
@@ -284,13 +288,15 @@ impl StackConverter {
 
     /// Takes a stack going from callee to root caller.
     ///
-    /// Returns an iterator going from root caller to callee.
+    /// Returns an iterator going from root caller to callee, starting with
+    /// `extra_first_frame` and ending with `extra_last_frame`, if present.
     pub fn convert_stack<'a>(
         &'a mut self,
         thread: ThreadHandle,
         stack: &'a [StackFrame],
         lib_mappings: &'a LibMappingsHierarchy,
         extra_first_frame: Option<FrameHandle>,
+        extra_last_frame: Option<FrameHandle>,
     ) -> ConvertedStackIter<'a> {
         let pass1 = FirstPassIter(stack.iter().cloned().rev());
         let pass2 = SecondPassIter {
@@ -309,6 +315,7 @@ impl StackConverter {
             inner: pass3,
             thread,
             pending_frame_handle: extra_first_frame,
+            last_frame_handle: extra_last_frame,
             js_name_for_baseline_interpreter: None,
         };
         ConvertedStackIter(pass4)
@@ -1,6 +1,8 @@
 use std::collections::hash_map::Entry;
 
-use fxprof_processed_profile::{CpuDelta, FrameHandle, MarkerHandle, ThreadHandle, Timestamp};
+use fxprof_processed_profile::{
+    CpuDelta, FrameHandle, MarkerHandle, ProcessHandle, ThreadHandle, Timestamp,
+};
 
 use super::types::{FastHashMap, StackFrame, StackMode};
 
@@ -35,15 +37,90 @@ impl UnresolvedSamples {
         cpu_delta: CpuDelta,
         weight: i32,
         extra_label_frame: Option<FrameHandle>,
+    ) {
+        self.push_sample(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            SampleData { weight, cpu_delta },
+            extra_label_frame,
+            None,
+        );
+    }
+
+    /// Adds a sample with an extra label frame below the leaf of the stack, for
+    /// example for the state of an off-CPU thread.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_sample_with_leaf_frame(
+        &mut self,
+        thread_handle: ThreadHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        cpu_delta: CpuDelta,
+        weight: i32,
+        extra_leaf_frame: Option<FrameHandle>,
+    ) {
+        self.push_sample(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            SampleData { weight, cpu_delta },
+            None,
+            extra_leaf_frame,
+        );
+    }
+
+    /// Adds a sample whose weight can have a fractional part, for example a
+    /// duration in milliseconds on a thread with the `TracingMs` weight type.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_sample_with_fractional_weight(
+        &mut self,
+        thread_handle: ThreadHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        cpu_delta: CpuDelta,
+        weight: f64,
+        extra_label_frame: Option<FrameHandle>,
+    ) {
+        self.samples_and_markers.push(UnresolvedSampleOrMarker {
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            extra_label_frame,
+            extra_leaf_frame: None,
+            sample_or_marker: SampleOrMarker::FractionalWeightSample(FractionalWeightSampleData {
+                cpu_delta,
+                weight,
+            }),
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push_sample(
+        &mut self,
+        thread_handle: ThreadHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        data: SampleData,
+        extra_label_frame: Option<FrameHandle>,
+        extra_leaf_frame: Option<FrameHandle>,
     ) {
         let sample_index = self.samples_and_markers.len();
+        let cpu_delta = data.cpu_delta;
         self.samples_and_markers.push(UnresolvedSampleOrMarker {
             thread_handle,
             timestamp,
             timestamp_mono,
             stack,
             extra_label_frame,
-            sample_or_marker: SampleOrMarker::Sample(SampleData { weight, cpu_delta }),
+            extra_leaf_frame,
+            sample_or_marker: SampleOrMarker::Sample(data),
         });
         self.prev_sample_info_per_thread.insert(
             thread_handle,
@@ -83,6 +160,7 @@ impl UnresolvedSamples {
                         timestamp_mono,
                         stack,
                         extra_label_frame,
+                        extra_leaf_frame: None,
                         sample_or_marker: SampleOrMarker::Sample(SampleData {
                             weight,
                             cpu_delta: CpuDelta::ZERO,
@@ -100,6 +178,7 @@ impl UnresolvedSamples {
                     timestamp_mono,
                     stack,
                     extra_label_frame,
+                    extra_leaf_frame: None,
                     sample_or_marker: SampleOrMarker::Sample(SampleData {
                         weight,
                         cpu_delta: CpuDelta::ZERO,
@@ -113,6 +192,35 @@ impl UnresolvedSamples {
         }
     }
 
+    /// Adds an allocation sample. `thread_handle` must be the main thread of `process`,
+    /// see `Profile::add_allocation_sample`.
+    #[allow(clippy::too_many_arguments)]
+    #[allow(unused)]
+    pub fn add_allocation_sample(
+        &mut self,
+        thread_handle: ThreadHandle,
+        process: ProcessHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        address: u64,
+        size: i64,
+    ) {
+        self.samples_and_markers.push(UnresolvedSampleOrMarker {
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            extra_label_frame: None,
+            extra_leaf_frame: None,
+            sample_or_marker: SampleOrMarker::Allocation(AllocationData {
+                process,
+                address,
+                size,
+            }),
+        });
+    }
+
     pub fn attach_stack_to_marker(
         &mut self,
         thread_handle: ThreadHandle,
@@ -127,6 +235,7 @@ impl UnresolvedSamples {
             timestamp_mono,
             stack,
             extra_label_frame: None,
+            extra_leaf_frame: None,
             sample_or_marker: SampleOrMarker::MarkerHandle(marker_handle),
         });
     }
@@ -139,13 +248,17 @@ pub struct UnresolvedSampleOrMarker {
     pub timestamp_mono: u64,
     pub stack: UnresolvedStackHandle,
     pub extra_label_frame: Option<FrameHandle>,
+    /// A label frame which is added below the leaf of the stack.
+    pub extra_leaf_frame: Option<FrameHandle>,
     pub sample_or_marker: SampleOrMarker,
 }
 
 #[derive(Debug, Clone)]
 pub enum SampleOrMarker {
     Sample(SampleData),
+    FractionalWeightSample(FractionalWeightSampleData),
     MarkerHandle(MarkerHandle),
+    Allocation(AllocationData),
 }
 
 #[derive(Debug, Clone)]
@@ -154,6 +267,20 @@ pub struct SampleData {
     pub weight: i32,
 }
 
+#[derive(Debug, Clone)]
+pub struct FractionalWeightSampleData {
+    pub cpu_delta: CpuDelta,
+    pub weight: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocationData {
+    pub process: ProcessHandle,
+    pub address: u64,
+    /// Positive for allocations, negative for deallocations.
+    pub size: i64,
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct UnresolvedStackHandle(u32);
 
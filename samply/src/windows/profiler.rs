@@ -1,5 +1,6 @@
 use std::os::windows::process::ExitStatusExt;
 use std::process::ExitStatus;
+use std::time::Instant;
 
 use fxprof_processed_profile::{Profile, ReferenceTimestamp, SamplingInterval};
 
@@ -7,6 +8,7 @@ use super::etw_gecko;
 use super::profile_context::ProfileContext;
 use crate::shared::ctrl_c::CtrlC;
 use crate::shared::included_processes::IncludedProcesses;
+use crate::shared::iteration_times::IterationTimes;
 use crate::shared::prop_types::{ProfileCreationProps, RecordingMode, RecordingProps};
 use crate::windows::elevated_helper::ElevatedHelperSession;
 
@@ -41,6 +43,11 @@ pub fn run(
     recording_props: RecordingProps,
     profile_creation_props: ProfileCreationProps,
 ) -> Result<(Profile, ExitStatus), i32> {
+    if let RecordingMode::Cgroup(_) = recording_mode {
+        eprintln!("Error: cgroups are only supported on Linux.");
+        std::process::exit(1)
+    }
+
     let timebase = std::time::SystemTime::now();
     let timebase = ReferenceTimestamp::from_system_time(timebase);
 
@@ -67,17 +74,34 @@ pub fn run(
             let _ = ctrl_c_receiver.blocking_recv();
             None
         }
-        RecordingMode::Pid(pid) => {
+        RecordingMode::ProcessName {
+            included_processes, ..
+        } => {
             let ctrl_c_receiver = CtrlC::observe_oneshot();
-            // TODO: check that process with this pid exists
-            eprintln!("Profiling process with pid {pid}...");
+            eprintln!(
+                "Profiling processes matching {:?}...",
+                included_processes.name_substrings
+            );
+            eprintln!("Press Ctrl+C to stop.");
+            // TODO: Respect recording_props.time_limit, if specified
+            // Wait for Ctrl+C.
+            let _ = ctrl_c_receiver.blocking_recv();
+            // We're recording all processes, so processes which are started
+            // during recording are included as well.
+            Some(included_processes)
+        }
+        RecordingMode::Cgroup(_) => unreachable!("checked above"),
+        RecordingMode::Pid(pids) => {
+            let ctrl_c_receiver = CtrlC::observe_oneshot();
+            // TODO: check that processes with these pids exist
+            eprintln!("Profiling processes with pids {pids:?}...");
             eprintln!("Press Ctrl+C to stop.");
             // TODO: Respect recording_props.time_limit, if specified
             // Wait for Ctrl+C.
             let _ = ctrl_c_receiver.blocking_recv();
             Some(IncludedProcesses {
                 name_substrings: Vec::new(),
-                pids: vec![pid],
+                pids,
             })
         }
         RecordingMode::Launch(process_launch_props) => {
@@ -86,7 +110,9 @@ pub fn run(
             let mut ctrl_c_receiver = CtrlC::observe_oneshot();
 
             let mut pids = Vec::new();
+            let mut iteration_times = IterationTimes::new(process_launch_props.iteration_count);
             for _ in 0..process_launch_props.iteration_count {
+                let start = Instant::now();
                 let mut child = std::process::Command::new(&process_launch_props.command_name);
                 child.args(&process_launch_props.args);
                 child.envs(process_launch_props.env_vars.iter().map(|(k, v)| (k, v)));
@@ -103,6 +129,7 @@ pub fn run(
                 // press Ctrl+C again, which would immediately terminate this process and not
                 // give us a chance to stop xperf.
                 let exit_status = child.wait().unwrap();
+                iteration_times.add(start.elapsed());
                 if !process_launch_props.ignore_exit_code && !exit_status.success() {
                     eprintln!(
                         "Skipping remaining iterations due to non-success exit status: \"{exit_status}\""
@@ -110,6 +137,7 @@ pub fn run(
                     break;
                 }
             }
+            iteration_times.print_summary();
 
             // The launched subprocess is done. From now on, we want to terminate if the user presses Ctrl+C.
             ctrl_c_receiver.close();